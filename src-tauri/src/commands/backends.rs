@@ -0,0 +1,934 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::helper::{HelperOp, SiteConfigSpec};
+use super::share::lan_site;
+use super::sites::Site;
+use super::webserver::{site_document_root, site_domains};
+
+/// Web servers ServerMark can manage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebServerKind {
+    Caddy,
+    Nginx,
+    Apache,
+}
+
+impl WebServerKind {
+    pub const ALL: [WebServerKind; 3] = [Self::Caddy, Self::Nginx, Self::Apache];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Caddy => "caddy",
+            Self::Nginx => "nginx",
+            Self::Apache => "apache",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "caddy" => Ok(Self::Caddy),
+            "nginx" => Ok(Self::Nginx),
+            "apache" | "apache2" | "httpd" => Ok(Self::Apache),
+            _ => Err("Unknown web server".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for WebServerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Ports the managed web server listens on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenPorts {
+    pub http: u16,
+    pub https: u16,
+}
+
+impl Default for ListenPorts {
+    fn default() -> Self {
+        Self {
+            http: 80,
+            https: 443,
+        }
+    }
+}
+
+impl ListenPorts {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `:port` suffix for URLs, empty for the standard port of the scheme
+    pub fn url_suffix(&self, secured: bool) -> String {
+        match (secured, self.http, self.https) {
+            (true, _, 443) | (false, 80, _) => String::new(),
+            (true, _, port) | (false, port, _) => format!(":{}", port),
+        }
+    }
+}
+
+/// Everything that differs between web servers: config rendering, file
+/// layout, installation and service control. Methods returning shell
+/// snippets are assembled into a single script run through pkexec; the
+/// `*_ops` methods express the same changes for the privileged helper.
+pub trait WebServerBackend {
+    fn kind(&self) -> WebServerKind;
+
+    /// systemd unit name
+    fn service(&self) -> &'static str;
+
+    /// Directories holding site configs; the first one is where configs are written
+    fn site_dirs(&self) -> Vec<&'static str>;
+
+    /// File name of a site's config inside the site directories
+    fn site_file_name(&self, site_name: &str) -> String;
+
+    /// Reverse of `site_file_name`, for files ServerMark generated
+    fn site_name_from_file<'a>(&self, file: &'a str) -> Option<&'a str>;
+
+    fn render_site(&self, site: &Site, ports: ListenPorts) -> String;
+
+    /// A site's config file: its server block, plus one for the LAN hostname
+    /// when the site is shared
+    fn render_site_config(&self, site: &Site, ports: ListenPorts) -> String {
+        let mut config = self.render_site(site, ports);
+        if let Some(shared) = lan_site(site) {
+            config.push('\n');
+            config.push_str(&self.render_site(&shared, ports));
+        }
+        config
+    }
+
+    fn install_script(&self, package_manager: &str) -> Result<String, String>;
+
+    /// Command checking the configuration before a reload
+    fn validate_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn site_config_path(&self, site_name: &str) -> String {
+        format!("{}/{}", self.site_dirs()[0], self.site_file_name(site_name))
+    }
+
+    /// Symlink `(target, link)` enabling a written site config, if the layout uses one
+    fn enabled_site_link(&self, _site_name: &str) -> Option<(String, String)> {
+        None
+    }
+
+    /// Shell commands enabling a written site config
+    fn enable_site_script(&self, site_name: &str) -> String {
+        match self.enabled_site_link(site_name) {
+            Some((target, link)) => format!("ln -sf '{}' '{}'\n", target, link),
+            None => String::new(),
+        }
+    }
+
+    /// Shell commands issuing a self-signed certificate when the site is secured
+    fn certificate_script(&self, site: &Site) -> String {
+        if !site.secured {
+            return String::new();
+        }
+        format!(
+            r#"if [ ! -f "/etc/servermark/ssl/{domain}.crt" ]; then
+    openssl req -x509 -nodes -days 365 -newkey rsa:2048 \
+        -keyout "/etc/servermark/ssl/{domain}.key" \
+        -out "/etc/servermark/ssl/{domain}.crt" \
+        -subj "/CN={domain}" 2>/dev/null
+fi
+"#,
+            domain = site.domain
+        )
+    }
+
+    fn certificate_ops(&self, site: &Site) -> Vec<HelperOp> {
+        if !site.secured {
+            return Vec::new();
+        }
+        vec![HelperOp::IssueCertificate {
+            domain: site.domain.clone(),
+        }]
+    }
+
+    /// Shell commands pointing the server's global listeners at `ports`
+    fn ports_script(&self, _ports: ListenPorts) -> String {
+        String::new()
+    }
+
+    /// Helper counterpart of `ports_script`; `None` when the change touches
+    /// files outside the helper's allow-list and needs the script instead
+    fn ports_ops(&self, _ports: ListenPorts) -> Option<Vec<HelperOp>> {
+        Some(Vec::new())
+    }
+
+    /// Write, enable and (if needed) issue certificates for a site
+    fn write_site_script(&self, site: &Site, ports: ListenPorts) -> String {
+        format!(
+            "# Site: {}\ncat > '{}' << 'SITEEOF'\n{}\nSITEEOF\n{}{}{}",
+            site.name,
+            self.site_config_path(&site.name),
+            self.render_site_config(site, ports),
+            self.enable_site_script(&site.name),
+            self.certificate_script(site),
+            lan_site(site)
+                .map(|shared| self.certificate_script(&shared))
+                .unwrap_or_default()
+        )
+    }
+
+    fn write_site_ops(&self, site: &Site, ports: ListenPorts) -> Vec<HelperOp> {
+        let mut ops = vec![HelperOp::WriteSiteConfig {
+            server: self.kind(),
+            site: SiteConfigSpec::new(site, ports),
+        }];
+        if let Some((target, link)) = self.enabled_site_link(&site.name) {
+            ops.push(HelperOp::LinkManagedFile { target, link });
+        }
+        ops.extend(self.certificate_ops(site));
+        if let Some(shared) = lan_site(site) {
+            ops.extend(self.certificate_ops(&shared));
+        }
+        ops
+    }
+
+    fn remove_site_script(&self, site_name: &str) -> String {
+        self.site_dirs()
+            .iter()
+            .map(|dir| format!("rm -f '{}/{}'\n", dir, self.site_file_name(site_name)))
+            .collect()
+    }
+
+    /// Remove every ServerMark site config
+    fn clear_sites_script(&self) -> String {
+        self.site_dirs()
+            .iter()
+            .map(|dir| format!("rm -f {}/{}\n", dir, self.site_file_name("*")))
+            .collect()
+    }
+
+    fn remove_site_ops(&self, site_name: &str) -> Vec<HelperOp> {
+        self.site_dirs()
+            .iter()
+            .map(|dir| HelperOp::RemoveManagedFile {
+                path: format!("{}/{}", dir, self.site_file_name(site_name)),
+            })
+            .collect()
+    }
+
+    /// Remove the ServerMark site configs currently on disk
+    fn clear_sites_ops(&self) -> Vec<HelperOp> {
+        let mut ops = Vec::new();
+        for dir in self.site_dirs() {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let file = entry.file_name().to_string_lossy().to_string();
+                if self.site_name_from_file(&file).is_some() {
+                    ops.push(HelperOp::RemoveManagedFile {
+                        path: format!("{}/{}", dir, file),
+                    });
+                }
+            }
+        }
+        ops
+    }
+
+    /// Create the directories site configs are written to
+    fn prepare_script(&self) -> String {
+        self.site_dirs()
+            .iter()
+            .map(|dir| format!("mkdir -p {}\n", dir))
+            .collect()
+    }
+
+    fn reload_script(&self) -> String {
+        let service = self.service();
+        match self.validate_command() {
+            Some(check) => format!(
+                "{} && systemctl reload {} || systemctl restart {}\n",
+                check, service, service
+            ),
+            None => format!(
+                "systemctl reload {} || systemctl restart {}\n",
+                service, service
+            ),
+        }
+    }
+
+    fn start_script(&self) -> String {
+        let service = self.service();
+        match self.validate_command() {
+            Some(check) => format!(
+                "systemctl enable {}\n{} && systemctl start {}\n",
+                service, check, service
+            ),
+            None => format!(
+                "systemctl enable {}\nsystemctl start {}\n",
+                service, service
+            ),
+        }
+    }
+
+    /// The helper validates the config itself before reloading
+    fn reload_ops(&self) -> Vec<HelperOp> {
+        vec![self.unit_op("reload")]
+    }
+
+    fn start_ops(&self) -> Vec<HelperOp> {
+        vec![self.unit_op("enable"), self.unit_op("start")]
+    }
+
+    fn stop_ops(&self) -> Vec<HelperOp> {
+        vec![self.unit_op("stop"), self.unit_op("disable")]
+    }
+
+    fn unit_op(&self, action: &str) -> HelperOp {
+        HelperOp::ControlUnit {
+            unit: self.service().to_string(),
+            action: action.to_string(),
+        }
+    }
+
+    fn stop_script(&self) -> String {
+        format!(
+            "systemctl stop {} 2>/dev/null || true\nsystemctl disable {} 2>/dev/null || true\n",
+            self.service(),
+            self.service()
+        )
+    }
+}
+
+/// Backend for a web server kind
+pub fn backend(kind: WebServerKind) -> Box<dyn WebServerBackend> {
+    match kind {
+        WebServerKind::Caddy => Box::new(CaddyBackend),
+        WebServerKind::Nginx => Box::new(NginxBackend),
+        WebServerKind::Apache => Box::new(ApacheBackend::detect()),
+    }
+}
+
+/// Every backend and distro layout, for code that must recognise any managed file
+pub fn all_backends() -> Vec<Box<dyn WebServerBackend>> {
+    vec![
+        Box::new(CaddyBackend),
+        Box::new(NginxBackend),
+        Box::new(ApacheBackend {
+            layout: ApacheLayout::Debian,
+        }),
+        Box::new(ApacheBackend {
+            layout: ApacheLayout::Suse,
+        }),
+        Box::new(ApacheBackend {
+            layout: ApacheLayout::RedHat,
+        }),
+    ]
+}
+
+fn php_socket(site: &Site) -> String {
+    format!("/var/run/php/php{}-fpm.sock", site.php_version)
+}
+
+fn package_install_command(package_manager: &str, packages: &str) -> Result<String, String> {
+    Ok(match package_manager {
+        "apt" => format!("apt install -y {}", packages),
+        "dnf" => format!("dnf install -y {}", packages),
+        "pacman" => format!("pacman -S --noconfirm {}", packages),
+        "zypper" => format!("zypper install -y {}", packages),
+        _ => return Err("Unsupported package manager".to_string()),
+    })
+}
+
+// ============================================================================
+// Caddy
+// ============================================================================
+
+pub struct CaddyBackend;
+
+/// Global options file written when Caddy runs on alternative ports
+pub const CADDY_PORTS_FILE_STEM: &str = "00-servermark-ports";
+
+pub fn caddy_ports_path() -> String {
+    format!("/etc/caddy/sites.d/{}.conf", CADDY_PORTS_FILE_STEM)
+}
+
+pub fn caddy_ports_options(ports: ListenPorts) -> String {
+    format!(
+        "{{\n    http_port {}\n    https_port {}\n}}\n",
+        ports.http, ports.https
+    )
+}
+
+impl WebServerBackend for CaddyBackend {
+    fn kind(&self) -> WebServerKind {
+        WebServerKind::Caddy
+    }
+
+    fn service(&self) -> &'static str {
+        "caddy"
+    }
+
+    fn site_dirs(&self) -> Vec<&'static str> {
+        vec!["/etc/caddy/sites.d"]
+    }
+
+    fn site_file_name(&self, site_name: &str) -> String {
+        format!("{}.conf", site_name)
+    }
+
+    fn site_name_from_file<'a>(&self, file: &'a str) -> Option<&'a str> {
+        // servermark.conf is the placeholder created at install time
+        file.strip_suffix(".conf")
+            .filter(|name| *name != "servermark" && *name != CADDY_PORTS_FILE_STEM)
+    }
+
+    fn render_site(&self, site: &Site, ports: ListenPorts) -> String {
+        let (scheme, tls_directive) = if site.secured {
+            ("https", "    tls internal\n")
+        } else {
+            ("http", "")
+        };
+        let port = ports.url_suffix(site.secured);
+        let domain = site_domains(site)
+            .iter()
+            .map(|d| format!("{}://{}{}", scheme, d, port))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            r#"{domain} {{
+{tls_directive}    root * {public_path}
+
+    php_fastcgi unix/{php_socket}
+    file_server
+
+    encode gzip
+}}
+"#,
+            domain = domain,
+            tls_directive = tls_directive,
+            public_path = site_document_root(site),
+            php_socket = php_socket(site),
+        )
+    }
+
+    /// Global options must be the first block of the Caddyfile; the sites.d
+    /// import sits at the top and files are imported in name order.
+    fn ports_script(&self, ports: ListenPorts) -> String {
+        if ports.is_default() {
+            return format!("rm -f '{}'\n", caddy_ports_path());
+        }
+        format!(
+            "cat > '{}' << 'SITEEOF'\n{}SITEEOF\n",
+            caddy_ports_path(),
+            caddy_ports_options(ports)
+        )
+    }
+
+    fn ports_ops(&self, ports: ListenPorts) -> Option<Vec<HelperOp>> {
+        let op = if ports.is_default() {
+            HelperOp::RemoveManagedFile {
+                path: caddy_ports_path(),
+            }
+        } else {
+            HelperOp::WriteCaddyPorts {
+                http: ports.http,
+                https: ports.https,
+            }
+        };
+        Some(vec![op])
+    }
+
+    /// Caddy issues `tls internal` certificates itself
+    fn certificate_script(&self, _site: &Site) -> String {
+        String::new()
+    }
+
+    fn certificate_ops(&self, _site: &Site) -> Vec<HelperOp> {
+        Vec::new()
+    }
+
+    fn prepare_script(&self) -> String {
+        "mkdir -p /etc/caddy/sites.d\nchown -R root:root /etc/caddy/sites.d 2>/dev/null || true\nchmod 755 /etc/caddy/sites.d\n"
+            .to_string()
+    }
+
+    fn install_script(&self, package_manager: &str) -> Result<String, String> {
+        let install_cmd = package_install_command(package_manager, "caddy")?;
+
+        // Uses /etc/caddy/sites.d/ which Caddy can access (unlike user's home)
+        Ok(format!(
+            r##"
+set -e
+
+# 1. Install Caddy
+{install_cmd}
+
+# 2. Add caddy user to www-data group (for PHP-FPM socket access)
+usermod -aG www-data caddy || true
+
+# 3. Create ServerMark sites directory
+mkdir -p /etc/caddy/sites.d
+chown caddy:caddy /etc/caddy/sites.d
+chmod 755 /etc/caddy/sites.d
+
+# 4. Create empty Caddyfile for ServerMark sites
+echo "# ServerMark sites" > /etc/caddy/sites.d/servermark.conf
+chown caddy:caddy /etc/caddy/sites.d/servermark.conf
+
+# 5. Add import to system Caddyfile if not present
+IMPORT_LINE="import /etc/caddy/sites.d/*.conf"
+if ! grep -q "sites.d" /etc/caddy/Caddyfile 2>/dev/null; then
+    # Prepend import line to existing Caddyfile
+    if [ -f /etc/caddy/Caddyfile ]; then
+        TEMP_FILE=$(mktemp)
+        echo "$IMPORT_LINE" > "$TEMP_FILE"
+        echo "" >> "$TEMP_FILE"
+        cat /etc/caddy/Caddyfile >> "$TEMP_FILE"
+        mv "$TEMP_FILE" /etc/caddy/Caddyfile
+    else
+        echo "$IMPORT_LINE" > /etc/caddy/Caddyfile
+    fi
+fi
+
+# 6. Enable and start Caddy
+systemctl enable caddy
+systemctl restart caddy
+"##,
+            install_cmd = install_cmd
+        ))
+    }
+}
+
+// ============================================================================
+// Nginx
+// ============================================================================
+
+pub struct NginxBackend;
+
+impl WebServerBackend for NginxBackend {
+    fn kind(&self) -> WebServerKind {
+        WebServerKind::Nginx
+    }
+
+    fn service(&self) -> &'static str {
+        "nginx"
+    }
+
+    fn site_dirs(&self) -> Vec<&'static str> {
+        vec!["/etc/nginx/sites-available", "/etc/nginx/sites-enabled"]
+    }
+
+    fn site_file_name(&self, site_name: &str) -> String {
+        format!("servermark-{}", site_name)
+    }
+
+    fn site_name_from_file<'a>(&self, file: &'a str) -> Option<&'a str> {
+        file.strip_prefix("servermark-")
+    }
+
+    fn validate_command(&self) -> Option<&'static str> {
+        Some("nginx -t")
+    }
+
+    fn enabled_site_link(&self, site_name: &str) -> Option<(String, String)> {
+        let file = self.site_file_name(site_name);
+        Some((
+            format!("/etc/nginx/sites-available/{}", file),
+            format!("/etc/nginx/sites-enabled/{}", file),
+        ))
+    }
+
+    fn render_site(&self, site: &Site, ports: ListenPorts) -> String {
+        let ssl_config = if site.secured {
+            format!(
+                r#"
+    listen {port} ssl;
+    ssl_certificate /etc/servermark/ssl/{domain}.crt;
+    ssl_certificate_key /etc/servermark/ssl/{domain}.key;
+"#,
+                port = ports.https,
+                domain = site.domain
+            )
+        } else {
+            format!("    listen {};", ports.http)
+        };
+
+        format!(
+            r#"server {{
+    server_name {server_names};
+{ssl_config}
+    root {public_path};
+    index index.php index.html;
+
+    location / {{
+        try_files $uri $uri/ /index.php?$query_string;
+    }}
+
+    location ~ \.php$ {{
+        fastcgi_pass unix:{php_socket};
+        fastcgi_param SCRIPT_FILENAME $realpath_root$fastcgi_script_name;
+        include fastcgi_params;
+    }}
+
+    location ~ /\.(?!well-known).* {{
+        deny all;
+    }}
+}}
+"#,
+            server_names = site_domains(site).join(" "),
+            ssl_config = ssl_config,
+            public_path = site_document_root(site),
+            php_socket = php_socket(site),
+        )
+    }
+
+    fn install_script(&self, package_manager: &str) -> Result<String, String> {
+        let install_cmd = package_install_command(package_manager, "nginx")?;
+
+        Ok(format!(
+            r#"
+set -e
+{install_cmd}
+systemctl enable nginx
+systemctl start nginx
+"#,
+            install_cmd = install_cmd
+        ))
+    }
+}
+
+// ============================================================================
+// Apache httpd (mod_proxy_fcgi)
+// ============================================================================
+
+/// Where a distro keeps Apache virtual hosts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApacheLayout {
+    /// Debian/Ubuntu: sites-available + sites-enabled, unit `apache2`
+    Debian,
+    /// openSUSE: vhosts.d, unit `apache2`
+    Suse,
+    /// Fedora/RHEL/Arch: conf.d, unit `httpd`
+    RedHat,
+}
+
+pub struct ApacheBackend {
+    pub layout: ApacheLayout,
+}
+
+impl ApacheBackend {
+    pub fn detect() -> Self {
+        let layout = if Path::new("/etc/apache2/sites-available").exists() {
+            ApacheLayout::Debian
+        } else if Path::new("/etc/apache2/vhosts.d").exists() {
+            ApacheLayout::Suse
+        } else if Path::new("/etc/httpd").exists() {
+            ApacheLayout::RedHat
+        } else if Path::new("/etc/debian_version").exists() {
+            ApacheLayout::Debian
+        } else {
+            ApacheLayout::RedHat
+        };
+        Self { layout }
+    }
+
+    fn layout_for_package_manager(package_manager: &str) -> ApacheLayout {
+        match package_manager {
+            "apt" => ApacheLayout::Debian,
+            "zypper" => ApacheLayout::Suse,
+            _ => ApacheLayout::RedHat,
+        }
+    }
+}
+
+impl WebServerBackend for ApacheBackend {
+    fn kind(&self) -> WebServerKind {
+        WebServerKind::Apache
+    }
+
+    fn service(&self) -> &'static str {
+        match self.layout {
+            ApacheLayout::Debian | ApacheLayout::Suse => "apache2",
+            ApacheLayout::RedHat => "httpd",
+        }
+    }
+
+    fn site_dirs(&self) -> Vec<&'static str> {
+        match self.layout {
+            ApacheLayout::Debian => {
+                vec!["/etc/apache2/sites-available", "/etc/apache2/sites-enabled"]
+            }
+            ApacheLayout::Suse => vec!["/etc/apache2/vhosts.d"],
+            ApacheLayout::RedHat => vec!["/etc/httpd/conf.d"],
+        }
+    }
+
+    fn site_file_name(&self, site_name: &str) -> String {
+        format!("servermark-{}.conf", site_name)
+    }
+
+    fn site_name_from_file<'a>(&self, file: &'a str) -> Option<&'a str> {
+        file.strip_prefix("servermark-")?.strip_suffix(".conf")
+    }
+
+    fn validate_command(&self) -> Option<&'static str> {
+        Some("apachectl configtest")
+    }
+
+    fn enabled_site_link(&self, site_name: &str) -> Option<(String, String)> {
+        if self.layout != ApacheLayout::Debian {
+            return None;
+        }
+        let file = self.site_file_name(site_name);
+        Some((
+            format!("/etc/apache2/sites-available/{}", file),
+            format!("/etc/apache2/sites-enabled/{}", file),
+        ))
+    }
+
+    fn render_site(&self, site: &Site, ports: ListenPorts) -> String {
+        let public_path = site_document_root(site);
+        let domains = site_domains(site);
+
+        let aliases = if domains.len() > 1 {
+            format!("    ServerAlias {}\n", domains[1..].join(" "))
+        } else {
+            String::new()
+        };
+
+        let (port, ssl_config) = if site.secured {
+            (
+                ports.https,
+                format!(
+                    r#"    SSLEngine on
+    SSLCertificateFile /etc/servermark/ssl/{domain}.crt
+    SSLCertificateKeyFile /etc/servermark/ssl/{domain}.key
+"#,
+                    domain = site.domain
+                ),
+            )
+        } else {
+            (ports.http, String::new())
+        };
+
+        format!(
+            r#"<VirtualHost *:{port}>
+    ServerName {domain}
+{aliases}{ssl_config}    DocumentRoot "{public_path}"
+
+    <Directory "{public_path}">
+        AllowOverride All
+        Require all granted
+        DirectoryIndex index.php index.html
+    </Directory>
+
+    <FilesMatch \.php$>
+        SetHandler "proxy:unix:{php_socket}|fcgi://localhost"
+    </FilesMatch>
+</VirtualHost>
+"#,
+            port = port,
+            domain = site.domain,
+            aliases = aliases,
+            ssl_config = ssl_config,
+            public_path = public_path,
+            php_socket = php_socket(site),
+        )
+    }
+
+    /// Rewrite the distro's `Listen` directives (also restores 80/443)
+    fn ports_script(&self, ports: ListenPorts) -> String {
+        match self.layout {
+            ApacheLayout::Debian => format!(
+                r#"cat > /etc/apache2/ports.conf << 'SITEEOF'
+Listen {http}
+<IfModule ssl_module>
+    Listen {https}
+</IfModule>
+<IfModule mod_gnutls.c>
+    Listen {https}
+</IfModule>
+SITEEOF
+"#,
+                http = ports.http,
+                https = ports.https
+            ),
+            ApacheLayout::Suse => format!(
+                "sed -i -E 's/^Listen [0-9]+$/Listen {}/; s/^([[:space:]]+)Listen [0-9]+$/\\1Listen {}/' /etc/apache2/listen.conf\n",
+                ports.http, ports.https
+            ),
+            ApacheLayout::RedHat => format!(
+                "sed -i -E 's/^Listen [0-9]+$/Listen {}/' /etc/httpd/conf/httpd.conf\nsed -i -E 's/^Listen [0-9]+ https$/Listen {} https/' /etc/httpd/conf.d/ssl.conf 2>/dev/null || true\n",
+                ports.http, ports.https
+            ),
+        }
+    }
+
+    /// `Listen` lives in the distro's own config files
+    fn ports_ops(&self, _ports: ListenPorts) -> Option<Vec<HelperOp>> {
+        None
+    }
+
+    fn install_script(&self, package_manager: &str) -> Result<String, String> {
+        let layout = Self::layout_for_package_manager(package_manager);
+        let (packages, modules) = match layout {
+            ApacheLayout::Debian => (
+                "apache2",
+                "a2enmod proxy proxy_fcgi setenvif rewrite ssl headers\na2dissite 000-default 2>/dev/null || true\n",
+            ),
+            ApacheLayout::Suse => (
+                "apache2",
+                "a2enmod proxy\na2enmod proxy_fcgi\na2enmod rewrite\na2enmod ssl\n",
+            ),
+            ApacheLayout::RedHat if package_manager == "pacman" => (
+                "apache",
+                r#"mkdir -p /etc/httpd/conf.d
+sed -i -E 's/^#(LoadModule (proxy|proxy_fcgi|rewrite|ssl|socache_shmcb)_module)/\1/' /etc/httpd/conf/httpd.conf
+grep -q '^IncludeOptional conf.d/\*.conf' /etc/httpd/conf/httpd.conf || echo 'IncludeOptional conf.d/*.conf' >> /etc/httpd/conf/httpd.conf
+"#,
+            ),
+            ApacheLayout::RedHat => ("httpd mod_ssl", ""),
+        };
+        let install_cmd = package_install_command(package_manager, packages)?;
+        let service = Self { layout }.service();
+
+        Ok(format!(
+            r#"
+set -e
+{install_cmd}
+
+# Enable PHP-FPM proxying, rewrites (.htaccess) and TLS
+{modules}
+# Let Apache reach the PHP-FPM sockets
+usermod -aG www-data {user} 2>/dev/null || true
+
+systemctl enable {service}
+systemctl restart {service}
+"#,
+            install_cmd = install_cmd,
+            modules = modules,
+            user = match layout {
+                ApacheLayout::Debian => "www-data",
+                ApacheLayout::Suse => "wwwrun",
+                ApacheLayout::RedHat if package_manager == "pacman" => "http",
+                ApacheLayout::RedHat => "apache",
+            },
+            service = service,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::helper::validate_managed_path;
+    use crate::commands::share::{LanShare, LanShareMode};
+    use crate::commands::sites::SiteType;
+
+    fn site(secured: bool) -> Site {
+        Site {
+            id: "site-1".to_string(),
+            name: "legacy".to_string(),
+            path: "/home/dev/Code/legacy".to_string(),
+            domain: "legacy.test".to_string(),
+            php_version: "7.4".to_string(),
+            secured,
+            site_type: SiteType::Static,
+            proxy_target: None,
+            laravel: None,
+            document_root: None,
+            aliases: vec!["www.legacy.test".to_string()],
+            manifest: None,
+            lan_share: None,
+        }
+    }
+
+    #[test]
+    fn test_kind_round_trip() {
+        for kind in WebServerKind::ALL {
+            assert_eq!(WebServerKind::parse(kind.as_str()).unwrap(), kind);
+        }
+        assert_eq!(
+            serde_json::from_str::<WebServerKind>("\"nginx\"").unwrap(),
+            WebServerKind::Nginx
+        );
+        assert!(WebServerKind::parse("lighttpd").is_err());
+    }
+
+    #[test]
+    fn test_site_file_names_round_trip() {
+        for kind in WebServerKind::ALL {
+            let backend = backend(kind);
+            let file = backend.site_file_name("shop");
+            assert_eq!(backend.site_name_from_file(&file), Some("shop"));
+        }
+        assert_eq!(CaddyBackend.site_name_from_file("servermark.conf"), None);
+    }
+
+    #[test]
+    fn test_write_site_ops_stay_within_helper_allow_list() {
+        let ops = NginxBackend.write_site_ops(&site(true), ListenPorts::default());
+        assert_eq!(ops.len(), 3);
+        assert!(matches!(ops[1], HelperOp::LinkManagedFile { .. }));
+        assert!(matches!(ops[2], HelperOp::IssueCertificate { .. }));
+
+        for op in ops {
+            match op {
+                HelperOp::WriteSiteConfig { server, site } => {
+                    assert_eq!(server, WebServerKind::Nginx);
+                    assert_eq!(site.domains, vec!["legacy.test", "www.legacy.test"]);
+                }
+                HelperOp::LinkManagedFile { target, link } => {
+                    assert!(validate_managed_path(&target).is_ok());
+                    assert!(validate_managed_path(&link).is_ok());
+                }
+                _ => {}
+            }
+        }
+
+        let caddy = CaddyBackend.write_site_ops(&site(true), ListenPorts::default());
+        assert_eq!(caddy.len(), 1);
+    }
+
+    #[test]
+    fn test_shared_site_gets_lan_server_block_and_certificate() {
+        let mut shared = site(true);
+        shared.lan_share = Some(LanShare {
+            mode: LanShareMode::NipIo,
+            hostname: "legacy.192-168-1-20.nip.io".to_string(),
+            address: "192.168.1.20".parse().unwrap(),
+        });
+
+        let config = NginxBackend.render_site_config(&shared, ListenPorts::default());
+        assert_eq!(config.matches("server {").count(), 2);
+        assert!(config.contains("server_name legacy.192-168-1-20.nip.io;"));
+        assert!(config.contains("/etc/servermark/ssl/legacy.192-168-1-20.nip.io.crt"));
+
+        let ops = NginxBackend.write_site_ops(&shared, ListenPorts::default());
+        assert!(matches!(
+            &ops[3],
+            HelperOp::IssueCertificate { domain } if domain == "legacy.192-168-1-20.nip.io"
+        ));
+    }
+
+    #[test]
+    fn test_apache_render() {
+        let apache = ApacheBackend {
+            layout: ApacheLayout::Debian,
+        };
+        let config = apache.render_site(&site(false), ListenPorts::default());
+        assert!(config.starts_with("<VirtualHost *:80>"));
+        assert!(config.contains("ServerAlias www.legacy.test"));
+        assert!(config.contains("AllowOverride All"));
+        assert!(config.contains("proxy:unix:/var/run/php/php7.4-fpm.sock|fcgi://localhost"));
+
+        let secured = apache.render_site(&site(true), ListenPorts::default());
+        assert!(secured.starts_with("<VirtualHost *:443>"));
+        assert!(secured.contains("SSLCertificateFile /etc/servermark/ssl/legacy.test.crt"));
+    }
+}
@@ -0,0 +1,609 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+
+use super::docker::{
+    create_container_with, CreateContainerParams, MountKind, PortMapping, VolumeMapping,
+};
+use super::engine::EngineClient;
+use super::ownership::ownership_labels;
+
+// Backend catalog of the services ServerMark runs in containers. Each preset
+// carries everything needed to create a working container: image and tags,
+// ports, environment with the default credentials, a health probe and where
+// the data lives. Containers are named `servermark-<preset>` and keep their
+// data in the `servermark-<preset>-<volume>` named volume.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PresetVolume {
+    pub name: &'static str,
+    pub container: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServicePreset {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub category: &'static str, // "database" | "cache" | "search" | "mail" | "storage" | "queue" | "websocket" | "tools"
+    pub image: &'static str,
+    pub default_version: &'static str,
+    pub versions: &'static [&'static str],
+    pub ports: &'static [u16],
+    #[serde(serialize_with = "as_map")]
+    pub environment: &'static [(&'static str, &'static str)],
+    /// Connection credentials the environment sets up, for display
+    #[serde(serialize_with = "as_map")]
+    pub credentials: &'static [(&'static str, &'static str)],
+    /// Shell command run inside the container, with the container's
+    /// environment; images with their own HEALTHCHECK leave it unset
+    pub health_check: Option<&'static str>,
+    pub volume: Option<PresetVolume>,
+    /// Arguments passed after the image
+    pub command: &'static [&'static str],
+}
+
+fn as_map<S: Serializer>(
+    pairs: &&'static [(&'static str, &'static str)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(pairs.iter().copied())
+}
+
+pub const SERVICE_PRESETS: &[ServicePreset] = &[
+    ServicePreset {
+        id: "mysql",
+        name: "MySQL",
+        description: "Popular open-source relational database",
+        category: "database",
+        image: "mysql",
+        default_version: "8.0",
+        versions: &["9.1", "8.4", "8.0", "5.7"],
+        ports: &[3306],
+        environment: &[
+            ("MYSQL_ROOT_PASSWORD", "secret"),
+            ("MYSQL_DATABASE", "servermark"),
+        ],
+        credentials: &[("username", "root"), ("password", "secret")],
+        // Reads the password from the environment, so overriding it keeps the check working
+        health_check: Some(
+            "MYSQL_PWD=\"$MYSQL_ROOT_PASSWORD\" mysqladmin ping -h 127.0.0.1 -uroot --silent",
+        ),
+        volume: Some(PresetVolume {
+            name: "mysql_data",
+            container: "/var/lib/mysql",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "mariadb",
+        name: "MariaDB",
+        description: "Community-developed fork of MySQL",
+        category: "database",
+        image: "mariadb",
+        default_version: "11.4",
+        versions: &["11.4", "10.11", "10.6"],
+        ports: &[3306],
+        environment: &[
+            ("MARIADB_ROOT_PASSWORD", "secret"),
+            ("MARIADB_DATABASE", "servermark"),
+        ],
+        credentials: &[("username", "root"), ("password", "secret")],
+        health_check: Some("healthcheck.sh --connect --innodb_initialized"),
+        volume: Some(PresetVolume {
+            name: "mariadb_data",
+            container: "/var/lib/mysql",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "postgresql",
+        name: "PostgreSQL",
+        description: "Advanced open-source relational database",
+        category: "database",
+        image: "postgres",
+        default_version: "16",
+        versions: &["17", "16", "15", "14", "13"],
+        ports: &[5432],
+        environment: &[
+            ("POSTGRES_PASSWORD", "secret"),
+            ("POSTGRES_DB", "servermark"),
+        ],
+        credentials: &[("username", "postgres"), ("password", "secret")],
+        health_check: Some("pg_isready -U postgres"),
+        volume: Some(PresetVolume {
+            name: "postgres_data",
+            container: "/var/lib/postgresql/data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "mongodb",
+        name: "MongoDB",
+        description: "NoSQL document database",
+        category: "database",
+        image: "mongo",
+        default_version: "7",
+        versions: &["7", "6", "5"],
+        ports: &[27017],
+        environment: &[
+            ("MONGO_INITDB_ROOT_USERNAME", "root"),
+            ("MONGO_INITDB_ROOT_PASSWORD", "secret"),
+        ],
+        credentials: &[("username", "root"), ("password", "secret")],
+        health_check: Some("mongosh --quiet --eval 'db.runCommand({ ping: 1 })'"),
+        volume: Some(PresetVolume {
+            name: "mongo_data",
+            container: "/data/db",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "redis",
+        name: "Redis",
+        description: "In-memory data structure store",
+        category: "cache",
+        image: "redis",
+        default_version: "7-alpine",
+        versions: &["7-alpine", "7", "6-alpine", "6"],
+        ports: &[6379],
+        environment: &[],
+        credentials: &[],
+        health_check: Some("redis-cli ping"),
+        volume: Some(PresetVolume {
+            name: "redis_data",
+            container: "/data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "valkey",
+        name: "Valkey",
+        description: "Open-source Redis fork, wire compatible",
+        category: "cache",
+        image: "valkey/valkey",
+        default_version: "8-alpine",
+        versions: &["8-alpine", "8", "7.2-alpine"],
+        ports: &[6379],
+        environment: &[],
+        credentials: &[],
+        health_check: Some("valkey-cli ping"),
+        volume: Some(PresetVolume {
+            name: "valkey_data",
+            container: "/data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "memcached",
+        name: "Memcached",
+        description: "Distributed memory caching system",
+        category: "cache",
+        image: "memcached",
+        default_version: "alpine",
+        versions: &["alpine", "latest"],
+        ports: &[11211],
+        environment: &[],
+        credentials: &[],
+        health_check: None,
+        volume: None,
+        command: &[],
+    },
+    ServicePreset {
+        id: "mailpit",
+        name: "Mailpit",
+        description: "Email testing tool with web UI",
+        category: "mail",
+        image: "axllent/mailpit",
+        default_version: "latest",
+        versions: &["latest"],
+        ports: &[1025, 8025],
+        environment: &[],
+        credentials: &[],
+        health_check: None, // The image ships its own
+        volume: None,
+        command: &[],
+    },
+    ServicePreset {
+        id: "meilisearch",
+        name: "Meilisearch",
+        description: "Fast typo-tolerant search engine",
+        category: "search",
+        image: "getmeili/meilisearch",
+        default_version: "v1.11",
+        versions: &["v1.11", "v1.10", "v1.9"],
+        ports: &[7700],
+        environment: &[
+            ("MEILI_MASTER_KEY", "masterKey"),
+            ("MEILI_NO_ANALYTICS", "true"),
+        ],
+        credentials: &[("master_key", "masterKey")],
+        health_check: Some("wget --no-verbose --spider http://127.0.0.1:7700/health"),
+        volume: Some(PresetVolume {
+            name: "meilisearch_data",
+            container: "/meili_data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "typesense",
+        name: "Typesense",
+        description: "Open-source search engine for instant search",
+        category: "search",
+        image: "typesense/typesense",
+        default_version: "27.1",
+        versions: &["27.1", "26.0"],
+        ports: &[8108],
+        environment: &[
+            ("TYPESENSE_API_KEY", "xyz"),
+            ("TYPESENSE_DATA_DIR", "/typesense-data"),
+            ("TYPESENSE_ENABLE_CORS", "true"),
+        ],
+        credentials: &[("api_key", "xyz")],
+        health_check: Some("bash -c 'exec 3<>/dev/tcp/127.0.0.1/8108'"),
+        volume: Some(PresetVolume {
+            name: "typesense_data",
+            container: "/typesense-data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "elasticsearch",
+        name: "Elasticsearch",
+        description: "Distributed search and analytics engine",
+        category: "search",
+        image: "docker.elastic.co/elasticsearch/elasticsearch",
+        default_version: "8.15.3",
+        versions: &["8.15.3", "7.17.24"],
+        ports: &[9200],
+        environment: &[
+            ("discovery.type", "single-node"),
+            ("xpack.security.enabled", "false"),
+            ("ES_JAVA_OPTS", "-Xms512m -Xmx512m"),
+        ],
+        credentials: &[],
+        health_check: Some("curl -fs http://127.0.0.1:9200/_cluster/health"),
+        volume: Some(PresetVolume {
+            name: "elasticsearch_data",
+            container: "/usr/share/elasticsearch/data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "opensearch",
+        name: "OpenSearch",
+        description: "Open-source fork of Elasticsearch",
+        category: "search",
+        image: "opensearchproject/opensearch",
+        default_version: "2.17.1",
+        versions: &["2.17.1", "1.3.19"],
+        ports: &[9200],
+        environment: &[
+            ("discovery.type", "single-node"),
+            ("DISABLE_SECURITY_PLUGIN", "true"),
+            ("DISABLE_INSTALL_DEMO_CONFIG", "true"),
+            ("OPENSEARCH_JAVA_OPTS", "-Xms512m -Xmx512m"),
+        ],
+        credentials: &[],
+        health_check: Some("curl -fs http://127.0.0.1:9200/_cluster/health"),
+        volume: Some(PresetVolume {
+            name: "opensearch_data",
+            container: "/usr/share/opensearch/data",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "minio",
+        name: "MinIO",
+        description: "S3-compatible object storage",
+        category: "storage",
+        image: "minio/minio",
+        default_version: "latest",
+        versions: &["latest"],
+        ports: &[9000, 9001],
+        environment: &[
+            ("MINIO_ROOT_USER", "minioadmin"),
+            ("MINIO_ROOT_PASSWORD", "minioadmin"),
+        ],
+        credentials: &[("username", "minioadmin"), ("password", "minioadmin")],
+        health_check: Some("mc ready local"),
+        volume: Some(PresetVolume {
+            name: "minio_data",
+            container: "/data",
+        }),
+        command: &["server", "/data", "--console-address", ":9001"],
+    },
+    ServicePreset {
+        id: "rabbitmq",
+        name: "RabbitMQ",
+        description: "Message broker with management UI",
+        category: "queue",
+        image: "rabbitmq",
+        default_version: "4-management",
+        versions: &["4-management", "3-management"],
+        ports: &[5672, 15672],
+        environment: &[
+            ("RABBITMQ_DEFAULT_USER", "servermark"),
+            ("RABBITMQ_DEFAULT_PASS", "secret"),
+        ],
+        credentials: &[("username", "servermark"), ("password", "secret")],
+        health_check: Some("rabbitmq-diagnostics -q ping"),
+        volume: Some(PresetVolume {
+            name: "rabbitmq_data",
+            container: "/var/lib/rabbitmq",
+        }),
+        command: &[],
+    },
+    ServicePreset {
+        id: "soketi",
+        name: "Soketi",
+        description: "Pusher-compatible WebSocket server for Laravel Echo (Reverb protocol)",
+        category: "websocket",
+        image: "quay.io/soketi/soketi",
+        default_version: "latest-16-alpine",
+        versions: &["latest-16-alpine", "1.6-16-alpine"],
+        ports: &[6001, 9601],
+        environment: &[
+            ("SOKETI_DEFAULT_APP_ID", "app-id"),
+            ("SOKETI_DEFAULT_APP_KEY", "app-key"),
+            ("SOKETI_DEFAULT_APP_SECRET", "app-secret"),
+            ("SOKETI_METRICS_SERVER_PORT", "9601"),
+        ],
+        credentials: &[
+            ("app_id", "app-id"),
+            ("app_key", "app-key"),
+            ("app_secret", "app-secret"),
+        ],
+        health_check: Some("wget -q --spider http://127.0.0.1:6001/ready"),
+        volume: None,
+        command: &[],
+    },
+    ServicePreset {
+        id: "adminer",
+        name: "Adminer",
+        description: "Database management in single PHP file",
+        category: "tools",
+        image: "adminer",
+        default_version: "latest",
+        versions: &["latest"],
+        ports: &[8080],
+        environment: &[],
+        credentials: &[],
+        health_check: None,
+        volume: None,
+        command: &[],
+    },
+];
+
+/// Normalize service aliases (`postgres`, `pgsql`, `reverb`) to preset ids
+pub fn preset_id(name: &str) -> &str {
+    match name {
+        "postgres" | "pgsql" => "postgresql",
+        "mongo" => "mongodb",
+        "pusher" | "reverb" => "soketi",
+        other => other,
+    }
+}
+
+pub fn find_preset(name: &str) -> Option<&'static ServicePreset> {
+    let id = preset_id(name);
+    SERVICE_PRESETS.iter().find(|p| p.id == id)
+}
+
+/// The preset an image reference belongs to (`mysql:8.0`,
+/// `docker.io/library/postgres:16`, `quay.io/soketi/soketi:latest-16-alpine`)
+pub fn preset_for_image(image: &str) -> Option<&'static ServicePreset> {
+    let name_end = image.rfind('/').map_or(0, |slash| slash + 1);
+    let repository = match image[name_end..].find([':', '@']) {
+        Some(tag) => &image[..name_end + tag],
+        None => image,
+    };
+    let repository = repository
+        .strip_prefix("docker.io/library/")
+        .or_else(|| repository.strip_prefix("docker.io/"))
+        .unwrap_or(repository);
+    SERVICE_PRESETS.iter().find(|p| p.image == repository)
+}
+
+/// Overrides for a preset container; everything is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresetOptions {
+    /// Image tag, the preset's default version when unset
+    pub version: Option<String>,
+    /// Suffix replacing the preset id in the container and volume names, to
+    /// run two versions side by side (`mysql57` gives `servermark-mysql57`)
+    pub name: Option<String>,
+    /// Host port per container port
+    pub ports: HashMap<u16, u16>,
+    pub environment: HashMap<String, String>,
+    /// Site the container is for, recorded in its labels
+    pub site: Option<String>,
+    pub memory_limit: Option<u64>, // Bytes
+    pub cpu_limit: Option<f64>,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Container parameters for a preset with the given overrides applied
+pub fn preset_container_params(
+    preset: &ServicePreset,
+    options: &PresetOptions,
+) -> Result<CreateContainerParams, String> {
+    let version = options
+        .version
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .unwrap_or(preset.default_version);
+    if !valid_name(version) {
+        return Err(format!("Invalid version: {}", version));
+    }
+    let suffix = options.name.as_deref().unwrap_or(preset.id);
+    if !valid_name(suffix) {
+        return Err(format!("Invalid container name: {}", suffix));
+    }
+    let name = format!("servermark-{}", suffix);
+
+    let mut environment: HashMap<String, String> = preset
+        .environment
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    environment.extend(options.environment.clone());
+
+    Ok(CreateContainerParams {
+        image: format!("{}:{}", preset.image, version),
+        ports: preset
+            .ports
+            .iter()
+            .map(|&p| PortMapping {
+                host: options.ports.get(&p).copied().unwrap_or(p),
+                container: p,
+                protocol: "tcp".to_string(),
+            })
+            .collect(),
+        environment,
+        volumes: preset.volume.as_ref().map(|volume| {
+            vec![VolumeMapping {
+                name: format!("{}-{}", name, volume.name),
+                container: volume.container.to_string(),
+                kind: MountKind::Volume,
+                read_only: false,
+            }]
+        }),
+        health_cmd: preset.health_check.map(str::to_string),
+        command: preset.command.iter().map(|s| s.to_string()).collect(),
+        labels: ownership_labels(Some(preset.id), options.site.as_deref()),
+        memory_limit: options.memory_limit,
+        cpu_limit: options.cpu_limit,
+        name,
+    })
+}
+
+/// List the services that can be created in one click
+#[tauri::command]
+pub fn list_service_presets() -> Vec<ServicePreset> {
+    SERVICE_PRESETS.to_vec()
+}
+
+/// Create and start a container from a catalog preset
+#[tauri::command]
+pub async fn create_service_from_preset(
+    preset_id: String,
+    options: Option<PresetOptions>,
+) -> Result<String, String> {
+    let preset =
+        find_preset(&preset_id).ok_or_else(|| format!("Unknown service: {}", preset_id))?;
+    let params = preset_container_params(preset, &options.unwrap_or_default())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        create_container_with(&EngineClient::connect()?, &params)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ownership::{LABEL_PRESET, LABEL_SITE};
+    use super::*;
+
+    #[test]
+    fn test_presets_are_consistent() {
+        for preset in SERVICE_PRESETS {
+            assert!(
+                preset.versions.contains(&preset.default_version),
+                "{} default version is not listed",
+                preset.id
+            );
+            assert!(!preset.ports.is_empty(), "{} exposes no port", preset.id);
+            assert_eq!(
+                SERVICE_PRESETS.iter().filter(|p| p.id == preset.id).count(),
+                1
+            );
+        }
+        assert_eq!(find_preset("pgsql").unwrap().image, "postgres");
+        assert_eq!(find_preset("reverb").unwrap().id, "soketi");
+        assert!(find_preset("oracle").is_none());
+    }
+
+    #[test]
+    fn test_preset_for_image() {
+        assert_eq!(preset_for_image("mysql:8.0").unwrap().id, "mysql");
+        assert_eq!(
+            preset_for_image("docker.io/library/postgres:16")
+                .unwrap()
+                .id,
+            "postgresql"
+        );
+        assert_eq!(
+            preset_for_image("quay.io/soketi/soketi:latest-16-alpine")
+                .unwrap()
+                .id,
+            "soketi"
+        );
+        assert_eq!(preset_for_image("valkey/valkey").unwrap().id, "valkey");
+        assert!(preset_for_image("localhost:5000/mysql:8").is_none());
+        assert!(preset_for_image("mysql-router:8").is_none());
+    }
+
+    #[test]
+    fn test_preset_container_params() {
+        let mysql = find_preset("mysql").unwrap();
+        let params = preset_container_params(mysql, &PresetOptions::default()).unwrap();
+        assert_eq!(params.image, "mysql:8.0");
+        assert_eq!(params.name, "servermark-mysql");
+        assert_eq!(params.ports[0].host, 3306);
+        assert_eq!(params.environment["MYSQL_ROOT_PASSWORD"], "secret");
+        let volumes = params.volumes.unwrap();
+        assert_eq!(volumes[0].name, "servermark-mysql-mysql_data");
+        assert_eq!(volumes[0].container, "/var/lib/mysql");
+        assert!(params
+            .health_cmd
+            .unwrap()
+            .contains("$MYSQL_ROOT_PASSWORD\" mysqladmin ping"));
+
+        let options = PresetOptions {
+            version: Some("5.7".to_string()),
+            name: Some("mysql57".to_string()),
+            ports: HashMap::from([(3306, 3307)]),
+            environment: HashMap::from([("MYSQL_DATABASE".to_string(), "shop".to_string())]),
+            site: Some("site-1".to_string()),
+            ..Default::default()
+        };
+        let params = preset_container_params(mysql, &options).unwrap();
+        assert_eq!(params.image, "mysql:5.7");
+        assert_eq!(params.name, "servermark-mysql57");
+        assert_eq!(params.ports[0].host, 3307);
+        assert_eq!(params.ports[0].container, 3306);
+        assert_eq!(params.environment["MYSQL_DATABASE"], "shop");
+        assert_eq!(params.labels[LABEL_PRESET], "mysql");
+        assert_eq!(params.labels[LABEL_SITE], "site-1");
+        assert_eq!(
+            params.volumes.unwrap()[0].name,
+            "servermark-mysql57-mysql_data"
+        );
+
+        let minio =
+            preset_container_params(find_preset("minio").unwrap(), &Default::default()).unwrap();
+        assert_eq!(minio.command[0], "server");
+
+        let bad = PresetOptions {
+            name: Some("a b".to_string()),
+            ..Default::default()
+        };
+        assert!(preset_container_params(mysql, &bad).is_err());
+    }
+
+    #[test]
+    fn test_presets_serialize_maps() {
+        let json = serde_json::to_value(find_preset("postgresql").unwrap()).unwrap();
+        assert_eq!(json["environment"]["POSTGRES_PASSWORD"], "secret");
+        assert_eq!(json["credentials"]["username"], "postgres");
+        assert_eq!(json["volume"]["container"], "/var/lib/postgresql/data");
+    }
+}
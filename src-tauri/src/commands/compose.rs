@@ -0,0 +1,681 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serde_yaml::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::catalog::{find_preset, preset_for_image};
+use super::docker::{query_containers, Container, PortMapping};
+use super::dotenv::DotEnv;
+use super::engine::EngineClient;
+use super::sites::{load_sites_config, Site};
+
+// Docker Compose projects shipped in a site's repository (Laravel Sail
+// included). The compose file is parsed here, with `.env` interpolation, to
+// list services without a compose CLI; up/down go through whichever CLI is
+// installed, under the project name `servermark-<site>`.
+
+/// Compose file names, in the order `docker compose` looks them up
+pub const COMPOSE_FILES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+pub const COMPOSE_PROJECT_PREFIX: &str = "servermark-";
+pub const LABEL_COMPOSE_PROJECT: &str = "com.docker.compose.project";
+pub const LABEL_COMPOSE_SERVICE: &str = "com.docker.compose.service";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComposePort {
+    pub host: Option<u16>, // None when the engine picks a port
+    pub container: u16,
+    pub protocol: String,
+    /// `.env` variable the host port is read from (`FORWARD_DB_PORT`)
+    pub variable: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposeService {
+    pub name: String,
+    pub image: Option<String>,
+    pub build: bool,
+    pub ports: Vec<ComposePort>,
+    pub environment: HashMap<String, String>,
+    pub preset: Option<String>,
+    /// The service's container, once the project is up
+    pub container: Option<Container>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposeProject {
+    pub site_id: String,
+    pub project: String,
+    pub file: String,
+    pub services: Vec<ComposeService>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ComposeUpReport {
+    pub containers: Vec<Container>,
+    pub updated_keys: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+pub fn find_compose_file(dir: &Path) -> Option<PathBuf> {
+    COMPOSE_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Compose project name of a site (`My Shop` becomes `servermark-my-shop`)
+pub fn compose_project_name(site_name: &str) -> String {
+    let slug: String = site_name
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}{}", COMPOSE_PROJECT_PREFIX, slug.trim_matches('-'))
+}
+
+/// Site id of a ServerMark compose project
+pub fn site_for_project(project: &str) -> Option<String> {
+    load_sites_config()
+        .sites
+        .into_iter()
+        .find(|s| compose_project_name(&s.name) == project)
+        .map(|s| s.id)
+}
+
+/// Expand `$VAR`, `${VAR}`, `${VAR:-default}`, `${VAR-default}` and `$$`
+pub fn interpolate(value: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                out.push('$');
+                continue;
+            };
+            let expr = &braced[..end];
+            rest = &braced[end + 1..];
+            let (name, default, empty_is_unset) = if let Some((n, d)) = expr.split_once(":-") {
+                (n, Some(d), true)
+            } else if let Some((n, d)) = expr.split_once('-') {
+                (n, Some(d), false)
+            } else {
+                (expr, None, false)
+            };
+            match lookup(name) {
+                Some(v) if !(empty_is_unset && v.is_empty()) => out.push_str(&v),
+                _ => out.push_str(default.unwrap_or_default()),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            out.push_str(&lookup(&rest[..end]).unwrap_or_default());
+            rest = &rest[end..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The variable a raw value is read from, when it is only `${VAR...}` or `$VAR`
+fn variable_name(raw: &str) -> Option<String> {
+    let name = match raw.strip_prefix("${") {
+        Some(braced) => braced
+            .strip_suffix('}')?
+            .split([':', '-'])
+            .next()
+            .unwrap_or_default(),
+        None => raw.strip_prefix('$')?,
+    };
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| name.to_string())
+}
+
+/// Split on `:` outside of `${...}`
+fn split_port_spec(spec: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in spec.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => {
+                parts.push(&spec[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&spec[start..]);
+    parts
+}
+
+/// A port in short (`[ip:]host:container[/protocol]`) or long syntax.
+/// Port ranges are skipped.
+fn parse_port(entry: &Value, lookup: &dyn Fn(&str) -> Option<String>) -> Option<ComposePort> {
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+
+    if let Value::Mapping(_) = entry {
+        let published = entry.get("published").and_then(scalar);
+        return Some(ComposePort {
+            host: published
+                .as_deref()
+                .and_then(|p| interpolate(p, lookup).parse().ok()),
+            container: interpolate(&scalar(entry.get("target")?)?, lookup)
+                .parse()
+                .ok()?,
+            protocol: entry
+                .get("protocol")
+                .and_then(Value::as_str)
+                .unwrap_or("tcp")
+                .to_string(),
+            variable: published.as_deref().and_then(variable_name),
+        });
+    }
+
+    let spec = scalar(entry)?;
+    let (spec, protocol) = spec.split_once('/').unwrap_or((&spec, "tcp"));
+    let parts = split_port_spec(spec);
+    let container = interpolate(parts.last()?, lookup).parse().ok()?;
+    let host_raw = (parts.len() >= 2).then(|| parts[parts.len() - 2]);
+    let host = match host_raw.map(|raw| interpolate(raw, lookup)) {
+        Some(host) if !host.is_empty() => Some(host.parse().ok()?),
+        _ => None,
+    };
+    Some(ComposePort {
+        host,
+        container,
+        protocol: protocol.to_string(),
+        variable: host_raw.and_then(variable_name),
+    })
+}
+
+/// `environment` as a mapping or a `KEY=value` list; bare keys are read with `lookup`
+fn parse_environment(
+    spec: Option<&Value>,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> HashMap<String, String> {
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(interpolate(s, lookup)),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    };
+    match spec {
+        Some(Value::Mapping(map)) => map
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.as_str()?;
+                Some((key.to_string(), scalar(value).or_else(|| lookup(key))?))
+            })
+            .collect(),
+        Some(Value::Sequence(list)) => list
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.as_str()?;
+                match entry.split_once('=') {
+                    Some((key, value)) => Some((key.to_string(), interpolate(value, lookup))),
+                    None => Some((entry.to_string(), lookup(entry)?)),
+                }
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Services of a compose file, variables resolved with `lookup`
+pub fn parse_compose(
+    content: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<ComposeService>, String> {
+    let doc: Value =
+        serde_yaml::from_str(content).map_err(|e| format!("Invalid compose file: {}", e))?;
+    let Some(services) = doc.get("services").and_then(Value::as_mapping) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(services
+        .iter()
+        .filter_map(|(name, spec)| {
+            let name = name.as_str()?.to_string();
+            let image = spec
+                .get("image")
+                .and_then(Value::as_str)
+                .map(|image| interpolate(image, lookup));
+            let ports = spec
+                .get("ports")
+                .and_then(Value::as_sequence)
+                .map(|ports| ports.iter().filter_map(|p| parse_port(p, lookup)).collect())
+                .unwrap_or_default();
+            // Sail's images aren't the official ones, so fall back to the service name
+            let preset = image
+                .as_deref()
+                .and_then(preset_for_image)
+                .or_else(|| find_preset(&name))
+                .map(|p| p.id.to_string());
+            Some(ComposeService {
+                build: spec.get("build").is_some(),
+                name,
+                image,
+                ports,
+                environment: parse_environment(spec.get("environment"), lookup),
+                preset,
+                container: None,
+            })
+        })
+        .collect())
+}
+
+/// `.env` keys pointing the site at a service: (preset, host key, port key).
+/// Without a port key the host key gets a URL.
+const ENV_PORTS: &[(&str, &str, Option<&str>)] = &[
+    ("mysql", "DB_HOST", Some("DB_PORT")),
+    ("mariadb", "DB_HOST", Some("DB_PORT")),
+    ("postgresql", "DB_HOST", Some("DB_PORT")),
+    ("redis", "REDIS_HOST", Some("REDIS_PORT")),
+    ("valkey", "REDIS_HOST", Some("REDIS_PORT")),
+    ("memcached", "MEMCACHED_HOST", Some("MEMCACHED_PORT")),
+    ("mailpit", "MAIL_HOST", Some("MAIL_PORT")),
+    ("typesense", "TYPESENSE_HOST", Some("TYPESENSE_PORT")),
+    ("meilisearch", "MEILISEARCH_HOST", None),
+];
+
+/// Point `.env` at the ports the services are published on. The site runs on
+/// the host, so service names (`DB_HOST=mysql`) become `127.0.0.1`.
+/// Credentials are left alone: compose files read them from `.env`.
+pub fn apply_compose_ports(
+    env: &mut DotEnv,
+    services: &[(String, Vec<PortMapping>)],
+) -> Vec<String> {
+    let database = match env.get("DB_CONNECTION") {
+        Some("pgsql") => Some(&["postgresql"][..]),
+        Some("mysql") | Some("mariadb") => Some(&["mysql", "mariadb"][..]),
+        _ => None,
+    };
+    let mut written = HashSet::new();
+    let mut updated = Vec::new();
+
+    for (preset, ports) in services {
+        let Some(&(_, host_key, port_key)) = ENV_PORTS.iter().find(|(p, _, _)| p == preset) else {
+            continue;
+        };
+        if host_key == "DB_HOST" && database.is_some_and(|kinds| !kinds.contains(&preset.as_str()))
+        {
+            continue;
+        }
+        let Some(port) = find_preset(preset)
+            .and_then(|p| p.ports.first())
+            .and_then(|target| ports.iter().find(|m| m.container == *target))
+            .map(|m| m.host)
+        else {
+            continue;
+        };
+        if !written.insert(host_key) {
+            continue; // The first matching service wins
+        }
+
+        let values = match port_key {
+            Some(port_key) => vec![
+                (host_key, "127.0.0.1".to_string()),
+                (port_key, port.to_string()),
+            ],
+            None => vec![(host_key, format!("http://127.0.0.1:{}", port))],
+        };
+        for (key, value) in values {
+            if env.set(key, &value) {
+                updated.push(key.to_string());
+            }
+        }
+    }
+    updated
+}
+
+/// The compose CLI: `docker compose`, `podman compose`, or the standalone tools
+fn compose_cli() -> Result<(&'static str, &'static [&'static str]), String> {
+    const CANDIDATES: &[(&str, &[&str])] = &[
+        ("docker", &["compose"]),
+        ("podman", &["compose"]),
+        ("docker-compose", &[]),
+        ("podman-compose", &[]),
+    ];
+    CANDIDATES
+        .iter()
+        .copied()
+        .find(|(program, prefix)| {
+            Command::new(program)
+                .args(*prefix)
+                .arg("version")
+                .output()
+                .is_ok_and(|o| o.status.success())
+        })
+        .ok_or_else(|| {
+            "No compose CLI found (docker compose, podman compose or docker-compose)".to_string()
+        })
+}
+
+fn run_compose(site: &Site, project: &str, file: &Path, args: &[&str]) -> Result<String, String> {
+    let (program, prefix) = compose_cli()?;
+    let output = Command::new(program)
+        .args(prefix)
+        .args(["-p", project, "-f"])
+        .arg(file)
+        .args(args)
+        .current_dir(&site.path)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn find_site(id: &str) -> Result<Site, String> {
+    load_sites_config()
+        .sites
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| "Site not found".to_string())
+}
+
+fn site_compose_file(site: &Site) -> Result<PathBuf, String> {
+    find_compose_file(Path::new(&site.path))
+        .ok_or_else(|| format!("No compose file in {}", site.path))
+}
+
+/// Containers of a compose project
+fn project_containers(client: &EngineClient, project: &str) -> Result<Vec<Container>, String> {
+    query_containers(
+        client,
+        json!({ "label": [format!("{}={}", LABEL_COMPOSE_PROJECT, project)] }),
+    )
+}
+
+/// Services of a site's compose file, interpolated with the environment and `.env`
+fn site_compose_services(site: &Site, file: &Path) -> Result<Vec<ComposeService>, String> {
+    let env = DotEnv::load(&Path::new(&site.path).join(".env")).unwrap_or_default();
+    let lookup = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .or_else(|| env.get(name).map(str::to_string))
+    };
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    parse_compose(&content, &lookup)
+}
+
+/// Services started when none are named: the backing services ServerMark
+/// knows. The app service (Sail's `laravel.test`) needs a build and would
+/// compete with the host web server for its ports.
+fn default_services(services: &[ComposeService]) -> Vec<String> {
+    services
+        .iter()
+        .filter(|s| s.preset.is_some() && !s.build)
+        .map(|s| s.name.clone())
+        .collect()
+}
+
+/// The site's compose project, or None when it has no compose file
+#[tauri::command]
+pub fn get_compose_project(site_id: String) -> Result<Option<ComposeProject>, String> {
+    let site = find_site(&site_id)?;
+    let Some(file) = find_compose_file(Path::new(&site.path)) else {
+        return Ok(None);
+    };
+    let mut services = site_compose_services(&site, &file)?;
+
+    let project = compose_project_name(&site.name);
+    // Without a running engine the services are listed without containers
+    let containers = EngineClient::connect()
+        .and_then(|client| project_containers(&client, &project))
+        .unwrap_or_default();
+    for service in &mut services {
+        service.container = containers
+            .iter()
+            .find(|c| c.compose_service.as_deref() == Some(service.name.as_str()))
+            .cloned();
+    }
+
+    Ok(Some(ComposeProject {
+        site_id: site.id,
+        project,
+        file: file.to_string_lossy().to_string(),
+        services,
+    }))
+}
+
+/// Start services of the site's compose project (the given ones, else
+/// `default_services`) and point its `.env` at the published ports
+#[tauri::command]
+pub async fn compose_up(
+    site_id: String,
+    services: Option<Vec<String>>,
+) -> Result<ComposeUpReport, String> {
+    tauri::async_runtime::spawn_blocking(move || compose_up_blocking(&site_id, services))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn compose_up_blocking(
+    site_id: &str,
+    services: Option<Vec<String>>,
+) -> Result<ComposeUpReport, String> {
+    let site = find_site(site_id)?;
+    let file = site_compose_file(&site)?;
+    let project = compose_project_name(&site.name);
+
+    let services = match services {
+        Some(services) => services,
+        None => default_services(&site_compose_services(&site, &file)?),
+    };
+    if services.is_empty() {
+        return Err("No services to start: none of them is a service ServerMark knows".to_string());
+    }
+    let mut args = vec!["up", "-d"];
+    args.extend(services.iter().map(String::as_str));
+    run_compose(&site, &project, &file, &args)?;
+
+    let mut report = ComposeUpReport {
+        containers: project_containers(&EngineClient::connect()?, &project)?,
+        ..Default::default()
+    };
+
+    let env_path = Path::new(&site.path).join(".env");
+    if env_path.exists() {
+        let published: Vec<(String, Vec<PortMapping>)> = report
+            .containers
+            .iter()
+            .filter_map(|c| {
+                let name = c.compose_service.as_deref()?;
+                let preset = preset_for_image(&c.image).or_else(|| find_preset(name))?;
+                Some((preset.id.to_string(), c.ports.clone()))
+            })
+            .collect();
+        let mut env = DotEnv::load(&env_path)?;
+        report.updated_keys = apply_compose_ports(&mut env, &published);
+        if !report.updated_keys.is_empty() {
+            env.save(&env_path)?;
+        }
+    } else {
+        report
+            .warnings
+            .push("No .env file, ports were not written".to_string());
+    }
+
+    Ok(report)
+}
+
+/// Stop and remove the site's compose project, with its volumes if asked
+#[tauri::command]
+pub async fn compose_down(site_id: String, remove_volumes: bool) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let site = find_site(&site_id)?;
+        let file = site_compose_file(&site)?;
+        let mut args = vec!["down"];
+        if remove_volumes {
+            args.push("--volumes");
+        }
+        run_compose(&site, &compose_project_name(&site.name), &file, &args)?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAIL: &str = r#"
+services:
+    laravel.test:
+        build:
+            context: './vendor/laravel/sail/runtimes/8.3'
+        image: 'sail-8.3/app'
+        ports:
+            - '${APP_PORT:-80}:80'
+            - '${VITE_PORT:-5173}:${VITE_PORT:-5173}'
+    mysql:
+        image: 'mysql/mysql-server:8.0'
+        ports:
+            - '${FORWARD_DB_PORT:-3306}:3306'
+        environment:
+            MYSQL_ROOT_HOST: '%'
+            MYSQL_DATABASE: '${DB_DATABASE}'
+            MYSQL_ALLOW_EMPTY_PASSWORD: 1
+    redis:
+        image: 'redis:alpine'
+        ports:
+            - target: 6379
+              published: ${FORWARD_REDIS_PORT:-6379}
+    mailpit:
+        image: 'axllent/mailpit:latest'
+        ports:
+            - '127.0.0.1:1025:1025'
+            - '8025'
+            - '9000-9010:9000-9010'
+"#;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars = lookup(&[("APP_PORT", "8080"), ("EMPTY", "")]);
+        assert_eq!(interpolate("${APP_PORT:-80}:80", &vars), "8080:80");
+        assert_eq!(interpolate("${MISSING:-80}", &vars), "80");
+        assert_eq!(interpolate("${EMPTY:-80}|${EMPTY-80}", &vars), "80|");
+        assert_eq!(interpolate("$APP_PORT/$$HOME", &vars), "8080/$HOME");
+    }
+
+    #[test]
+    fn test_parse_sail_compose() {
+        let vars = lookup(&[("FORWARD_DB_PORT", "33060"), ("DB_DATABASE", "shop")]);
+        let services = parse_compose(SAIL, &vars).unwrap();
+        let names: Vec<&str> = services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["laravel.test", "mysql", "redis", "mailpit"]);
+
+        assert!(services[0].build);
+        assert_eq!(services[0].preset, None);
+        assert_eq!(services[0].ports[1].host, Some(5173));
+        assert_eq!(services[0].ports[1].container, 5173);
+
+        assert_eq!(services[1].preset.as_deref(), Some("mysql"));
+        assert_eq!(services[1].environment["MYSQL_DATABASE"], "shop");
+        assert_eq!(services[1].environment["MYSQL_ALLOW_EMPTY_PASSWORD"], "1");
+        assert_eq!(
+            services[1].ports,
+            vec![ComposePort {
+                host: Some(33060),
+                container: 3306,
+                protocol: "tcp".to_string(),
+                variable: Some("FORWARD_DB_PORT".to_string()),
+            }]
+        );
+        assert_eq!(services[2].ports[0].host, Some(6379));
+        assert_eq!(
+            services[2].ports[0].variable.as_deref(),
+            Some("FORWARD_REDIS_PORT")
+        );
+
+        let mailpit = &services[3];
+        assert_eq!(mailpit.preset.as_deref(), Some("mailpit"));
+        assert_eq!(mailpit.ports.len(), 2); // The range is skipped
+        assert_eq!(mailpit.ports[0].host, Some(1025));
+        assert_eq!(mailpit.ports[0].variable, None);
+        assert_eq!(mailpit.ports[1].host, None);
+
+        assert_eq!(
+            default_services(&services),
+            vec!["mysql", "redis", "mailpit"]
+        );
+    }
+
+    #[test]
+    fn test_apply_compose_ports() {
+        let mut env = DotEnv::parse(
+            "DB_CONNECTION=mysql\nDB_HOST=mysql\nDB_PORT=3306\nDB_PASSWORD=password\nREDIS_HOST=redis\n",
+        );
+        let port = |host, container| PortMapping {
+            host,
+            container,
+            protocol: "tcp".to_string(),
+        };
+        let services = vec![
+            ("postgresql".to_string(), vec![port(54320, 5432)]),
+            ("mysql".to_string(), vec![port(33060, 3306)]),
+            ("redis".to_string(), vec![port(6379, 6379)]),
+            ("meilisearch".to_string(), vec![port(7700, 7700)]),
+        ];
+
+        let updated = apply_compose_ports(&mut env, &services);
+        assert_eq!(
+            updated,
+            vec![
+                "DB_HOST",
+                "DB_PORT",
+                "REDIS_HOST",
+                "REDIS_PORT",
+                "MEILISEARCH_HOST"
+            ]
+        );
+        assert_eq!(env.get("DB_PORT"), Some("33060"));
+        assert_eq!(env.get("DB_PASSWORD"), Some("password"));
+        assert_eq!(env.get("MEILISEARCH_HOST"), Some("http://127.0.0.1:7700"));
+    }
+
+    #[test]
+    fn test_compose_project_name() {
+        assert_eq!(compose_project_name("My Shop"), "servermark-my-shop");
+        assert_eq!(compose_project_name("api.test_2"), "servermark-api-test_2");
+    }
+}
@@ -0,0 +1,443 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::process::Command;
+
+use super::backends::{backend, ListenPorts, WebServerKind};
+use super::rootless::{rootless_site_config_path, user_systemctl, user_unit_active, USER_UNIT};
+use super::sites::{load_sites_config, Site, SiteType};
+use super::webserver::{load_webserver_config, site_document_root, site_domains};
+
+/// Result of a single diagnostic check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    pub id: String,
+    pub label: String,
+    pub status: String, // "pass" | "warn" | "fail"
+    pub message: String,
+    pub hint: Option<String>,
+    pub fix: Option<String>, // Fix id accepted by `fix_site_issue`
+}
+
+impl DiagnosticCheck {
+    pub fn pass(id: &str, label: &str, message: impl Into<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            status: "pass".to_string(),
+            message: message.into(),
+            hint: None,
+            fix: None,
+        }
+    }
+
+    pub fn warn(id: &str, label: &str, message: impl Into<String>, hint: &str) -> Self {
+        Self {
+            status: "warn".to_string(),
+            hint: Some(hint.to_string()),
+            ..Self::pass(id, label, message)
+        }
+    }
+
+    pub fn fail(id: &str, label: &str, message: impl Into<String>, hint: &str) -> Self {
+        Self {
+            status: "fail".to_string(),
+            hint: Some(hint.to_string()),
+            ..Self::pass(id, label, message)
+        }
+    }
+
+    pub fn with_fix(mut self, fix: &str) -> Self {
+        self.fix = Some(fix.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteDiagnostics {
+    pub site_id: String,
+    pub domain: String,
+    pub web_server: String,
+    pub healthy: bool,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+pub fn unit_active(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-active", unit])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+pub fn unit_enabled(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-enabled", unit])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "enabled")
+        .unwrap_or(false)
+}
+
+pub fn php_fpm_socket(php_version: &str) -> String {
+    format!("/var/run/php/php{}-fpm.sock", php_version)
+}
+
+/// Whether `/etc/hosts` maps `domain` (exact host name, not a substring)
+pub fn hosts_has_domain(hosts: &str, domain: &str) -> bool {
+    hosts.lines().any(|line| {
+        let line = line.split('#').next().unwrap_or("");
+        line.split_whitespace().skip(1).any(|name| name == domain)
+    })
+}
+
+/// Path of the enabled config file for a site on the given server
+pub fn site_config_path(server: WebServerKind, site_name: &str) -> String {
+    let backend = backend(server);
+    let dir = backend.site_dirs().last().copied().unwrap_or_default();
+    format!("{}/{}", dir, backend.site_file_name(site_name))
+}
+
+pub fn uses_php(site: &Site) -> bool {
+    !matches!(site.site_type, SiteType::Proxy)
+}
+
+fn check_document_root(site: &Site) -> DiagnosticCheck {
+    let root = site_document_root(site);
+    if Path::new(&root).is_dir() {
+        DiagnosticCheck::pass("document_root", "Document root", root)
+    } else {
+        DiagnosticCheck::fail(
+            "document_root",
+            "Document root",
+            format!("{} does not exist", root),
+            "Create the directory or fix `document_root` in servermark.yml",
+        )
+        .with_fix("create_document_root")
+    }
+}
+
+fn check_php_fpm(site: &Site) -> Vec<DiagnosticCheck> {
+    let unit = format!("php{}-fpm", site.php_version);
+    let socket = php_fpm_socket(&site.php_version);
+
+    let service = if unit_active(&unit) {
+        DiagnosticCheck::pass("php_fpm", "PHP-FPM", format!("{} is running", unit))
+    } else if Path::new(&format!("/usr/sbin/php-fpm{}", site.php_version)).exists() {
+        DiagnosticCheck::fail(
+            "php_fpm",
+            "PHP-FPM",
+            format!("{} is not running", unit),
+            "Start the PHP-FPM service for this version",
+        )
+        .with_fix("start_php_fpm")
+    } else {
+        DiagnosticCheck::fail(
+            "php_fpm",
+            "PHP-FPM",
+            format!("PHP-FPM {} is not installed", site.php_version),
+            "Install the PHP version from the PHP page, or switch the site to an installed version",
+        )
+    };
+
+    let socket_check = if Path::new(&socket).exists() {
+        DiagnosticCheck::pass("php_fpm_socket", "FPM socket", socket)
+    } else {
+        DiagnosticCheck::fail(
+            "php_fpm_socket",
+            "FPM socket",
+            format!("{} does not exist", socket),
+            "The web server cannot reach PHP without this socket (502 Bad Gateway)",
+        )
+        .with_fix("start_php_fpm")
+    };
+
+    vec![service, socket_check]
+}
+
+fn check_web_server(site: &Site, server: WebServerKind, rootless: bool) -> Vec<DiagnosticCheck> {
+    let (unit, active, path) = if rootless {
+        (
+            USER_UNIT.to_string(),
+            user_unit_active(),
+            rootless_site_config_path(&site.name)
+                .to_string_lossy()
+                .to_string(),
+        )
+    } else {
+        let unit = backend(server).service();
+        (
+            unit.to_string(),
+            unit_active(unit),
+            site_config_path(server, &site.name),
+        )
+    };
+
+    let running = if active {
+        DiagnosticCheck::pass("web_server", "Web server", format!("{} is running", unit))
+    } else {
+        DiagnosticCheck::fail(
+            "web_server",
+            "Web server",
+            format!("{} is not running", unit),
+            "Start the web server",
+        )
+        .with_fix("start_web_server")
+    };
+
+    let config = if Path::new(&path).exists() {
+        DiagnosticCheck::pass("site_config", "Site config", path)
+    } else {
+        DiagnosticCheck::fail(
+            "site_config",
+            "Site config",
+            format!("{} is missing", path),
+            "Regenerate the site configuration",
+        )
+        .with_fix("sync_site_config")
+    };
+
+    vec![running, config]
+}
+
+fn check_hosts(site: &Site) -> DiagnosticCheck {
+    let hosts = fs::read_to_string("/etc/hosts").unwrap_or_default();
+    let missing: Vec<&str> = site_domains(site)
+        .into_iter()
+        .filter(|d| !hosts_has_domain(&hosts, d))
+        .collect();
+
+    if missing.is_empty() {
+        DiagnosticCheck::pass("hosts_entry", "Hosts entry", "Present in /etc/hosts")
+    } else {
+        DiagnosticCheck::warn(
+            "hosts_entry",
+            "Hosts entry",
+            format!("Missing from /etc/hosts: {}", missing.join(", ")),
+            "Not required when DNS is configured, but needed as a fallback",
+        )
+        .with_fix("sync_site_config")
+    }
+}
+
+fn check_dns(site: &Site) -> DiagnosticCheck {
+    match (site.domain.as_str(), 80).to_socket_addrs() {
+        Ok(addrs) => {
+            let addrs: Vec<_> = addrs.map(|a| a.ip()).collect();
+            if !addrs.is_empty() && addrs.iter().all(|ip| ip.is_loopback()) {
+                DiagnosticCheck::pass("dns", "DNS resolution", format!("{:?}", addrs))
+            } else {
+                DiagnosticCheck::fail(
+                    "dns",
+                    "DNS resolution",
+                    format!("{} resolves to {:?}", site.domain, addrs),
+                    "The domain must resolve to 127.0.0.1; check dnsmasq and the hosts file",
+                )
+            }
+        }
+        Err(e) => DiagnosticCheck::fail(
+            "dns",
+            "DNS resolution",
+            format!("{} does not resolve: {}", site.domain, e),
+            "Install DNS from the settings page or add a hosts entry",
+        )
+        .with_fix("sync_site_config"),
+    }
+}
+
+fn check_certificate(site: &Site, server: WebServerKind) -> Option<DiagnosticCheck> {
+    if !site.secured {
+        return None;
+    }
+
+    // Caddy issues and renews `tls internal` certificates on its own
+    if server == WebServerKind::Caddy {
+        return Some(DiagnosticCheck::pass(
+            "certificate",
+            "TLS certificate",
+            "Managed by Caddy (tls internal)",
+        ));
+    }
+
+    let cert = format!("/etc/servermark/ssl/{}.crt", site.domain);
+    if !Path::new(&cert).exists() {
+        return Some(
+            DiagnosticCheck::fail(
+                "certificate",
+                "TLS certificate",
+                format!("{} is missing", cert),
+                "Issue a new certificate",
+            )
+            .with_fix("renew_certificate"),
+        );
+    }
+
+    let valid = Command::new("openssl")
+        .args(["x509", "-checkend", "0", "-noout", "-in", &cert])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+
+    Some(if valid {
+        DiagnosticCheck::pass("certificate", "TLS certificate", cert)
+    } else {
+        DiagnosticCheck::fail(
+            "certificate",
+            "TLS certificate",
+            format!("{} has expired", cert),
+            "Issue a new certificate",
+        )
+        .with_fix("renew_certificate")
+    })
+}
+
+fn check_http(site: &Site, ports: ListenPorts) -> DiagnosticCheck {
+    let scheme = if site.secured { "https" } else { "http" };
+    let url = format!(
+        "{}://{}{}/",
+        scheme,
+        site.domain,
+        ports.url_suffix(site.secured)
+    );
+
+    let code = Command::new("curl")
+        .args([
+            "-sk",
+            "-o",
+            "/dev/null",
+            "-w",
+            "%{http_code}",
+            "--max-time",
+            "5",
+            &url,
+        ])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    match code.as_str() {
+        "" | "000" => DiagnosticCheck::fail(
+            "http",
+            "HTTP response",
+            format!("No response from {}", url),
+            "Check that the web server is running and listening on this port",
+        ),
+        "502" | "503" | "504" => DiagnosticCheck::fail(
+            "http",
+            "HTTP response",
+            format!("{} returned {}", url, code),
+            "The web server cannot reach PHP-FPM; check the FPM service and socket",
+        ),
+        c if c.starts_with('5') => DiagnosticCheck::warn(
+            "http",
+            "HTTP response",
+            format!("{} returned {}", url, code),
+            "The application raised an error; check storage/logs",
+        ),
+        _ => DiagnosticCheck::pass(
+            "http",
+            "HTTP response",
+            format!("{} returned {}", url, code),
+        ),
+    }
+}
+
+/// Run all checks for a site against the active web server
+pub fn run_site_diagnostics(site: &Site) -> SiteDiagnostics {
+    let config = load_webserver_config();
+    // Rootless mode always runs Caddy, whatever the system server is
+    let server = if config.rootless.is_some() {
+        WebServerKind::Caddy
+    } else {
+        config.active
+    };
+    let mut checks = vec![check_document_root(site)];
+
+    if uses_php(site) {
+        checks.extend(check_php_fpm(site));
+    }
+    checks.extend(check_web_server(site, server, config.rootless.is_some()));
+    checks.push(check_hosts(site));
+    checks.push(check_dns(site));
+    checks.extend(check_certificate(site, server));
+    checks.push(check_http(site, config.ports()));
+
+    SiteDiagnostics {
+        site_id: site.id.clone(),
+        domain: site.domain.clone(),
+        web_server: server.to_string(),
+        healthy: checks.iter().all(|c| c.status != "fail"),
+        checks,
+    }
+}
+
+/// Diagnose why a site is not being served
+#[tauri::command]
+pub async fn diagnose_site(id: String) -> Result<SiteDiagnostics, String> {
+    tauri::async_runtime::spawn_blocking(move || diagnose_site_blocking(&id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn diagnose_site_blocking(id: &str) -> Result<SiteDiagnostics, String> {
+    let config = load_sites_config();
+    let site = config
+        .sites
+        .iter()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+
+    Ok(run_site_diagnostics(site))
+}
+
+/// Apply a one-click fix suggested by `diagnose_site`
+#[tauri::command]
+pub async fn fix_site_issue(id: String, fix: String) -> Result<SiteDiagnostics, String> {
+    tauri::async_runtime::spawn_blocking(move || fix_site_issue_blocking(&id, &fix))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn fix_site_issue_blocking(id: &str, fix: &str) -> Result<SiteDiagnostics, String> {
+    let config = load_sites_config();
+    let site = config
+        .sites
+        .iter()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+
+    match fix {
+        "create_document_root" => fs::create_dir_all(site_document_root(site))
+            .map_err(|e| format!("Failed to create document root: {}", e))?,
+        "start_php_fpm" => super::services::start_service(format!("php{}-fpm", site.php_version))?,
+        "start_web_server" => {
+            let config = load_webserver_config();
+            if config.rootless.is_some() {
+                user_systemctl(&["start", USER_UNIT])?
+            } else {
+                super::services::start_service(backend(config.active).service().to_string())?
+            }
+        }
+        "sync_site_config" => super::webserver::webserver_update_site(&site.name)?,
+        "renew_certificate" => {
+            super::webserver::execute_webserver_operation("renew_certificate", Some(&site.name))?
+        }
+        _ => return Err(format!("Unknown fix: {}", fix)),
+    }
+
+    Ok(run_site_diagnostics(site))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hosts_has_domain_matches_exact_names() {
+        let hosts = "127.0.0.1 localhost\n127.0.0.1 myapp.test # servermark\n#127.0.0.1 old.test\n";
+        assert!(hosts_has_domain(hosts, "myapp.test"));
+        assert!(!hosts_has_domain(hosts, "app.test"));
+        assert!(!hosts_has_domain(hosts, "old.test"));
+        assert!(!hosts_has_domain(hosts, "127.0.0.1"));
+    }
+}
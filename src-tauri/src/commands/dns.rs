@@ -0,0 +1,928 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process::Command;
+
+use super::diagnostics::{unit_active, unit_enabled};
+use super::dns_responder::{
+    disable_responder, enable_responder, responder_running, RESPONDER_PORT,
+};
+use super::helper::{helper_available, helper_request, HelperOp};
+use super::sites::load_sites_config;
+
+// Local DNS for the ServerMark TLDs. How queries reach dnsmasq depends on the
+// system's resolver stack, so each stack has a backend. The stack found at
+// install time is recorded, and reconfiguring or uninstalling uses that one.
+// On systemd-resolved, ServerMark's own responder can stand in for dnsmasq.
+
+const DNSMASQ_CONF_PATH: &str = "/etc/dnsmasq.d/servermark.conf";
+const DNSMASQ_BASE_CONF_PATH: &str = "/etc/dnsmasq.d/servermark-base.conf";
+const RESOLVED_CONF_PATH: &str = "/etc/systemd/resolved.conf.d/servermark.conf";
+const NM_DNSMASQ_CONF_PATH: &str = "/etc/NetworkManager/dnsmasq.d/servermark.conf";
+pub const DNS_STATE_PATH: &str = "/etc/servermark/dns.json";
+const RESOLV_CONF_BACKUP: &str = "/etc/servermark/resolv.conf.backup";
+
+/// resolvconf record holding the local nameserver
+const RESOLVCONF_RECORD: &str = "lo.servermark";
+
+/// Prefix of lines in the distro's dnsmasq.conf disabled during install
+const DISABLED_MARKER: &str = "#servermark#";
+
+/// How the system resolves names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolverStack {
+    /// systemd-resolved stub, which can route single domains
+    Resolved,
+    /// NetworkManager running its own dnsmasq (`dns=dnsmasq`)
+    NetworkManager,
+    /// resolv.conf generated by resolvconf or openresolv
+    Resolvconf,
+    /// Hand-maintained /etc/resolv.conf
+    Static,
+}
+
+/// What answers queries for the TLDs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsServer {
+    #[default]
+    Dnsmasq,
+    /// ServerMark's built-in responder (a user service), systemd-resolved only
+    Builtin,
+}
+
+/// What the install changed, so uninstalling can restore it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsInstallState {
+    pub stack: ResolverStack,
+    pub dnsmasq_was_enabled: bool,
+    #[serde(default)]
+    pub server: DnsServer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsStatus {
+    pub dnsmasq_installed: bool,
+    pub dnsmasq_running: bool,
+    pub dnsmasq_configured: bool,  // Answers for every configured TLD
+    pub resolver_configured: bool, // Queries for every TLD reach the local server
+    pub responder_running: bool,   // Built-in responder user service
+    pub tlds: Vec<String>,
+    pub stack: ResolverStack,                   // Detected now
+    pub installed_stack: Option<ResolverStack>, // Recorded at install time
+    pub server: DnsServer,                      // Installed, or dnsmasq
+    pub port: Option<u16>, // Local server port on 127.0.0.1, None when NetworkManager runs it
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// `nameserver` addresses of a resolv.conf
+pub fn parse_nameservers(resolv_conf: &str) -> Vec<String> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            (fields.next() == Some("nameserver"))
+                .then(|| fields.next())
+                .flatten()
+                .map(|ns| ns.to_string())
+        })
+        .collect()
+}
+
+fn is_loopback(address: &str) -> bool {
+    let address = address.split('%').next().unwrap_or(address);
+    address
+        .parse::<IpAddr>()
+        .map(|ip| ip.is_loopback())
+        .unwrap_or(false)
+}
+
+fn classify_stack(
+    resolv_conf: &str,
+    resolved_active: bool,
+    nm_dns_mode: Option<&str>,
+    resolvconf_installed: bool,
+) -> ResolverStack {
+    let nameservers = parse_nameservers(resolv_conf);
+    if resolved_active && nameservers.iter().any(|ns| ns == "127.0.0.53") {
+        ResolverStack::Resolved
+    } else if nm_dns_mode == Some("dnsmasq") {
+        ResolverStack::NetworkManager
+    } else if resolvconf_installed {
+        ResolverStack::Resolvconf
+    } else {
+        ResolverStack::Static
+    }
+}
+
+/// `dns=` mode of a running NetworkManager (conf.d overrides the main file)
+fn nm_dns_mode() -> Option<String> {
+    if !unit_active("NetworkManager") {
+        return None;
+    }
+    let mut files = vec![Path::new("/etc/NetworkManager/NetworkManager.conf").to_path_buf()];
+    if let Ok(entries) = fs::read_dir("/etc/NetworkManager/conf.d") {
+        let mut extra: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+        extra.sort();
+        files.extend(extra);
+    }
+    files
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("dns="))
+                .map(|mode| mode.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .last()
+}
+
+pub fn detect_resolver_stack() -> ResolverStack {
+    let resolv_conf = fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+    let resolvconf_installed = ["/sbin/resolvconf", "/usr/sbin/resolvconf"]
+        .iter()
+        .any(|p| Path::new(p).exists());
+    classify_stack(
+        &resolv_conf,
+        unit_active("systemd-resolved"),
+        nm_dns_mode().as_deref(),
+        resolvconf_installed,
+    )
+}
+
+/// Upstream servers the system uses now, for stacks where dnsmasq becomes the
+/// primary resolver (our own 127.0.0.1 entry is skipped)
+fn system_upstreams() -> Vec<String> {
+    parse_nameservers(&fs::read_to_string("/etc/resolv.conf").unwrap_or_default())
+        .into_iter()
+        .filter(|ns| !is_loopback(ns))
+        .collect()
+}
+
+/// State recorded by `install_dns`; installs from older versions wrote no
+/// state and always used systemd-resolved
+pub fn installed_dns_state() -> Option<DnsInstallState> {
+    match fs::read_to_string(DNS_STATE_PATH) {
+        Ok(content) => serde_json::from_str(&content).ok(),
+        Err(_) if Path::new(DNSMASQ_CONF_PATH).exists() => Some(DnsInstallState {
+            stack: ResolverStack::Resolved,
+            dnsmasq_was_enabled: false,
+            server: DnsServer::Dnsmasq,
+        }),
+        Err(_) => None,
+    }
+}
+
+// ============================================================================
+// Config rendering
+// ============================================================================
+
+/// Address lines resolving every TLD to localhost, plus the port when
+/// ServerMark runs dnsmasq itself
+fn dnsmasq_conf(tlds: &[String], port: Option<u16>) -> String {
+    let mut conf = String::from("# ServerMark DNS configuration\n");
+    for tld in tlds {
+        conf.push_str(&format!(
+            "# Resolve *.{tld} domains to localhost\naddress=/.{tld}/127.0.0.1\naddress=/.{tld}/::1\n",
+            tld = tld
+        ));
+    }
+    if let Some(port) = port {
+        conf.push_str(&format!("\nport={}\n", port));
+    }
+    conf
+}
+
+/// Listener settings; with `upstreams` dnsmasq also forwards everything else
+fn dnsmasq_base_conf(upstreams: &[String]) -> String {
+    let mut conf = String::from(
+        "# ServerMark base DNS configuration\nlisten-address=127.0.0.1\nbind-interfaces\nno-resolv\ncache-size=1000\n",
+    );
+    for upstream in upstreams {
+        conf.push_str(&format!("server={}\n", upstream));
+    }
+    conf
+}
+
+/// Earlier hand-made setups may already ship a base config
+fn needs_base_conf() -> bool {
+    !["local-dev.conf", "base.conf"]
+        .iter()
+        .any(|f| Path::new("/etc/dnsmasq.d").join(f).exists())
+}
+
+fn resolved_conf(tlds: &[String], port: u16) -> String {
+    let domains: Vec<String> = tlds.iter().map(|tld| format!("~{}", tld)).collect();
+    format!(
+        "[Resolve]\n# Forward ServerMark TLD queries to the local DNS server\nDNS=127.0.0.1#{}\nDomains={}\n",
+        port,
+        domains.join(" ")
+    )
+}
+
+fn resolved_drop_in_forwards(tlds: &[String]) -> bool {
+    fs::read_to_string(RESOLVED_CONF_PATH)
+        .map(|content| resolved_forwards(&content, tlds))
+        .unwrap_or(false)
+}
+
+fn resolved_forwards(content: &str, tlds: &[String]) -> bool {
+    let routed: Vec<&str> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Domains="))
+        .flat_map(|domains| domains.split_whitespace())
+        .collect();
+    tlds.iter()
+        .all(|tld| routed.contains(&format!("~{}", tld).as_str()))
+}
+
+fn unit_op(unit: &str, action: &str) -> HelperOp {
+    HelperOp::ControlUnit {
+        unit: unit.to_string(),
+        action: action.to_string(),
+    }
+}
+
+/// Put dnsmasq back the way it was before the install
+fn restore_dnsmasq_script(state: &DnsInstallState) -> String {
+    if state.dnsmasq_was_enabled {
+        "systemctl restart dnsmasq 2>/dev/null || true\n".to_string()
+    } else {
+        "systemctl stop dnsmasq 2>/dev/null || true\nsystemctl disable dnsmasq 2>/dev/null || true\n"
+            .to_string()
+    }
+}
+
+fn restore_dnsmasq_ops(state: &DnsInstallState) -> Vec<HelperOp> {
+    if state.dnsmasq_was_enabled {
+        vec![unit_op("dnsmasq", "restart")]
+    } else {
+        vec![unit_op("dnsmasq", "stop"), unit_op("dnsmasq", "disable")]
+    }
+}
+
+// ============================================================================
+// Backends
+// ============================================================================
+
+/// Everything that differs between resolver stacks. Like the web server
+/// backends, script methods feed a single pkexec call and `*_ops` methods
+/// express the same change for the privileged helper where possible.
+pub trait DnsBackend {
+    fn stack(&self) -> ResolverStack;
+
+    /// Port on 127.0.0.1 answering for the TLDs, `None` when the stack runs
+    /// its own dnsmasq
+    fn listen_port(&self) -> Option<u16>;
+
+    /// Whether ServerMark installs and runs the system dnsmasq
+    fn runs_dnsmasq(&self) -> bool {
+        self.listen_port().is_some()
+    }
+
+    /// Every file this backend may write, for uninstalling
+    fn managed_paths(&self) -> Vec<&'static str>;
+
+    /// Config files `(path, contents)` for `tlds`
+    fn config_files(&self, tlds: &[String]) -> Result<Vec<(String, String)>, String>;
+
+    /// Whether queries for every TLD reach the local server
+    fn resolver_configured(&self, tlds: &[String]) -> bool;
+
+    /// Shell commands putting freshly written config files into effect
+    fn activate_script(&self) -> String;
+
+    /// Helper counterpart of `activate_script`; `None` when it touches files
+    /// outside the helper's allow-list
+    fn activate_ops(&self) -> Option<Vec<HelperOp>>;
+
+    /// Shell commands undoing `activate_script` once the files are removed
+    fn deactivate_script(&self, state: &DnsInstallState) -> String;
+
+    fn deactivate_ops(&self, state: &DnsInstallState) -> Option<Vec<HelperOp>>;
+}
+
+pub fn dns_backend(stack: ResolverStack, server: DnsServer) -> Box<dyn DnsBackend> {
+    if server == DnsServer::Builtin {
+        return Box::new(BuiltinBackend);
+    }
+    match stack {
+        ResolverStack::Resolved => Box::new(ResolvedBackend),
+        ResolverStack::NetworkManager => Box::new(NetworkManagerBackend),
+        ResolverStack::Resolvconf => Box::new(PrimaryResolverBackend { resolvconf: true }),
+        ResolverStack::Static => Box::new(PrimaryResolverBackend { resolvconf: false }),
+    }
+}
+
+/// dnsmasq on port 5353 behind systemd-resolved, which routes only the
+/// ServerMark TLDs to it; no upstreams needed
+pub struct ResolvedBackend;
+
+impl DnsBackend for ResolvedBackend {
+    fn stack(&self) -> ResolverStack {
+        ResolverStack::Resolved
+    }
+
+    fn listen_port(&self) -> Option<u16> {
+        Some(5353)
+    }
+
+    fn managed_paths(&self) -> Vec<&'static str> {
+        vec![
+            DNSMASQ_CONF_PATH,
+            DNSMASQ_BASE_CONF_PATH,
+            RESOLVED_CONF_PATH,
+        ]
+    }
+
+    fn config_files(&self, tlds: &[String]) -> Result<Vec<(String, String)>, String> {
+        let mut files = vec![(
+            DNSMASQ_CONF_PATH.to_string(),
+            dnsmasq_conf(tlds, self.listen_port()),
+        )];
+        if needs_base_conf() {
+            files.push((DNSMASQ_BASE_CONF_PATH.to_string(), dnsmasq_base_conf(&[])));
+        }
+        files.push((RESOLVED_CONF_PATH.to_string(), resolved_conf(tlds, 5353)));
+        Ok(files)
+    }
+
+    fn resolver_configured(&self, tlds: &[String]) -> bool {
+        resolved_drop_in_forwards(tlds)
+    }
+
+    fn activate_script(&self) -> String {
+        "systemctl restart systemd-resolved\nsystemctl enable dnsmasq\nsystemctl restart dnsmasq\n"
+            .to_string()
+    }
+
+    fn activate_ops(&self) -> Option<Vec<HelperOp>> {
+        Some(vec![
+            unit_op("systemd-resolved", "restart"),
+            unit_op("dnsmasq", "enable"),
+            unit_op("dnsmasq", "restart"),
+        ])
+    }
+
+    fn deactivate_script(&self, state: &DnsInstallState) -> String {
+        format!(
+            "systemctl restart systemd-resolved\n{}",
+            restore_dnsmasq_script(state)
+        )
+    }
+
+    fn deactivate_ops(&self, state: &DnsInstallState) -> Option<Vec<HelperOp>> {
+        let mut ops = vec![unit_op("systemd-resolved", "restart")];
+        ops.extend(restore_dnsmasq_ops(state));
+        Some(ops)
+    }
+}
+
+/// The built-in responder behind systemd-resolved: no package to install and
+/// dnsmasq is left alone. The user unit itself is managed outside the
+/// privileged script, see `dns_responder`.
+pub struct BuiltinBackend;
+
+impl DnsBackend for BuiltinBackend {
+    fn stack(&self) -> ResolverStack {
+        ResolverStack::Resolved
+    }
+
+    fn listen_port(&self) -> Option<u16> {
+        Some(RESPONDER_PORT)
+    }
+
+    fn runs_dnsmasq(&self) -> bool {
+        false
+    }
+
+    fn managed_paths(&self) -> Vec<&'static str> {
+        vec![RESOLVED_CONF_PATH]
+    }
+
+    fn config_files(&self, tlds: &[String]) -> Result<Vec<(String, String)>, String> {
+        Ok(vec![(
+            RESOLVED_CONF_PATH.to_string(),
+            resolved_conf(tlds, RESPONDER_PORT),
+        )])
+    }
+
+    fn resolver_configured(&self, tlds: &[String]) -> bool {
+        resolved_drop_in_forwards(tlds)
+    }
+
+    fn activate_script(&self) -> String {
+        "systemctl restart systemd-resolved\n".to_string()
+    }
+
+    fn activate_ops(&self) -> Option<Vec<HelperOp>> {
+        Some(vec![unit_op("systemd-resolved", "restart")])
+    }
+
+    fn deactivate_script(&self, _state: &DnsInstallState) -> String {
+        self.activate_script()
+    }
+
+    fn deactivate_ops(&self, _state: &DnsInstallState) -> Option<Vec<HelperOp>> {
+        self.activate_ops()
+    }
+}
+
+/// NetworkManager's own dnsmasq picks up extra address lines from its
+/// dnsmasq.d and keeps forwarding everything else as before
+pub struct NetworkManagerBackend;
+
+impl DnsBackend for NetworkManagerBackend {
+    fn stack(&self) -> ResolverStack {
+        ResolverStack::NetworkManager
+    }
+
+    fn listen_port(&self) -> Option<u16> {
+        None
+    }
+
+    fn managed_paths(&self) -> Vec<&'static str> {
+        vec![NM_DNSMASQ_CONF_PATH]
+    }
+
+    fn config_files(&self, tlds: &[String]) -> Result<Vec<(String, String)>, String> {
+        Ok(vec![(
+            NM_DNSMASQ_CONF_PATH.to_string(),
+            dnsmasq_conf(tlds, None),
+        )])
+    }
+
+    /// NetworkManager already sends every query through its dnsmasq
+    fn resolver_configured(&self, _tlds: &[String]) -> bool {
+        nm_dns_mode().as_deref() == Some("dnsmasq")
+    }
+
+    fn activate_script(&self) -> String {
+        "systemctl reload NetworkManager\n".to_string()
+    }
+
+    fn activate_ops(&self) -> Option<Vec<HelperOp>> {
+        Some(vec![unit_op("NetworkManager", "reload")])
+    }
+
+    fn deactivate_script(&self, _state: &DnsInstallState) -> String {
+        self.activate_script()
+    }
+
+    fn deactivate_ops(&self, _state: &DnsInstallState) -> Option<Vec<HelperOp>> {
+        self.activate_ops()
+    }
+}
+
+/// dnsmasq on 127.0.0.1:53 as the system's first nameserver, forwarding to
+/// the upstreams found at install time. With resolvconf the nameserver is
+/// added as a record of its own; otherwise resolv.conf is edited and backed up.
+pub struct PrimaryResolverBackend {
+    pub resolvconf: bool,
+}
+
+impl DnsBackend for PrimaryResolverBackend {
+    fn stack(&self) -> ResolverStack {
+        if self.resolvconf {
+            ResolverStack::Resolvconf
+        } else {
+            ResolverStack::Static
+        }
+    }
+
+    fn listen_port(&self) -> Option<u16> {
+        Some(53)
+    }
+
+    fn managed_paths(&self) -> Vec<&'static str> {
+        vec![DNSMASQ_CONF_PATH, DNSMASQ_BASE_CONF_PATH]
+    }
+
+    fn config_files(&self, tlds: &[String]) -> Result<Vec<(String, String)>, String> {
+        let upstreams = system_upstreams();
+        if upstreams.is_empty() {
+            return Err("No upstream DNS server found in /etc/resolv.conf".to_string());
+        }
+        Ok(vec![
+            (
+                DNSMASQ_CONF_PATH.to_string(),
+                dnsmasq_conf(tlds, self.listen_port()),
+            ),
+            (
+                DNSMASQ_BASE_CONF_PATH.to_string(),
+                dnsmasq_base_conf(&upstreams),
+            ),
+        ])
+    }
+
+    fn resolver_configured(&self, _tlds: &[String]) -> bool {
+        let resolv_conf = fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+        parse_nameservers(&resolv_conf).first().map(String::as_str) == Some("127.0.0.1")
+    }
+
+    fn activate_script(&self) -> String {
+        let resolver = if self.resolvconf {
+            format!(
+                "echo 'nameserver 127.0.0.1' | resolvconf -a {}\n",
+                RESOLVCONF_RECORD
+            )
+        } else {
+            format!(
+                r#"[ -f {backup} ] || cp /etc/resolv.conf {backup}
+{{ echo 'nameserver 127.0.0.1'; grep -v '^nameserver 127\.0\.0\.1$' {backup}; }} > /etc/resolv.conf.servermark-tmp
+cat /etc/resolv.conf.servermark-tmp > /etc/resolv.conf
+rm -f /etc/resolv.conf.servermark-tmp
+"#,
+                backup = RESOLV_CONF_BACKUP
+            )
+        };
+        format!(
+            "systemctl enable dnsmasq\nsystemctl restart dnsmasq\n{}",
+            resolver
+        )
+    }
+
+    fn activate_ops(&self) -> Option<Vec<HelperOp>> {
+        None
+    }
+
+    fn deactivate_script(&self, state: &DnsInstallState) -> String {
+        let resolver = if self.resolvconf {
+            format!("resolvconf -d {} 2>/dev/null || true\n", RESOLVCONF_RECORD)
+        } else {
+            format!(
+                "if [ -f {backup} ]; then cat {backup} > /etc/resolv.conf; rm -f {backup}; fi\n",
+                backup = RESOLV_CONF_BACKUP
+            )
+        };
+        format!("{}{}", resolver, restore_dnsmasq_script(state))
+    }
+
+    fn deactivate_ops(&self, _state: &DnsInstallState) -> Option<Vec<HelperOp>> {
+        None
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+#[tauri::command]
+pub fn detect_dns() -> DnsStatus {
+    let tlds = load_sites_config().tlds();
+    let stack = detect_resolver_stack();
+    let installed = installed_dns_state();
+    let installed_stack = installed.as_ref().map(|s| s.stack);
+    let server = installed.map(|s| s.server).unwrap_or_default();
+    let dns = dns_backend(installed_stack.unwrap_or(stack), server);
+    let port = dns.listen_port();
+
+    // Check if dnsmasq is installed
+    let dnsmasq_installed = Command::new("which")
+        .arg("dnsmasq")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+
+    // Check if dnsmasq is running (via systemctl or by checking if its port is in use)
+    let dnsmasq_running = match (server, port) {
+        (DnsServer::Builtin, _) => unit_active("dnsmasq"),
+        (_, Some(port)) => {
+            unit_active("dnsmasq")
+                || Command::new("ss")
+                    .args(["-tlnp"])
+                    .output()
+                    .map(|o| {
+                        let out = String::from_utf8_lossy(&o.stdout);
+                        out.contains(&format!(":{} ", port)) && out.contains("dnsmasq")
+                    })
+                    .unwrap_or(false)
+        }
+        (_, None) => unit_active("NetworkManager"),
+    };
+
+    // Check that every TLD is configured in dnsmasq (ServerMark config or any other)
+    let dnsmasq_configs: Vec<String> = ["/etc/dnsmasq.d", "/etc/NetworkManager/dnsmasq.d"]
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .collect();
+    let dnsmasq_configured = tlds.iter().all(|tld| {
+        dnsmasq_configs.iter().any(|content| {
+            content.contains(&format!("address=/.{}/", tld))
+                || content.contains(&format!("address=/{}/", tld))
+        })
+    });
+
+    DnsStatus {
+        dnsmasq_installed,
+        dnsmasq_running,
+        dnsmasq_configured,
+        resolver_configured: dns.resolver_configured(&tlds),
+        responder_running: responder_running(),
+        tlds,
+        stack,
+        installed_stack,
+        server,
+        port,
+    }
+}
+
+fn write_files_script(files: &[(String, String)]) -> String {
+    files
+        .iter()
+        .map(|(path, contents)| {
+            let dir = Path::new(path)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            format!(
+                "mkdir -p '{}'\ncat > '{}' << 'SERVERMARKEOF'\n{}SERVERMARKEOF\n",
+                dir, path, contents
+            )
+        })
+        .collect()
+}
+
+fn run_privileged(script: &str, context: &str) -> Result<(), String> {
+    let output = Command::new("pkexec")
+        .args(["bash", "-c", script])
+        .output()
+        .map_err(|e| format!("{}: {}", context, e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Err(format!("{}:\n{}\n{}", context, stdout, stderr));
+    }
+
+    Ok(())
+}
+
+/// Whether the distro's dnsmasq.conf sets a port, which would override ours
+fn main_conf_sets_port() -> bool {
+    fs::read_to_string("/etc/dnsmasq.conf")
+        .map(|conf| conf.lines().any(|l| l.trim_start().starts_with("port=")))
+        .unwrap_or(false)
+}
+
+/// Install local DNS with `server` (default: the one installed, else dnsmasq)
+#[tauri::command]
+pub fn install_dns(package_manager: String, server: Option<DnsServer>) -> Result<(), String> {
+    let tlds = load_sites_config().tlds();
+    let installed = installed_dns_state();
+    let server = server
+        .or(installed.as_ref().map(|s| s.server))
+        .unwrap_or_default();
+
+    if server == DnsServer::Builtin && detect_resolver_stack() != ResolverStack::Resolved {
+        return Err("The built-in DNS server needs systemd-resolved".to_string());
+    }
+
+    // Reinstalling keeps the stack (and prior state) of the first install;
+    // switching servers starts over from the restored setup
+    let installed = match installed {
+        Some(state) if state.server != server => {
+            uninstall_dns()?;
+            None
+        }
+        installed => installed,
+    };
+    let state = installed.unwrap_or_else(|| DnsInstallState {
+        stack: detect_resolver_stack(),
+        dnsmasq_was_enabled: unit_enabled("dnsmasq"),
+        server,
+    });
+    let dns = dns_backend(state.stack, server);
+    let state_json = serde_json::to_string_pretty(&state)
+        .map_err(|e| format!("Failed to serialize DNS state: {}", e))?;
+    // Rendered here for the script; the helper renders its own from the TLDs
+    let mut files = dns.config_files(&tlds)?;
+    files.push((DNS_STATE_PATH.to_string(), state_json));
+
+    // Answering before systemd-resolved starts routing to it
+    if server == DnsServer::Builtin {
+        enable_responder()?;
+    }
+
+    let standalone = dns.runs_dnsmasq();
+
+    // The helper cannot install packages or edit the distro's dnsmasq.conf
+    let helper_ops = dns.activate_ops().filter(|_| {
+        helper_available()
+            && (!standalone || (!main_conf_sets_port() && detect_dns().dnsmasq_installed))
+    });
+    if let Some(activate) = helper_ops {
+        let mut ops = Vec::new();
+        if standalone {
+            ops.push(unit_op("dnsmasq", "stop"));
+        }
+        ops.push(HelperOp::WriteDnsConfig {
+            stack: state.stack,
+            server,
+            tlds: tlds.clone(),
+        });
+        ops.push(HelperOp::WriteDnsState { state });
+        ops.extend(activate);
+        return helper_request(ops).map_err(|e| format!("Failed to install DNS: {}", e));
+    }
+
+    let mut script = String::from("set -e\n\necho \"=== ServerMark DNS Setup ===\"\n\n");
+    if standalone {
+        let install_cmd = match package_manager.as_str() {
+            "apt" => "apt install -y dnsmasq",
+            "dnf" => "dnf install -y dnsmasq",
+            "pacman" => "pacman -S --noconfirm dnsmasq",
+            "zypper" => "zypper install -y dnsmasq",
+            _ => return Err("Unsupported package manager".to_string()),
+        };
+        script.push_str(&format!(
+            r#"# Install dnsmasq if not present
+if ! command -v dnsmasq &> /dev/null; then
+    echo "Installing dnsmasq..."
+    {install_cmd}
+fi
+
+# Stop dnsmasq to reconfigure
+systemctl stop dnsmasq 2>/dev/null || true
+
+# Keep the distro's dnsmasq.conf from overriding our port
+if [ -f /etc/dnsmasq.conf ]; then
+    sed -i 's/^port=/{marker}port=/' /etc/dnsmasq.conf
+fi
+
+"#,
+            install_cmd = install_cmd,
+            marker = DISABLED_MARKER
+        ));
+    }
+    script.push_str(&format!("# Configure for {:?}\n", dns.stack()));
+    script.push_str(&write_files_script(&files));
+    script.push_str(&dns.activate_script());
+
+    if let Some(port) = dns.listen_port().filter(|_| standalone) {
+        script.push_str(&format!(
+            r#"
+sleep 1
+if systemctl is-active --quiet dnsmasq; then
+    echo "✓ dnsmasq is running on port {port}"
+else
+    echo "✗ dnsmasq failed to start"
+    journalctl -u dnsmasq -n 5 --no-pager
+    exit 1
+fi
+"#,
+            port = port
+        ));
+    }
+    script.push_str(&format!(
+        "\necho \"All {} domains will now resolve to 127.0.0.1\"\n",
+        tlds.iter()
+            .map(|tld| format!("*.{}", tld))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+
+    run_privileged(&script, "Failed to install DNS")
+}
+
+/// Regenerate the DNS configuration for new TLDs; a no-op until DNS is installed
+pub fn reconfigure_dns(tlds: &[String]) -> Result<(), String> {
+    let Some(state) = installed_dns_state() else {
+        return Ok(());
+    };
+    let dns = dns_backend(state.stack, state.server);
+    let files = dns.config_files(tlds)?;
+
+    // The responder reads the TLDs when it starts
+    if state.server == DnsServer::Builtin {
+        enable_responder()?;
+    }
+
+    if helper_available() {
+        if let Some(activate) = dns.activate_ops() {
+            let mut ops = vec![HelperOp::WriteDnsConfig {
+                stack: state.stack,
+                server: state.server,
+                tlds: tlds.to_vec(),
+            }];
+            ops.extend(activate);
+            return helper_request(ops).map_err(|e| format!("Failed to update DNS: {}", e));
+        }
+    }
+
+    let mut script = String::from("set -e\n");
+    script.push_str(&write_files_script(&files));
+    script.push_str(&dns.activate_script());
+    run_privileged(&script, "Failed to update DNS")
+}
+
+/// Remove ServerMark's DNS setup and restore what the install changed
+#[tauri::command]
+pub fn uninstall_dns() -> Result<DnsStatus, String> {
+    let state = installed_dns_state().ok_or("ServerMark DNS is not installed")?;
+    let dns = dns_backend(state.stack, state.server);
+    let mut paths = dns.managed_paths();
+    paths.push(DNS_STATE_PATH);
+
+    let marker_left = fs::read_to_string("/etc/dnsmasq.conf")
+        .map(|conf| conf.contains(DISABLED_MARKER))
+        .unwrap_or(false);
+    let helper_ops = dns
+        .deactivate_ops(&state)
+        .filter(|_| helper_available() && !marker_left);
+
+    if let Some(deactivate) = helper_ops {
+        let mut ops: Vec<HelperOp> = paths
+            .iter()
+            .map(|path| HelperOp::RemoveManagedFile {
+                path: path.to_string(),
+            })
+            .collect();
+        ops.extend(deactivate);
+        helper_request(ops).map_err(|e| format!("Failed to uninstall DNS: {}", e))?;
+    } else {
+        let mut script: String = paths
+            .iter()
+            .map(|path| format!("rm -f '{}'\n", path))
+            .collect();
+        script.push_str(&format!(
+            "[ -f /etc/dnsmasq.conf ] && sed -i 's/^{}port=/port=/' /etc/dnsmasq.conf\n",
+            DISABLED_MARKER
+        ));
+        script.push_str(&dns.deactivate_script(&state));
+        run_privileged(&script, "Failed to uninstall DNS")?;
+    }
+
+    if state.server == DnsServer::Builtin {
+        disable_responder()?;
+    }
+
+    Ok(detect_dns())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_stack() {
+        let stub = "# managed by systemd-resolved\nnameserver 127.0.0.53\noptions edns0\n";
+        let plain = "nameserver 10.0.0.1\nnameserver fe80::1%eth0\n";
+
+        assert_eq!(
+            classify_stack(stub, true, None, true),
+            ResolverStack::Resolved
+        );
+        // resolved running but not in the path
+        assert_eq!(
+            classify_stack(plain, true, Some("dnsmasq"), false),
+            ResolverStack::NetworkManager
+        );
+        assert_eq!(
+            classify_stack(plain, false, Some("default"), true),
+            ResolverStack::Resolvconf
+        );
+        assert_eq!(
+            classify_stack(plain, false, None, false),
+            ResolverStack::Static
+        );
+
+        let upstreams: Vec<String> =
+            parse_nameservers("nameserver 127.0.0.1\nnameserver ::1\nnameserver 10.0.0.1\n")
+                .into_iter()
+                .filter(|ns| !is_loopback(ns))
+                .collect();
+        assert_eq!(upstreams, ["10.0.0.1"]);
+    }
+
+    #[test]
+    fn test_render_configs_for_every_tld() {
+        let tlds = vec!["test".to_string(), "dev.internal".to_string()];
+
+        let conf = dnsmasq_conf(&tlds, Some(5353));
+        assert!(conf.contains("address=/.test/127.0.0.1\n"));
+        assert!(conf.contains("address=/.dev.internal/::1\n"));
+        assert!(conf.ends_with("port=5353\n"));
+        assert!(!dnsmasq_conf(&tlds, None).contains("port="));
+
+        let resolved = resolved_conf(&tlds, RESPONDER_PORT);
+        assert!(resolved.contains("DNS=127.0.0.1#5354\n"));
+        assert!(resolved.contains("Domains=~test ~dev.internal\n"));
+        assert!(resolved_forwards(&resolved, &tlds));
+        assert!(!resolved_forwards(&resolved, &["localhost".to_string()]));
+
+        let base = dnsmasq_base_conf(&["10.0.0.1".to_string()]);
+        assert!(base.contains("no-resolv\n"));
+        assert!(base.ends_with("server=10.0.0.1\n"));
+        assert!(!base.contains("8.8.8.8"));
+    }
+}
@@ -0,0 +1,327 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use super::doctor::query_local_dns;
+use super::rootless::user_systemctl;
+use super::sites::load_sites_config;
+use super::system::app_executable;
+
+// A minimal authoritative DNS server for the ServerMark TLDs, run as a user
+// service in place of dnsmasq. systemd-resolved routes only the TLDs here, so
+// every name under them gets localhost and anything else is refused; nothing
+// is forwarded.
+
+/// Port on 127.0.0.1, next to the 5353 a dnsmasq install uses
+pub const RESPONDER_PORT: u16 = 5354;
+
+pub const RESPONDER_UNIT: &str = "servermark-dns.service";
+
+const TTL: u32 = 60;
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u16 = 1;
+const RCODE_NOTIMP: u16 = 4;
+const RCODE_REFUSED: u16 = 5;
+
+fn read_u16(packet: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]))
+}
+
+fn under_tlds(name: &str, tlds: &[String]) -> bool {
+    tlds.iter().any(|tld| {
+        name == tld
+            || name
+                .strip_suffix(tld.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+/// Header of a reply to `flags`, keeping the opcode and recursion-desired bits
+fn reply_header(id: &[u8], flags: u16, rcode: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let authoritative = if rcode == 0 { 0x0400 } else { 0 };
+    let flags = 0x8000 | (flags & 0x7900) | authoritative | rcode;
+    let mut reply = Vec::with_capacity(64);
+    reply.extend_from_slice(id);
+    reply.extend_from_slice(&flags.to_be_bytes());
+    reply.extend_from_slice(&questions.to_be_bytes());
+    reply.extend_from_slice(&answers.to_be_bytes());
+    reply.extend_from_slice(&[0, 0, 0, 0]); // No authority or additional records
+    reply
+}
+
+/// Reply to a query packet: A and AAAA records pointing at localhost for names
+/// under `tlds`, REFUSED for anything else. `None` for packets to drop.
+pub fn answer_query(query: &[u8], tlds: &[String]) -> Option<Vec<u8>> {
+    let id = query.get(..2)?;
+    let flags = read_u16(query, 2)?;
+    if flags & 0x8000 != 0 {
+        return None; // A response, not a query
+    }
+    if (flags >> 11) & 0xF != 0 {
+        return Some(reply_header(id, flags, RCODE_NOTIMP, 0, 0));
+    }
+    if read_u16(query, 4)? != 1 {
+        return Some(reply_header(id, flags, RCODE_FORMERR, 0, 0));
+    }
+
+    let mut labels = Vec::new();
+    let mut at = 12;
+    loop {
+        let len = *query.get(at)? as usize;
+        at += 1;
+        if len == 0 {
+            break;
+        }
+        if len > 63 {
+            return Some(reply_header(id, flags, RCODE_FORMERR, 0, 0));
+        }
+        labels.push(String::from_utf8_lossy(query.get(at..at + len)?).to_ascii_lowercase());
+        at += len;
+    }
+    let qtype = read_u16(query, at)?;
+    let qclass = read_u16(query, at + 2)?;
+    let question = &query[12..at + 4];
+
+    if !under_tlds(&labels.join("."), tlds) {
+        let mut reply = reply_header(id, flags, RCODE_REFUSED, 1, 0);
+        reply.extend_from_slice(question);
+        return Some(reply);
+    }
+
+    let mut records: Vec<(u16, Vec<u8>)> = Vec::new();
+    if qclass == CLASS_IN || qclass == TYPE_ANY {
+        if qtype == TYPE_A || qtype == TYPE_ANY {
+            records.push((TYPE_A, vec![127, 0, 0, 1]));
+        }
+        if qtype == TYPE_AAAA || qtype == TYPE_ANY {
+            let mut loopback = vec![0; 16];
+            loopback[15] = 1;
+            records.push((TYPE_AAAA, loopback));
+        }
+    }
+
+    let mut reply = reply_header(id, flags, 0, 1, records.len() as u16);
+    reply.extend_from_slice(question);
+    for (rtype, data) in records {
+        reply.extend_from_slice(&[0xC0, 12]); // Pointer to the question name
+        reply.extend_from_slice(&rtype.to_be_bytes());
+        reply.extend_from_slice(&CLASS_IN.to_be_bytes());
+        reply.extend_from_slice(&TTL.to_be_bytes());
+        reply.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        reply.extend_from_slice(&data);
+    }
+    Some(reply)
+}
+
+/// Answer queries on `socket` until it fails
+pub fn serve(socket: &UdpSocket, tlds: &[String]) -> std::io::Result<()> {
+    let mut buf = [0u8; 512];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(reply) = answer_query(&buf[..len], tlds) {
+            let _ = socket.send_to(&reply, peer);
+        }
+    }
+}
+
+/// Entry point of `servermark --dns-responder` (started by the user unit).
+/// TLDs are read once; changing them restarts the unit.
+pub fn run_dns_responder() -> i32 {
+    let tlds = load_sites_config().tlds();
+    let socket = match UdpSocket::bind(("127.0.0.1", RESPONDER_PORT)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Failed to bind 127.0.0.1:{}: {}", RESPONDER_PORT, e);
+            return 1;
+        }
+    };
+
+    println!(
+        "Answering {} on 127.0.0.1:{}",
+        tlds.iter()
+            .map(|tld| format!("*.{}", tld))
+            .collect::<Vec<_>>()
+            .join(", "),
+        RESPONDER_PORT
+    );
+    match serve(&socket, &tlds) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("DNS responder stopped: {}", e);
+            1
+        }
+    }
+}
+
+// ============================================================================
+// User unit
+// ============================================================================
+
+fn unit_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("systemd")
+        .join("user")
+        .join(RESPONDER_UNIT)
+}
+
+fn render_unit(exe: &str) -> String {
+    format!(
+        r#"[Unit]
+Description=ServerMark DNS responder
+After=network.target
+
+[Service]
+ExecStart="{exe}" --dns-responder
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+"#,
+        exe = exe
+    )
+}
+
+/// The binary the installed unit runs, None without a unit
+pub fn unit_binary() -> Option<PathBuf> {
+    let unit = fs::read_to_string(unit_path()).ok()?;
+    unit_exec(&unit)
+}
+
+fn unit_exec(unit: &str) -> Option<PathBuf> {
+    unit.lines()
+        .find_map(|line| line.strip_prefix("ExecStart="))
+        .and_then(|exec| exec.strip_suffix(" --dns-responder"))
+        .map(|exe| PathBuf::from(exe.trim_matches('"')))
+}
+
+pub fn responder_running() -> bool {
+    Command::new("systemctl")
+        .args(["--user", "is-active", RESPONDER_UNIT])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Install and (re)start the user unit, then check that it answers for the
+/// primary TLD
+pub fn enable_responder() -> Result<(), String> {
+    let exe = app_executable()?;
+    let path = unit_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, render_unit(&exe.to_string_lossy()))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    user_systemctl(&["daemon-reload"])?;
+    user_systemctl(&["enable", RESPONDER_UNIT])?;
+    user_systemctl(&["restart", RESPONDER_UNIT])?;
+
+    let probe = format!("servermark-probe.{}", load_sites_config().tld);
+    let mut last_error = String::new();
+    for _ in 0..5 {
+        match query_local_dns(RESPONDER_PORT, &probe) {
+            Ok(answers) if !answers.is_empty() => return Ok(()),
+            Ok(_) => last_error = format!("no address for {}", probe),
+            Err(e) => last_error = e,
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    Err(format!(
+        "The DNS responder is not answering: {}",
+        last_error
+    ))
+}
+
+pub fn disable_responder() -> Result<(), String> {
+    let path = unit_path();
+    if !path.exists() {
+        return Ok(());
+    }
+    let _ = user_systemctl(&["disable", "--now", RESPONDER_UNIT]);
+    fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    user_systemctl(&["daemon-reload"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_answer_query() {
+        let tlds = vec!["test".to_string(), "dev.internal".to_string()];
+
+        let reply = answer_query(&query("App.Test", TYPE_AAAA), &tlds).unwrap();
+        assert_eq!(&reply[..4], &[0x12, 0x34, 0x85, 0x00]); // Authoritative, no error
+        assert_eq!(&reply[6..8], &[0, 1]);
+        assert!(reply.ends_with(&[0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]));
+
+        let reply = answer_query(&query("api.shop.dev.internal", TYPE_ANY), &tlds).unwrap();
+        assert_eq!(&reply[6..8], &[0, 2]);
+
+        // Other record types exist but are empty
+        let reply = answer_query(&query("app.test", 15), &tlds).unwrap();
+        assert_eq!(reply[3] & 0x0F, 0);
+        assert_eq!(&reply[6..8], &[0, 0]);
+
+        // Nothing is forwarded
+        for name in ["example.com", "latest", "internal"] {
+            let reply = answer_query(&query(name, TYPE_A), &tlds).unwrap();
+            assert_eq!(reply[3] & 0x0F, RCODE_REFUSED as u8);
+        }
+
+        let mut response = query("app.test", TYPE_A);
+        response[2] |= 0x80;
+        assert!(answer_query(&response, &tlds).is_none());
+        assert!(answer_query(&query("app.test", TYPE_A)[..14], &tlds).is_none());
+    }
+
+    #[test]
+    fn test_unit_exec() {
+        let unit = render_unit("/home/me/My Apps/ServerMark.AppImage");
+        assert_eq!(
+            unit_exec(&unit),
+            Some(PathBuf::from("/home/me/My Apps/ServerMark.AppImage"))
+        );
+        assert_eq!(unit_exec("[Service]\n"), None);
+    }
+
+    #[test]
+    fn test_serve_answers_over_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        thread::spawn(move || serve(&socket, &["test".to_string()]));
+
+        assert_eq!(
+            query_local_dns(port, "myapp.test").unwrap(),
+            vec![std::net::Ipv4Addr::LOCALHOST]
+        );
+        assert!(query_local_dns(port, "example.com").unwrap().is_empty());
+    }
+}
@@ -1,5 +1,17 @@
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::catalog::preset_for_image;
+use super::compose::{LABEL_COMPOSE_PROJECT, LABEL_COMPOSE_SERVICE};
+use super::engine::{
+    demux_stream, encode, split_stream, ApiContainer, ApiContainerDetails, ApiPort, ApiStats,
+    ApiVersion, EngineClient,
+};
+use super::images::pull_with_progress;
+use super::ownership::{owned_containers, ownership_labels};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuntimeInfo {
@@ -17,339 +29,848 @@ pub struct Container {
     pub status: String,
     pub ports: Vec<PortMapping>,
     pub created: String,
+    pub health: Option<ContainerHealth>, // None when the container has no health check
+    pub labels: HashMap<String, String>,
+    pub mounts: Vec<ContainerMount>,
+    pub preset: Option<String>,
+    pub site: Option<String>, // Site id the container was created for
+    #[serde(default)]
+    pub adopted: bool, // Created elsewhere, managed on request
+    #[serde(default)]
+    pub compose_project: Option<String>,
+    #[serde(default)]
+    pub compose_service: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Health check state of a running container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerHealth {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+impl ContainerHealth {
+    /// Parse the `State.Health.Status` value of an inspect
+    fn from_state(state: &str) -> Option<Self> {
+        match state.trim() {
+            "starting" => Some(Self::Starting),
+            "healthy" => Some(Self::Healthy),
+            "unhealthy" => Some(Self::Unhealthy),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PortMapping {
     pub host: u16,
     pub container: u16,
     pub protocol: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerMount {
+    pub kind: String, // "volume" | "bind" | "tmpfs"
+    pub name: Option<String>,
+    pub source: String,
+    pub destination: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContainerStats {
+    pub id: String,
+    pub cpu_percent: f64,
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    pub network_rx: u64,
+    pub network_tx: u64,
+    pub block_read: u64,
+    pub block_write: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateContainerParams {
     pub image: String,
     pub name: String,
     pub ports: Vec<PortMapping>,
-    pub environment: std::collections::HashMap<String, String>,
+    pub environment: HashMap<String, String>,
     pub volumes: Option<Vec<VolumeMapping>>,
+    #[serde(default)]
+    pub health_cmd: Option<String>,
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Extra labels; the `servermark.managed` label is always added
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub memory_limit: Option<u64>, // Bytes
+    #[serde(default)]
+    pub cpu_limit: Option<f64>, // CPUs, e.g. 1.5
 }
 
+/// A named volume, or a host directory bind-mounted into the container
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VolumeMapping {
-    pub name: String,
+    pub name: String, // Volume name, or absolute host path for a bind mount
     pub container: String,
+    #[serde(default)]
+    pub kind: MountKind,
+    #[serde(default)]
+    pub read_only: bool,
 }
 
-/// Detect available container runtime (Docker or Podman)
-#[tauri::command]
-pub fn detect_container_runtime() -> Result<RuntimeInfo, String> {
-    // Try Docker first
-    if let Ok(output) = Command::new("docker")
-        .args(["version", "--format", "{{.Server.Version}}"])
-        .output()
-    {
-        if output.status.success() {
-            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-
-            // Get API version
-            let api_version = Command::new("docker")
-                .args(["version", "--format", "{{.Server.APIVersion}}"])
-                .output()
-                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
-                .unwrap_or_default();
-
-            return Ok(RuntimeInfo {
-                runtime: "docker".to_string(),
-                version,
-                api_version,
-                available: true,
-            });
-        }
-    }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MountKind {
+    #[default]
+    Volume,
+    Bind,
+}
 
-    // Try Podman
-    if let Ok(output) = Command::new("podman")
-        .args(["version", "--format", "{{.Version}}"])
-        .output()
-    {
-        if output.status.success() {
-            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-
-            return Ok(RuntimeInfo {
-                runtime: "podman".to_string(),
-                version,
-                api_version: String::new(),
-                available: true,
-            });
+impl VolumeMapping {
+    /// `source:target[:ro]` entry of `HostConfig.Binds`
+    fn bind_spec(&self) -> Result<String, String> {
+        match self.kind {
+            MountKind::Volume if self.name.is_empty() || self.name.contains('/') => {
+                Err(format!("Invalid volume name: {}", self.name))
+            }
+            MountKind::Bind if !self.name.starts_with('/') => Err(format!(
+                "Bind mount source must be an absolute path: {}",
+                self.name
+            )),
+            _ if !self.container.starts_with('/') => Err(format!(
+                "Mount target must be an absolute path: {}",
+                self.container
+            )),
+            _ => Ok(format!(
+                "{}:{}{}",
+                self.name,
+                self.container,
+                if self.read_only { ":ro" } else { "" }
+            )),
         }
     }
+}
+
+/// Detect available container runtime (Docker or Podman)
+#[tauri::command]
+pub fn detect_container_runtime() -> Result<RuntimeInfo, String> {
+    let Ok(client) = EngineClient::connect() else {
+        return Ok(RuntimeInfo {
+            runtime: "none".to_string(),
+            version: String::new(),
+            api_version: String::new(),
+            available: false,
+        });
+    };
+
+    let version: ApiVersion = client.get("/version")?;
+    let podman = version
+        .components
+        .iter()
+        .any(|c| c.name.to_lowercase().contains("podman"));
 
     Ok(RuntimeInfo {
-        runtime: "none".to_string(),
-        version: String::new(),
-        api_version: String::new(),
-        available: false,
+        runtime: if podman { "podman" } else { "docker" }.to_string(),
+        version: version.version,
+        api_version: version.api_version,
+        available: true,
     })
 }
 
-/// Get the container runtime command (docker or podman)
-fn get_runtime_cmd() -> Result<String, String> {
-    // Check Docker first
-    if Command::new("docker")
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
-    {
-        return Ok("docker".to_string());
+/// Published ports, once per mapping (the engine lists IPv4 and IPv6 bindings separately)
+fn container_ports(ports: &[ApiPort]) -> Vec<PortMapping> {
+    let mut mappings: Vec<PortMapping> = Vec::new();
+    for port in ports {
+        let Some(host) = port.public_port else {
+            continue;
+        };
+        let mapping = PortMapping {
+            host,
+            container: port.private_port,
+            protocol: port.protocol.clone(),
+        };
+        if !mappings.contains(&mapping) {
+            mappings.push(mapping);
+        }
+    }
+    mappings.sort_by_key(|p| (p.container, p.host));
+    mappings
+}
+
+/// Health from a status like "Up 2 minutes (healthy)" or "Up 3 seconds (health: starting)"
+fn parse_health(status: &str) -> Option<ContainerHealth> {
+    if status.contains("(health: starting)") {
+        Some(ContainerHealth::Starting)
+    } else if status.contains("(unhealthy)") {
+        Some(ContainerHealth::Unhealthy)
+    } else if status.contains("(healthy)") {
+        Some(ContainerHealth::Healthy)
+    } else {
+        None
     }
+}
 
-    // Check Podman
-    if Command::new("podman")
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
-    {
-        return Ok("podman".to_string());
+fn to_container(api: ApiContainer) -> Container {
+    let status = match api.state.as_str() {
+        "running" | "paused" | "restarting" => api.state.clone(),
+        _ => "stopped".to_string(),
+    };
+    Container {
+        name: api
+            .names
+            .first()
+            .map(|n| n.trim_start_matches('/').to_string())
+            .unwrap_or_default(),
+        status,
+        ports: container_ports(&api.ports),
+        created: chrono::DateTime::from_timestamp(api.created, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default(),
+        health: parse_health(&api.status.to_lowercase()),
+        mounts: api
+            .mounts
+            .into_iter()
+            .map(|m| ContainerMount {
+                kind: m.kind,
+                name: m.name,
+                source: m.source,
+                destination: m.destination,
+            })
+            .collect(),
+        id: api.id,
+        image: api.image,
+        compose_project: api.labels.get(LABEL_COMPOSE_PROJECT).cloned(),
+        compose_service: api.labels.get(LABEL_COMPOSE_SERVICE).cloned(),
+        labels: api.labels,
+        preset: None,
+        site: None,
+        adopted: false,
     }
+}
 
-    Err("No container runtime found. Please install Docker or Podman.".to_string())
+/// Containers matching the engine's list filters
+pub fn query_containers(client: &EngineClient, filters: Value) -> Result<Vec<Container>, String> {
+    let containers: Vec<ApiContainer> = client.get(&format!(
+        "/containers/json?all=1&filters={}",
+        encode(&filters.to_string())
+    ))?;
+    Ok(containers.into_iter().map(to_container).collect())
 }
 
-/// List all ServerMark containers
+/// List all ServerMark containers: labelled at creation, or adopted
 #[tauri::command]
 pub fn list_containers() -> Result<Vec<Container>, String> {
-    let runtime = get_runtime_cmd()?;
-
-    let output = Command::new(&runtime)
-        .args([
-            "ps",
-            "-a",
-            "--filter",
-            "name=servermark-",
-            "--format",
-            "{{.ID}}|{{.Names}}|{{.Image}}|{{.Status}}|{{.Ports}}|{{.CreatedAt}}",
-        ])
-        .output()
-        .map_err(|e| format!("Failed to list containers: {}", e))?;
-
-    if !output.status.success() {
-        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    owned_containers(&EngineClient::connect()?)
+}
+
+/// The engine's smallest accepted memory limit
+const MIN_MEMORY_LIMIT: u64 = 6 * 1024 * 1024;
+
+fn nano_cpus(cpus: f64) -> u64 {
+    (cpus * 1e9).round() as u64
+}
+
+/// Reject limits the engine would refuse or read as "unlimited"
+fn check_limits(memory_limit: Option<u64>, cpu_limit: Option<f64>) -> Result<(), String> {
+    if memory_limit.is_some_and(|memory| memory < MIN_MEMORY_LIMIT) {
+        return Err("The memory limit must be at least 6 MB".to_string());
     }
+    if cpu_limit.is_some_and(|cpus| cpus <= 0.0) {
+        return Err("The CPU limit must be positive".to_string());
+    }
+    Ok(())
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut containers = Vec::new();
+/// Body of `POST /containers/{id}/update`. Swap is capped at twice the
+/// memory limit, the engine's default for a limit set at creation.
+fn limits_body(memory_limit: Option<u64>, cpu_limit: Option<f64>) -> Result<Value, String> {
+    check_limits(memory_limit, cpu_limit)?;
+    let mut body = json!({});
+    if let Some(memory) = memory_limit {
+        body["Memory"] = json!(memory);
+        body["MemorySwap"] = json!(memory * 2);
+    }
+    if let Some(cpus) = cpu_limit {
+        body["NanoCpus"] = json!(nano_cpus(cpus));
+    }
+    if body.as_object().is_some_and(|b| b.is_empty()) {
+        return Err("No limit to update".to_string());
+    }
+    Ok(body)
+}
 
-    for line in stdout.lines() {
-        if line.is_empty() {
-            continue;
-        }
+/// Change the memory and CPU limits of a container without recreating it;
+/// unset limits are left as they are
+#[tauri::command]
+pub fn update_container_limits(
+    id: String,
+    memory_limit: Option<u64>,
+    cpu_limit: Option<f64>,
+) -> Result<(), String> {
+    let body = limits_body(memory_limit, cpu_limit)?;
+    EngineClient::connect()?
+        .call(
+            "POST",
+            &format!("/containers/{}/update", encode(&id)),
+            Some(&body),
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Failed to update container limits: {}", e))
+}
 
-        let parts: Vec<&str> = line.split('|').collect();
-        if parts.len() >= 5 {
-            let status_str = parts[3].to_lowercase();
-            let status = if status_str.contains("up") {
-                "running"
-            } else if status_str.contains("exited") {
-                "stopped"
-            } else if status_str.contains("paused") {
-                "paused"
-            } else {
-                "stopped"
-            };
-
-            // Parse ports (simplified)
-            let ports = parse_ports(parts[4]);
-
-            containers.push(Container {
-                id: parts[0].to_string(),
-                name: parts[1].to_string(),
-                image: parts[2].to_string(),
-                status: status.to_string(),
-                ports,
-                created: parts.get(5).unwrap_or(&"").to_string(),
-            });
-        }
+/// Labels stamped on the container and its named volumes
+fn container_labels(params: &CreateContainerParams) -> HashMap<String, String> {
+    let mut labels = params.labels.clone();
+    labels.extend(ownership_labels(None, None));
+    labels
+}
+
+/// Body of `POST /containers/create`
+fn create_body(params: &CreateContainerParams) -> Result<Value, String> {
+    let mut exposed = serde_json::Map::new();
+    let mut bindings = serde_json::Map::new();
+    for port in &params.ports {
+        let key = format!("{}/{}", port.container, port.protocol);
+        exposed.insert(key.clone(), json!({}));
+        bindings.insert(key, json!([{ "HostPort": port.host.to_string() }]));
     }
 
-    Ok(containers)
+    let mut env: Vec<String> = params
+        .environment
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    env.sort();
+
+    check_limits(params.memory_limit, params.cpu_limit)?;
+
+    let binds = params
+        .volumes
+        .iter()
+        .flatten()
+        .map(VolumeMapping::bind_spec)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut body = json!({
+        "Image": params.image,
+        "Env": env,
+        "Labels": container_labels(params),
+        "ExposedPorts": exposed,
+        "HostConfig": {
+            "PortBindings": bindings,
+            "Binds": binds,
+            "RestartPolicy": { "Name": "unless-stopped" },
+        },
+    });
+    if !params.command.is_empty() {
+        body["Cmd"] = json!(params.command);
+    }
+    if let Some(memory) = params.memory_limit {
+        body["HostConfig"]["Memory"] = json!(memory);
+    }
+    if let Some(cpus) = params.cpu_limit {
+        body["HostConfig"]["NanoCpus"] = json!(nano_cpus(cpus));
+    }
+    if let Some(health_cmd) = &params.health_cmd {
+        body["Healthcheck"] = json!({
+            "Test": ["CMD-SHELL", health_cmd],
+            "Interval": 5_000_000_000u64,
+            "Retries": 10,
+        });
+    }
+    Ok(body)
 }
 
-/// Parse Docker port string like "0.0.0.0:3306->3306/tcp"
-fn parse_ports(port_str: &str) -> Vec<PortMapping> {
-    let mut ports = Vec::new();
+/// Split an image reference into repository and tag (`latest` when unset)
+pub fn image_tag(image: &str) -> (&str, &str) {
+    let name_start = image.rfind('/').map_or(0, |slash| slash + 1);
+    match image[name_start..].rfind(':') {
+        Some(colon) => (
+            &image[..name_start + colon],
+            &image[name_start + colon + 1..],
+        ),
+        None => (image, "latest"),
+    }
+}
 
-    for mapping in port_str.split(',') {
-        let mapping = mapping.trim();
-        if mapping.is_empty() {
-            continue;
-        }
+/// Pull an image, blocking until done
+pub fn pull_image_blocking(client: &EngineClient, image: &str) -> Result<(), String> {
+    pull_with_progress(client, image, |_| {})
+}
 
-        // Format: 0.0.0.0:3306->3306/tcp or :::3306->3306/tcp
-        if let Some(arrow_pos) = mapping.find("->") {
-            let host_part = &mapping[..arrow_pos];
-            let container_part = &mapping[arrow_pos + 2..];
-
-            // Extract host port
-            let host_port = host_part
-                .rsplit(':')
-                .next()
-                .and_then(|p| p.parse::<u16>().ok());
-
-            // Extract container port and protocol
-            let (container_port, protocol) = if let Some(slash_pos) = container_part.find('/') {
-                (
-                    container_part[..slash_pos].parse::<u16>().ok(),
-                    &container_part[slash_pos + 1..],
-                )
-            } else {
-                (container_part.parse::<u16>().ok(), "tcp")
-            };
-
-            if let (Some(hp), Some(cp)) = (host_port, container_port) {
-                ports.push(PortMapping {
-                    host: hp,
-                    container: cp,
-                    protocol: protocol.to_string(),
-                });
-            }
+/// Create and start a container, pulling its image first when missing
+pub fn create_container_with(
+    client: &EngineClient,
+    params: &CreateContainerParams,
+) -> Result<String, String> {
+    let body = create_body(params)?;
+
+    // Named volumes are created up front so they carry the labels too
+    for volume in params.volumes.iter().flatten() {
+        if volume.kind == MountKind::Volume {
+            client.call(
+                "POST",
+                "/volumes/create",
+                Some(&json!({ "Name": volume.name, "Labels": container_labels(params) })),
+            )?;
         }
     }
 
-    ports
+    let path = format!("/containers/create?name={}", encode(&params.name));
+
+    let mut response = client.request("POST", &path, Some(&body))?;
+    if response.status == 404 {
+        pull_image_blocking(client, &params.image)?;
+        response = client.request("POST", &path, Some(&body))?;
+    }
+    if response.status >= 400 {
+        return Err(super::engine::api_error(&response));
+    }
+
+    let id = response.json::<Value>()?["Id"]
+        .as_str()
+        .ok_or("Container created without an id")?
+        .to_string();
+    client.call("POST", &format!("/containers/{}/start", encode(&id)), None)?;
+    Ok(id)
 }
 
-/// Create and start a new container
+/// Create and start a new container. Off the main thread, as a missing image
+/// is pulled first; call `pull_image` beforehand to show its progress.
 #[tauri::command]
-pub fn create_container(params: CreateContainerParams) -> Result<String, String> {
-    let runtime = get_runtime_cmd()?;
+pub async fn create_container(params: CreateContainerParams) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        create_container_with(&EngineClient::connect()?, &params)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    let mut args = vec![
-        "run".to_string(),
-        "-d".to_string(),
-        "--name".to_string(),
-        params.name.clone(),
-    ];
+pub fn inspect_container(client: &EngineClient, id: &str) -> Result<ApiContainerDetails, String> {
+    client.get(&format!("/containers/{}/json", encode(id)))
+}
 
-    // Add port mappings
-    for port in &params.ports {
-        args.push("-p".to_string());
-        args.push(format!(
-            "{}:{}/{}",
-            port.host, port.container, port.protocol
-        ));
-    }
+#[derive(Debug, Clone)]
+pub struct ExecOutput {
+    pub exit_code: i64,
+    pub stdout: String,
+    pub stderr: String,
+}
 
-    // Add environment variables
-    for (key, value) in &params.environment {
-        args.push("-e".to_string());
-        args.push(format!("{}={}", key, value));
-    }
+/// Run a command inside a running container
+pub fn exec_in_container(
+    client: &EngineClient,
+    id: &str,
+    cmd: &[&str],
+) -> Result<ExecOutput, String> {
+    exec_in_container_with_env(client, id, cmd, &[])
+}
 
-    // Add volumes
-    if let Some(volumes) = &params.volumes {
-        for vol in volumes {
-            args.push("-v".to_string());
-            args.push(format!("{}:{}", vol.name, vol.container));
+/// `exec_in_container` with extra `KEY=value` environment entries, which
+/// keeps secrets off the command line
+pub fn exec_in_container_with_env(
+    client: &EngineClient,
+    id: &str,
+    cmd: &[&str],
+    env: &[String],
+) -> Result<ExecOutput, String> {
+    let created = client.call(
+        "POST",
+        &format!("/containers/{}/exec", encode(id)),
+        Some(&json!({ "Cmd": cmd, "Env": env, "AttachStdout": true, "AttachStderr": true })),
+    )?;
+    let exec_id = created.json::<Value>()?["Id"]
+        .as_str()
+        .ok_or("Exec created without an id")?
+        .to_string();
+
+    let output = client.call(
+        "POST",
+        &format!("/exec/{}/start", encode(&exec_id)),
+        Some(&json!({ "Detach": false, "Tty": false })),
+    )?;
+    let exec: Value = client.get(&format!("/exec/{}/json", encode(&exec_id)))?;
+    let (stdout, stderr) = split_stream(&output.body);
+    Ok(ExecOutput {
+        exit_code: exec["ExitCode"].as_i64().unwrap_or(-1),
+        stdout,
+        stderr,
+    })
+}
+
+/// Run the catalog probe for a container's image inside it. `None` when the
+/// image has no probe.
+fn run_preset_probe(client: &EngineClient, id: &str, image: &str) -> Option<bool> {
+    let probe = preset_for_image(image)?.health_check?;
+    Some(
+        exec_in_container(client, id, &["sh", "-c", probe])
+            .map(|output| output.exit_code == 0)
+            .unwrap_or(false),
+    )
+}
+
+/// Block until a container accepts connections: its health check passes, or
+/// for containers without one, the catalog probe for its image succeeds.
+/// Containers with neither are ready once running.
+pub fn wait_until_ready(id: &str, timeout: Duration) -> Result<(), String> {
+    let client = EngineClient::connect()?;
+    let started = Instant::now();
+
+    loop {
+        let details = inspect_container(&client, id)?;
+        let health = details
+            .state
+            .health
+            .as_ref()
+            .and_then(|h| ContainerHealth::from_state(&h.status));
+
+        match (details.state.status.as_str(), health) {
+            ("exited" | "dead", _) => {
+                return Err(format!("Container {} exited, check its logs", id))
+            }
+            ("running", Some(ContainerHealth::Healthy)) => return Ok(()),
+            ("running", Some(ContainerHealth::Unhealthy)) => {
+                return Err(format!("Container {} is unhealthy, check its logs", id))
+            }
+            ("running", None)
+                if run_preset_probe(&client, id, &details.config.image).unwrap_or(true) =>
+            {
+                return Ok(())
+            }
+            _ => {}
+        }
+
+        if started.elapsed() >= timeout {
+            return Err(format!(
+                "Container {} was not ready after {} seconds",
+                id,
+                timeout.as_secs()
+            ));
         }
+        thread::sleep(Duration::from_secs(1));
     }
+}
 
-    // Add restart policy
-    args.push("--restart".to_string());
-    args.push("unless-stopped".to_string());
+/// Wait for a container to be ready, e.g. before creating databases on a
+/// freshly started MySQL
+#[tauri::command]
+pub async fn wait_for_container_ready(id: String, timeout: Option<u64>) -> Result<(), String> {
+    let timeout = Duration::from_secs(timeout.unwrap_or(60));
+    tauri::async_runtime::spawn_blocking(move || wait_until_ready(&id, timeout))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-    // Add image
-    args.push(params.image);
+impl ContainerStats {
+    /// Usage from a stats sample, computed the way `docker stats` does
+    pub fn from_api(id: &str, stats: &ApiStats) -> Self {
+        let cpu_delta = stats
+            .cpu_stats
+            .cpu_usage
+            .total_usage
+            .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+        let system_delta = stats
+            .cpu_stats
+            .system_cpu_usage
+            .saturating_sub(stats.precpu_stats.system_cpu_usage);
+        let cpus = match stats.cpu_stats.online_cpus {
+            0 => stats.cpu_stats.cpu_usage.percpu_usage.len().max(1) as f64,
+            n => n as f64,
+        };
+        let cpu_percent = if system_delta > 0 {
+            cpu_delta as f64 / system_delta as f64 * cpus * 100.0
+        } else {
+            0.0
+        };
+
+        // Page cache is reclaimable: `cache` on cgroup v1, `inactive_file` on v2
+        let memory = &stats.memory_stats;
+        let cache = memory
+            .stats
+            .get("inactive_file")
+            .or_else(|| memory.stats.get("cache"))
+            .copied()
+            .unwrap_or(0);
+
+        let io = |op: &str| {
+            stats
+                .blkio_stats
+                .io_service_bytes_recursive
+                .iter()
+                .filter(|e| e.op.eq_ignore_ascii_case(op))
+                .map(|e| e.value)
+                .sum()
+        };
+
+        Self {
+            id: id.to_string(),
+            cpu_percent,
+            memory_usage: memory.usage.saturating_sub(cache),
+            memory_limit: memory.limit,
+            network_rx: stats.networks.values().map(|n| n.rx_bytes).sum(),
+            network_tx: stats.networks.values().map(|n| n.tx_bytes).sum(),
+            block_read: io("read"),
+            block_write: io("write"),
+        }
+    }
+}
 
-    let output = Command::new(&runtime)
-        .args(&args)
-        .output()
-        .map_err(|e| format!("Failed to create container: {}", e))?;
+pub fn container_stats(client: &EngineClient, id: &str) -> Result<ContainerStats, String> {
+    let stats: ApiStats = client.get(&format!("/containers/{}/stats?stream=false", encode(id)))?;
+    Ok(ContainerStats::from_api(id, &stats))
+}
 
-    if output.status.success() {
-        let container_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        Ok(container_id)
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
-    }
+/// One sample of a container's resource usage
+#[tauri::command]
+pub fn get_container_stats(id: String) -> Result<ContainerStats, String> {
+    container_stats(&EngineClient::connect()?, &id)
 }
 
 /// Start a container
 #[tauri::command]
 pub fn start_container(id: String) -> Result<(), String> {
-    let runtime = get_runtime_cmd()?;
-
-    let output = Command::new(&runtime)
-        .args(["start", &id])
-        .output()
-        .map_err(|e| format!("Failed to start container: {}", e))?;
-
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
-    }
+    EngineClient::connect()?
+        .call("POST", &format!("/containers/{}/start", encode(&id)), None)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to start container: {}", e))
 }
 
 /// Stop a container
 #[tauri::command]
 pub fn stop_container(id: String) -> Result<(), String> {
-    let runtime = get_runtime_cmd()?;
-
-    let output = Command::new(&runtime)
-        .args(["stop", &id])
-        .output()
-        .map_err(|e| format!("Failed to stop container: {}", e))?;
-
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
-    }
+    EngineClient::connect()?
+        .call("POST", &format!("/containers/{}/stop", encode(&id)), None)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to stop container: {}", e))
 }
 
 /// Remove a container
 #[tauri::command]
 pub fn remove_container(id: String, force: bool) -> Result<(), String> {
-    let runtime = get_runtime_cmd()?;
+    EngineClient::connect()?
+        .call(
+            "DELETE",
+            &format!("/containers/{}?force={}", encode(&id), force),
+            None,
+        )
+        .map(|_| ())
+        .map_err(|e| format!("Failed to remove container: {}", e))
+}
 
-    let mut args = vec!["rm"];
-    if force {
-        args.push("-f");
+/// Get container logs
+#[tauri::command]
+pub fn get_container_logs(id: String, lines: Option<u32>) -> Result<String, String> {
+    let response = EngineClient::connect()?
+        .call(
+            "GET",
+            &format!(
+                "/containers/{}/logs?stdout=1&stderr=1&tail={}",
+                encode(&id),
+                lines.unwrap_or(100)
+            ),
+            None,
+        )
+        .map_err(|e| format!("Failed to get logs: {}", e))?;
+    // Combined stdout and stderr (logs can be on either)
+    Ok(demux_stream(&response.body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine::FakeEngine;
+    use super::super::ownership::{LABEL_MANAGED, LABEL_PRESET};
+    use super::*;
+
+    #[test]
+    fn test_parse_health() {
+        assert_eq!(
+            parse_health("up 3 seconds (health: starting)"),
+            Some(ContainerHealth::Starting)
+        );
+        assert_eq!(
+            parse_health("up 2 minutes (healthy)"),
+            Some(ContainerHealth::Healthy)
+        );
+        assert_eq!(
+            parse_health("up 5 minutes (unhealthy)"),
+            Some(ContainerHealth::Unhealthy)
+        );
+        assert_eq!(parse_health("up 2 hours"), None);
+        assert_eq!(parse_health("exited (0) 3 hours ago"), None);
     }
-    args.push(&id);
 
-    let output = Command::new(&runtime)
-        .args(&args)
-        .output()
-        .map_err(|e| format!("Failed to remove container: {}", e))?;
+    #[test]
+    fn test_list_containers_from_api() {
+        let engine = FakeEngine::start(vec![(
+            "GET",
+            "/containers/json",
+            200,
+            r#"[{"Id":"abc","Names":["/servermark-mysql"],"Image":"mysql:8.0","State":"running",
+                "Status":"Up 2 minutes (healthy)","Created":1700000000,
+                "Ports":[{"IP":"0.0.0.0","PrivatePort":3306,"PublicPort":3306,"Type":"tcp"},
+                         {"IP":"::","PrivatePort":3306,"PublicPort":3306,"Type":"tcp"},
+                         {"PrivatePort":33060,"Type":"tcp"}],
+                "Labels":{"a|b":"c"},
+                "Mounts":[{"Type":"volume","Name":"servermark-mysql-mysql_data",
+                           "Source":"/var/lib/docker/volumes/x","Destination":"/var/lib/mysql"}]},
+               {"Id":"def","Names":["/servermark-a|b"],"Image":"redis","State":"exited",
+                "Status":"Exited (0) 1 hour ago","Created":1700000000,"Ports":null,"Labels":null,"Mounts":null}]"#
+                .to_string(),
+        )]);
+
+        let containers =
+            query_containers(&engine.client, json!({ "name": ["servermark-"] })).unwrap();
+        assert_eq!(containers.len(), 2);
+        let mysql = &containers[0];
+        assert_eq!(mysql.name, "servermark-mysql");
+        assert_eq!(mysql.status, "running");
+        assert_eq!(mysql.health, Some(ContainerHealth::Healthy));
+        assert_eq!(
+            mysql.ports,
+            vec![PortMapping {
+                host: 3306,
+                container: 3306,
+                protocol: "tcp".to_string(),
+            }]
+        );
+        assert_eq!(mysql.labels["a|b"], "c");
+        assert_eq!(mysql.mounts[0].destination, "/var/lib/mysql");
+        assert_eq!(containers[1].name, "servermark-a|b");
+        assert_eq!(containers[1].status, "stopped");
+        assert!(containers[1].ports.is_empty());
+
+        assert!(engine.requests()[0].starts_with(
+            "GET /containers/json?all=1&filters=%7B%22name%22%3A%5B%22servermark-%22%5D%7D"
+        ));
+    }
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
+    #[test]
+    fn test_create_container_pulls_missing_image() {
+        let engine = FakeEngine::start(vec![
+            (
+                "POST",
+                "/images/create",
+                200,
+                r#"{"status":"Pulling"}"#.to_string(),
+            ),
+            ("POST", "/containers/abc/start", 204, String::new()),
+        ]);
+        let params = CreateContainerParams {
+            image: "valkey/valkey:8-alpine".to_string(),
+            name: "servermark-valkey".to_string(),
+            ports: vec![PortMapping {
+                host: 6380,
+                container: 6379,
+                protocol: "tcp".to_string(),
+            }],
+            environment: HashMap::new(),
+            volumes: None,
+            health_cmd: Some("valkey-cli ping".to_string()),
+            command: Vec::new(),
+            labels: HashMap::from([(LABEL_PRESET.to_string(), "valkey".to_string())]),
+            memory_limit: Some(512 * 1024 * 1024),
+            cpu_limit: Some(1.5),
+        };
+
+        // The fake has no create route, so the image counts as missing
+        let err = create_container_with(&engine.client, &params).unwrap_err();
+        assert_eq!(err, "not found");
+        let requests = engine.requests();
+        assert!(requests[0].starts_with("POST /containers/create?name=servermark-valkey {"));
+        assert_eq!(
+            requests[1],
+            "POST /images/create?fromImage=valkey%2Fvalkey&tag=8-alpine "
+        );
+
+        let body = create_body(&params).unwrap();
+        assert_eq!(
+            body["HostConfig"]["PortBindings"]["6379/tcp"][0]["HostPort"],
+            "6380"
+        );
+        assert_eq!(body["Healthcheck"]["Test"][1], "valkey-cli ping");
+        assert!(body.get("Cmd").is_none());
+        assert_eq!(body["Labels"][LABEL_MANAGED], "true");
+        assert_eq!(body["Labels"][LABEL_PRESET], "valkey");
+        assert_eq!(body["HostConfig"]["Memory"], 536870912);
+        assert_eq!(body["HostConfig"]["NanoCpus"], 1_500_000_000u64);
+
+        let zero_cpus = CreateContainerParams {
+            cpu_limit: Some(0.0),
+            ..params.clone()
+        };
+        assert!(create_body(&zero_cpus).is_err());
+        let tiny_memory = CreateContainerParams {
+            memory_limit: Some(1024),
+            ..params
+        };
+        assert!(create_body(&tiny_memory).is_err());
     }
-}
 
-/// Get container logs
-#[tauri::command]
-pub fn get_container_logs(id: String, lines: Option<u32>) -> Result<String, String> {
-    let runtime = get_runtime_cmd()?;
+    #[test]
+    fn test_volume_bind_specs() {
+        let mount = |name: &str, kind, read_only| VolumeMapping {
+            name: name.to_string(),
+            container: "/data".to_string(),
+            kind,
+            read_only,
+        };
+        assert_eq!(
+            mount("servermark-mysql-data", MountKind::Volume, false).bind_spec(),
+            Ok("servermark-mysql-data:/data".to_string())
+        );
+        assert_eq!(
+            mount("/home/me/seed", MountKind::Bind, true).bind_spec(),
+            Ok("/home/me/seed:/data:ro".to_string())
+        );
+        assert!(mount("./seed", MountKind::Bind, false).bind_spec().is_err());
+        assert!(mount("/home/me/seed", MountKind::Volume, false)
+            .bind_spec()
+            .is_err());
+    }
 
-    let lines_str = lines.unwrap_or(100).to_string();
-    let output = Command::new(&runtime)
-        .args(["logs", "--tail", &lines_str, &id])
-        .output()
-        .map_err(|e| format!("Failed to get logs: {}", e))?;
+    #[test]
+    fn test_limits_body() {
+        let body = limits_body(Some(1024 * 1024 * 1024), None).unwrap();
+        assert_eq!(body["Memory"], 1073741824);
+        assert_eq!(body["MemorySwap"], 2147483648u64);
+        assert!(body.get("NanoCpus").is_none());
+        assert_eq!(
+            limits_body(None, Some(0.5)).unwrap()["NanoCpus"],
+            500_000_000
+        );
+        assert!(limits_body(None, None).is_err());
+        assert!(limits_body(Some(1024), None).is_err());
+        assert!(limits_body(None, Some(0.0)).is_err());
+    }
 
-    if output.status.success() {
-        // Combine stdout and stderr (logs can be on either)
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Ok(format!("{}{}", stdout, stderr))
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
+    #[test]
+    fn test_image_tag() {
+        assert_eq!(image_tag("mysql:8.0"), ("mysql", "8.0"));
+        assert_eq!(image_tag("mysql"), ("mysql", "latest"));
+        assert_eq!(
+            image_tag("localhost:5000/app/mysql"),
+            ("localhost:5000/app/mysql", "latest")
+        );
+    }
+
+    #[test]
+    fn test_stats_from_api() {
+        let stats: ApiStats = serde_json::from_str(
+            r#"{"cpu_stats":{"cpu_usage":{"total_usage":400},"system_cpu_usage":2000,"online_cpus":4},
+                "precpu_stats":{"cpu_usage":{"total_usage":200},"system_cpu_usage":1000},
+                "memory_stats":{"usage":1000,"limit":4000,"stats":{"inactive_file":200}},
+                "networks":{"eth0":{"rx_bytes":10,"tx_bytes":20},"eth1":{"rx_bytes":1,"tx_bytes":2}},
+                "blkio_stats":{"io_service_bytes_recursive":[{"op":"Read","value":5},{"op":"write","value":7}]}}"#,
+        )
+        .unwrap();
+        let usage = ContainerStats::from_api("abc", &stats);
+        assert_eq!(usage.cpu_percent, 80.0);
+        assert_eq!(usage.memory_usage, 800);
+        assert_eq!(usage.network_rx, 11);
+        assert_eq!(usage.network_tx, 22);
+        assert_eq!((usage.block_read, usage.block_write), (5, 7));
     }
 }
@@ -0,0 +1,552 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::{Ipv4Addr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use super::backends::{backend, ListenPorts, WebServerKind};
+use super::diagnostics::{php_fpm_socket, unit_enabled, uses_php, DiagnosticCheck};
+use super::dns::{detect_dns, DnsServer, DnsStatus};
+use super::dns_responder::unit_binary;
+use super::docker::{detect_container_runtime, RuntimeInfo};
+use super::helper::{helper_available, helper_request, validate_managed_path, HelperOp};
+use super::php::{get_php_versions, PhpVersion};
+use super::ports::{is_managed_server, port_owners, PortOwner};
+use super::sites::{load_sites_config, SitesConfig};
+use super::system::{
+    detect_native_services, detect_system, detect_web_server, NativeServicesStatus, SystemInfo,
+    WebServerStatus,
+};
+use super::webserver::load_webserver_config;
+
+/// Environment-wide health report
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub system: Option<SystemInfo>,
+    pub web_server: WebServerStatus,
+    pub dns: DnsStatus,
+    pub native_services: NativeServicesStatus,
+    pub php_versions: Vec<PhpVersion>,
+    pub container_runtime: Option<RuntimeInfo>,
+    pub healthy: bool,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+/// Build a minimal DNS query for an A record
+fn build_dns_query(id: u16, name: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(32 + name.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&[0x01, 0x00]); // Recursion desired
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // One question
+    for label in name.trim_end_matches('.').split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&[0, 1, 0, 1]); // Type A, class IN
+    packet
+}
+
+/// Extract IPv4 answers from a DNS response
+fn parse_dns_answers(packet: &[u8]) -> Vec<Ipv4Addr> {
+    let read_u16 = |at: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]))
+    };
+    // Skip a (possibly compressed) name starting at `at`
+    let skip_name = |mut at: usize| -> Option<usize> {
+        loop {
+            let len = *packet.get(at)? as usize;
+            if len == 0 {
+                return Some(at + 1);
+            }
+            if len & 0xC0 == 0xC0 {
+                return Some(at + 2);
+            }
+            at += len + 1;
+        }
+    };
+
+    let mut answers = Vec::new();
+    let (Some(questions), Some(count)) = (read_u16(4), read_u16(6)) else {
+        return answers;
+    };
+
+    let mut at = 12;
+    for _ in 0..questions {
+        match skip_name(at) {
+            Some(next) => at = next + 4,
+            None => return answers,
+        }
+    }
+
+    for _ in 0..count {
+        let Some(next) = skip_name(at) else { break };
+        let (Some(rtype), Some(len)) = (read_u16(next), read_u16(next + 8)) else {
+            break;
+        };
+        let data = next + 10;
+        if rtype == 1 && len == 4 {
+            if let Some(ip) = packet.get(data..data + 4) {
+                answers.push(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]));
+            }
+        }
+        at = data + len as usize;
+    }
+
+    answers
+}
+
+/// Ask a DNS server on localhost for the A records of `name`
+pub fn query_local_dns(port: u16, name: &str) -> Result<Vec<Ipv4Addr>, String> {
+    let socket = UdpSocket::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .map_err(|e| e.to_string())?;
+    socket
+        .send_to(&build_dns_query(0x5eed, name), ("127.0.0.1", port))
+        .map_err(|e| e.to_string())?;
+
+    let mut buf = [0u8; 512];
+    let (len, _) = socket
+        .recv_from(&mut buf)
+        .map_err(|e| format!("no answer: {}", e))?;
+    Ok(parse_dns_answers(&buf[..len]))
+}
+
+/// Generated configs in the web server directories that no longer match a site
+pub fn stale_site_configs(config: &SitesConfig) -> Vec<String> {
+    let known = |name: &str| config.sites.iter().any(|s| s.name == name);
+    let mut stale = Vec::new();
+
+    for kind in WebServerKind::ALL {
+        let server = backend(kind);
+        for dir in server.site_dirs() {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let file = entry.file_name().to_string_lossy().to_string();
+                let path = entry.path().to_string_lossy().to_string();
+                // Only files the helper would accept, which also keeps odd
+                // names out of the removal script
+                if validate_managed_path(&path).is_err() {
+                    continue;
+                }
+                if let Some(name) = server.site_name_from_file(&file) {
+                    if !known(name) {
+                        stale.push(path);
+                    }
+                }
+            }
+        }
+    }
+
+    stale.sort();
+    stale
+}
+
+fn server_running(web: &WebServerStatus, kind: WebServerKind) -> bool {
+    match kind {
+        WebServerKind::Caddy => web.caddy_running,
+        WebServerKind::Nginx => web.nginx_running,
+        WebServerKind::Apache => web.apache_running,
+    }
+}
+
+/// Whether anything other than the active web server holds its ports
+fn check_port_owners(owners: &[PortOwner], active: WebServerKind) -> DiagnosticCheck {
+    let conflicts: Vec<&PortOwner> = owners
+        .iter()
+        .filter(|owner| !is_managed_server(owner, &[active]))
+        .collect();
+    if conflicts.is_empty() {
+        return DiagnosticCheck::pass(
+            "port_conflicts",
+            "Web server ports",
+            format!("No other service holds the {} ports", active),
+        );
+    }
+
+    let described: Vec<String> = conflicts
+        .iter()
+        .map(|owner| format!("port {} is used by {}", owner.port, owner.describe()))
+        .collect();
+    let inactive: Vec<WebServerKind> = WebServerKind::ALL
+        .into_iter()
+        .filter(|k| *k != active)
+        .collect();
+    let check = DiagnosticCheck::fail(
+        "port_conflicts",
+        "Web server ports",
+        described.join(", "),
+        "Stop the conflicting service, or switch ServerMark to alternative ports",
+    );
+    // Inactive web servers can be stopped in one go
+    if conflicts
+        .iter()
+        .all(|owner| is_managed_server(owner, &inactive))
+    {
+        check.with_fix("disable_inactive_server")
+    } else {
+        check
+    }
+}
+
+fn check_port_conflicts(
+    web: &WebServerStatus,
+    active: WebServerKind,
+    ports: ListenPorts,
+) -> Vec<DiagnosticCheck> {
+    let mut checks = vec![check_port_owners(
+        &port_owners(&[ports.http, ports.https]),
+        active,
+    )];
+
+    let running: Vec<&str> = WebServerKind::ALL
+        .into_iter()
+        .filter(|k| server_running(web, *k))
+        .map(|k| k.as_str())
+        .collect();
+    let enabled: Vec<&str> = WebServerKind::ALL
+        .into_iter()
+        .filter(|k| unit_enabled(backend(*k).service()))
+        .map(|k| k.as_str())
+        .collect();
+
+    if running.len() > 1 {
+        checks.push(
+            DiagnosticCheck::fail(
+                "both_servers_running",
+                "Web servers",
+                format!("Several web servers are running: {}", running.join(", ")),
+                "Only the active web server should run",
+            )
+            .with_fix("disable_inactive_server"),
+        );
+    } else if enabled.len() > 1 {
+        checks.push(
+            DiagnosticCheck::warn(
+                "both_servers_enabled",
+                "Web servers",
+                format!(
+                    "Several web servers are enabled at boot: {}",
+                    enabled.join(", ")
+                ),
+                "The one that starts first takes port 80; disable the inactive servers",
+            )
+            .with_fix("disable_inactive_server"),
+        );
+    }
+
+    checks.push(if server_running(web, active) {
+        DiagnosticCheck::pass(
+            "active_server",
+            "Active web server",
+            format!("{} is running", active),
+        )
+    } else {
+        DiagnosticCheck::fail(
+            "active_server",
+            "Active web server",
+            format!("{} is not running", active),
+            "Start the active web server",
+        )
+        .with_fix("start_web_server")
+    });
+
+    checks
+}
+
+fn check_dns_answers(dns: &DnsStatus, tld: &str) -> DiagnosticCheck {
+    let builtin = dns.server == DnsServer::Builtin;
+    if !builtin && !dns.dnsmasq_installed {
+        return DiagnosticCheck::warn(
+            "dns",
+            "DNS",
+            "dnsmasq is not installed",
+            "Install DNS from the settings page so new sites resolve without hosts entries",
+        );
+    }
+
+    let probe = format!("servermark-doctor.{}", tld);
+
+    // NetworkManager runs its own dnsmasq; ask through the system resolver
+    let Some(port) = dns.port else {
+        let loopback = (probe.as_str(), 80)
+            .to_socket_addrs()
+            .map(|mut addrs| addrs.any(|a| a.ip().is_loopback()))
+            .unwrap_or(false);
+        return if loopback {
+            DiagnosticCheck::pass(
+                "dns",
+                "DNS",
+                format!("NetworkManager's dnsmasq answers *.{}", tld),
+            )
+        } else {
+            DiagnosticCheck::fail(
+                "dns",
+                "DNS",
+                format!("{} does not resolve to localhost", probe),
+                "Reinstall DNS to regenerate the dnsmasq configuration",
+            )
+            .with_fix("install_dns")
+        };
+    };
+
+    if builtin {
+        if let Some(binary) = unit_binary().filter(|binary| !binary.exists()) {
+            return DiagnosticCheck::fail(
+                "dns",
+                "DNS",
+                format!(
+                    "The DNS responder runs {}, which no longer exists",
+                    binary.display()
+                ),
+                "Restart the DNS responder to point it at this ServerMark",
+            )
+            .with_fix("start_dns_responder");
+        }
+    }
+
+    let (server, start_fix) = if builtin {
+        ("The built-in DNS server", "start_dns_responder")
+    } else {
+        ("dnsmasq", "start_dnsmasq")
+    };
+    match query_local_dns(port, &probe) {
+        Ok(answers) if answers.contains(&Ipv4Addr::LOCALHOST) => DiagnosticCheck::pass(
+            "dns",
+            "DNS",
+            format!("{} answers *.{} on port {}", server, tld, port),
+        ),
+        Ok(answers) => DiagnosticCheck::fail(
+            "dns",
+            "DNS",
+            format!("{} answered {} with {:?}", server, probe, answers),
+            "Reinstall DNS to regenerate the DNS configuration",
+        )
+        .with_fix("install_dns"),
+        Err(e) => DiagnosticCheck::fail(
+            "dns",
+            "DNS",
+            format!("{} is not answering on port {}: {}", server, port, e),
+            "Start it or reinstall DNS",
+        )
+        .with_fix(start_fix),
+    }
+}
+
+fn check_site_fpm(config: &SitesConfig) -> DiagnosticCheck {
+    let missing: Vec<String> = config
+        .sites
+        .iter()
+        .filter(|s| uses_php(s) && !Path::new(&php_fpm_socket(&s.php_version)).exists())
+        .map(|s| format!("{} (PHP {})", s.name, s.php_version))
+        .collect();
+
+    if missing.is_empty() {
+        DiagnosticCheck::pass(
+            "site_fpm",
+            "PHP-FPM for sites",
+            "All sites have a running FPM",
+        )
+    } else {
+        DiagnosticCheck::fail(
+            "site_fpm",
+            "PHP-FPM for sites",
+            format!("No FPM socket for: {}", missing.join(", ")),
+            "Install or start PHP-FPM for these versions, or switch the sites to an installed version",
+        )
+    }
+}
+
+fn check_stale_configs(config: &SitesConfig) -> DiagnosticCheck {
+    let stale = stale_site_configs(config);
+    if stale.is_empty() {
+        DiagnosticCheck::pass("stale_configs", "Site configs", "No stale configs")
+    } else {
+        DiagnosticCheck::warn(
+            "stale_configs",
+            "Site configs",
+            format!("Configs for removed sites: {}", stale.join(", ")),
+            "Remove them so old domains stop being served",
+        )
+        .with_fix("remove_stale_configs")
+    }
+}
+
+fn check_container_runtime(runtime: Option<&RuntimeInfo>) -> DiagnosticCheck {
+    match runtime {
+        Some(r) if r.available => DiagnosticCheck::pass(
+            "container_runtime",
+            "Container runtime",
+            format!("{} {}", r.runtime, r.version),
+        ),
+        _ => DiagnosticCheck::warn(
+            "container_runtime",
+            "Container runtime",
+            "Neither Docker nor Podman is available",
+            "Install Docker or Podman to run database and cache containers",
+        ),
+    }
+}
+
+/// Run every environment check and combine the detection results
+#[tauri::command]
+pub async fn run_doctor() -> Result<DoctorReport, String> {
+    tauri::async_runtime::spawn_blocking(run_doctor_blocking)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn run_doctor_blocking() -> DoctorReport {
+    let sites = load_sites_config();
+    let webserver = load_webserver_config();
+    let active = webserver.active;
+
+    let web_server = detect_web_server();
+    let dns = detect_dns();
+    let php_versions = get_php_versions().unwrap_or_default();
+    let container_runtime = detect_container_runtime().ok();
+
+    let mut checks = check_port_conflicts(&web_server, active, webserver.ports());
+    checks.push(check_dns_answers(&dns, &sites.tld));
+    if !php_versions.iter().any(|v| v.installed) {
+        checks.push(DiagnosticCheck::warn(
+            "php_installed",
+            "PHP",
+            "No PHP version is installed",
+            "Install a PHP version from the PHP page",
+        ));
+    }
+    checks.push(check_site_fpm(&sites));
+    checks.push(check_stale_configs(&sites));
+    checks.push(check_container_runtime(container_runtime.as_ref()));
+
+    DoctorReport {
+        system: detect_system().ok(),
+        web_server,
+        dns,
+        native_services: detect_native_services(),
+        php_versions,
+        container_runtime,
+        healthy: checks.iter().all(|c| c.status != "fail"),
+        checks,
+    }
+}
+
+/// Apply a one-click fix suggested by `run_doctor`
+#[tauri::command]
+pub async fn fix_doctor_issue(fix: String) -> Result<DoctorReport, String> {
+    tauri::async_runtime::spawn_blocking(move || fix_doctor_issue_blocking(&fix))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn fix_doctor_issue_blocking(fix: &str) -> Result<DoctorReport, String> {
+    let active = backend(load_webserver_config().active);
+
+    let script = match fix {
+        "disable_inactive_server" => {
+            let mut script: String = WebServerKind::ALL
+                .into_iter()
+                .filter(|k| *k != active.kind())
+                .map(|k| backend(k).stop_script())
+                .collect();
+            script.push_str(&active.start_script());
+            script
+        }
+        "start_web_server" => active.start_script(),
+        "start_dnsmasq" => "systemctl enable --now dnsmasq\n".to_string(),
+        "start_dns_responder" => {
+            super::dns_responder::enable_responder()?;
+            return Ok(run_doctor_blocking());
+        }
+        "remove_stale_configs" => {
+            let stale = stale_site_configs(&load_sites_config());
+            if helper_available() {
+                let ops = stale
+                    .into_iter()
+                    .map(|path| HelperOp::RemoveManagedFile { path })
+                    .collect();
+                helper_request(ops).map_err(|e| format!("Failed to apply fix: {}", e))?;
+                let _ = helper_request(active.reload_ops());
+                return Ok(run_doctor_blocking());
+            }
+            let mut script: String = stale
+                .iter()
+                .map(|path| format!("rm -f '{}'\n", path))
+                .collect();
+            script.push_str(&format!(
+                "{{ {}; }} || true\n",
+                active.reload_script().trim_end()
+            ));
+            script
+        }
+        "install_dns" => {
+            let package_manager = detect_system()?.package_manager;
+            super::dns::install_dns(package_manager, None)?;
+            return Ok(run_doctor_blocking());
+        }
+        _ => return Err(format!("Unknown fix: {}", fix)),
+    };
+
+    let output = Command::new("pkexec")
+        .args(["bash", "-c", &script])
+        .output()
+        .map_err(|e| format!("Failed to apply fix: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to apply fix: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(run_doctor_blocking())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dns_answers() {
+        let mut response = build_dns_query(7, "app.test");
+        response[2] = 0x81; // Response flag
+        response[7] = 1; // One answer
+        response.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+
+        assert_eq!(parse_dns_answers(&response), vec![Ipv4Addr::LOCALHOST]);
+        assert!(parse_dns_answers(&response[..5]).is_empty());
+    }
+
+    #[test]
+    fn test_check_port_owners() {
+        let owner = |port: u16, process: &str| PortOwner {
+            port,
+            address: "0.0.0.0".to_string(),
+            uid: 0,
+            pid: Some(100),
+            process: Some(process.to_string()),
+            unit: Some(format!("{}.service", process)),
+            container: None,
+        };
+
+        let check = check_port_owners(&[owner(80, "apache2")], WebServerKind::Apache);
+        assert_eq!(check.status, "pass");
+
+        let check = check_port_owners(
+            &[owner(80, "caddy"), owner(443, "caddy")],
+            WebServerKind::Apache,
+        );
+        assert_eq!(check.status, "fail");
+        assert_eq!(check.fix.as_deref(), Some("disable_inactive_server"));
+
+        let check = check_port_owners(&[owner(80, "haproxy")], WebServerKind::Nginx);
+        assert_eq!(check.status, "fail");
+        assert!(check.message.contains("haproxy"), "{}", check.message);
+        assert_eq!(check.fix, None);
+    }
+}
@@ -0,0 +1,490 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use super::sites::load_sites_config;
+
+/// A parsed `.env` file that round-trips losslessly.
+///
+/// Comments, blank lines, quoting and key order are preserved; only entries
+/// touched through `set` are re-rendered.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DotEnv {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Entry(Entry),
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    key: String,
+    value: String,
+    quote: Quote,
+    export: bool,
+    /// Original source text (may span several lines for multi-line values)
+    raw: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quote {
+    None,
+    Single,
+    Double,
+}
+
+impl DotEnv {
+    pub fn parse(content: &str) -> Self {
+        let mut lines = Vec::new();
+        let mut source = content.split('\n').peekable();
+
+        while let Some(line) = source.next() {
+            // The empty string after a final newline is not a line of its own
+            if line.is_empty() && source.peek().is_none() {
+                break;
+            }
+
+            let Some((key, export, rest)) = split_assignment(line) else {
+                lines.push(Line::Other(line.to_string()));
+                continue;
+            };
+
+            let mut raw = line.to_string();
+            let mut value_src = rest.to_string();
+
+            // Double-quoted values may continue on the following lines
+            if value_src.trim_start().starts_with('"') {
+                while closing_quote(value_src.trim_start(), '"').is_none() {
+                    match source.next() {
+                        Some(next) => {
+                            raw.push('\n');
+                            raw.push_str(next);
+                            value_src.push('\n');
+                            value_src.push_str(next);
+                        }
+                        None => break,
+                    }
+                }
+            }
+
+            let (value, quote) = parse_value(&value_src);
+            lines.push(Line::Entry(Entry {
+                key,
+                value,
+                quote,
+                export,
+                raw,
+            }));
+        }
+
+        Self {
+            lines,
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read .env: {}", e))?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| format!("Failed to write .env: {}", e))
+    }
+
+    /// Value of the first definition of `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .find(|e| e.key == key)
+            .map(|e| e.value.as_str())
+    }
+
+    /// Set `key`, updating it in place or inserting it next to related keys.
+    /// Returns whether the file changed.
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        let mut found = false;
+        let mut changed = false;
+
+        for line in &mut self.lines {
+            if let Line::Entry(entry) = line {
+                if entry.key == key {
+                    found = true;
+                    if entry.value != value {
+                        entry.value = value.to_string();
+                        entry.raw = render_entry(entry);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if !found {
+            let mut entry = Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+                quote: Quote::None,
+                export: false,
+                raw: String::new(),
+            };
+            entry.raw = render_entry(&entry);
+
+            if self.lines.is_empty() {
+                self.trailing_newline = true;
+            }
+            let at = self.insert_position(key);
+            self.lines.insert(at, Line::Entry(entry));
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Remove every definition of `key`. Returns whether the file changed.
+    pub fn unset(&mut self, key: &str) -> bool {
+        let before = self.lines.len();
+        self.lines
+            .retain(|line| !matches!(line, Line::Entry(e) if e.key == key));
+        before != self.lines.len()
+    }
+
+    /// All `(key, value)` pairs in file order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries().map(|e| (e.key.as_str(), e.value.as_str()))
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(entry) => Some(entry),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// After the last key sharing the same prefix (`DB_`, `REDIS_`...), or at the end
+    fn insert_position(&self, key: &str) -> usize {
+        let prefix = key.split('_').next().unwrap_or(key);
+        self.lines
+            .iter()
+            .rposition(|line| match line {
+                Line::Entry(e) => e.key.split('_').next() == Some(prefix),
+                Line::Other(_) => false,
+            })
+            .map(|i| i + 1)
+            .unwrap_or_else(|| {
+                // Skip trailing blank lines so the new key stays attached to the content
+                self.lines
+                    .iter()
+                    .rposition(|line| !matches!(line, Line::Other(s) if s.trim().is_empty()))
+                    .map(|i| i + 1)
+                    .unwrap_or(0)
+            })
+    }
+}
+
+impl std::fmt::Display for DotEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rendered: Vec<&str> = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Entry(entry) => entry.raw.as_str(),
+                Line::Other(text) => text.as_str(),
+            })
+            .collect();
+
+        write!(f, "{}", rendered.join("\n"))?;
+        if self.trailing_newline {
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `key` is a valid variable name
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Split `[export ]KEY=rest`, returning `None` for comments and other lines
+fn split_assignment(line: &str) -> Option<(String, bool, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('#') {
+        return None;
+    }
+
+    let (export, body) = match trimmed.strip_prefix("export ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, trimmed),
+    };
+
+    let (key, rest) = body.split_once('=')?;
+    let key = key.trim();
+    is_valid_key(key).then(|| (key.to_string(), export, rest))
+}
+
+/// Byte offset of the closing quote in `s` (which starts with the opening quote)
+fn closing_quote(s: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        if quote == '"' && c == '\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        if c == quote && !escaped {
+            return Some(i);
+        }
+        escaped = false;
+    }
+    None
+}
+
+fn parse_value(src: &str) -> (String, Quote) {
+    let src = src.trim_start();
+
+    if src.starts_with('"') {
+        let end = closing_quote(src, '"').unwrap_or(src.len());
+        let inner = &src[1..end.max(1)];
+        return (unescape_double(inner), Quote::Double);
+    }
+
+    if src.starts_with('\'') {
+        let end = closing_quote(src, '\'').unwrap_or(src.len());
+        return (src[1..end.max(1)].to_string(), Quote::Single);
+    }
+
+    // Unquoted: an inline comment starts at whitespace followed by '#'
+    let value = match src.find(" #").or_else(|| src.find("\t#")) {
+        Some(pos) => &src[..pos],
+        None => src,
+    };
+    (value.trim().to_string(), Quote::None)
+}
+
+fn unescape_double(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn needs_quotes(value: &str) -> bool {
+    value
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '#' | '"' | '\'' | '\\' | '='))
+}
+
+/// Render an entry, keeping its original quote style when possible
+fn render_entry(entry: &Entry) -> String {
+    let value = &entry.value;
+    let quote = match entry.quote {
+        Quote::Single if !value.contains('\'') && !value.contains('\n') => Quote::Single,
+        Quote::None if !needs_quotes(value) => Quote::None,
+        Quote::Double => Quote::Double,
+        _ if needs_quotes(value) => Quote::Double,
+        _ => Quote::None,
+    };
+
+    let rendered = match quote {
+        Quote::None => value.clone(),
+        Quote::Single => format!("'{}'", value),
+        Quote::Double => format!(
+            "\"{}\"",
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        ),
+    };
+
+    let export = if entry.export { "export " } else { "" };
+    format!("{}{}={}", export, entry.key, rendered)
+}
+
+// ============================================================================
+// Site .env editor
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvVariable {
+    pub key: String,
+    pub value: String,
+}
+
+/// A single edit from the UI: `value: None` removes the key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvChange {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+fn site_env_path(id: &str) -> Result<std::path::PathBuf, String> {
+    let config = load_sites_config();
+    let site = config
+        .sites
+        .iter()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+    Ok(Path::new(&site.path).join(".env"))
+}
+
+/// Read a site's `.env` as key/value pairs
+#[tauri::command]
+pub fn get_site_env(id: String) -> Result<Vec<EnvVariable>, String> {
+    let path = site_env_path(&id)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let env = DotEnv::load(&path)?;
+    Ok(env
+        .iter()
+        .map(|(key, value)| EnvVariable {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+        .collect())
+}
+
+/// Apply edits to a site's `.env`, preserving comments and ordering
+#[tauri::command]
+pub fn set_site_env(id: String, changes: Vec<EnvChange>) -> Result<Vec<EnvVariable>, String> {
+    if let Some(change) = changes.iter().find(|c| !is_valid_key(&c.key)) {
+        return Err(format!("Invalid variable name: {}", change.key));
+    }
+
+    let path = site_env_path(&id)?;
+    let mut env = if path.exists() {
+        DotEnv::load(&path)?
+    } else {
+        DotEnv::default()
+    };
+
+    let mut changed = false;
+    for change in &changes {
+        changed |= match &change.value {
+            Some(value) => env.set(&change.key, value),
+            None => env.unset(&change.key),
+        };
+    }
+
+    if changed {
+        env.save(&path)?;
+    }
+
+    get_site_env(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"APP_NAME="My App"
+APP_ENV=local
+# Database
+DB_CONNECTION=mysql
+DB_HOST=mysql # inside sail
+DB_PASSWORD='p@ss#word'
+
+export MAIL_FROM_NAME="${APP_NAME}"
+PRIVATE_KEY="-----BEGIN-----
+abc
+-----END-----"
+"#;
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        assert_eq!(DotEnv::parse(SAMPLE).to_string(), SAMPLE);
+        assert_eq!(DotEnv::parse("A=1").to_string(), "A=1");
+        assert_eq!(DotEnv::parse("").to_string(), "");
+    }
+
+    #[test]
+    fn test_get_values() {
+        let env = DotEnv::parse(SAMPLE);
+        assert_eq!(env.get("APP_NAME"), Some("My App"));
+        assert_eq!(env.get("DB_HOST"), Some("mysql"));
+        assert_eq!(env.get("DB_PASSWORD"), Some("p@ss#word"));
+        assert_eq!(env.get("MAIL_FROM_NAME"), Some("${APP_NAME}"));
+        assert_eq!(
+            env.get("PRIVATE_KEY"),
+            Some("-----BEGIN-----\nabc\n-----END-----")
+        );
+        assert_eq!(env.get("MISSING"), None);
+    }
+
+    #[test]
+    fn test_set_existing_keeps_surroundings() {
+        let mut env = DotEnv::parse(SAMPLE);
+        assert!(env.set("DB_HOST", "mysql-primary"));
+        assert!(!env.set("APP_ENV", "local"));
+        assert!(env.set("APP_NAME", "Shop"));
+
+        let out = env.to_string();
+        assert!(out.contains("DB_HOST=mysql-primary\n"));
+        assert!(out.contains("APP_NAME=\"Shop\"\n"));
+        assert!(out.contains("# Database\n"));
+        assert!(out.contains("DB_PASSWORD='p@ss#word'\n"));
+    }
+
+    #[test]
+    fn test_set_new_key_groups_by_prefix() {
+        let mut env = DotEnv::parse("APP_NAME=x\nDB_HOST=a\n\nMAIL_HOST=m\n");
+        env.set("DB_PORT", "3306");
+        env.set("REDIS_HOST", "127.0.0.1");
+        env.set("APP_URL", "http://x.test");
+
+        assert_eq!(
+            env.to_string(),
+            "APP_NAME=x\nAPP_URL=http://x.test\nDB_HOST=a\nDB_PORT=3306\n\nMAIL_HOST=m\nREDIS_HOST=127.0.0.1\n"
+        );
+    }
+
+    #[test]
+    fn test_unset() {
+        let mut env = DotEnv::parse("A=1\n# keep\nB=2\nA=3\n");
+        assert!(env.unset("A"));
+        assert!(!env.unset("A"));
+        assert_eq!(env.to_string(), "# keep\nB=2\n");
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(is_valid_key("DB_HOST"));
+        assert!(!is_valid_key("1ABC"));
+        assert!(!is_valid_key("A B"));
+        assert!(!is_valid_key(""));
+    }
+
+    #[test]
+    fn test_set_quotes_when_needed() {
+        let mut env = DotEnv::parse("");
+        env.set("APP_NAME", "Two Words");
+        assert_eq!(env.get("APP_NAME"), Some("Two Words"));
+        assert_eq!(env.to_string(), "APP_NAME=\"Two Words\"\n");
+    }
+}
@@ -0,0 +1,1011 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Client for the Docker Engine API over its unix socket. Podman serves the
+// same API on its own socket (system-wide, or per user when rootless).
+// Requests are plain HTTP/1.1 with `Connection: close`, so every response
+// ends at EOF.
+
+/// Oldest API version with everything used here; Podman's compat API serves it
+const MIN_API_VERSION: (u32, u32) = (1, 41);
+
+/// Newest API version requests are written against. Engines refuse versions
+/// that are too old for them (Docker 29 wants 1.44), so each connection uses
+/// the engine's own version, capped at this one.
+const MAX_API_VERSION: (u32, u32) = (1, 47);
+
+/// Read timeout while streaming, so idle streams can still be stopped
+const STREAM_TICK: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct EngineClient {
+    socket: PathBuf,
+    /// Path prefix such as `/v1.47`; empty for unversioned requests
+    api_prefix: String,
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_slice(&self.body).map_err(|e| format!("Unexpected API response: {}", e))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+/// `1.44` as `(1, 44)`
+fn parse_api_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.trim_start_matches('v').split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+fn version_prefix((major, minor): (u32, u32)) -> String {
+    format!("/v{}.{}", major, minor)
+}
+
+/// Sockets to try, most specific first: `DOCKER_HOST`, the system Docker
+/// socket, rootless Docker, then Podman (per user, then system-wide)
+fn candidate_sockets() -> Vec<PathBuf> {
+    let mut sockets = Vec::new();
+    if let Some(path) = std::env::var("DOCKER_HOST")
+        .ok()
+        .and_then(|host| host.strip_prefix("unix://").map(PathBuf::from))
+    {
+        sockets.push(path);
+    }
+    sockets.push(PathBuf::from("/var/run/docker.sock"));
+    if let Some(runtime_dir) = dirs::runtime_dir() {
+        sockets.push(runtime_dir.join("docker.sock"));
+        sockets.push(runtime_dir.join("podman").join("podman.sock"));
+    }
+    sockets.push(PathBuf::from("/run/podman/podman.sock"));
+    sockets
+}
+
+impl EngineClient {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+            api_prefix: version_prefix(MIN_API_VERSION),
+        }
+    }
+
+    /// The first socket that answers a ping, speaking the engine's API version
+    pub fn connect() -> Result<Self, String> {
+        candidate_sockets()
+            .into_iter()
+            .filter(|path| path.exists())
+            .map(Self::new)
+            .find(|client| client.ping())
+            .ok_or_else(|| {
+                "No Docker or Podman API socket found. Start Docker, or enable Podman's with `systemctl --user enable --now podman.socket`.".to_string()
+            })?
+            .negotiate()
+    }
+
+    /// Ask the engine for its API version (`/version` answers unversioned)
+    /// and settle on the newest one both sides speak
+    fn negotiate(mut self) -> Result<Self, String> {
+        self.api_prefix = String::new();
+        let version: ApiVersion = self.get("/version")?;
+        let engine = parse_api_version(&version.api_version)
+            .ok_or_else(|| format!("Unknown engine API version {}", version.api_version))?;
+        if engine < MIN_API_VERSION {
+            return Err(format!(
+                "The engine's API version {} is too old; ServerMark needs {}.{} or newer",
+                version.api_version, MIN_API_VERSION.0, MIN_API_VERSION.1
+            ));
+        }
+        self.api_prefix = version_prefix(engine.min(MAX_API_VERSION));
+        Ok(self)
+    }
+
+    pub fn ping(&self) -> bool {
+        self.send("GET", "/_ping", None, Some(Duration::from_secs(2)))
+            .map(|r| r.status == 200)
+            .unwrap_or(false)
+    }
+
+    fn socket_stream(&self, timeout: Option<Duration>) -> Result<UnixStream, String> {
+        let stream = UnixStream::connect(&self.socket)
+            .map_err(|e| format!("Failed to connect to {}: {}", self.socket.display(), e))?;
+        stream
+            .set_read_timeout(timeout)
+            .map_err(|e| e.to_string())?;
+        Ok(stream)
+    }
+
+    /// Connect and write a request
+    fn open(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+        timeout: Option<Duration>,
+    ) -> Result<UnixStream, String> {
+        let mut stream = self.socket_stream(timeout)?;
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let request = format!(
+            "{} {}{} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            self.api_prefix,
+            path,
+            body.len(),
+            body
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| format!("Failed to send API request: {}", e))?;
+        Ok(stream)
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+        timeout: Option<Duration>,
+    ) -> Result<Response, String> {
+        let mut stream = self.open(method, path, body, timeout)?;
+        let mut raw = Vec::new();
+        stream
+            .read_to_end(&mut raw)
+            .map_err(|e| format!("Failed to read API response: {}", e))?;
+        parse_response(&raw)
+    }
+
+    /// Send a request and hand the body to `on_data` as it arrives, for
+    /// endpoints that stream (stats, logs, pull progress). Runs until the
+    /// engine ends the response or `on_data` returns false; while the stream
+    /// is idle `on_data` is called with no data, so it can stop it.
+    pub fn stream(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+        mut on_data: impl FnMut(&[u8]) -> bool,
+    ) -> Result<(), String> {
+        let stream = self.open(method, path, body, Some(STREAM_TICK))?;
+        let mut reader = BufReader::new(stream);
+        let failed = |e: std::io::Error| format!("Failed to read API response: {}", e);
+
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line, &mut on_data).map_err(failed)? {
+            return Ok(());
+        }
+        let status = String::from_utf8_lossy(&line)
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or("Malformed API status line")?;
+
+        let mut chunked = false;
+        let mut length = None;
+        loop {
+            line.clear();
+            if !read_line(&mut reader, &mut line, &mut on_data).map_err(failed)? {
+                return Ok(());
+            }
+            let header = String::from_utf8_lossy(&line);
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                let (name, value) = (name.trim(), value.trim());
+                if name.eq_ignore_ascii_case("transfer-encoding") {
+                    chunked = value.eq_ignore_ascii_case("chunked");
+                } else if name.eq_ignore_ascii_case("content-length") {
+                    length = value.parse::<usize>().ok();
+                }
+            }
+        }
+
+        // Error bodies are collected for their message
+        let mut error_body = Vec::new();
+        let mut deliver = |data: &[u8]| {
+            if status >= 400 {
+                error_body.extend_from_slice(data);
+                true
+            } else {
+                on_data(data)
+            }
+        };
+
+        if chunked {
+            loop {
+                line.clear();
+                if !read_line(&mut reader, &mut line, &mut deliver).map_err(failed)? {
+                    break;
+                }
+                let size_line = String::from_utf8_lossy(&line);
+                let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+                let size = usize::from_str_radix(size_hex, 16)
+                    .map_err(|_| format!("Bad chunk size: {}", size_hex))?;
+                if size == 0 {
+                    break;
+                }
+                let mut chunk = vec![0; size + 2]; // With its trailing CRLF
+                if !read_full(&mut reader, &mut chunk, &mut deliver).map_err(failed)?
+                    || !deliver(&chunk[..size])
+                {
+                    break;
+                }
+            }
+        } else {
+            let mut remaining = length.unwrap_or(usize::MAX);
+            let mut buf = [0u8; 8192];
+            while remaining > 0 {
+                let want = remaining.min(buf.len());
+                match reader.read(&mut buf[..want]) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        remaining -= n;
+                        if !deliver(&buf[..n]) {
+                            break;
+                        }
+                    }
+                    Err(e) if idle(&e) => {
+                        if !deliver(&[]) {
+                            break;
+                        }
+                    }
+                    Err(e) => return Err(failed(e)),
+                }
+            }
+        }
+
+        if status >= 400 {
+            return Err(api_error(&Response {
+                status,
+                body: error_body,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Send a request; error statuses are returned as-is
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response, String> {
+        self.send(method, path, body, None)
+    }
+
+    /// Send a request and turn 4xx/5xx statuses into the API's error message
+    pub fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Response, String> {
+        let response = self.request(method, path, body)?;
+        if response.status >= 400 {
+            return Err(api_error(&response));
+        }
+        Ok(response)
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.call("GET", path, None)?.json()
+    }
+
+    /// Send a request whose body is copied from `body` as it is written, for
+    /// uploads too large to hold in memory (tar archives)
+    pub fn upload(
+        &self,
+        method: &str,
+        path: &str,
+        content_type: &str,
+        length: u64,
+        body: &mut impl Read,
+    ) -> Result<Response, String> {
+        let mut stream = self.socket_stream(None)?;
+        let head = format!(
+            "{} {}{} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            method, self.api_prefix, path, content_type, length
+        );
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|()| std::io::copy(body, &mut stream).map(|_| ()))
+            .map_err(|e| format!("Failed to send API request: {}", e))?;
+
+        let mut raw = Vec::new();
+        stream
+            .read_to_end(&mut raw)
+            .map_err(|e| format!("Failed to read API response: {}", e))?;
+        let response = parse_response(&raw)?;
+        if response.status >= 400 {
+            return Err(api_error(&response));
+        }
+        Ok(response)
+    }
+}
+
+fn idle(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Read a line, waiting out idle timeouts; false when `on_idle` cancels
+fn read_line(
+    reader: &mut impl BufRead,
+    line: &mut Vec<u8>,
+    on_idle: &mut impl FnMut(&[u8]) -> bool,
+) -> std::io::Result<bool> {
+    loop {
+        // Bytes read before a timeout stay in `line`
+        match reader.read_until(b'\n', line) {
+            Ok(_) => return Ok(true),
+            Err(e) if idle(&e) => {
+                if !on_idle(&[]) {
+                    return Ok(false);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fill `buf`, waiting out idle timeouts; false when `on_idle` cancels
+fn read_full(
+    reader: &mut impl Read,
+    buf: &mut [u8],
+    on_idle: &mut impl FnMut(&[u8]) -> bool,
+) -> std::io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if idle(&e) => {
+                if !on_idle(&[]) {
+                    return Ok(false);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Splits streamed bytes into complete lines
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    pub fn push(&mut self, data: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(data);
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+}
+
+/// Error message of an API error response (`{"message": "..."}`)
+pub fn api_error(response: &Response) -> String {
+    serde_json::from_slice::<Value>(&response.body)
+        .ok()
+        .and_then(|v| v["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("API error {}: {}", response.status, response.text().trim()))
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, String> {
+    let header_end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("Malformed API response")?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or("Malformed API status line")?;
+    let chunked = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+        })
+    });
+
+    let body = &raw[header_end + 4..];
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(Response { status, body })
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("Truncated chunked response")?;
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|_| format!("Bad chunk size: {}", size_hex))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = body.get(..size).ok_or("Truncated chunked response")?;
+        decoded.extend_from_slice(chunk);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// Percent-encode a path segment or query value
+pub fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Frames of a log or exec stream as (stream, bytes). Containers without a TTY
+/// multiplex stdout (1) and stderr (2) in frames with an 8-byte header
+/// (stream, 0, 0, 0, big-endian size); TTY output is a single stdout frame.
+fn stream_frames(body: &[u8]) -> Vec<(u8, &[u8])> {
+    let multiplexed = body.len() >= 8 && body[0] <= 2 && body[1..4] == [0, 0, 0];
+    if !multiplexed {
+        return vec![(1, body)];
+    }
+
+    let mut frames = Vec::new();
+    let mut rest = body;
+    while rest.len() >= 8 {
+        let size = u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let end = (8 + size).min(rest.len());
+        frames.push((rest[0], &rest[8..end]));
+        rest = &rest[end..];
+    }
+    frames
+}
+
+/// Text of a log or exec stream, stdout and stderr interleaved
+pub fn demux_stream(body: &[u8]) -> String {
+    let text: Vec<u8> = stream_frames(body)
+        .into_iter()
+        .flat_map(|(_, bytes)| bytes.iter().copied())
+        .collect();
+    String::from_utf8_lossy(&text).to_string()
+}
+
+/// Stdout and stderr of an exec stream
+pub fn split_stream(body: &[u8]) -> (String, String) {
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    for (stream, bytes) in stream_frames(body) {
+        if stream == 2 {
+            &mut stderr
+        } else {
+            &mut stdout
+        }
+        .extend_from_slice(bytes);
+    }
+    (
+        String::from_utf8_lossy(&stdout).to_string(),
+        String::from_utf8_lossy(&stderr).to_string(),
+    )
+}
+
+/// Incremental `demux_stream` for a followed log stream, whose frames can be
+/// split across reads
+#[derive(Debug, Default)]
+pub struct StreamDemuxer {
+    pending: Vec<u8>,
+    multiplexed: Option<bool>,
+}
+
+impl StreamDemuxer {
+    /// Payload bytes of the frames completed by `data`
+    pub fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+        let multiplexed = match self.multiplexed {
+            Some(multiplexed) => multiplexed,
+            None if self.pending.len() < 8 => return Vec::new(),
+            None => {
+                let p = &self.pending;
+                let multiplexed = p[0] <= 2 && p[1..4] == [0, 0, 0];
+                self.multiplexed = Some(multiplexed);
+                multiplexed
+            }
+        };
+        if !multiplexed {
+            return std::mem::take(&mut self.pending);
+        }
+
+        let mut payload = Vec::new();
+        while self.pending.len() >= 8 {
+            let p = &self.pending;
+            let size = u32::from_be_bytes([p[4], p[5], p[6], p[7]]) as usize;
+            if p.len() < 8 + size {
+                break;
+            }
+            payload.extend_from_slice(&p[8..8 + size]);
+            self.pending.drain(..8 + size);
+        }
+        payload
+    }
+}
+
+// ============================================================================
+// API types
+// ============================================================================
+
+/// Entry of `GET /containers/json`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiContainer {
+    pub id: String,
+    #[serde(default)]
+    pub names: Vec<String>,
+    pub image: String,
+    pub state: String,
+    pub status: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub ports: Vec<ApiPort>,
+    pub created: i64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub labels: HashMap<String, String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub mounts: Vec<ApiMount>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiPort {
+    pub private_port: u16,
+    pub public_port: Option<u16>,
+    #[serde(rename = "Type")]
+    pub protocol: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiMount {
+    #[serde(rename = "Type")]
+    pub kind: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub source: String,
+    pub destination: String,
+}
+
+/// `GET /containers/{id}/json`, the parts used here
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiContainerDetails {
+    pub id: String,
+    pub state: ApiState,
+    pub config: ApiConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiState {
+    pub status: String,
+    pub health: Option<ApiHealth>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiHealth {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiConfig {
+    pub image: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub labels: HashMap<String, String>,
+    /// `KEY=value` entries
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub env: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiVersion {
+    pub version: String,
+    pub api_version: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub components: Vec<ApiComponent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiComponent {
+    pub name: String,
+}
+
+/// `GET /containers/{id}/stats?stream=false`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiStats {
+    pub cpu_stats: ApiCpuStats,
+    pub precpu_stats: ApiCpuStats,
+    pub memory_stats: ApiMemoryStats,
+    #[serde(deserialize_with = "null_as_default")]
+    pub networks: HashMap<String, ApiNetworkStats>,
+    pub blkio_stats: ApiBlkioStats,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiCpuStats {
+    pub cpu_usage: ApiCpuUsage,
+    pub system_cpu_usage: u64,
+    pub online_cpus: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiCpuUsage {
+    pub total_usage: u64,
+    #[serde(deserialize_with = "null_as_default")]
+    pub percpu_usage: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiMemoryStats {
+    pub usage: u64,
+    pub limit: u64,
+    #[serde(deserialize_with = "null_as_default")]
+    pub stats: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiNetworkStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiBlkioStats {
+    #[serde(deserialize_with = "null_as_default")]
+    pub io_service_bytes_recursive: Vec<ApiBlkioEntry>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiBlkioEntry {
+    pub op: String,
+    pub value: u64,
+}
+
+/// Entry of `GET /volumes`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiVolume {
+    pub name: String,
+    #[serde(default)]
+    pub driver: String,
+    #[serde(default)]
+    pub mountpoint: String,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiVolumeList {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub volumes: Vec<ApiVolume>,
+}
+
+/// `GET /system/df`, volumes only
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiDiskUsage {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub volumes: Vec<ApiVolumeUsage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiVolumeUsage {
+    pub name: String,
+    pub usage_data: Option<ApiUsageData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApiUsageData {
+    pub size: i64, // -1 when not computed
+}
+
+/// Podman and older Docker versions send `null` for empty lists and maps
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// ============================================================================
+// Fake engine for tests
+// ============================================================================
+
+/// A canned API server on a temporary unix socket. Requests are answered by
+/// the first route whose method matches and whose path prefix matches the
+/// path after the API version (if any); unmatched requests get a 404.
+#[cfg(test)]
+pub struct FakeEngine {
+    pub client: EngineClient,
+    requests: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl FakeEngine {
+    pub fn start(routes: Vec<(&'static str, &'static str, u16, String)>) -> Self {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixListener;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "servermark-engine-{}-{}.sock",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let target = parts.next().unwrap_or_default();
+                let versioned = target
+                    .strip_prefix("/v")
+                    .and_then(|rest| rest.find('/').map(|end| &rest[end..]))
+                    .filter(|_| target.as_bytes().get(2).is_some_and(u8::is_ascii_digit));
+                let path = versioned.unwrap_or(target).to_string();
+                log.lock().unwrap().push(format!(
+                    "{} {} {}",
+                    method,
+                    path,
+                    String::from_utf8_lossy(&body)
+                ));
+
+                let (status, reply) = routes
+                    .iter()
+                    .find(|(m, prefix, _, _)| *m == method && path.starts_with(prefix))
+                    .map(|(_, _, status, reply)| (*status, reply.clone()))
+                    .unwrap_or((404, r#"{"message":"not found"}"#.to_string()));
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+            }
+        });
+
+        Self {
+            client: EngineClient::new(path),
+            requests,
+        }
+    }
+
+    /// `METHOD path body` of every request so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response_decodes_chunked_bodies() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n[{}]\r\n3;x=y\r\nabc\r\n0\r\n\r\n";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"[{}]abc");
+
+        let raw =
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 26\r\n\r\n{\"message\":\"no such image\"}";
+        let response = parse_response(raw).unwrap();
+        assert_eq!(api_error(&response), "no such image");
+    }
+
+    #[test]
+    fn test_negotiates_api_version() {
+        let engine = FakeEngine::start(vec![
+            (
+                "GET",
+                "/version",
+                200,
+                r#"{"Version":"29.0.0","ApiVersion":"1.52"}"#.to_string(),
+            ),
+            ("GET", "/volumes", 200, r#"{"Volumes":[]}"#.to_string()),
+        ]);
+        let client = engine.client.clone().negotiate().unwrap();
+        assert_eq!(client.api_prefix, "/v1.47");
+        client.call("GET", "/volumes", None).unwrap();
+        assert_eq!(engine.requests(), vec!["GET /version ", "GET /volumes "]);
+
+        let podman = FakeEngine::start(vec![(
+            "GET",
+            "/version",
+            200,
+            r#"{"Version":"5.2.0","ApiVersion":"1.41"}"#.to_string(),
+        )]);
+        let client = podman.client.clone().negotiate().unwrap();
+        assert_eq!(client.api_prefix, "/v1.41");
+    }
+
+    #[test]
+    fn test_rejects_engine_below_minimum_api_version() {
+        let engine = FakeEngine::start(vec![(
+            "GET",
+            "/version",
+            200,
+            r#"{"Version":"19.03.15","ApiVersion":"1.40"}"#.to_string(),
+        )]);
+        let err = engine.client.clone().negotiate().unwrap_err();
+        assert!(err.contains("1.40 is too old"), "{}", err);
+    }
+
+    #[test]
+    fn test_demux_stream_and_encode() {
+        let mut framed = vec![1, 0, 0, 0, 0, 0, 0, 6];
+        framed.extend_from_slice(b"ready\n");
+        framed.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 5]);
+        framed.extend_from_slice(b"warn\n");
+        assert_eq!(demux_stream(&framed), "ready\nwarn\n");
+        assert_eq!(
+            split_stream(&framed),
+            ("ready\n".to_string(), "warn\n".to_string())
+        );
+        assert_eq!(demux_stream(b"tty output\n"), "tty output\n");
+
+        assert_eq!(
+            encode(r#"{"name":["x"]}"#),
+            "%7B%22name%22%3A%5B%22x%22%5D%7D"
+        );
+    }
+
+    #[test]
+    fn test_stream_demuxer_joins_split_frames() {
+        let mut framed = vec![1, 0, 0, 0, 0, 0, 0, 6];
+        framed.extend_from_slice(b"ready\n");
+        framed.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 5]);
+        framed.extend_from_slice(b"warn\n");
+
+        let mut demuxer = StreamDemuxer::default();
+        let mut text = Vec::new();
+        for chunk in framed.chunks(3) {
+            text.extend(demuxer.push(chunk));
+        }
+        assert_eq!(text, b"ready\nwarn\n");
+
+        let mut tty = StreamDemuxer::default();
+        assert!(tty.push(b"tty").is_empty());
+        assert_eq!(tty.push(b" output\n"), b"tty output\n");
+        assert_eq!(tty.push(b"more"), b"more");
+    }
+
+    #[test]
+    fn test_client_against_fake_engine() {
+        let engine = FakeEngine::start(vec![
+            ("GET", "/_ping", 200, "OK".to_string()),
+            (
+                "GET",
+                "/version",
+                200,
+                r#"{"Version":"5.2.1","ApiVersion":"1.41","Components":[{"Name":"Podman Engine"}]}"#
+                    .to_string(),
+            ),
+        ]);
+        assert!(engine.client.ping());
+        let version: ApiVersion = engine.client.get("/version").unwrap();
+        assert_eq!(version.version, "5.2.1");
+        assert_eq!(version.components[0].name, "Podman Engine");
+
+        let err = engine
+            .client
+            .call("DELETE", "/containers/abc", None)
+            .unwrap_err();
+        assert_eq!(err, "not found");
+        assert_eq!(engine.requests()[2], "DELETE /containers/abc ");
+    }
+
+    #[test]
+    fn test_stream_chunked_with_idle_ticks() {
+        use std::os::unix::net::UnixListener;
+
+        let path =
+            std::env::temp_dir().join(format!("servermark-stream-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n{\"a\":1\r\n",
+                )
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(700)); // Longer than a tick
+            stream.write_all(b"3\r\n}\n{\r\n").unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            let _ = stream.write_all(b"4\r\n\"b\":\r\n0\r\n\r\n");
+        });
+
+        let client = EngineClient::new(&path);
+        let mut lines = LineBuffer::default();
+        let mut received = Vec::new();
+        let mut idle_ticks = 0;
+        client
+            .stream("GET", "/containers/x/logs", None, |data| {
+                if data.is_empty() {
+                    idle_ticks += 1;
+                }
+                received.extend(lines.push(data));
+                // Stop once the first line is complete
+                received.is_empty()
+            })
+            .unwrap();
+        assert_eq!(received, vec![r#"{"a":1}"#]);
+        assert!(idle_ticks >= 1);
+    }
+}
@@ -0,0 +1,1034 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use super::backends::{
+    all_backends, backend, caddy_ports_options, caddy_ports_path, ListenPorts, WebServerKind,
+    CADDY_PORTS_FILE_STEM,
+};
+use super::dns::{dns_backend, DnsInstallState, DnsServer, ResolverStack, DNS_STATE_PATH};
+use super::hosts::{update_hosts, HOSTS_FILE};
+use super::ports::socket_owners;
+use super::share::{LanShare, LanShareMode};
+use super::sites::{Site, SiteType, SitesConfig};
+use super::system::app_executable;
+use super::webserver::{site_document_root, site_domains};
+
+// Root helper exposing a small set of typed, validated operations over a Unix
+// socket. systemd starts it on demand (`servermark-helper --privileged-helper`);
+// once installed it replaces the `pkexec bash -c` scripts. Config files are
+// rendered by the helper from structured fields, never taken as raw contents.
+
+pub const HELPER_SOCKET: &str = "/run/servermark-helper.sock";
+const HELPER_LOG: &str = "/var/log/servermark-helper.log";
+const HELPER_GROUP: &str = "servermark";
+const SSL_DIR: &str = "/etc/servermark/ssl";
+const CADDY_CERT_DIR: &str = "/var/lib/caddy/.local/share/caddy/certificates/local";
+/// Root-owned copy of the binary the helper unit runs
+const HELPER_BINARY: &str = "/usr/local/libexec/servermark-helper";
+
+/// Directories a home directory may not be in, as it would hand them to its user
+const SYSTEM_DIRS: &[&str] = &[
+    "/etc", "/usr", "/bin", "/sbin", "/lib", "/lib64", "/boot", "/proc", "/sys", "/dev", "/run",
+    "/root",
+];
+
+/// Longest request line the helper reads
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// Exit after this long without a request; systemd starts us again on demand
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Directories (besides web server site directories) where managed files may live,
+/// with the file name prefix they must carry
+const MANAGED_DIRS: &[(&str, &str)] = &[
+    ("/etc/dnsmasq.d", "servermark"),
+    ("/etc/systemd/resolved.conf.d", "servermark"),
+    ("/etc/NetworkManager/dnsmasq.d", "servermark"),
+    ("/etc/servermark", ""),
+    ("/etc/caddy/sites.d", CADDY_PORTS_FILE_STEM),
+];
+
+/// Units the helper may control, besides `phpX.Y-fpm` and `servermark-*`
+const MANAGED_UNITS: &[&str] = &[
+    "caddy",
+    "nginx",
+    "apache2",
+    "httpd",
+    "dnsmasq",
+    "systemd-resolved",
+    "NetworkManager",
+];
+
+/// A single privileged operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum HelperOp {
+    WriteSiteConfig {
+        server: WebServerKind,
+        site: SiteConfigSpec,
+    },
+    WriteCaddyPorts {
+        http: u16,
+        https: u16,
+    },
+    WriteDnsConfig {
+        stack: ResolverStack,
+        server: DnsServer,
+        tlds: Vec<String>,
+    },
+    WriteDnsState {
+        state: DnsInstallState,
+    },
+    RemoveManagedFile {
+        path: String,
+    },
+    LinkManagedFile {
+        target: String,
+        link: String,
+    },
+    IssueCertificate {
+        domain: String,
+    },
+    RemoveCertificate {
+        domain: String,
+    },
+    WriteHostsBlock {
+        names: Vec<String>,
+    },
+    ControlUnit {
+        unit: String,
+        action: String,
+    },
+    ChownSitePath {
+        path: String,
+    },
+    /// Pids holding listening sockets, which only root can see for root's sockets
+    SocketOwners {
+        inodes: Vec<u64>,
+    },
+}
+
+/// What a site's web server config is rendered from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SiteConfigSpec {
+    pub name: String,
+    /// Primary domain first, then aliases
+    pub domains: Vec<String>,
+    pub lan_hostname: Option<String>,
+    pub document_root: String,
+    pub php_version: String,
+    pub secured: bool,
+    pub http_port: u16,
+    pub https_port: u16,
+}
+
+impl SiteConfigSpec {
+    pub fn new(site: &Site, ports: ListenPorts) -> Self {
+        Self {
+            name: site.name.clone(),
+            domains: site_domains(site).iter().map(|d| d.to_string()).collect(),
+            lan_hostname: site.lan_share.as_ref().map(|share| share.hostname.clone()),
+            document_root: site_document_root(site),
+            php_version: site.php_version.clone(),
+            secured: site.secured,
+            http_port: ports.http,
+            https_port: ports.https,
+        }
+    }
+
+    /// Every field ends up in a root-read config file: names and domains are
+    /// checked character by character, the document root must be in `home`
+    fn validate(&self, home: &Path) -> Result<(), String> {
+        if !valid_name(&self.name) {
+            return Err(format!("Invalid site name: {}", self.name));
+        }
+        if self.domains.is_empty() {
+            return Err("A site needs a domain".to_string());
+        }
+        for domain in self.domains.iter().chain(&self.lan_hostname) {
+            validate_domain(domain)?;
+        }
+        let version = !self.php_version.is_empty()
+            && self
+                .php_version
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '.');
+        if !version {
+            return Err(format!("Invalid PHP version: {}", self.php_version));
+        }
+        if self.http_port == 0 || self.https_port == 0 {
+            return Err("Invalid listen port".to_string());
+        }
+
+        let root = Path::new(&self.document_root);
+        let plain = plain_absolute(root)
+            && self
+                .document_root
+                .chars()
+                .all(|c| c.is_alphanumeric() || "/._-+@".contains(c));
+        let inside = |root: &Path| root.starts_with(home) && root != home;
+        // An existing root may still lead out of the home directory through a symlink
+        let resolved = fs::canonicalize(root).map_or(true, |real| inside(&real));
+        if plain && inside(root) && resolved {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} is not a document root inside your home directory",
+                self.document_root
+            ))
+        }
+    }
+
+    /// The site as far as the web server config is concerned
+    fn site(&self) -> Site {
+        Site {
+            id: String::new(),
+            name: self.name.clone(),
+            path: self.document_root.clone(),
+            domain: self.domains[0].clone(),
+            php_version: self.php_version.clone(),
+            secured: self.secured,
+            site_type: SiteType::Static,
+            proxy_target: None,
+            laravel: None,
+            document_root: Some(self.document_root.clone()),
+            aliases: self.domains[1..].to_vec(),
+            manifest: None,
+            // Only the hostname is rendered
+            lan_share: self.lan_hostname.clone().map(|hostname| LanShare {
+                mode: LanShareMode::NipIo,
+                hostname,
+                address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            }),
+        }
+    }
+
+    fn ports(&self) -> ListenPorts {
+        ListenPorts {
+            http: self.http_port,
+            https: self.https_port,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HelperRequest {
+    ops: Vec<HelperOp>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HelperResponse {
+    ok: bool,
+    completed: usize,
+    error: Option<String>,
+    /// Answer to `SocketOwners`: socket inode to pid
+    #[serde(default)]
+    socket_owners: HashMap<u64, u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelperStatus {
+    pub installed: bool,
+    pub reachable: bool,
+    pub socket: String,
+}
+
+impl HelperOp {
+    /// One-line summary for the audit log
+    fn summary(&self) -> String {
+        match self {
+            Self::WriteSiteConfig { server, site } => format!(
+                "write_site_config {} {} {} -> {}",
+                server,
+                site.name,
+                site.domains.join(","),
+                site.document_root
+            ),
+            Self::WriteCaddyPorts { http, https } => {
+                format!("write_caddy_ports {} {}", http, https)
+            }
+            Self::WriteDnsConfig {
+                stack,
+                server,
+                tlds,
+            } => format!(
+                "write_dns_config {:?} {:?} {}",
+                stack,
+                server,
+                tlds.join(",")
+            ),
+            Self::WriteDnsState { state } => {
+                format!("write_dns_state {:?} {:?}", state.stack, state.server)
+            }
+            Self::RemoveManagedFile { path } => format!("remove_managed_file {}", path),
+            Self::LinkManagedFile { target, link } => {
+                format!("link_managed_file {} -> {}", link, target)
+            }
+            Self::IssueCertificate { domain } => format!("issue_certificate {}", domain),
+            Self::RemoveCertificate { domain } => format!("remove_certificate {}", domain),
+            Self::WriteHostsBlock { names } => format!("write_hosts_block {}", names.join(",")),
+            Self::ControlUnit { unit, action } => format!("control_unit {} {}", action, unit),
+            Self::ChownSitePath { path } => format!("chown_site_path {}", path),
+            Self::SocketOwners { inodes } => format!("socket_owners {} sockets", inodes.len()),
+        }
+    }
+}
+
+// ============================================================================
+// Validation
+// ============================================================================
+
+fn plain_absolute(path: &Path) -> bool {
+    path.is_absolute()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::RootDir | Component::Normal(_)))
+}
+
+/// Only ServerMark's own files: site configs in the web server directories
+/// and `servermark*` files in a few config directories
+pub fn validate_managed_path(path: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    let not_allowed = || format!("{} is not a ServerMark managed file", path.display());
+    if !plain_absolute(path) {
+        return Err(not_allowed());
+    }
+    let (Some(dir), Some(file)) = (path.parent(), path.file_name()) else {
+        return Err(not_allowed());
+    };
+    let dir = dir.to_string_lossy();
+    let file = file.to_string_lossy();
+
+    let site_file = all_backends().iter().any(|backend| {
+        backend.site_dirs().contains(&dir.as_ref())
+            && backend
+                .site_name_from_file(&file)
+                .is_some_and(|name| name != "*" && valid_name(name))
+    });
+    let managed_file = MANAGED_DIRS
+        .iter()
+        .any(|(managed, prefix)| dir == *managed && file.starts_with(prefix));
+
+    if site_file || managed_file {
+        Ok(())
+    } else {
+        Err(not_allowed())
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with('.')
+}
+
+pub fn validate_domain(domain: &str) -> Result<(), String> {
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid domain: {}", domain))
+    }
+}
+
+pub fn validate_unit(unit: &str, action: &str) -> Result<(), String> {
+    if !matches!(
+        action,
+        "start" | "stop" | "restart" | "reload" | "enable" | "disable"
+    ) {
+        return Err(format!("Unknown action: {}", action));
+    }
+
+    let name = unit.strip_suffix(".service").unwrap_or(unit);
+    let php_fpm = name
+        .strip_prefix("php")
+        .and_then(|rest| rest.strip_suffix("-fpm"))
+        .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'));
+    let own = name
+        .strip_prefix("servermark-")
+        .is_some_and(|rest| valid_name(rest) && !rest.contains('.'));
+
+    if MANAGED_UNITS.contains(&name) || php_fpm || own {
+        Ok(())
+    } else {
+        Err(format!("{} is not a unit ServerMark manages", unit))
+    }
+}
+
+// ============================================================================
+// Helper process
+// ============================================================================
+
+/// Replace a file through a temporary sibling so readers never see a partial write
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), String> {
+    let tmp = path.with_extension("servermark-tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    if let Ok(meta) = fs::metadata(path) {
+        let _ = fs::set_permissions(&tmp, meta.permissions());
+    }
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Uid of the process on the other end of the socket
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and sized for SO_PEERCRED
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0).then_some(cred.uid)
+}
+
+/// Home directory and primary group of a uid from the passwd database
+fn passwd_entry(uid: u32) -> Option<(PathBuf, u32)> {
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    // SAFETY: all pointers reference live buffers of the advertised sizes
+    let rc = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: on success pw_dir points into `buf`, which is still alive
+    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+    Some((PathBuf::from(dir.to_string_lossy().to_string()), pwd.pw_gid))
+}
+
+/// The caller's home directory, resolved; the only place site files may live
+fn user_home(uid: u32) -> Result<PathBuf, String> {
+    let home = passwd_entry(uid)
+        .and_then(|(home, _)| fs::canonicalize(home).ok())
+        .ok_or("Unknown user")?;
+    let system = home == Path::new("/") || SYSTEM_DIRS.iter().any(|dir| home.starts_with(dir));
+    if system {
+        return Err(format!("{} is not a user home directory", home.display()));
+    }
+    Ok(home)
+}
+
+/// Resolved paths of the sites in the user's sites.json, below their home
+fn registered_site_paths(home: &Path) -> Vec<PathBuf> {
+    let config: SitesConfig = fs::read_to_string(home.join(".config/servermark/sites.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    config
+        .sites
+        .iter()
+        .filter_map(|site| fs::canonicalize(&site.path).ok())
+        .filter(|path| path.starts_with(home) && path != home)
+        .collect()
+}
+
+/// `path` must be the caller's own file or directory in one of their
+/// registered sites, and not a symlink
+fn check_site_path(uid: u32, path: &str) -> Result<PathBuf, String> {
+    let home = user_home(uid)?;
+    let meta = fs::symlink_metadata(path).map_err(|e| format!("Invalid path {}: {}", path, e))?;
+    if meta.file_type().is_symlink() {
+        return Err(format!("{} is a symlink", path));
+    }
+    let path = fs::canonicalize(path).map_err(|e| format!("Invalid path {}: {}", path, e))?;
+    if !path.starts_with(&home) || path == home {
+        return Err(format!(
+            "{} is not inside your home directory",
+            path.display()
+        ));
+    }
+    if meta.uid() != uid {
+        return Err(format!("{} is not owned by you", path.display()));
+    }
+    if !registered_site_paths(&home)
+        .iter()
+        .any(|site| path.starts_with(site))
+    {
+        return Err(format!("{} is not in one of your sites", path.display()));
+    }
+    Ok(path)
+}
+
+/// Write a file the helper rendered, within the managed paths
+fn write_managed_file(path: &str, contents: &str) -> Result<(), String> {
+    validate_managed_path(path)?;
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    write_atomic(Path::new(path), contents)?;
+    let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o644));
+    Ok(())
+}
+
+fn execute_op(op: &HelperOp, uid: u32, response: &mut HelperResponse) -> Result<(), String> {
+    match op {
+        HelperOp::WriteSiteConfig { server, site } => {
+            site.validate(&user_home(uid)?)?;
+            let server = backend(*server);
+            let contents = server.render_site_config(&site.site(), site.ports());
+            write_managed_file(&server.site_config_path(&site.name), &contents)
+        }
+        HelperOp::WriteCaddyPorts { http, https } => {
+            let ports = ListenPorts {
+                http: *http,
+                https: *https,
+            };
+            write_managed_file(&caddy_ports_path(), &caddy_ports_options(ports))
+        }
+        HelperOp::WriteDnsConfig {
+            stack,
+            server,
+            tlds,
+        } => {
+            for tld in tlds {
+                validate_domain(tld)?;
+            }
+            for (path, contents) in dns_backend(*stack, *server).config_files(tlds)? {
+                write_managed_file(&path, &contents)?;
+            }
+            Ok(())
+        }
+        HelperOp::WriteDnsState { state } => {
+            let contents = serde_json::to_string_pretty(state)
+                .map_err(|e| format!("Failed to serialize DNS state: {}", e))?;
+            write_managed_file(DNS_STATE_PATH, &contents)
+        }
+        HelperOp::RemoveManagedFile { path } => {
+            validate_managed_path(path)?;
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("Failed to remove {}: {}", path, e))
+                }
+                _ => Ok(()),
+            }
+        }
+        HelperOp::LinkManagedFile { target, link } => {
+            validate_managed_path(target)?;
+            validate_managed_path(link)?;
+            let _ = fs::remove_file(link);
+            std::os::unix::fs::symlink(target, link)
+                .map_err(|e| format!("Failed to link {}: {}", link, e))
+        }
+        HelperOp::IssueCertificate { domain } => {
+            validate_domain(domain)?;
+            let cert = format!("{}/{}.crt", SSL_DIR, domain);
+            if Path::new(&cert).exists() {
+                return Ok(());
+            }
+            fs::create_dir_all(SSL_DIR)
+                .map_err(|e| format!("Failed to create {}: {}", SSL_DIR, e))?;
+            run(
+                "openssl",
+                &[
+                    "req",
+                    "-x509",
+                    "-nodes",
+                    "-days",
+                    "365",
+                    "-newkey",
+                    "rsa:2048",
+                    "-keyout",
+                    &format!("{}/{}.key", SSL_DIR, domain),
+                    "-out",
+                    &cert,
+                    "-subj",
+                    &format!("/CN={}", domain),
+                ],
+            )
+        }
+        HelperOp::RemoveCertificate { domain } => {
+            validate_domain(domain)?;
+            let _ = fs::remove_file(format!("{}/{}.crt", SSL_DIR, domain));
+            let _ = fs::remove_file(format!("{}/{}.key", SSL_DIR, domain));
+            let _ = fs::remove_dir_all(format!("{}/{}", CADDY_CERT_DIR, domain));
+            Ok(())
+        }
+        HelperOp::WriteHostsBlock { names } => {
+            for name in names {
+                validate_domain(name)?;
+            }
+            let hosts = fs::read_to_string(HOSTS_FILE)
+                .map_err(|e| format!("Failed to read {}: {}", HOSTS_FILE, e))?;
+            let updated = update_hosts(&hosts, names);
+            if updated == hosts {
+                return Ok(());
+            }
+            write_atomic(Path::new(HOSTS_FILE), &updated)
+        }
+        HelperOp::ControlUnit { unit, action } => {
+            validate_unit(unit, action)?;
+            // Refuse to load a broken web server config
+            if matches!(action.as_str(), "start" | "restart" | "reload") {
+                let name = unit.strip_suffix(".service").unwrap_or(unit);
+                let validate = WebServerKind::ALL
+                    .into_iter()
+                    .map(super::backends::backend)
+                    .find(|b| b.service() == name)
+                    .and_then(|b| b.validate_command());
+                if let Some(check) = validate {
+                    let mut parts = check.split_whitespace();
+                    let program = parts.next().unwrap_or_default();
+                    run(program, &parts.collect::<Vec<_>>())?;
+                }
+            }
+            match action.as_str() {
+                "reload" => run("systemctl", &["reload-or-restart", unit]),
+                // Stopping a server that is not installed is not a failure
+                "stop" | "disable" => {
+                    let _ = run("systemctl", &[action, unit]);
+                    Ok(())
+                }
+                _ => run("systemctl", &[action, unit]),
+            }
+        }
+        HelperOp::ChownSitePath { path } => {
+            let path = check_site_path(uid, path)?;
+            let path = path.to_string_lossy();
+            // Symlinks are never followed: chown changes links themselves, and
+            // chmod runs as the user, who may only change their own files
+            run("chown", &["-hRP", &format!("{}:www-data", uid), &path])?;
+            let (_, gid) = passwd_entry(uid).ok_or("Unknown user")?;
+            let output = Command::new("chmod")
+                .args(["-R", "ug+rwX", &path])
+                .uid(uid)
+                .gid(gid)
+                .output()
+                .map_err(|e| format!("Failed to run chmod: {}", e))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "chmod -R ug+rwX {} failed: {}",
+                    path,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            Ok(())
+        }
+        HelperOp::SocketOwners { inodes } => {
+            let owners = socket_owners();
+            response.socket_owners.extend(
+                inodes
+                    .iter()
+                    .filter_map(|inode| owners.get(inode).map(|pid| (*inode, *pid))),
+            );
+            Ok(())
+        }
+    }
+}
+
+fn audit(uid: u32, op: &HelperOp, result: &Result<(), String>) {
+    let line = format!(
+        "{} uid={} {} {}\n",
+        chrono::Local::now().to_rfc3339(),
+        uid,
+        op.summary(),
+        match result {
+            Ok(()) => "ok".to_string(),
+            Err(e) => format!("error: {}", e),
+        }
+    );
+    if let Ok(mut log) = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(HELPER_LOG)
+    {
+        let _ = log.write_all(line.as_bytes());
+    }
+}
+
+fn handle_connection(stream: UnixStream) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(|e| e.to_string())?;
+    let uid = peer_uid(&stream).ok_or("Could not identify the caller")?;
+
+    let mut line = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_BYTES))
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read request: {}", e))?;
+
+    let mut response = HelperResponse {
+        ok: true,
+        completed: 0,
+        error: None,
+        socket_owners: HashMap::new(),
+    };
+    match serde_json::from_str::<HelperRequest>(&line) {
+        Ok(request) => {
+            for op in &request.ops {
+                let result = execute_op(op, uid, &mut response);
+                audit(uid, op, &result);
+                if let Err(e) = result {
+                    response.ok = false;
+                    response.error = Some(e);
+                    break;
+                }
+                response.completed += 1;
+            }
+        }
+        Err(e) => {
+            response.ok = false;
+            response.error = Some(format!("Invalid request: {}", e));
+        }
+    }
+
+    let mut payload = serde_json::to_string(&response).map_err(|e| e.to_string())?;
+    payload.push('\n');
+    (&stream)
+        .write_all(payload.as_bytes())
+        .map_err(|e| format!("Failed to reply: {}", e))
+}
+
+/// Socket passed by systemd, or a freshly bound one when started by hand
+fn helper_listener() -> Result<UnixListener, String> {
+    if std::env::var("LISTEN_FDS").as_deref() == Ok("1") {
+        // SAFETY: systemd hands over the listening socket as fd 3
+        return Ok(unsafe { UnixListener::from_raw_fd(3) });
+    }
+
+    let _ = fs::remove_file(HELPER_SOCKET);
+    let listener = UnixListener::bind(HELPER_SOCKET)
+        .map_err(|e| format!("Failed to bind {}: {}", HELPER_SOCKET, e))?;
+    let _ = fs::set_permissions(HELPER_SOCKET, fs::Permissions::from_mode(0o660));
+    let _ = run("chgrp", &[HELPER_GROUP, HELPER_SOCKET]);
+    Ok(listener)
+}
+
+/// Entry point of `servermark --privileged-helper`; returns the exit code
+pub fn run_privileged_helper() -> i32 {
+    // SAFETY: geteuid has no preconditions
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("The privileged helper must run as root");
+        return 1;
+    }
+
+    let listener = match helper_listener() {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = listener.set_nonblocking(true) {
+        eprintln!("Failed to configure socket: {}", e);
+        return 1;
+    }
+
+    let mut last_request = Instant::now();
+    while last_request.elapsed() < IDLE_TIMEOUT {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+                if let Err(e) = handle_connection(stream) {
+                    eprintln!("{}", e);
+                }
+                last_request = Instant::now();
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+// ============================================================================
+// Client
+// ============================================================================
+
+/// Whether the helper is installed and this user may talk to it
+pub fn helper_available() -> bool {
+    UnixStream::connect(HELPER_SOCKET).is_ok()
+}
+
+/// Run a batch of operations through the helper; stops at the first failure
+pub fn helper_request(ops: Vec<HelperOp>) -> Result<(), String> {
+    send_request(ops).map(|_| ())
+}
+
+/// Pids of the processes holding the listening sockets `inodes`
+pub fn helper_socket_owners(inodes: Vec<u64>) -> Result<HashMap<u64, u32>, String> {
+    send_request(vec![HelperOp::SocketOwners { inodes }]).map(|r| r.socket_owners)
+}
+
+fn send_request(ops: Vec<HelperOp>) -> Result<HelperResponse, String> {
+    let stream = UnixStream::connect(HELPER_SOCKET)
+        .map_err(|e| format!("Failed to reach the privileged helper: {}", e))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(120)))
+        .map_err(|e| e.to_string())?;
+
+    let mut payload = serde_json::to_string(&HelperRequest { ops })
+        .map_err(|e| format!("Failed to serialize request: {}", e))?;
+    payload.push('\n');
+    (&stream)
+        .write_all(payload.as_bytes())
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read helper response: {}", e))?;
+    let response: HelperResponse =
+        serde_json::from_str(&line).map_err(|e| format!("Invalid helper response: {}", e))?;
+
+    if response.ok {
+        Ok(response)
+    } else {
+        Err(response
+            .error
+            .unwrap_or_else(|| "Privileged helper failed".to_string()))
+    }
+}
+
+fn helper_units_script(exe: &str, user: &str) -> String {
+    format!(
+        r#"
+set -e
+groupadd -f {group}
+usermod -aG {group} {user}
+
+# Run from a root-owned copy, never from a path the user can write to
+install -D -o root -g root -m 0755 '{exe}' {binary}
+
+cat > /etc/systemd/system/servermark-helper.socket << 'UNITEOF'
+[Unit]
+Description=ServerMark privileged helper socket
+
+[Socket]
+ListenStream={socket}
+SocketMode=0660
+SocketUser=root
+SocketGroup={group}
+
+[Install]
+WantedBy=sockets.target
+UNITEOF
+
+cat > /etc/systemd/system/servermark-helper.service << 'UNITEOF'
+[Unit]
+Description=ServerMark privileged helper
+Requires=servermark-helper.socket
+
+[Service]
+ExecStart={binary} --privileged-helper
+UNITEOF
+
+touch {log}
+chmod 600 {log}
+systemctl daemon-reload
+systemctl enable --now servermark-helper.socket
+"#,
+        group = HELPER_GROUP,
+        user = user,
+        socket = HELPER_SOCKET,
+        exe = exe,
+        binary = HELPER_BINARY,
+        log = HELPER_LOG,
+    )
+}
+
+/// Install the socket-activated helper (one password prompt); run again
+/// after updating ServerMark to update the helper's copy of the binary
+#[tauri::command]
+pub fn install_helper() -> Result<HelperStatus, String> {
+    let exe = app_executable()?;
+    let user = std::env::var("USER").map_err(|_| "Could not determine the current user")?;
+
+    let output = Command::new("pkexec")
+        .args([
+            "bash",
+            "-c",
+            &helper_units_script(&exe.to_string_lossy(), &user),
+        ])
+        .output()
+        .map_err(|e| format!("Failed to install helper: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to install helper: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(get_helper_status())
+}
+
+/// Whether the helper is installed and reachable (the group change needs a new login)
+#[tauri::command]
+pub fn get_helper_status() -> HelperStatus {
+    HelperStatus {
+        installed: Path::new("/etc/systemd/system/servermark-helper.socket").exists(),
+        reachable: helper_available(),
+        socket: HELPER_SOCKET.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registered_site_paths() {
+        let home = std::env::temp_dir().join(format!("servermark-home-{}", std::process::id()));
+        fs::create_dir_all(home.join("shop/storage")).unwrap();
+        fs::create_dir_all(home.join(".config/servermark")).unwrap();
+        let home = fs::canonicalize(&home).unwrap();
+        let site = |path: &Path| {
+            format!(
+                r#"{{"id":"s","name":"Shop","path":"{}","domain":"shop.test","php_version":"8.3",
+                    "secured":false,"site_type":"laravel","proxy_target":null,"laravel":null}}"#,
+                path.display()
+            )
+        };
+        fs::write(
+            home.join(".config/servermark/sites.json"),
+            format!(
+                r#"{{"sites":[{},{},{}],"tld":"test","sites_path":"/srv"}}"#,
+                site(&home.join("shop")),
+                site(&home),
+                site(Path::new("/etc"))
+            ),
+        )
+        .unwrap();
+
+        let sites = registered_site_paths(&home);
+        fs::remove_dir_all(&home).unwrap();
+        assert_eq!(sites, vec![home.join("shop")]);
+    }
+
+    #[test]
+    fn test_validate_managed_path() {
+        assert!(validate_managed_path("/etc/caddy/sites.d/shop.conf").is_ok());
+        assert!(validate_managed_path("/etc/nginx/sites-enabled/servermark-shop").is_ok());
+        assert!(validate_managed_path("/etc/httpd/conf.d/servermark-shop.conf").is_ok());
+        assert!(validate_managed_path("/etc/dnsmasq.d/servermark.conf").is_ok());
+        assert!(validate_managed_path("/etc/caddy/sites.d/00-servermark-ports.conf").is_ok());
+
+        assert!(validate_managed_path("/etc/nginx/sites-enabled/default").is_err());
+        assert!(validate_managed_path("/etc/caddy/sites.d/../Caddyfile").is_err());
+        assert!(validate_managed_path("/etc/caddy/Caddyfile").is_err());
+        assert!(validate_managed_path("/etc/dnsmasq.d/other.conf").is_err());
+        assert!(validate_managed_path("etc/caddy/sites.d/shop.conf").is_err());
+    }
+
+    #[test]
+    fn test_validate_unit_and_domain() {
+        assert!(validate_unit("nginx", "reload").is_ok());
+        assert!(validate_unit("php8.3-fpm.service", "restart").is_ok());
+        assert!(validate_unit("servermark-http.socket", "start").is_err());
+        assert!(validate_unit("sshd", "stop").is_err());
+        assert!(validate_unit("nginx", "mask").is_err());
+
+        assert!(validate_domain("my-app.test").is_ok());
+        assert!(validate_domain("app.test; rm -rf /").is_err());
+        assert!(validate_domain("-bad.test").is_err());
+    }
+
+    fn spec() -> SiteConfigSpec {
+        SiteConfigSpec {
+            name: "shop".to_string(),
+            domains: vec!["shop.test".to_string(), "www.shop.test".to_string()],
+            lan_hostname: Some("shop.local".to_string()),
+            document_root: "/home/dev/shop/public".to_string(),
+            php_version: "8.3".to_string(),
+            secured: true,
+            http_port: 80,
+            https_port: 443,
+        }
+    }
+
+    #[test]
+    fn test_site_config_spec_validation() {
+        let home = Path::new("/home/dev");
+        assert!(spec().validate(home).is_ok());
+
+        for root in [
+            "/etc",
+            "/home/dev",
+            "/home/dev/../../etc",
+            "shop/public",
+            "/home/dev/shop\"; ErrorLog \"|/bin/sh",
+        ] {
+            let spec = SiteConfigSpec {
+                document_root: root.to_string(),
+                ..spec()
+            };
+            assert!(spec.validate(home).is_err(), "{}", root);
+        }
+
+        let mut bad = spec();
+        bad.domains = vec!["shop.test\nSITEEOF".to_string()];
+        assert!(bad.validate(home).is_err());
+        let mut bad = spec();
+        bad.php_version = "8.3;rm".to_string();
+        assert!(bad.validate(home).is_err());
+        let mut bad = spec();
+        bad.name = "../shop".to_string();
+        assert!(bad.validate(home).is_err());
+    }
+
+    #[test]
+    fn test_helper_renders_the_same_site_config() {
+        let ports = ListenPorts::default();
+        let site = spec().site();
+        let rendered = SiteConfigSpec::new(&site, ports);
+        assert_eq!(rendered, spec());
+        for server in all_backends() {
+            assert_eq!(
+                server.render_site_config(&rendered.site(), rendered.ports()),
+                server.render_site_config(&site, ports)
+            );
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs;
+
+use super::sites::Site;
+use super::webserver::site_domains;
+
+// ServerMark's host names live in a delimited block of /etc/hosts that is
+// rewritten as a whole from sites.json. Lines outside the block are left
+// alone, except that loose entries older versions appended for the same
+// names are folded into it, and their loopback Docker hostnames dropped.
+
+pub const HOSTS_FILE: &str = "/etc/hosts";
+const BLOCK_BEGIN: &str = "# BEGIN servermark";
+const BLOCK_END: &str = "# END servermark";
+
+/// Common Docker hostnames that can resolve to localhost (opt-in, for Laravel
+/// projects migrated from Docker)
+pub const DOCKER_HOSTNAMES: &[&str] = &[
+    "mysql",
+    "mariadb",
+    "postgres",
+    "redis",
+    "memcached",
+    "mailhog",
+    "mailpit",
+    "meilisearch",
+    "elasticsearch",
+    "mongo",
+    "mongodb",
+    "rabbitmq",
+    "minio",
+];
+
+/// Every name the managed block should map: site domains and aliases, then
+/// the Docker hostnames when enabled
+pub fn managed_host_names(sites: &[Site], docker_aliases: bool) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let docker: &[&str] = if docker_aliases {
+        DOCKER_HOSTNAMES
+    } else {
+        &[]
+    };
+    for name in sites
+        .iter()
+        .flat_map(site_domains)
+        .chain(docker.iter().copied())
+    {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// The block itself, with an IPv4 and an IPv6 entry per name
+pub fn render_hosts_block(names: &[String]) -> String {
+    let mut block = format!("{}\n", BLOCK_BEGIN);
+    for name in names {
+        block.push_str(&format!("127.0.0.1 {}\n::1 {}\n", name, name));
+    }
+    block.push_str(BLOCK_END);
+    block.push('\n');
+    block
+}
+
+/// Drop `names` from a loopback line; `None` when no host name is left
+fn strip_names(line: &str, names: &[String]) -> Option<String> {
+    let (entry, comment) = match line.find('#') {
+        Some(i) => (&line[..i], &line[i..]),
+        None => (line, ""),
+    };
+    let mut fields = entry.split_whitespace();
+    let ip = fields.next();
+    let hosts: Vec<&str> = fields.collect();
+    let loopback = matches!(ip, Some("127.0.0.1") | Some("::1"));
+
+    if !loopback || !hosts.iter().any(|h| names.iter().any(|n| n == h)) {
+        return Some(line.to_string());
+    }
+
+    let kept: Vec<&str> = hosts
+        .into_iter()
+        .filter(|h| !names.iter().any(|n| n == h))
+        .collect();
+    if kept.is_empty() {
+        return None;
+    }
+    let mut line = format!("{} {}", ip.unwrap_or_default(), kept.join(" "));
+    if !comment.is_empty() {
+        line.push(' ');
+        line.push_str(comment);
+    }
+    Some(line)
+}
+
+/// Replace the managed block in place (or append it) and fold loose loopback
+/// entries for the same names into it. Loose loopback Docker hostnames are
+/// always dropped, as only the block maps them now. An empty name list
+/// removes the block.
+pub fn update_hosts(hosts: &str, names: &[String]) -> String {
+    let mut loose = names.to_vec();
+    loose.extend(DOCKER_HOSTNAMES.iter().map(|name| name.to_string()));
+
+    let mut before = String::new();
+    let mut after = String::new();
+    let mut in_block = false;
+    let mut seen_block = false;
+
+    for line in hosts.lines() {
+        match line.trim() {
+            BLOCK_BEGIN => {
+                in_block = true;
+                seen_block = true;
+                continue;
+            }
+            BLOCK_END if in_block => {
+                in_block = false;
+                continue;
+            }
+            _ if in_block => continue,
+            _ => {}
+        }
+        if let Some(line) = strip_names(line, &loose) {
+            let out = if seen_block { &mut after } else { &mut before };
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    if names.is_empty() {
+        return before + &after;
+    }
+    if !before.is_empty() && !seen_block && !before.ends_with("\n\n") {
+        before.push('\n');
+    }
+    before + &render_hosts_block(names) + &after
+}
+
+/// Shell commands replacing /etc/hosts with the updated copy (same directory,
+/// so the final `mv` is atomic); empty when nothing changes
+pub fn hosts_script(names: &[String]) -> String {
+    let hosts = fs::read_to_string(HOSTS_FILE).unwrap_or_default();
+    let updated = update_hosts(&hosts, names);
+    if updated == hosts {
+        return String::new();
+    }
+    format!(
+        "cat > '{file}.servermark-tmp' << 'HOSTSEOF'\n{contents}HOSTSEOF\nchmod 644 '{file}.servermark-tmp'\nmv -f '{file}.servermark-tmp' '{file}'\n",
+        file = HOSTS_FILE,
+        contents = updated
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_update_hosts_replaces_block_and_folds_loose_entries() {
+        let hosts =
+            "127.0.0.1 localhost\n127.0.0.1 myapp.test\n127.0.0.1 app.test www.app.test # site\n";
+
+        let updated = update_hosts(hosts, &names(&["app.test"]));
+        assert_eq!(
+            updated,
+            "127.0.0.1 localhost\n127.0.0.1 myapp.test\n127.0.0.1 www.app.test # site\n\n\
+             # BEGIN servermark\n127.0.0.1 app.test\n::1 app.test\n# END servermark\n"
+        );
+        assert_eq!(update_hosts(&updated, &names(&["app.test"])), updated);
+
+        // Removing app.test leaves myapp.test alone
+        let replaced = update_hosts(&updated, &names(&["shop.test"]));
+        assert!(replaced.contains("127.0.0.1 myapp.test\n"));
+        assert!(!replaced.contains(" app.test"));
+        assert!(replaced.contains("::1 shop.test\n# END servermark\n"));
+    }
+
+    #[test]
+    fn test_update_hosts_keeps_surrounding_lines_and_drops_empty_block() {
+        let hosts = "127.0.0.1 localhost\n# BEGIN servermark\n127.0.0.1 old.test\n# END servermark\n10.0.0.5 nas\n";
+
+        let updated = update_hosts(hosts, &names(&["new.test"]));
+        assert_eq!(
+            updated,
+            "127.0.0.1 localhost\n# BEGIN servermark\n127.0.0.1 new.test\n::1 new.test\n# END servermark\n10.0.0.5 nas\n"
+        );
+        assert_eq!(
+            update_hosts(hosts, &[]),
+            "127.0.0.1 localhost\n10.0.0.5 nas\n"
+        );
+    }
+
+    #[test]
+    fn test_update_hosts_drops_legacy_docker_hostnames() {
+        let hosts = "127.0.0.1 localhost\n127.0.0.1 mysql\n127.0.0.1 redis\n::1 redis\n10.0.0.7 mysql\n127.0.0.1 nas mailpit\n";
+
+        assert_eq!(
+            update_hosts(hosts, &names(&["app.test"])),
+            "127.0.0.1 localhost\n10.0.0.7 mysql\n127.0.0.1 nas\n\n\
+             # BEGIN servermark\n127.0.0.1 app.test\n::1 app.test\n# END servermark\n"
+        );
+        assert_eq!(
+            update_hosts(hosts, &[]),
+            "127.0.0.1 localhost\n10.0.0.7 mysql\n127.0.0.1 nas\n"
+        );
+    }
+}
@@ -0,0 +1,558 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use super::compose::LABEL_COMPOSE_PROJECT;
+use super::docker::image_tag;
+use super::engine::{encode, EngineClient, LineBuffer};
+use super::ownership::{owned_containers, replace_container_id};
+
+// Image pulls with progress, and updates of managed containers. A container
+// is out of date when the image it runs is no longer what its tag points to,
+// locally or in the registry. Updating recreates it from its own settings
+// with the new image, keeping its volumes.
+
+/// Minimum time between "image-pull-progress" events of the same status
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Payload of "image-pull-progress"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImagePullProgress {
+    pub image: String,
+    /// Latest status line (`Downloading`, `Pull complete`, `Digest: ...`)
+    pub status: String,
+    pub layer: Option<String>,
+    /// Bytes downloaded and to download, over the layers seen so far
+    pub current: u64,
+    pub total: u64,
+    pub layers: usize,
+    pub layers_done: usize,
+}
+
+/// A line of the `POST /images/create` stream
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PullMessage {
+    status: String,
+    id: Option<String>,
+    progress_detail: Option<ProgressDetail>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProgressDetail {
+    current: u64,
+    total: u64,
+}
+
+#[derive(Debug, Default)]
+struct LayerState {
+    current: u64,
+    total: u64,
+    done: bool,
+}
+
+/// Folds the stream's per-layer messages into overall progress
+#[derive(Debug, Default)]
+struct PullTracker {
+    image: String,
+    layers: HashMap<String, LayerState>,
+}
+
+impl PullTracker {
+    fn apply(&mut self, message: &PullMessage) -> ImagePullProgress {
+        // Lines without an id are about the whole image (`Pulling from ...`)
+        if let Some(id) = &message.id {
+            if !message.status.starts_with("Pulling from") {
+                let layer = self.layers.entry(id.clone()).or_default();
+                match message.status.as_str() {
+                    "Downloading" => {
+                        if let Some(detail) = &message.progress_detail {
+                            layer.current = detail.current;
+                            layer.total = detail.total.max(layer.total);
+                        }
+                    }
+                    "Download complete" | "Pull complete" | "Already exists" => {
+                        layer.current = layer.total;
+                        layer.done |= message.status != "Download complete";
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        ImagePullProgress {
+            image: self.image.clone(),
+            status: message.status.clone(),
+            layer: message.id.clone(),
+            current: self.layers.values().map(|l| l.current).sum(),
+            total: self.layers.values().map(|l| l.total).sum(),
+            layers: self.layers.len(),
+            layers_done: self.layers.values().filter(|l| l.done).count(),
+        }
+    }
+}
+
+/// Pull an image, reporting progress for every line the engine sends
+pub fn pull_with_progress(
+    client: &EngineClient,
+    image: &str,
+    mut on_progress: impl FnMut(&ImagePullProgress),
+) -> Result<(), String> {
+    let (repository, tag) = image_tag(image);
+    let mut lines = LineBuffer::default();
+    let mut tracker = PullTracker {
+        image: image.to_string(),
+        ..Default::default()
+    };
+    let mut error = None;
+    let mut handle = |line: &String| {
+        let Ok(message) = serde_json::from_str::<PullMessage>(line) else {
+            return true;
+        };
+        // Failures mid-pull arrive as an `error` line in the progress stream
+        if let Some(e) = message.error {
+            error = Some(e);
+            return false;
+        }
+        on_progress(&tracker.apply(&message));
+        true
+    };
+
+    client.stream(
+        "POST",
+        &format!(
+            "/images/create?fromImage={}&tag={}",
+            encode(repository),
+            encode(tag)
+        ),
+        None,
+        |data| lines.push(data).iter().all(&mut handle),
+    )?;
+    // The last line may end with the stream rather than a newline
+    lines.push(b"\n").iter().all(handle);
+
+    match error {
+        Some(e) => Err(format!("Failed to pull {}: {}", image, e)),
+        None => Ok(()),
+    }
+}
+
+/// Pull with progress as "image-pull-progress" events, at most one per
+/// `PROGRESS_INTERVAL` while the status stays the same
+fn pull_emitting(app: &AppHandle, client: &EngineClient, image: &str) -> Result<(), String> {
+    let mut last: Option<(Instant, String)> = None;
+    pull_with_progress(client, image, |progress| {
+        let due = last.as_ref().map_or(true, |(at, status)| {
+            *status != progress.status || at.elapsed() >= PROGRESS_INTERVAL
+        });
+        if due {
+            let _ = app.emit("image-pull-progress", progress);
+            last = Some((Instant::now(), progress.status.clone()));
+        }
+    })
+}
+
+/// An image reference as it appears in API paths, which take it unescaped
+fn image_path(image: &str) -> Result<&str, String> {
+    let valid = !image.is_empty()
+        && image
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@".contains(c));
+    if valid {
+        Ok(image)
+    } else {
+        Err(format!("Invalid image reference: {}", image))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerUpdate {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// Digest the local tag was pulled by
+    pub local_digest: Option<String>,
+    /// Digest the tag points to in the registry
+    pub remote_digest: Option<String>,
+    pub update_available: bool,
+    /// Why the check failed (registry unreachable, image built locally, ...)
+    pub error: Option<String>,
+}
+
+fn check_update(client: &EngineClient, id: &str) -> Result<ContainerUpdate, String> {
+    let details: Value = client.get(&format!("/containers/{}/json", encode(id)))?;
+    let image = details["Config"]["Image"].as_str().unwrap_or_default();
+    let mut update = ContainerUpdate {
+        id: details["Id"].as_str().unwrap_or(id).to_string(),
+        name: details["Name"]
+            .as_str()
+            .unwrap_or_default()
+            .trim_start_matches('/')
+            .to_string(),
+        image: image.to_string(),
+        local_digest: None,
+        remote_digest: None,
+        update_available: false,
+        error: None,
+    };
+    if image.starts_with("sha256:") || image.contains('@') {
+        update.error = Some("Not created from a tag".to_string());
+        return Ok(update);
+    }
+    let path = image_path(image)?;
+
+    // What the tag points to locally; a newer pull moves it off the container's image
+    let local: Value = client.get(&format!("/images/{}/json", path))?;
+    let digests: Vec<&str> = local["RepoDigests"]
+        .as_array()
+        .map(|digests| {
+            digests
+                .iter()
+                .filter_map(|d| d.as_str()?.split_once('@').map(|(_, digest)| digest))
+                .collect()
+        })
+        .unwrap_or_default();
+    update.local_digest = digests.first().map(|d| d.to_string());
+    let pulled_newer = local["Id"] != details["Image"];
+
+    match client.get::<Value>(&format!("/distribution/{}/json", path)) {
+        Ok(remote) => {
+            let digest = remote["Descriptor"]["digest"].as_str().map(str::to_string);
+            update.update_available = pulled_newer
+                || digest
+                    .as_deref()
+                    .is_some_and(|digest| !digests.contains(&digest));
+            update.remote_digest = digest;
+        }
+        Err(e) => {
+            update.update_available = pulled_newer;
+            update.error = Some(e);
+        }
+    }
+    Ok(update)
+}
+
+/// Create body reproducing a container. Settings equal to what its old image
+/// supplied are dropped, so the new image's defaults (`PATH`, version
+/// variables, entrypoint) apply. Every volume stays mounted where it was.
+fn recreate_body(details: &Value, old_image: &Value) -> Value {
+    let mut body = details["Config"].clone();
+    let defaults = &old_image["Config"];
+
+    if let Some(env) = body["Env"].as_array_mut() {
+        let image_env = defaults["Env"].as_array().cloned().unwrap_or_default();
+        env.retain(|entry| !image_env.contains(entry));
+    }
+    if let Some(labels) = body["Labels"].as_object_mut() {
+        if let Some(image_labels) = defaults["Labels"].as_object() {
+            labels.retain(|key, value| image_labels.get(key) != Some(value));
+        }
+    }
+    if let Some(config) = body.as_object_mut() {
+        for key in [
+            "Cmd",
+            "Entrypoint",
+            "WorkingDir",
+            "User",
+            "StopSignal",
+            "Healthcheck",
+        ] {
+            if config.get(key).is_some() && config.get(key) == defaults.get(key) {
+                config.remove(key);
+            }
+        }
+        // The engine defaults the host name to the id of the container
+        let hostname = config.get("Hostname").and_then(Value::as_str);
+        if hostname.is_some_and(|h| details["Id"].as_str().unwrap_or_default().starts_with(h)) {
+            config.remove("Hostname");
+        }
+    }
+
+    let mut host_config = details["HostConfig"].clone();
+    let mut binds: Vec<String> = host_config["Binds"]
+        .as_array()
+        .map(|binds| {
+            binds
+                .iter()
+                .filter_map(|b| b.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    // Volumes the image declared were mounted without a bind; reuse them by name
+    for mount in details["Mounts"].as_array().into_iter().flatten() {
+        let (Some(name), Some(destination)) =
+            (mount["Name"].as_str(), mount["Destination"].as_str())
+        else {
+            continue;
+        };
+        let bound = binds
+            .iter()
+            .any(|b| b.split(':').nth(1) == Some(destination))
+            || host_config["Mounts"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|m| m["Target"] == destination);
+        if mount["Type"] == "volume" && !bound {
+            binds.push(format!("{}:{}", name, destination));
+        }
+    }
+    host_config["Binds"] = json!(binds);
+    body["HostConfig"] = host_config;
+    body
+}
+
+/// Replace a container with one created from the same settings and volumes
+/// and the current image of its tag. The old one is kept until the new one
+/// has started, and put back if it fails to.
+fn recreate_with(client: &EngineClient, id: &str) -> Result<String, String> {
+    let details: Value = client.get(&format!("/containers/{}/json", encode(id)))?;
+    if details["Config"]["Labels"][LABEL_COMPOSE_PROJECT].is_string() {
+        return Err(
+            "This container belongs to a compose project; update it with compose up".to_string(),
+        );
+    }
+    let old_id = details["Id"].as_str().unwrap_or(id).to_string();
+    let name = details["Name"]
+        .as_str()
+        .unwrap_or_default()
+        .trim_start_matches('/')
+        .to_string();
+    let running = details["State"]["Running"].as_bool().unwrap_or(false);
+    let old_image: Value = details["Image"]
+        .as_str()
+        .and_then(|image| client.get(&format!("/images/{}/json", encode(image))).ok())
+        .unwrap_or(Value::Null);
+    let body = recreate_body(&details, &old_image);
+
+    let container = |action: &str| format!("/containers/{}/{}", encode(&old_id), action);
+    if running {
+        client.call("POST", &container("stop"), None)?;
+    }
+    if let Err(e) = client.call(
+        "POST",
+        &container(&format!("rename?name={}", encode(&format!("{}-old", name)))),
+        None,
+    ) {
+        if running {
+            let _ = client.call("POST", &container("start"), None);
+        }
+        return Err(e);
+    }
+    let restore_old = || {
+        let _ = client.call(
+            "POST",
+            &container(&format!("rename?name={}", encode(&name))),
+            None,
+        );
+        if running {
+            let _ = client.call("POST", &container("start"), None);
+        }
+    };
+
+    let created = client
+        .call(
+            "POST",
+            &format!("/containers/create?name={}", encode(&name)),
+            Some(&body),
+        )
+        .and_then(|response| {
+            response.json::<Value>()?["Id"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "Container created without an id".to_string())
+        });
+    let new_id = match created {
+        Ok(new_id) => new_id,
+        Err(e) => {
+            restore_old();
+            return Err(format!("Failed to recreate {}: {}", name, e));
+        }
+    };
+    if let Err(e) = client.call(
+        "POST",
+        &format!("/containers/{}/start", encode(&new_id)),
+        None,
+    ) {
+        let _ = client.call(
+            "DELETE",
+            &format!("/containers/{}?force=true", encode(&new_id)),
+            None,
+        );
+        restore_old();
+        return Err(format!("Failed to start the recreated {}: {}", name, e));
+    }
+
+    // Named volumes are not removed with the container
+    client.call("DELETE", &format!("/containers/{}", encode(&old_id)), None)?;
+    replace_container_id(&old_id, &new_id)?;
+    Ok(new_id)
+}
+
+/// Pull an image, streaming layer progress as "image-pull-progress" events
+#[tauri::command]
+pub async fn pull_image(app: AppHandle, image: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        pull_emitting(&app, &EngineClient::connect()?, &image)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Compare the image of containers (all ServerMark containers when `ids` is
+/// empty) with the current image of their tag
+#[tauri::command]
+pub async fn check_container_updates(
+    ids: Option<Vec<String>>,
+) -> Result<Vec<ContainerUpdate>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let client = EngineClient::connect()?;
+        let ids = match ids {
+            Some(ids) if !ids.is_empty() => ids,
+            _ => owned_containers(&client)?
+                .into_iter()
+                .map(|c| c.id)
+                .collect(),
+        };
+        ids.iter().map(|id| check_update(&client, id)).collect()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Update a container: pull its tag (unless `pull` is false) and recreate it
+/// with the same settings and volumes. Returns the new container's id.
+#[tauri::command]
+pub async fn recreate_container(
+    app: AppHandle,
+    id: String,
+    pull: Option<bool>,
+) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let client = EngineClient::connect()?;
+        if pull.unwrap_or(true) {
+            let details: Value = client.get(&format!("/containers/{}/json", encode(&id)))?;
+            let image = details["Config"]["Image"].as_str().unwrap_or_default();
+            pull_emitting(&app, &client, image_path(image)?)?;
+        }
+        recreate_with(&client, &id)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine::FakeEngine;
+    use super::*;
+
+    #[test]
+    fn test_pull_progress_is_aggregated_over_layers() {
+        let stream = [
+            r#"{"status":"Pulling from library/mysql","id":"8.0"}"#,
+            r#"{"status":"Pulling fs layer","progressDetail":{},"id":"a1"}"#,
+            r#"{"status":"Already exists","progressDetail":{},"id":"b2"}"#,
+            r#"{"status":"Downloading","progressDetail":{"current":40,"total":100},"id":"a1"}"#,
+            r#"{"status":"Downloading","progressDetail":{"current":100,"total":100},"id":"a1"}"#,
+            r#"{"status":"Pull complete","progressDetail":{},"id":"a1"}"#,
+            r#"{"status":"Status: Downloaded newer image for mysql:8.0"}"#,
+        ]
+        .join("\n");
+        let engine = FakeEngine::start(vec![("POST", "/images/create", 200, stream)]);
+
+        let mut seen = Vec::new();
+        pull_with_progress(&engine.client, "mysql:8.0", |p| seen.push(p.clone())).unwrap();
+
+        assert_eq!(seen.len(), 7);
+        assert_eq!((seen[3].current, seen[3].total), (40, 100));
+        assert_eq!(seen[3].layers, 2);
+        assert_eq!(seen[3].layers_done, 1);
+        let last = seen.last().unwrap();
+        assert_eq!((last.current, last.total, last.layers_done), (100, 100, 2));
+        assert_eq!(last.layer, None);
+        assert_eq!(
+            engine.requests(),
+            vec!["POST /images/create?fromImage=mysql&tag=8.0 "]
+        );
+    }
+
+    #[test]
+    fn test_pull_error_in_stream() {
+        let stream = [
+            r#"{"status":"Pulling from library/mysql","id":"9.9"}"#,
+            r#"{"errorDetail":{"message":"manifest unknown"},"error":"manifest unknown"}"#,
+        ]
+        .join("\n");
+        let engine = FakeEngine::start(vec![("POST", "/images/create", 200, stream)]);
+
+        let err = pull_with_progress(&engine.client, "mysql:9.9", |_| {}).unwrap_err();
+        assert_eq!(err, "Failed to pull mysql:9.9: manifest unknown");
+    }
+
+    #[test]
+    fn test_check_update_compares_digests() {
+        let container = r#"{"Id":"abc","Name":"/servermark-mysql","Image":"sha256:old",
+            "Config":{"Image":"mysql:8.0"}}"#;
+        let engine = FakeEngine::start(vec![
+            ("GET", "/containers/abc/json", 200, container.to_string()),
+            (
+                "GET",
+                "/images/mysql:8.0/json",
+                200,
+                r#"{"Id":"sha256:old","RepoDigests":["mysql@sha256:aaa"]}"#.to_string(),
+            ),
+            (
+                "GET",
+                "/distribution/mysql:8.0/json",
+                200,
+                r#"{"Descriptor":{"digest":"sha256:bbb"}}"#.to_string(),
+            ),
+        ]);
+
+        let update = check_update(&engine.client, "abc").unwrap();
+        assert_eq!(update.name, "servermark-mysql");
+        assert_eq!(update.local_digest.as_deref(), Some("sha256:aaa"));
+        assert_eq!(update.remote_digest.as_deref(), Some("sha256:bbb"));
+        assert!(update.update_available);
+        assert_eq!(update.error, None);
+    }
+
+    #[test]
+    fn test_recreate_body_drops_image_defaults_and_keeps_volumes() {
+        let details = json!({
+            "Id": "0123456789ab",
+            "Config": {
+                "Hostname": "0123456789ab",
+                "Image": "mysql:8.0",
+                "Env": ["MYSQL_ROOT_PASSWORD=secret", "MYSQL_VERSION=8.0.35"],
+                "Cmd": ["mysqld"],
+                "Labels": {"servermark.managed": "true", "org.image": "mysql"}
+            },
+            "HostConfig": {"Binds": ["/srv/conf:/etc/mysql/conf.d:ro"], "Memory": 0},
+            "Mounts": [
+                {"Type": "bind", "Source": "/srv/conf", "Destination": "/etc/mysql/conf.d"},
+                {"Type": "volume", "Name": "f00d", "Destination": "/var/lib/mysql"}
+            ]
+        });
+        let old_image = json!({"Config": {
+            "Env": ["MYSQL_VERSION=8.0.35"],
+            "Cmd": ["mysqld"],
+            "Labels": {"org.image": "mysql"}
+        }});
+
+        let body = recreate_body(&details, &old_image);
+        assert_eq!(body["Env"], json!(["MYSQL_ROOT_PASSWORD=secret"]));
+        assert_eq!(body["Labels"], json!({"servermark.managed": "true"}));
+        assert!(body.get("Cmd").is_none());
+        assert!(body.get("Hostname").is_none());
+        assert_eq!(body["Image"], "mysql:8.0");
+        assert_eq!(
+            body["HostConfig"]["Binds"],
+            json!(["/srv/conf:/etc/mysql/conf.d:ro", "f00d:/var/lib/mysql"])
+        );
+    }
+}
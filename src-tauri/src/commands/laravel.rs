@@ -398,7 +398,7 @@ fn get_site_slug(site_path: &str) -> String {
 }
 
 /// Get the systemd user service name for a site
-fn get_queue_service_name(site_path: &str) -> String {
+pub fn get_queue_service_name(site_path: &str) -> String {
     format!("servermark-queue-{}.service", get_site_slug(site_path))
 }
 
@@ -0,0 +1,558 @@
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::engine::{encode, EngineClient, LineBuffer, StreamDemuxer};
+use super::laravel::get_queue_service_name;
+use super::system::service_unit;
+
+// Following logs as they are written. Every source is turned into a stream
+// of lines with a timestamp and a detected level, emitted in batches as
+// "log-lines" events until `stop_follow`. Containers are followed through the
+// engine API, systemd units through `journalctl -f -o json`, log files
+// through `tail -F`.
+
+/// Where to follow logs from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LogSource {
+    Container {
+        id: String,
+    },
+    /// A service known to `get_service_logs` (`nginx`, `mysql`, ...)
+    Service {
+        service: String,
+    },
+    /// Any systemd unit, in the user manager when `user` is set
+    Unit {
+        unit: String,
+        #[serde(default)]
+        user: bool,
+    },
+    /// A file in the site's `storage/logs`, the current `laravel*.log` by default
+    LaravelLog {
+        site_path: String,
+        file: Option<String>,
+    },
+    QueueWorker {
+        site_path: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl LogLevel {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "DEBUG" | "TRACE" => Some(Self::Debug),
+            "INFO" | "NOTE" | "LOG" => Some(Self::Info),
+            "NOTICE" => Some(Self::Notice),
+            "WARN" | "WARNING" => Some(Self::Warning),
+            "ERROR" | "ERR" | "FATAL" => Some(Self::Error),
+            "CRITICAL" | "CRIT" | "ALERT" | "EMERGENCY" | "EMERG" | "PANIC" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+
+    /// From a syslog priority (0 emergency to 7 debug)
+    fn from_priority(priority: u8) -> Self {
+        match priority {
+            0..=2 => Self::Critical,
+            3 => Self::Error,
+            4 => Self::Warning,
+            5 => Self::Notice,
+            6 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogLine {
+    pub timestamp: Option<String>, // RFC 3339
+    pub level: Option<LogLevel>,
+    pub message: String,
+}
+
+/// Payload of "log-lines"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogBatch {
+    pub handle: u64,
+    pub lines: Vec<LogLine>,
+}
+
+/// Payload of "log-follow-ended", sent when a source stops on its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogFollowEnded {
+    pub handle: u64,
+    pub error: Option<String>,
+}
+
+/// Level named in a log line: `local.ERROR:` (Monolog), `[Warning]` (MySQL),
+/// `[error]` (nginx), `ERROR:` (PostgreSQL), `level=warn` (logfmt)
+pub fn detect_level(message: &str) -> Option<LogLevel> {
+    message
+        .split(|c: char| c.is_whitespace() || c == '[' || c == ']')
+        .filter(|token| !token.is_empty())
+        .take(6) // Levels come early; later words are the message
+        .find_map(|token| {
+            let token = token.trim_end_matches(':');
+            let name = token
+                .rsplit_once('.')
+                .map(|(_, level)| level)
+                .or_else(|| token.strip_prefix("level="))
+                .unwrap_or(token);
+            // Bare words only count when written as a level (upper case or bracketed)
+            let marked = token.ends_with(':')
+                || token.contains('.')
+                || token.starts_with("level=")
+                || name.chars().all(|c| c.is_ascii_uppercase())
+                || message.contains(&format!("[{}]", name));
+            if marked {
+                LogLevel::from_name(name)
+            } else {
+                None
+            }
+        })
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// `[2024-05-01 10:00:00] local.ERROR: message` in Laravel's local time
+pub fn parse_laravel_line(line: &str) -> LogLine {
+    let parsed = line
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("] "))
+        .and_then(|(stamp, message)| {
+            let naive = NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(stamp, "%Y-%m-%dT%H:%M:%S%.f%:z"))
+                .ok()?;
+            let local = Local.from_local_datetime(&naive).earliest()?;
+            Some((rfc3339(local.with_timezone(&Utc)), message))
+        });
+
+    match parsed {
+        Some((timestamp, message)) => LogLine {
+            timestamp: Some(timestamp),
+            level: detect_level(message),
+            message: message.to_string(),
+        },
+        // Stack trace and other continuation lines
+        None => LogLine {
+            timestamp: None,
+            level: None,
+            message: line.to_string(),
+        },
+    }
+}
+
+/// A line of `journalctl -o json`
+pub fn parse_journal_entry(line: &str) -> Option<LogLine> {
+    let entry: Value = serde_json::from_str(line).ok()?;
+    let message = match &entry["MESSAGE"] {
+        Value::String(text) => text.clone(),
+        // Non-UTF-8 messages come as byte arrays
+        Value::Array(bytes) => String::from_utf8_lossy(
+            &bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect::<Vec<_>>(),
+        )
+        .to_string(),
+        _ => return None,
+    };
+    let timestamp = entry["__REALTIME_TIMESTAMP"]
+        .as_str()
+        .and_then(|micros| micros.parse::<i64>().ok())
+        .and_then(DateTime::from_timestamp_micros)
+        .map(rfc3339);
+    let level = entry["PRIORITY"]
+        .as_str()
+        .and_then(|p| p.parse::<u8>().ok())
+        .map(LogLevel::from_priority);
+
+    Some(LogLine {
+        timestamp,
+        // Most services log everything at the default priority
+        level: detect_level(&message).or(level),
+        message,
+    })
+}
+
+/// A container log line requested with `timestamps=1`
+pub fn parse_container_line(line: &str) -> LogLine {
+    let parsed = line.split_once(' ').and_then(|(stamp, message)| {
+        let time = DateTime::parse_from_rfc3339(stamp).ok()?;
+        Some((rfc3339(time.with_timezone(&Utc)), message))
+    });
+    let (timestamp, message) = match parsed {
+        Some((timestamp, message)) => (Some(timestamp), message),
+        None => (None, line),
+    };
+    LogLine {
+        timestamp,
+        level: detect_level(message),
+        message: message.to_string(),
+    }
+}
+
+/// The log file to follow in a site's `storage/logs`: the named one, or the
+/// most recently written `laravel*.log` (daily logs get a date suffix)
+fn laravel_log_path(site_path: &str, file: Option<&str>) -> Result<PathBuf, String> {
+    let dir = Path::new(site_path).join("storage").join("logs");
+    if let Some(file) = file {
+        if file.contains('/') || file.starts_with('.') {
+            return Err(format!("Invalid log file: {}", file));
+        }
+        return Ok(dir.join(file));
+    }
+
+    let newest = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with("laravel") && name.ends_with(".log")
+        })
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok());
+    Ok(newest
+        .map(|entry| entry.path())
+        .unwrap_or_else(|| dir.join("laravel.log")))
+}
+
+// ============================================================================
+// Followers
+// ============================================================================
+
+/// Turns a line of a follow command's output into a log line
+type LineParser = fn(&str) -> Option<LogLine>;
+
+struct Follower {
+    stop: Arc<AtomicBool>,
+    child: Option<Child>,
+}
+
+impl Follower {
+    fn stop(mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Running log followers by handle (Tauri managed state)
+#[derive(Default)]
+pub struct LogFollowers {
+    followers: Mutex<HashMap<u64, Follower>>,
+}
+
+static NEXT_FOLLOWER: AtomicU64 = AtomicU64::new(1);
+
+impl LogFollowers {
+    fn insert(&self, handle: u64, follower: Follower) {
+        self.followers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(handle, follower);
+    }
+
+    fn take(&self, handle: u64) -> Option<Follower> {
+        self.followers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&handle)
+    }
+
+    /// Stop every follower, when the app exits
+    pub fn stop_all(&self) {
+        let followers: Vec<Follower> = {
+            let mut followers = self.followers.lock().unwrap_or_else(|e| e.into_inner());
+            followers.drain().map(|(_, f)| f).collect()
+        };
+        for follower in followers {
+            follower.stop();
+        }
+    }
+}
+
+fn emit_lines(app: &AppHandle, handle: u64, lines: Vec<LogLine>) {
+    if !lines.is_empty() {
+        let _ = app.emit("log-lines", LogBatch { handle, lines });
+    }
+}
+
+/// A follower's stream is over: drop its entry (reaping the command) and
+/// tell the frontend, unless `stop_follow` ended it
+fn ended(app: &AppHandle, handle: u64, stop: &AtomicBool, error: Option<String>) {
+    let stopped = stop.load(Ordering::SeqCst);
+    if let Some(follower) = app.state::<LogFollowers>().take(handle) {
+        follower.stop();
+    }
+    if !stopped {
+        let _ = app.emit("log-follow-ended", LogFollowEnded { handle, error });
+    }
+}
+
+/// Read a command's output until it exits (or is killed by `stop_follow`)
+fn watch_output(
+    app: AppHandle,
+    handle: u64,
+    stop: Arc<AtomicBool>,
+    mut output: impl Read + Send + 'static,
+    parse: LineParser,
+) {
+    thread::spawn(move || {
+        let mut lines = LineBuffer::default();
+        let mut buf = [0u8; 8192];
+        let error = loop {
+            match output.read(&mut buf) {
+                Ok(0) => break None,
+                Ok(n) => emit_lines(
+                    &app,
+                    handle,
+                    lines
+                        .push(&buf[..n])
+                        .iter()
+                        .filter_map(|l| parse(l))
+                        .collect(),
+                ),
+                Err(e) => break Some(e.to_string()),
+            }
+        };
+        ended(&app, handle, &stop, error);
+    });
+}
+
+fn follow_container(
+    app: AppHandle,
+    handle: u64,
+    stop: Arc<AtomicBool>,
+    id: String,
+    tail: u32,
+) -> Result<(), String> {
+    let client = EngineClient::connect()?;
+    thread::spawn(move || {
+        let mut demuxer = StreamDemuxer::default();
+        let mut lines = LineBuffer::default();
+        let result = client.stream(
+            "GET",
+            &format!(
+                "/containers/{}/logs?follow=1&stdout=1&stderr=1&timestamps=1&tail={}",
+                encode(&id),
+                tail
+            ),
+            None,
+            |data| {
+                let text = demuxer.push(data);
+                emit_lines(
+                    &app,
+                    handle,
+                    lines
+                        .push(&text)
+                        .iter()
+                        .map(|l| parse_container_line(l))
+                        .collect(),
+                );
+                !stop.load(Ordering::SeqCst)
+            },
+        );
+        ended(&app, handle, &stop, result.err());
+    });
+    Ok(())
+}
+
+fn spawn_follow_command(program: &str, args: &[String]) -> Result<Child, String> {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))
+}
+
+fn journal_args(unit: &str, user: bool, tail: u32) -> Vec<String> {
+    let mut args = Vec::new();
+    if user {
+        args.push("--user".to_string());
+    }
+    args.extend(["-f", "-o", "json", "-n", &tail.to_string(), "-u", unit].map(str::to_string));
+    args
+}
+
+/// Start following a log source; lines arrive as "log-lines" events.
+/// Returns a handle for `stop_follow`.
+#[tauri::command]
+pub fn follow_logs(
+    app: AppHandle,
+    followers: State<'_, LogFollowers>,
+    source: LogSource,
+    lines: Option<u32>,
+) -> Result<u64, String> {
+    let tail = lines.unwrap_or(100);
+    let handle = NEXT_FOLLOWER.fetch_add(1, Ordering::SeqCst);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let (program, args, parse): (&str, Vec<String>, LineParser) = match source {
+        LogSource::Container { id } => {
+            // Registered first, so a stream ending right away finds its entry
+            followers.insert(
+                handle,
+                Follower {
+                    stop: stop.clone(),
+                    child: None,
+                },
+            );
+            if let Err(e) = follow_container(app, handle, stop, id, tail) {
+                followers.take(handle);
+                return Err(e);
+            }
+            return Ok(handle);
+        }
+        LogSource::Service { service } => {
+            let unit =
+                service_unit(&service).ok_or_else(|| format!("Unknown service: {}", service))?;
+            (
+                "journalctl",
+                journal_args(unit, false, tail),
+                parse_journal_entry,
+            )
+        }
+        LogSource::Unit { unit, user } => (
+            "journalctl",
+            journal_args(&unit, user, tail),
+            parse_journal_entry,
+        ),
+        LogSource::QueueWorker { site_path } => (
+            "journalctl",
+            journal_args(&get_queue_service_name(&site_path), true, tail),
+            parse_journal_entry,
+        ),
+        LogSource::LaravelLog { site_path, file } => {
+            let path = laravel_log_path(&site_path, file.as_deref())?;
+            (
+                "tail",
+                vec![
+                    "-F".to_string(),
+                    "-n".to_string(),
+                    tail.to_string(),
+                    path.to_string_lossy().to_string(),
+                ],
+                |line| Some(parse_laravel_line(line)),
+            )
+        }
+    };
+
+    let mut child = spawn_follow_command(program, &args)?;
+    let output = child.stdout.take().ok_or("Failed to capture log output")?;
+    followers.insert(
+        handle,
+        Follower {
+            stop: stop.clone(),
+            child: Some(child),
+        },
+    );
+    watch_output(app, handle, stop, output, parse);
+    Ok(handle)
+}
+
+/// Stop following a log source
+#[tauri::command]
+pub fn stop_follow(followers: State<'_, LogFollowers>, handle: u64) -> Result<(), String> {
+    let follower = followers.take(handle).ok_or("No such log follower")?;
+    follower.stop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_level() {
+        let cases = [
+            (
+                "local.ERROR: SQLSTATE[HY000] Connection refused",
+                Some(LogLevel::Error),
+            ),
+            (
+                "2024-05-01T10:00:00Z 0 [Warning] [MY-010068] CA certificate",
+                Some(LogLevel::Warning),
+            ),
+            (
+                "2024/05/01 10:00:00 [error] 12#12: open() failed",
+                Some(LogLevel::Error),
+            ),
+            (
+                "ERROR:  database \"shop\" does not exist",
+                Some(LogLevel::Error),
+            ),
+            ("time=10:00 level=warn msg=slow", Some(LogLevel::Warning)),
+            (
+                "1:M 01 May 2024 10:00:00.000 * Ready to accept connections",
+                None,
+            ),
+            ("Fixed an error in the parser", None),
+        ];
+        for (line, level) in cases {
+            assert_eq!(detect_level(line), level, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_parse_laravel_line() {
+        let line = parse_laravel_line("[2024-05-01 10:00:00] local.ERROR: Oops {\"exception\":1}");
+        assert_eq!(line.level, Some(LogLevel::Error));
+        assert_eq!(line.message, "local.ERROR: Oops {\"exception\":1}");
+        let local = Local
+            .from_local_datetime(
+                &NaiveDateTime::parse_from_str("2024-05-01 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(line.timestamp, Some(rfc3339(local.with_timezone(&Utc))));
+
+        let trace = parse_laravel_line("#0 /app/vendor/laravel/framework/src/Foo.php(12)");
+        assert_eq!(trace.timestamp, None);
+        assert_eq!(trace.level, None);
+    }
+
+    #[test]
+    fn test_parse_journal_and_container_lines() {
+        let line = parse_journal_entry(
+            r#"{"MESSAGE":"Started nginx","PRIORITY":"6","__REALTIME_TIMESTAMP":"1714557600123456"}"#,
+        )
+        .unwrap();
+        assert_eq!(line.timestamp.as_deref(), Some("2024-05-01T10:00:00.123Z"));
+        assert_eq!(line.level, Some(LogLevel::Info));
+        let line = parse_journal_entry(r#"{"MESSAGE":[104,105],"PRIORITY":"3"}"#).unwrap();
+        assert_eq!(line.message, "hi");
+        assert_eq!(line.level, Some(LogLevel::Error));
+
+        let line = parse_container_line("2024-05-01T10:00:00.123456789Z [ERROR] boom");
+        assert_eq!(line.timestamp.as_deref(), Some("2024-05-01T10:00:00.123Z"));
+        assert_eq!(line.level, Some(LogLevel::Error));
+        assert_eq!(line.message, "[ERROR] boom");
+    }
+}
@@ -0,0 +1,622 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path};
+use std::process::Command;
+use std::time::Duration;
+
+use super::catalog::{find_preset, preset_container_params, PresetOptions};
+use super::docker::{create_container_with, CreateContainerParams};
+use super::engine::EngineClient;
+use super::helper::validate_domain;
+use super::php::get_php_versions;
+use super::sites::{load_sites_config, save_config, Site};
+
+/// File name of the per-project manifest, looked up at the project root
+pub const MANIFEST_FILE: &str = "servermark.yml";
+
+/// Project requirements declared in `servermark.yml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectManifest {
+    pub php: Option<PhpRequirement>,
+    pub document_root: Option<String>,
+    pub aliases: Vec<String>,
+    pub services: Vec<ServiceRequirement>,
+    pub databases: Vec<String>,
+    pub queue_worker: bool,
+    pub scheduler: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhpRequirement {
+    pub version: Option<String>,
+    pub extensions: Vec<String>,
+}
+
+/// A required container, written either as `mysql:8` or `{ name: mysql, version: "8" }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ServiceSpec")]
+pub struct ServiceRequirement {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ServiceSpec {
+    Short(String),
+    Full {
+        name: String,
+        version: Option<String>,
+    },
+}
+
+impl From<ServiceSpec> for ServiceRequirement {
+    fn from(spec: ServiceSpec) -> Self {
+        match spec {
+            ServiceSpec::Short(s) => match s.split_once(':') {
+                Some((name, version)) => Self {
+                    name: name.trim().to_string(),
+                    version: Some(version.trim().to_string()),
+                },
+                None => Self {
+                    name: s.trim().to_string(),
+                    version: None,
+                },
+            },
+            ServiceSpec::Full { name, version } => Self { name, version },
+        }
+    }
+}
+
+impl std::fmt::Display for ServiceRequirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(v) => write!(f, "{}:{}", self.name, v),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A single difference between the applied manifest and the one on disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestChange {
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestDiff {
+    pub site_id: String,
+    pub changes: Vec<ManifestChange>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManifestApplyReport {
+    pub applied: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Read `servermark.yml` from a project directory, if present
+pub fn read_manifest(project_path: &str) -> Result<Option<ProjectManifest>, String> {
+    let path = Path::new(project_path).join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", MANIFEST_FILE, e))?;
+    parse_manifest(&content).map(Some)
+}
+
+pub fn parse_manifest(content: &str) -> Result<ProjectManifest, String> {
+    if content.trim().is_empty() {
+        return Ok(ProjectManifest::default());
+    }
+    serde_yaml::from_str(content).map_err(|e| format!("Invalid {}: {}", MANIFEST_FILE, e))
+}
+
+/// Compare the previously applied manifest with the current one
+pub fn diff_manifests(
+    applied: Option<&ProjectManifest>,
+    current: &ProjectManifest,
+) -> Vec<ManifestChange> {
+    let empty = ProjectManifest::default();
+    let old = applied.unwrap_or(&empty);
+    let mut changes = Vec::new();
+
+    let mut push = |field: &str, from: Option<String>, to: Option<String>| {
+        if from != to {
+            changes.push(ManifestChange {
+                field: field.to_string(),
+                from,
+                to,
+            });
+        }
+    };
+
+    let php_version = |m: &ProjectManifest| m.php.as_ref().and_then(|p| p.version.clone());
+    let php_extensions = |m: &ProjectManifest| {
+        m.php
+            .as_ref()
+            .filter(|p| !p.extensions.is_empty())
+            .map(|p| p.extensions.join(", "))
+    };
+    let list = |items: &[String]| (!items.is_empty()).then(|| items.join(", "));
+    let services =
+        |m: &ProjectManifest| list(&m.services.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+    let flag = |b: bool| b.then(|| "enabled".to_string());
+
+    push("php.version", php_version(old), php_version(current));
+    push(
+        "php.extensions",
+        php_extensions(old),
+        php_extensions(current),
+    );
+    push(
+        "document_root",
+        old.document_root.clone(),
+        current.document_root.clone(),
+    );
+    push("aliases", list(&old.aliases), list(&current.aliases));
+    push("services", services(old), services(current));
+    push("databases", list(&old.databases), list(&current.databases));
+    push(
+        "queue_worker",
+        flag(old.queue_worker),
+        flag(current.queue_worker),
+    );
+    push("scheduler", flag(old.scheduler), flag(current.scheduler));
+
+    changes
+}
+
+/// Expand an alias to a full domain (`api` becomes `api.<first tld>`); it
+/// must be a valid name under one of the configured TLDs
+pub fn alias_domain(alias: &str, tlds: &[String]) -> Result<String, String> {
+    let alias = alias.trim().to_lowercase();
+    let domain = match tlds.first() {
+        Some(tld) if !alias.contains('.') => format!("{}.{}", alias, tld),
+        _ => alias.clone(),
+    };
+    validate_domain(&domain)?;
+    if tlds
+        .iter()
+        .any(|tld| domain.ends_with(&format!(".{}", tld)))
+    {
+        Ok(domain)
+    } else {
+        Err(format!(
+            "Alias {} is not under a configured TLD ({})",
+            alias,
+            tlds.join(", ")
+        ))
+    }
+}
+
+/// A document root must stay inside the project
+fn validate_document_root(root: &str) -> Result<(), String> {
+    let path = Path::new(root);
+    let inside = !root.is_empty()
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if inside {
+        Ok(())
+    } else {
+        Err(format!(
+            "document_root must be a path inside the project, got {}",
+            root
+        ))
+    }
+}
+
+/// Check a manifest's site-level settings, returning the expanded aliases.
+/// The PHP version is only checked against `php_versions` when they are given.
+fn validate_manifest(
+    manifest: &ProjectManifest,
+    tlds: &[String],
+    php_versions: Option<&[String]>,
+) -> Result<Vec<String>, String> {
+    let version = manifest.php.as_ref().and_then(|p| p.version.as_ref());
+    if let (Some(version), Some(php_versions)) = (version, php_versions) {
+        if !php_versions.contains(version) {
+            return Err(format!(
+                "PHP {} is not installed (installed: {})",
+                version,
+                php_versions.join(", ")
+            ));
+        }
+    }
+    if let Some(root) = &manifest.document_root {
+        validate_document_root(root)?;
+    }
+    manifest
+        .aliases
+        .iter()
+        .map(|alias| alias_domain(alias, tlds))
+        .collect()
+}
+
+/// Apply the site-level settings of a manifest (PHP version, document root,
+/// aliases). Nothing is applied when any of them is invalid. An explicitly
+/// chosen `php_version` wins over the manifest's, which is then not checked.
+pub fn apply_manifest_to_site(
+    site: &mut Site,
+    manifest: &ProjectManifest,
+    tlds: &[String],
+    php_version: Option<String>,
+) -> Result<(), String> {
+    let php_versions: Option<Vec<String>> = match php_version {
+        Some(_) => None,
+        None => Some(
+            get_php_versions()?
+                .into_iter()
+                .filter(|v| v.installed)
+                .map(|v| v.version)
+                .collect(),
+        ),
+    };
+    let aliases = validate_manifest(manifest, tlds, php_versions.as_deref())
+        .map_err(|e| format!("Invalid {}: {}", MANIFEST_FILE, e))?;
+
+    if let Some(version) =
+        php_version.or_else(|| manifest.php.as_ref().and_then(|p| p.version.clone()))
+    {
+        site.php_version = version;
+    }
+    site.document_root = manifest.document_root.clone();
+    site.aliases = aliases;
+    site.manifest = Some(manifest.clone());
+    Ok(())
+}
+
+/// Provision the external requirements of a manifest: containers, databases, workers
+pub fn provision_manifest(site: &Site, manifest: &ProjectManifest) -> ManifestApplyReport {
+    let mut report = ManifestApplyReport::default();
+
+    if let Some(php) = &manifest.php {
+        let missing = missing_php_extensions(&site.php_version, &php.extensions);
+        if !missing.is_empty() {
+            report.warnings.push(format!(
+                "PHP {} is missing extensions: {}",
+                site.php_version,
+                missing.join(", ")
+            ));
+        }
+    }
+
+    let existing: Vec<String> = super::docker::list_containers()
+        .map(|containers| containers.into_iter().map(|c| c.name).collect())
+        .unwrap_or_default();
+
+    for service in &manifest.services {
+        let Some(params) = service_container_params(service, site) else {
+            report
+                .warnings
+                .push(format!("Unknown service in manifest: {}", service.name));
+            continue;
+        };
+        if existing.contains(&params.name) {
+            continue;
+        }
+        match EngineClient::connect().and_then(|client| create_container_with(&client, &params)) {
+            Ok(_) => report
+                .applied
+                .push(format!("Created container {}", params.name)),
+            Err(e) => report
+                .warnings
+                .push(format!("Failed to create {}: {}", params.name, e)),
+        }
+    }
+
+    if !manifest.databases.is_empty() {
+        match database_target(manifest, site) {
+            Ok((db_type, container)) => {
+                create_manifest_databases(&mut report, &manifest.databases, db_type, container)
+            }
+            Err(e) => report.warnings.push(e),
+        }
+    }
+
+    if manifest.queue_worker {
+        match super::laravel::start_queue_worker(
+            site.path.clone(),
+            site.php_version.clone(),
+            site.name.clone(),
+        ) {
+            Ok(()) => report.applied.push("Started queue worker".to_string()),
+            Err(e) => report
+                .warnings
+                .push(format!("Failed to start queue worker: {}", e)),
+        }
+    }
+
+    if manifest.scheduler {
+        match super::laravel::enable_scheduler(site.path.clone(), site.php_version.clone()) {
+            Ok(()) => report.applied.push("Enabled scheduler".to_string()),
+            Err(e) => report
+                .warnings
+                .push(format!("Failed to enable scheduler: {}", e)),
+        }
+    }
+
+    report
+}
+
+fn create_manifest_databases(
+    report: &mut ManifestApplyReport,
+    databases: &[String],
+    db_type: &str,
+    container: Option<String>,
+) {
+    if let Some(container) = &container {
+        if let Err(e) = super::docker::wait_until_ready(container, Duration::from_secs(120)) {
+            report.warnings.push(e);
+        }
+    }
+    for database in databases {
+        match super::system::create_database(
+            db_type.to_string(),
+            database.clone(),
+            container.clone(),
+        ) {
+            Ok(()) => report
+                .applied
+                .push(format!("Created database {}", database)),
+            Err(e) => report
+                .warnings
+                .push(format!("Failed to create database {}: {}", database, e)),
+        }
+    }
+}
+
+/// Container parameters for a manifest service, from the service catalog
+fn service_container_params(
+    service: &ServiceRequirement,
+    site: &Site,
+) -> Option<CreateContainerParams> {
+    let preset = find_preset(&service.name)?;
+    let options = PresetOptions {
+        version: service.version.clone(),
+        site: Some(site.id.clone()),
+        ..Default::default()
+    };
+    preset_container_params(preset, &options).ok()
+}
+
+/// Pick where manifest databases are created: the first declared database
+/// container, or native MySQL when the manifest declares none
+fn database_target(
+    manifest: &ProjectManifest,
+    site: &Site,
+) -> Result<(&'static str, Option<String>), String> {
+    let Some((service, preset)) = manifest
+        .services
+        .iter()
+        .filter_map(|service| Some((service, find_preset(&service.name)?)))
+        .find(|(_, preset)| preset.category == "database")
+    else {
+        return Ok(("mysql", None));
+    };
+    let db_type = match preset.id {
+        "mysql" | "mariadb" => "mysql",
+        "postgresql" => "postgresql",
+        _ => return Err(format!("Databases cannot be created in {}", preset.name)),
+    };
+    let params = service_container_params(service, site)
+        .ok_or_else(|| format!("Invalid service in manifest: {}", service.name))?;
+    Ok((db_type, Some(params.name)))
+}
+
+fn missing_php_extensions(php_version: &str, extensions: &[String]) -> Vec<String> {
+    if extensions.is_empty() {
+        return Vec::new();
+    }
+
+    let loaded: Vec<String> = Command::new(format!("php{}", php_version))
+        .arg("-m")
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(|l| l.trim().to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    extensions
+        .iter()
+        .filter(|ext| !loaded.contains(&ext.to_lowercase()))
+        .cloned()
+        .collect()
+}
+
+/// Check whether a site's `servermark.yml` changed since it was last applied
+#[tauri::command]
+pub fn check_site_manifest(id: String) -> Result<Option<ManifestDiff>, String> {
+    let config = load_sites_config();
+    let site = config
+        .sites
+        .iter()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+
+    let Some(manifest) = read_manifest(&site.path)? else {
+        return Ok(None);
+    };
+
+    let changes = diff_manifests(site.manifest.as_ref(), &manifest);
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(ManifestDiff {
+        site_id: site.id.clone(),
+        changes,
+    }))
+}
+
+/// Apply a site's `servermark.yml` (after the user accepted the diff)
+#[tauri::command]
+pub async fn apply_site_manifest(id: String) -> Result<ManifestApplyReport, String> {
+    tauri::async_runtime::spawn_blocking(move || apply_site_manifest_blocking(&id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn apply_site_manifest_blocking(id: &str) -> Result<ManifestApplyReport, String> {
+    let mut config = load_sites_config();
+    let tlds = config.tlds();
+
+    let site = config
+        .sites
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+
+    let manifest = read_manifest(&site.path)?
+        .ok_or_else(|| format!("No {} found in {}", MANIFEST_FILE, site.path))?;
+
+    apply_manifest_to_site(site, &manifest, &tlds, None)?;
+    let updated = site.clone();
+    save_config(&config)?;
+
+    super::webserver::webserver_update_site(&updated.name)?;
+
+    Ok(provision_manifest(&updated, &manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+php:
+  version: "8.3"
+  extensions: [redis, intl]
+document_root: public
+aliases: [api, admin.shop.test]
+services:
+  - mysql:8
+  - name: redis
+databases: [shop, shop_testing]
+queue_worker: true
+scheduler: true
+"#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = parse_manifest(SAMPLE).unwrap();
+        let php = manifest.php.as_ref().unwrap();
+        assert_eq!(php.version.as_deref(), Some("8.3"));
+        assert_eq!(php.extensions, vec!["redis", "intl"]);
+        assert_eq!(manifest.document_root.as_deref(), Some("public"));
+        assert_eq!(
+            manifest.services,
+            vec![
+                ServiceRequirement {
+                    name: "mysql".to_string(),
+                    version: Some("8".to_string()),
+                },
+                ServiceRequirement {
+                    name: "redis".to_string(),
+                    version: None,
+                },
+            ]
+        );
+        assert!(manifest.queue_worker && manifest.scheduler);
+    }
+
+    #[test]
+    fn test_empty_manifest_is_default() {
+        assert_eq!(parse_manifest("").unwrap(), ProjectManifest::default());
+        assert!(parse_manifest("php: [").is_err());
+    }
+
+    #[test]
+    fn test_diff_manifests() {
+        let current = parse_manifest(SAMPLE).unwrap();
+        assert!(diff_manifests(Some(&current), &current).is_empty());
+
+        let mut changed = current.clone();
+        changed.php.as_mut().unwrap().version = Some("8.4".to_string());
+        changed.scheduler = false;
+
+        let changes = diff_manifests(Some(&current), &changed);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].field, "php.version");
+        assert_eq!(changes[0].from.as_deref(), Some("8.3"));
+        assert_eq!(changes[0].to.as_deref(), Some("8.4"));
+        assert_eq!(changes[1].field, "scheduler");
+        assert_eq!(changes[1].to, None);
+    }
+
+    #[test]
+    fn test_alias_domain() {
+        let tlds = vec!["test".to_string(), "localhost".to_string()];
+        assert_eq!(alias_domain("api", &tlds).unwrap(), "api.test");
+        assert_eq!(
+            alias_domain("Admin.Shop.test", &tlds).unwrap(),
+            "admin.shop.test"
+        );
+        assert_eq!(
+            alias_domain("shop.localhost", &tlds).unwrap(),
+            "shop.localhost"
+        );
+        assert!(alias_domain("example.com", &tlds).is_err());
+        assert!(alias_domain("api.test\nSITEEOF", &tlds).is_err());
+        assert!(alias_domain("test", &[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_manifest() {
+        let tlds = vec!["test".to_string()];
+        let php = vec!["8.3".to_string()];
+        let manifest = parse_manifest(SAMPLE).unwrap();
+        assert_eq!(
+            validate_manifest(&manifest, &tlds, Some(&php)).unwrap(),
+            vec!["api.test", "admin.shop.test"]
+        );
+
+        assert!(validate_manifest(&manifest, &tlds, Some(&["8.4".to_string()])).is_err());
+        assert!(validate_manifest(&manifest, &tlds, None).is_ok());
+        for root in ["/etc", "../other", "public/../../etc", ""] {
+            let manifest = ProjectManifest {
+                document_root: Some(root.to_string()),
+                ..manifest.clone()
+            };
+            assert!(
+                validate_manifest(&manifest, &tlds, Some(&php)).is_err(),
+                "{}",
+                root
+            );
+        }
+    }
+
+    #[test]
+    fn test_database_target() {
+        let site: Site = serde_json::from_value(serde_json::json!({
+            "id": "shop", "name": "shop", "path": "/var/www/shop", "domain": "shop.test",
+            "php_version": "8.3", "secured": false, "site_type": "laravel",
+        }))
+        .unwrap();
+        let target = |services: &str| {
+            let manifest = parse_manifest(&format!("services: [{}]", services)).unwrap();
+            database_target(&manifest, &site)
+        };
+
+        assert_eq!(target("redis").unwrap(), ("mysql", None));
+        assert_eq!(
+            target("redis, mariadb:11").unwrap(),
+            ("mysql", Some("servermark-mariadb".to_string()))
+        );
+        assert_eq!(
+            target("pgsql").unwrap(),
+            ("postgresql", Some("servermark-postgresql".to_string()))
+        );
+        assert!(target("mongo").is_err());
+    }
+}
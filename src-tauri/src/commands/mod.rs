@@ -1,15 +1,58 @@
+mod backends;
+mod catalog;
+mod compose;
+mod diagnostics;
+mod dns;
+mod dns_responder;
 mod docker;
+mod doctor;
+mod dotenv;
+mod engine;
+mod helper;
+mod hosts;
+mod images;
 mod laravel;
+mod logs;
+mod manifest;
+mod ownership;
 mod php;
+mod ports;
+mod provision;
+mod rootless;
+mod sail;
 mod services;
+mod share;
 mod sites;
+mod stats;
 mod system;
+mod tunnel;
+mod volumes;
 mod webserver;
 
+pub use catalog::*;
+pub use compose::*;
+pub use diagnostics::*;
+pub use dns::*;
+pub use dns_responder::run_dns_responder;
 pub use docker::*;
+pub use doctor::*;
+pub use dotenv::*;
+pub use helper::*;
+pub use images::*;
 pub use laravel::*;
+pub use logs::*;
+pub use manifest::*;
+pub use ownership::*;
 pub use php::*;
+pub use ports::*;
+pub use provision::*;
+pub use rootless::*;
+pub use sail::*;
 pub use services::*;
+pub use share::*;
 pub use sites::*;
+pub use stats::*;
 pub use system::*;
+pub use tunnel::*;
+pub use volumes::*;
 pub use webserver::*;
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use super::catalog::{find_preset, preset_for_image};
+use super::compose::{site_for_project, COMPOSE_PROJECT_PREFIX, LABEL_COMPOSE_PROJECT};
+use super::docker::{inspect_container, query_containers, Container};
+use super::engine::EngineClient;
+
+// Which containers belong to ServerMark. Containers and volumes it creates
+// carry the `servermark.*` labels, and compose projects it starts are named
+// `servermark-<site>`. Labels cannot be changed after creation, so adopting a
+// foreign container, or releasing a labelled one, is recorded in
+// containers.json instead. Containers created before labels existed are only
+// recognisable by their `servermark-` name; they are adopted once.
+
+pub const LABEL_MANAGED: &str = "servermark.managed";
+pub const LABEL_PRESET: &str = "servermark.preset";
+pub const LABEL_SITE: &str = "servermark.site";
+
+/// Name prefix of the preset containers, labelled or not
+const CONTAINER_NAME_PREFIX: &str = "servermark-";
+
+/// Labels for a container or volume ServerMark creates
+pub fn ownership_labels(preset: Option<&str>, site: Option<&str>) -> HashMap<String, String> {
+    let mut labels = HashMap::from([(LABEL_MANAGED.to_string(), "true".to_string())]);
+    if let Some(preset) = preset {
+        labels.insert(LABEL_PRESET.to_string(), preset.to_string());
+    }
+    if let Some(site) = site {
+        labels.insert(LABEL_SITE.to_string(), site.to_string());
+    }
+    labels
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdoptedContainer {
+    pub id: String,
+    pub preset: Option<String>,
+    pub site: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerOwnership {
+    /// Containers created elsewhere that ServerMark manages
+    pub adopted: Vec<AdoptedContainer>,
+    /// Ids of labelled containers the user released
+    pub released: Vec<String>,
+    /// Unlabelled containers from before ownership labels were adopted
+    pub legacy_migrated: bool,
+}
+
+fn get_ownership_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("servermark")
+        .join("containers.json")
+}
+
+fn load_ownership() -> ContainerOwnership {
+    fs::read_to_string(get_ownership_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_ownership(ownership: &ContainerOwnership) -> Result<(), String> {
+    let path = get_ownership_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(ownership)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write config: {}", e))?;
+    Ok(())
+}
+
+/// Adopt the unlabelled `servermark-*` containers created before ownership
+/// labels, once; returns whether `ownership` changed
+fn migrate_legacy_containers(ownership: &mut ContainerOwnership, containers: &[Container]) -> bool {
+    if ownership.legacy_migrated {
+        return false;
+    }
+    for container in containers {
+        let Some(suffix) = container.name.strip_prefix(CONTAINER_NAME_PREFIX) else {
+            continue;
+        };
+        let legacy = !container.labels.contains_key(LABEL_MANAGED) && !labelled(&container.labels);
+        if !legacy || ownership.adopted.iter().any(|a| a.id == container.id) {
+            continue;
+        }
+        ownership.adopted.push(AdoptedContainer {
+            id: container.id.clone(),
+            preset: preset_for_image(&container.image)
+                .or_else(|| find_preset(suffix))
+                .map(|p| p.id.to_string()),
+            site: None,
+        });
+    }
+    ownership.legacy_migrated = true;
+    true
+}
+
+/// containers.json, with the legacy containers among `containers` adopted
+fn load_ownership_for(containers: &[Container]) -> ContainerOwnership {
+    let mut ownership = load_ownership();
+    if migrate_legacy_containers(&mut ownership, containers) {
+        let _ = save_ownership(&ownership);
+    }
+    ownership
+}
+
+fn labelled(labels: &HashMap<String, String>) -> bool {
+    labels.get(LABEL_MANAGED).is_some_and(|v| v == "true")
+        || labels
+            .get(LABEL_COMPOSE_PROJECT)
+            .is_some_and(|p| p.starts_with(COMPOSE_PROJECT_PREFIX))
+}
+
+/// The container with its preset and site filled in, when ServerMark owns it
+fn owned(mut container: Container, ownership: &ContainerOwnership) -> Option<Container> {
+    if labelled(&container.labels) && !ownership.released.contains(&container.id) {
+        container.preset = container.labels.get(LABEL_PRESET).cloned().or_else(|| {
+            // Compose services: by image, else by service name (`mysql`, `pgsql`)
+            container.compose_project.as_ref()?;
+            preset_for_image(&container.image)
+                .or_else(|| container.compose_service.as_deref().and_then(find_preset))
+                .map(|p| p.id.to_string())
+        });
+        container.site = container.labels.get(LABEL_SITE).cloned();
+        return Some(container);
+    }
+    let adopted = ownership.adopted.iter().find(|a| a.id == container.id)?;
+    container.preset = adopted.preset.clone();
+    container.site = adopted.site.clone();
+    container.adopted = true;
+    Some(container)
+}
+
+/// Split containers into the ones ServerMark owns and the rest
+pub fn partition_owned(
+    containers: Vec<Container>,
+    ownership: &ContainerOwnership,
+) -> (Vec<Container>, Vec<Container>) {
+    let mut mine = Vec::new();
+    let mut others = Vec::new();
+    for container in containers {
+        match owned(container.clone(), ownership) {
+            Some(container) => mine.push(container),
+            None => others.push(container),
+        }
+    }
+    (mine, others)
+}
+
+pub fn owned_containers(client: &EngineClient) -> Result<Vec<Container>, String> {
+    let all = query_containers(client, json!({}))?;
+    let ownership = load_ownership_for(&all);
+    let mut mine = partition_owned(all, &ownership).0;
+    for container in mine.iter_mut().filter(|c| c.site.is_none()) {
+        if let Some(project) = &container.compose_project {
+            container.site = site_for_project(project);
+        }
+    }
+    Ok(mine)
+}
+
+/// Carry adoption or release over to a container recreated in place of `old`
+pub fn replace_container_id(old: &str, new: &str) -> Result<(), String> {
+    let mut ownership = load_ownership();
+    let mut changed = false;
+    for adopted in ownership.adopted.iter_mut().filter(|a| a.id == old) {
+        adopted.id = new.to_string();
+        changed = true;
+    }
+    for released in ownership.released.iter_mut().filter(|r| *r == old) {
+        *released = new.to_string();
+        changed = true;
+    }
+    if changed {
+        save_ownership(&ownership)
+    } else {
+        Ok(())
+    }
+}
+
+/// Containers ServerMark does not manage, for adoption
+#[tauri::command]
+pub fn list_adoptable_containers() -> Result<Vec<Container>, String> {
+    let all = query_containers(&EngineClient::connect()?, json!({}))?;
+    let ownership = load_ownership_for(&all);
+    Ok(partition_owned(all, &ownership).1)
+}
+
+/// Manage an existing container (e.g. one created before labels, or by hand)
+#[tauri::command]
+pub fn adopt_container(
+    id: String,
+    preset: Option<String>,
+    site: Option<String>,
+) -> Result<Container, String> {
+    let client = EngineClient::connect()?;
+    let details = inspect_container(&client, &id)?;
+    let mut ownership = load_ownership();
+
+    ownership.released.retain(|r| *r != details.id);
+    ownership.adopted.retain(|a| a.id != details.id);
+    if !labelled(&details.config.labels) {
+        ownership.adopted.push(AdoptedContainer {
+            id: details.id.clone(),
+            preset: preset
+                .or_else(|| preset_for_image(&details.config.image).map(|p| p.id.to_string())),
+            site,
+        });
+    }
+    save_ownership(&ownership)?;
+
+    let containers = query_containers(&client, json!({ "id": [details.id] }))?;
+    containers
+        .into_iter()
+        .find_map(|c| owned(c, &ownership))
+        .ok_or_else(|| format!("Container {} not found", id))
+}
+
+/// Stop managing a container; it keeps running and is left untouched
+#[tauri::command]
+pub fn unadopt_container(id: String) -> Result<(), String> {
+    let details = inspect_container(&EngineClient::connect()?, &id)?;
+    let mut ownership = load_ownership();
+
+    ownership.adopted.retain(|a| a.id != details.id);
+    if labelled(&details.config.labels) && !ownership.released.contains(&details.id) {
+        ownership.released.push(details.id);
+    }
+    save_ownership(&ownership)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(id: &str, labels: &[(&str, &str)]) -> Container {
+        let labels: HashMap<String, String> = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        serde_json::from_value(json!({
+            "id": id, "name": id, "image": "mysql:8.0", "status": "running", "ports": [],
+            "created": "", "health": null, "mounts": [],
+            "compose_project": labels.get(LABEL_COMPOSE_PROJECT), "labels": labels,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_partition_owned() {
+        let containers = vec![
+            container("a", &[(LABEL_MANAGED, "true"), (LABEL_PRESET, "mysql")]),
+            container("b", &[(LABEL_MANAGED, "true")]),
+            container("c", &[]),
+            container("servermark-lookalike", &[(LABEL_MANAGED, "false")]),
+            container("d", &[(LABEL_COMPOSE_PROJECT, "servermark-shop")]),
+            container("e", &[(LABEL_COMPOSE_PROJECT, "shop")]),
+        ];
+        let ownership = ContainerOwnership {
+            adopted: vec![AdoptedContainer {
+                id: "c".to_string(),
+                preset: Some("redis".to_string()),
+                site: Some("site-1".to_string()),
+            }],
+            released: vec!["b".to_string()],
+            legacy_migrated: true,
+        };
+
+        let (mine, others) = partition_owned(containers, &ownership);
+        let ids: Vec<&str> = mine.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c", "d"]);
+        assert_eq!(mine[0].preset.as_deref(), Some("mysql"));
+        assert!(!mine[0].adopted);
+        assert_eq!(mine[1].site.as_deref(), Some("site-1"));
+        assert!(mine[1].adopted);
+        assert_eq!(mine[2].preset.as_deref(), Some("mysql"));
+        let ids: Vec<&str> = others.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "servermark-lookalike", "e"]);
+    }
+
+    #[test]
+    fn test_migrate_legacy_containers() {
+        let mut legacy = container("servermark-mysql57", &[]);
+        legacy.image = "mysql:5.7".to_string();
+        let mut redis = container("servermark-redis", &[]);
+        redis.image = "registry.local/cache:7".to_string();
+        let containers = vec![
+            legacy,
+            redis,
+            container("servermark-lookalike", &[(LABEL_MANAGED, "false")]),
+            container("servermark-labelled", &[(LABEL_MANAGED, "true")]),
+            container("mysql", &[]),
+        ];
+
+        let mut ownership = ContainerOwnership::default();
+        assert!(migrate_legacy_containers(&mut ownership, &containers));
+        let adopted: Vec<(&str, Option<&str>)> = ownership
+            .adopted
+            .iter()
+            .map(|a| (a.id.as_str(), a.preset.as_deref()))
+            .collect();
+        assert_eq!(
+            adopted,
+            vec![
+                ("servermark-mysql57", Some("mysql")),
+                ("servermark-redis", Some("redis"))
+            ]
+        );
+        let (mine, _) = partition_owned(containers.clone(), &ownership);
+        assert_eq!(mine.len(), 3);
+
+        // Only once, so unadopting a legacy container sticks
+        ownership.adopted.clear();
+        assert!(!migrate_legacy_containers(&mut ownership, &containers));
+        assert!(ownership.adopted.is_empty());
+    }
+
+    #[test]
+    fn test_ownership_labels() {
+        let labels = ownership_labels(Some("mysql"), None);
+        assert_eq!(labels[LABEL_MANAGED], "true");
+        assert_eq!(labels[LABEL_PRESET], "mysql");
+        assert!(!labels.contains_key(LABEL_SITE));
+    }
+}
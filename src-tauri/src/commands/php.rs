@@ -190,6 +190,19 @@ pub fn switch_php_version(version: String) -> Result<(), String> {
         return Err(format!("PHP {} is not installed", version));
     }
 
+    // Rootless mode switches the user's own `php` in ~/.local/bin instead
+    if super::rootless::rootless_config().is_some() {
+        let bin_dir = dirs::home_dir()
+            .ok_or("Could not determine home directory")?
+            .join(".local/bin");
+        fs::create_dir_all(&bin_dir)
+            .map_err(|e| format!("Failed to create {}: {}", bin_dir.display(), e))?;
+        let link = bin_dir.join("php");
+        let _ = fs::remove_file(&link);
+        return std::os::unix::fs::symlink(&php_path, &link)
+            .map_err(|e| format!("Failed to switch PHP version: {}", e));
+    }
+
     // Use pkexec for privilege escalation
     let output = Command::new("pkexec")
         .args(["update-alternatives", "--set", "php", &php_path])
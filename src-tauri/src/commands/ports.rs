@@ -0,0 +1,506 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::process::Command;
+
+use super::backends::{backend, ListenPorts, WebServerKind};
+use super::docker::{query_containers, stop_container};
+use super::engine::EngineClient;
+use super::helper::{helper_available, helper_socket_owners, validate_unit};
+use super::ownership::owned_containers;
+use super::webserver::{execute_webserver_operation, load_webserver_config, save_webserver_config};
+
+/// A process or container listening on a TCP port
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortOwner {
+    pub port: u16,
+    pub address: String,
+    pub uid: u32,
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    pub unit: Option<String>,      // systemd unit, e.g. "apache2.service"
+    pub container: Option<String>, // container name when Docker/Podman publishes the port
+}
+
+impl PortOwner {
+    /// Human readable owner, e.g. "apache2 (apache2.service)"
+    pub fn describe(&self) -> String {
+        if let Some(container) = &self.container {
+            return format!("container {}", container);
+        }
+        match (&self.process, &self.unit) {
+            (Some(process), Some(unit)) => format!("{} ({})", process, unit),
+            (Some(process), None) => format!("{} (pid {})", process, self.pid.unwrap_or_default()),
+            (None, Some(unit)) => unit.clone(),
+            (None, None) => format!("an unknown process of uid {}", self.uid),
+        }
+    }
+}
+
+/// A listening socket from `/proc/net/tcp{,6}`
+#[derive(Debug, Clone, PartialEq)]
+struct ListeningSocket {
+    port: u16,
+    address: String,
+    uid: u32,
+    inode: u64,
+}
+
+const TCP_LISTEN: &str = "0A";
+
+/// Alternative ports tried by `use_alternative_ports`
+const ALTERNATIVE_HTTP_PORTS: &[u16] = &[8080, 8000, 8081, 8888];
+const ALTERNATIVE_HTTPS_PORTS: &[u16] = &[8443, 4443, 8444, 9443];
+
+/// Decode a `/proc/net/tcp` address; each 32-bit word is in host byte order
+fn parse_proc_address(hex: &str) -> Option<String> {
+    let words: Vec<u32> = (0..hex.len() / 8)
+        .map(|i| u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_ne_bytes()).collect();
+
+    match bytes.len() {
+        4 => Some(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string()),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            Some(Ipv6Addr::from(octets).to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Parse the listening sockets out of `/proc/net/tcp` or `/proc/net/tcp6`
+fn parse_proc_net_tcp(content: &str) -> Vec<ListeningSocket> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                return None;
+            }
+            let (address, port) = fields[1].split_once(':')?;
+            Some(ListeningSocket {
+                port: u16::from_str_radix(port, 16).ok()?,
+                address: parse_proc_address(address)?,
+                uid: fields[7].parse().ok()?,
+                inode: fields[9].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn listening_sockets() -> Vec<ListeningSocket> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| parse_proc_net_tcp(&content))
+        .collect()
+}
+
+/// Map socket inodes to the pids holding them. Only processes we may inspect
+/// show up; sockets of other users' processes stay unresolved.
+pub fn socket_owners() -> HashMap<u64, u32> {
+    let mut owners = HashMap::new();
+    let Ok(procs) = fs::read_dir("/proc") else {
+        return owners;
+    };
+
+    for entry in procs.filter_map(|e| e.ok()) {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.filter_map(|e| e.ok()) {
+            let target = fs::read_link(fd.path()).unwrap_or_default();
+            let target = target.to_string_lossy();
+            if let Some(inode) = target
+                .strip_prefix("socket:[")
+                .and_then(|rest| rest.strip_suffix(']'))
+                .and_then(|inode| inode.parse().ok())
+            {
+                owners.entry(inode).or_insert(pid);
+            }
+        }
+    }
+
+    owners
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// systemd unit from the process' cgroup path (`0::/system.slice/nginx.service`)
+fn unit_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .filter_map(|line| line.rsplit('/').find(|part| part.ends_with(".service")))
+        .next()
+        .map(|unit| unit.to_string())
+}
+
+fn process_unit(pid: u32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .ok()
+        .and_then(|cgroup| unit_from_cgroup(&cgroup))
+}
+
+/// Inode to pid from `ss -ltnpHe` lines (`... users:(("nginx",pid=812,fd=6)) ... ino:31337 ...`)
+fn parse_ss_owners(output: &str) -> HashMap<u64, u32> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let inode = line
+                .split_whitespace()
+                .find_map(|field| field.strip_prefix("ino:"))?
+                .parse()
+                .ok()?;
+            let pid = line.split_once("pid=")?.1;
+            let pid = pid[..pid.find(|c: char| !c.is_ascii_digit())?]
+                .parse()
+                .ok()?;
+            Some((inode, pid))
+        })
+        .collect()
+}
+
+/// Resolve sockets only root can see: through the helper when installed,
+/// else with `ss` under pkexec when a password prompt is acceptable
+fn privileged_socket_owners(inodes: Vec<u64>, prompt: bool) -> HashMap<u64, u32> {
+    if helper_available() {
+        return helper_socket_owners(inodes).unwrap_or_default();
+    }
+    if !prompt {
+        return HashMap::new();
+    }
+    Command::new("pkexec")
+        .args(["ss", "-ltnpHe"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| parse_ss_owners(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or_default()
+}
+
+/// Name of the container publishing `port`, if any
+fn container_on_port(port: u16) -> Option<String> {
+    let client = EngineClient::connect().ok()?;
+    query_containers(&client, json!({ "publish": [port.to_string()] }))
+        .ok()?
+        .into_iter()
+        .map(|c| c.name)
+        .find(|name| !name.is_empty())
+}
+
+/// Find who listens on each of `ports`. Root's sockets are resolved through
+/// the helper when installed, else their owner is left unknown.
+pub fn port_owners(ports: &[u16]) -> Vec<PortOwner> {
+    find_port_owners(ports, false)
+}
+
+/// Like `port_owners`, but asks for a password to resolve root's sockets
+/// when the helper is not installed; for actions that depend on the owner
+fn port_owners_privileged(ports: &[u16]) -> Vec<PortOwner> {
+    find_port_owners(ports, true)
+}
+
+fn find_port_owners(ports: &[u16], prompt: bool) -> Vec<PortOwner> {
+    let sockets: Vec<ListeningSocket> = listening_sockets()
+        .into_iter()
+        .filter(|s| ports.contains(&s.port))
+        .collect();
+    if sockets.is_empty() {
+        return Vec::new();
+    }
+
+    let mut inodes = socket_owners();
+    let hidden: Vec<u64> = sockets
+        .iter()
+        .filter(|s| !inodes.contains_key(&s.inode) && s.uid == 0)
+        .map(|s| s.inode)
+        .collect();
+    if !hidden.is_empty() {
+        inodes.extend(privileged_socket_owners(hidden, prompt));
+    }
+    let mut owners: Vec<PortOwner> = Vec::new();
+
+    for socket in sockets {
+        let pid = inodes.get(&socket.inode).copied();
+        let process = pid.and_then(process_name);
+        let unit = pid.and_then(process_unit);
+
+        // docker-proxy / rootlessport forward to a container
+        let forwarded = matches!(
+            process.as_deref(),
+            None | Some("docker-proxy") | Some("rootlessport") | Some("conmon")
+        );
+        let container = if forwarded {
+            container_on_port(socket.port)
+        } else {
+            None
+        };
+
+        let owner = PortOwner {
+            port: socket.port,
+            address: socket.address,
+            uid: socket.uid,
+            pid,
+            process,
+            unit,
+            container,
+        };
+
+        // IPv4 and IPv6 sockets of the same process are one owner
+        let duplicate = owners.iter().any(|o| {
+            o.port == owner.port
+                && o.pid == owner.pid
+                && o.unit == owner.unit
+                && o.container == owner.container
+        });
+        if !duplicate {
+            owners.push(owner);
+        }
+    }
+
+    owners
+}
+
+/// Whether an owner is one of the given managed web servers
+pub fn is_managed_server(owner: &PortOwner, servers: &[WebServerKind]) -> bool {
+    if owner.container.is_some() {
+        return false;
+    }
+    servers.iter().any(|kind| {
+        let service = backend(*kind).service();
+        owner.unit.as_deref() == Some(&format!("{}.service", service))
+            || owner.process.as_deref() == Some(service)
+    })
+}
+
+/// Fail with a readable message when something other than `allowed` holds the ports
+pub fn ensure_ports_available(ports: ListenPorts, allowed: &[WebServerKind]) -> Result<(), String> {
+    let conflicts: Vec<String> = port_owners_privileged(&[ports.http, ports.https])
+        .iter()
+        .filter(|owner| !is_managed_server(owner, allowed))
+        .map(|owner| format!("port {} is used by {}", owner.port, owner.describe()))
+        .collect();
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Port conflict: {}. Stop the conflicting service or use alternative ports.",
+            conflicts.join(", ")
+        ))
+    }
+}
+
+fn first_free_port(candidates: &[u16]) -> Result<u16, String> {
+    candidates
+        .iter()
+        .copied()
+        .find(|port| port_owners(&[*port]).is_empty())
+        .ok_or_else(|| format!("None of the ports {:?} are free", candidates))
+}
+
+/// Whether the owner is ServerMark's own: one of its containers, or a unit it manages
+fn is_servermark_owner(owner: &PortOwner, owned_containers: &[String]) -> bool {
+    match (&owner.container, &owner.unit) {
+        (Some(container), _) => owned_containers.contains(container),
+        (None, Some(unit)) => validate_unit(unit, "stop").is_ok(),
+        (None, None) => false,
+    }
+}
+
+/// Stop an owner for now. Units stay enabled, and only the user's own
+/// processes are killed.
+fn stop_owner(owner: &PortOwner) -> Result<(), String> {
+    // SAFETY: getuid has no preconditions
+    let own_process = owner.uid == unsafe { libc::getuid() };
+    let output = if let Some(container) = &owner.container {
+        return stop_container(container.clone());
+    } else if let Some(unit) = &owner.unit {
+        Command::new("pkexec")
+            .args(["systemctl", "stop", unit])
+            .output()
+    } else if let Some(pid) = owner.pid.filter(|_| own_process) {
+        Command::new("kill").arg(pid.to_string()).output()
+    } else {
+        return Err(format!(
+            "ServerMark does not stop {}; stop it yourself and try again",
+            owner.describe()
+        ));
+    };
+
+    let output = output.map_err(|e| format!("Failed to stop {}: {}", owner.describe(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to stop {}: {}",
+            owner.describe(),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+/// List the processes and containers listening on `ports`
+#[tauri::command]
+pub fn get_port_owners(ports: Vec<u16>) -> Vec<PortOwner> {
+    port_owners(&ports)
+}
+
+/// Resolve a conflict on `port`, either by stopping whatever holds it
+/// (`stop_owner`) or by moving ServerMark's server to free ports
+/// (`use_alternative_ports`). Owners ServerMark does not manage are only
+/// stopped once the user has seen them and passes `confirmed`. Returns the
+/// remaining owners of the configured ports.
+#[tauri::command]
+pub fn resolve_port_conflict(
+    port: u16,
+    action: String,
+    confirmed: Option<bool>,
+) -> Result<Vec<PortOwner>, String> {
+    let mut config = load_webserver_config();
+
+    match action.as_str() {
+        "stop_owner" => {
+            let owners: Vec<PortOwner> = port_owners_privileged(&[port])
+                .into_iter()
+                .filter(|o| !is_managed_server(o, &[config.active]))
+                .collect();
+            let owned: Vec<String> = if owners.iter().any(|o| o.container.is_some()) {
+                EngineClient::connect()
+                    .and_then(|client| owned_containers(&client))
+                    .map(|containers| containers.into_iter().map(|c| c.name).collect())
+                    .unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+
+            let foreign: Vec<String> = owners
+                .iter()
+                .filter(|o| !is_servermark_owner(o, &owned))
+                .map(|o| o.describe())
+                .collect();
+            if !foreign.is_empty() && !confirmed.unwrap_or(false) {
+                return Err(format!(
+                    "Port {} is used by {}, which ServerMark does not manage. Confirm to stop it.",
+                    port,
+                    foreign.join(", ")
+                ));
+            }
+            for owner in &owners {
+                stop_owner(owner)?;
+            }
+        }
+        "use_alternative_ports" => {
+            config.http_port = first_free_port(ALTERNATIVE_HTTP_PORTS)?;
+            config.https_port = first_free_port(ALTERNATIVE_HTTPS_PORTS)?;
+            save_webserver_config(&config)?;
+            execute_webserver_operation("sync_all", None)?;
+        }
+        "use_default_ports" => {
+            let ports = ListenPorts::default();
+            ensure_ports_available(ports, &[config.active])?;
+            config.http_port = ports.http;
+            config.https_port = ports.https;
+            save_webserver_config(&config)?;
+            execute_webserver_operation("sync_all", None)?;
+        }
+        _ => return Err(format!("Unknown action: {}", action)),
+    }
+
+    Ok(port_owners(&[config.http_port, config.https_port]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_net_tcp() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+   0: 00000000:0050 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 31337 1 0000000000000000 100 0 0 10 0\n   \
+   1: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 4242 1 0000000000000000 100 0 0 10 0\n   \
+   2: 0100007F:1F90 0100007F:A2B4 01 00000000:00000000 00:00000000 00000000  1000        0 4243 1 0000000000000000 20 4 30 10 -1\n";
+
+        let sockets = parse_proc_net_tcp(tcp);
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(sockets[0].port, 80);
+        assert_eq!(sockets[0].uid, 0);
+        assert_eq!(sockets[0].inode, 31337);
+        assert_eq!(sockets[1].port, 8080);
+        if cfg!(target_endian = "little") {
+            assert_eq!(sockets[0].address, "0.0.0.0");
+            assert_eq!(sockets[1].address, "127.0.0.1");
+            assert_eq!(
+                parse_proc_address("00000000000000000000000001000000").as_deref(),
+                Some("::1")
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_ss_owners() {
+        let output = "LISTEN 0 511 0.0.0.0:80 0.0.0.0:* users:((\"nginx\",pid=812,fd=6),(\"nginx\",pid=813,fd=6)) ino:31337 sk:1 cgroup:/system.slice/nginx.service <->\n\
+LISTEN 0 128 0.0.0.0:22 0.0.0.0:* ino:662 sk:2 cgroup:/ <->\n";
+        assert_eq!(parse_ss_owners(output), HashMap::from([(31337, 812)]));
+    }
+
+    #[test]
+    fn test_servermark_owners() {
+        let owner = |unit: Option<&str>, container: Option<&str>| PortOwner {
+            port: 80,
+            address: "0.0.0.0".to_string(),
+            uid: 0,
+            pid: Some(1234),
+            process: None,
+            unit: unit.map(str::to_string),
+            container: container.map(str::to_string),
+        };
+        let owned = vec!["servermark-mysql".to_string()];
+
+        assert!(is_servermark_owner(
+            &owner(Some("nginx.service"), None),
+            &owned
+        ));
+        assert!(is_servermark_owner(
+            &owner(None, Some("servermark-mysql")),
+            &owned
+        ));
+        assert!(!is_servermark_owner(
+            &owner(Some("docker.service"), None),
+            &owned
+        ));
+        assert!(!is_servermark_owner(
+            &owner(Some("sshd.service"), None),
+            &owned
+        ));
+        assert!(!is_servermark_owner(
+            &owner(None, Some("someone-else")),
+            &owned
+        ));
+        assert!(!is_servermark_owner(&owner(None, None), &owned));
+    }
+
+    #[test]
+    fn test_unit_from_cgroup() {
+        assert_eq!(
+            unit_from_cgroup("0::/system.slice/apache2.service\n").as_deref(),
+            Some("apache2.service")
+        );
+        assert_eq!(
+            unit_from_cgroup("0::/user.slice/user-1000.slice/session-2.scope\n"),
+            None
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use super::docker::{inspect_container, list_containers, wait_until_ready, Container};
+use super::dotenv::DotEnv;
+use super::engine::EngineClient;
+use super::sites::{load_sites_config, Site};
+use super::system::{create_database_as, detect_native_services, DbLogin};
+
+/// A database, cache or mail server ServerMark can wire a site to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManagedService {
+    pub kind: String,   // "mysql" | "postgresql" | "redis" | "mailpit"
+    pub source: String, // "container" | "native"
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub container_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnvProvisionReport {
+    pub database: Option<String>,
+    pub updated_keys: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Detect running managed services, containers first, then native services
+pub fn detect_managed_services() -> Vec<ManagedService> {
+    let client = EngineClient::connect().ok();
+    let mut services: Vec<ManagedService> = list_containers()
+        .unwrap_or_default()
+        .iter()
+        .filter(|c| c.status == "running")
+        .filter_map(|c| {
+            let env = client
+                .as_ref()
+                .and_then(|client| inspect_container(client, &c.id).ok())
+                .map(|details| details.config.env)
+                .unwrap_or_default();
+            container_service(c, &env)
+        })
+        .collect();
+
+    for native in detect_native_services().services {
+        if !native.running || services.iter().any(|s| s.kind == native.name) {
+            continue;
+        }
+        let (username, password) = match native.name.as_str() {
+            "mysql" => (Some("root".to_string()), None),
+            "postgresql" => (Some("postgres".to_string()), None),
+            "redis" => (None, None),
+            _ => continue,
+        };
+        services.push(ManagedService {
+            kind: native.name.clone(),
+            source: "native".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: native.port.unwrap_or_default(),
+            username,
+            password,
+            container_id: None,
+        });
+    }
+
+    services
+}
+
+/// Admin credentials a database container was started with, from its
+/// environment (`KEY=value` entries). `None` when they cannot be known, such
+/// as a random root password.
+pub fn container_credentials(kind: &str, env: &[String]) -> (Option<String>, Option<String>) {
+    let var = |key: &str| {
+        env.iter()
+            .find_map(|entry| entry.strip_prefix(key)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    let set = |key: &str| var(key).is_some_and(|value| !value.is_empty());
+    match kind {
+        "mysql" => {
+            let password = var("MYSQL_ROOT_PASSWORD")
+                .or_else(|| var("MARIADB_ROOT_PASSWORD"))
+                .filter(|password| !password.is_empty())
+                .or_else(|| {
+                    let empty = set("MYSQL_ALLOW_EMPTY_PASSWORD")
+                        || set("MARIADB_ALLOW_EMPTY_ROOT_PASSWORD");
+                    empty.then(String::new)
+                });
+            (password.as_ref().map(|_| "root".to_string()), password)
+        }
+        "postgresql" => {
+            let password = var("POSTGRES_PASSWORD").or_else(|| {
+                (var("POSTGRES_HOST_AUTH_METHOD").as_deref() == Some("trust")).then(String::new)
+            });
+            let username = var("POSTGRES_USER")
+                .filter(|user| !user.is_empty())
+                .unwrap_or_else(|| "postgres".to_string());
+            (password.as_ref().map(|_| username), password)
+        }
+        _ => (None, None),
+    }
+}
+
+/// Map a container to the service it provides, with the credentials from its environment
+fn container_service(container: &Container, env: &[String]) -> Option<ManagedService> {
+    let image = container.image.to_lowercase();
+    let (kind, internal_port) = if image.contains("mysql") || image.contains("mariadb") {
+        ("mysql", 3306)
+    } else if image.contains("postgres") {
+        ("postgresql", 5432)
+    } else if image.contains("redis") || image.contains("valkey") {
+        ("redis", 6379)
+    } else if image.contains("mailpit") || image.contains("mailhog") {
+        ("mailpit", 1025)
+    } else {
+        return None;
+    };
+    let (username, password) = container_credentials(kind, env);
+
+    let port = container
+        .ports
+        .iter()
+        .find(|p| p.container == internal_port)
+        .map(|p| p.host)?;
+
+    Some(ManagedService {
+        kind: kind.to_string(),
+        source: "container".to_string(),
+        host: "127.0.0.1".to_string(),
+        port,
+        username,
+        password,
+        container_id: Some(container.id.clone()),
+    })
+}
+
+/// Database name derived from a site name (`my-shop` becomes `my_shop`)
+pub fn database_name_for(site_name: &str) -> String {
+    site_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Write `DB_*`, `REDIS_*` and `MAIL_*` keys for the detected services.
+///
+/// `DB_DATABASE` is only touched when a database was created for the site,
+/// and `DB_USERNAME`/`DB_PASSWORD` are left alone with `keep_credentials`.
+pub fn apply_services_to_env(
+    env: &mut DotEnv,
+    services: &[ManagedService],
+    database: Option<&str>,
+    keep_credentials: bool,
+) -> Vec<String> {
+    let mut updated = Vec::new();
+    let mut set = |env: &mut DotEnv, key: &str, value: &str| {
+        if env.set(key, value) {
+            updated.push(key.to_string());
+        }
+    };
+
+    if let Some(db) = pick_database_service(env, services) {
+        let connection = if db.kind == "postgresql" {
+            "pgsql"
+        } else {
+            "mysql"
+        };
+        set(env, "DB_CONNECTION", connection);
+        set(env, "DB_HOST", &db.host);
+        set(env, "DB_PORT", &db.port.to_string());
+        if let Some(name) = database {
+            set(env, "DB_DATABASE", name);
+        }
+        // Native servers and containers with unknown passwords keep the site's own
+        if let (Some(username), Some(password), false) =
+            (&db.username, &db.password, keep_credentials)
+        {
+            set(env, "DB_USERNAME", username);
+            set(env, "DB_PASSWORD", password);
+        }
+    }
+
+    if let Some(redis) = services.iter().find(|s| s.kind == "redis") {
+        set(env, "REDIS_HOST", &redis.host);
+        set(env, "REDIS_PORT", &redis.port.to_string());
+    }
+
+    if let Some(mail) = services.iter().find(|s| s.kind == "mailpit") {
+        set(env, "MAIL_MAILER", "smtp");
+        set(env, "MAIL_HOST", &mail.host);
+        set(env, "MAIL_PORT", &mail.port.to_string());
+    }
+
+    updated
+}
+
+/// Prefer the server matching the current `DB_CONNECTION`, else the first one found
+fn pick_database_service<'a>(
+    env: &DotEnv,
+    services: &'a [ManagedService],
+) -> Option<&'a ManagedService> {
+    let wanted = match env.get("DB_CONNECTION") {
+        Some("pgsql") => Some("postgresql"),
+        Some("mysql") | Some("mariadb") => Some("mysql"),
+        _ => None,
+    };
+
+    wanted
+        .and_then(|kind| services.iter().find(|s| s.kind == kind))
+        .or_else(|| {
+            services
+                .iter()
+                .find(|s| s.kind == "mysql" || s.kind == "postgresql")
+        })
+}
+
+/// Provision a site's `.env` from the managed services, optionally creating
+/// its database and keeping the site's database credentials
+pub fn provision_env(
+    site: &Site,
+    create_db: bool,
+    keep_credentials: bool,
+) -> Result<EnvProvisionReport, String> {
+    let env_path = Path::new(&site.path).join(".env");
+    if !env_path.exists() {
+        return Ok(EnvProvisionReport::default());
+    }
+
+    let mut report = EnvProvisionReport::default();
+    let services = detect_managed_services();
+    let mut env = DotEnv::load(&env_path)?;
+
+    if create_db {
+        match pick_database_service(&env, &services) {
+            Some(db) => {
+                let name = database_name_for(&site.name);
+                let ready = match &db.container_id {
+                    Some(id) => wait_until_ready(id, Duration::from_secs(60)),
+                    None => Ok(()),
+                };
+                let login = db.username.clone().map(|username| DbLogin {
+                    username,
+                    password: db.password.clone(),
+                });
+                match ready.and_then(|()| {
+                    create_database_as(
+                        db.kind.clone(),
+                        name.clone(),
+                        db.container_id.clone(),
+                        login,
+                    )
+                }) {
+                    Ok(()) => report.database = Some(name),
+                    Err(e) => report
+                        .warnings
+                        .push(format!("Failed to create database {}: {}", name, e)),
+                }
+            }
+            None => report
+                .warnings
+                .push("No running MySQL or PostgreSQL server found".to_string()),
+        }
+    }
+
+    report.updated_keys = apply_services_to_env(
+        &mut env,
+        &services,
+        report.database.as_deref(),
+        keep_credentials,
+    );
+    if !report.updated_keys.is_empty() {
+        env.save(&env_path)?;
+    }
+
+    Ok(report)
+}
+
+/// List the services a site's `.env` can be wired to
+#[tauri::command]
+pub fn list_managed_services() -> Vec<ManagedService> {
+    detect_managed_services()
+}
+
+/// Wire a site's `.env` to the managed services
+#[tauri::command]
+pub async fn provision_site_env(
+    id: String,
+    create_database: bool,
+) -> Result<EnvProvisionReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let config = load_sites_config();
+        let site = config
+            .sites
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or("Site not found")?;
+
+        provision_env(site, create_database, false)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(kind: &str, port: u16) -> ManagedService {
+        ManagedService {
+            kind: kind.to_string(),
+            source: "container".to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            username: Some("root".to_string()),
+            password: Some("secret".to_string()),
+            container_id: Some(format!("{}-id", kind)),
+        }
+    }
+
+    #[test]
+    fn test_database_name_for() {
+        assert_eq!(database_name_for("My-Shop.v2"), "my_shop_v2");
+    }
+
+    #[test]
+    fn test_apply_services_to_env() {
+        let mut env = DotEnv::parse(
+            "APP_NAME=shop\n# DB\nDB_CONNECTION=pgsql\nDB_HOST=pgsql\nDB_DATABASE=laravel\n\nMAIL_MAILER=log\n",
+        );
+        let services = vec![
+            service("mysql", 3307),
+            service("postgresql", 5433),
+            service("redis", 6380),
+            service("mailpit", 1025),
+        ];
+
+        let updated = apply_services_to_env(&mut env, &services, None, false);
+
+        assert_eq!(env.get("DB_CONNECTION"), Some("pgsql"));
+        assert_eq!(env.get("DB_HOST"), Some("127.0.0.1"));
+        assert_eq!(env.get("DB_PORT"), Some("5433"));
+        assert_eq!(env.get("DB_DATABASE"), Some("laravel"));
+        assert_eq!(env.get("REDIS_PORT"), Some("6380"));
+        assert_eq!(env.get("MAIL_MAILER"), Some("smtp"));
+        assert!(!updated.contains(&"DB_CONNECTION".to_string()));
+        assert!(env.to_string().contains("# DB\n"));
+        assert_eq!(env.get("DB_USERNAME"), Some("root"));
+    }
+
+    #[test]
+    fn test_native_service_keeps_credentials() {
+        let mut env = DotEnv::parse("DB_CONNECTION=mysql\nDB_USERNAME=shop\nDB_PASSWORD=hunter2\n");
+        let native = ManagedService {
+            source: "native".to_string(),
+            password: None,
+            container_id: None,
+            ..service("mysql", 3306)
+        };
+
+        let updated = apply_services_to_env(&mut env, &[native], None, false);
+        assert_eq!(env.get("DB_USERNAME"), Some("shop"));
+        assert_eq!(env.get("DB_PASSWORD"), Some("hunter2"));
+        assert!(!updated.contains(&"DB_PASSWORD".to_string()));
+    }
+
+    #[test]
+    fn test_container_credentials() {
+        let env = |entries: &[&str]| entries.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            container_credentials("mysql", &env(&["MYSQL_ROOT_PASSWORD=s3cret"])),
+            (Some("root".to_string()), Some("s3cret".to_string()))
+        );
+        assert_eq!(
+            container_credentials("mysql", &env(&["MYSQL_ALLOW_EMPTY_PASSWORD=1"])),
+            (Some("root".to_string()), Some(String::new()))
+        );
+        assert_eq!(
+            container_credentials("mysql", &env(&["MYSQL_RANDOM_ROOT_PASSWORD=yes"])),
+            (None, None)
+        );
+        assert_eq!(
+            container_credentials(
+                "postgresql",
+                &env(&["POSTGRES_USER=sail", "POSTGRES_PASSWORD=password"])
+            ),
+            (Some("sail".to_string()), Some("password".to_string()))
+        );
+        assert_eq!(
+            container_credentials("postgresql", &env(&["POSTGRES_PASSWORD=secret"])),
+            (Some("postgres".to_string()), Some("secret".to_string()))
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::backends::{backend, CaddyBackend, ListenPorts, WebServerBackend, WebServerKind};
+use super::dns::detect_dns;
+use super::sites::{load_sites_config, Site};
+use super::webserver::{build_webserver_script, load_webserver_config, save_webserver_config};
+
+/// User-level Caddy instead of the system web server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootlessConfig {
+    pub method: String, // "forward" (80/443 proxied to high ports) | "capability"
+    pub http_port: u16,
+    pub https_port: u16,
+}
+
+impl RootlessConfig {
+    pub fn ports(&self) -> ListenPorts {
+        ListenPorts {
+            http: self.http_port,
+            https: self.https_port,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootlessStatus {
+    pub enabled: bool,
+    pub method: Option<String>,
+    pub caddy_running: bool,
+    pub config_dir: String,
+    pub http_port: Option<u16>,
+    pub https_port: Option<u16>,
+    pub dns_configured: bool, // Rootless mode does not write /etc/hosts
+    pub relogin_required: bool,
+}
+
+pub const USER_UNIT: &str = "servermark-caddy.service";
+
+/// Caddy admin endpoint, kept off 2019 so a system Caddy does not clash
+const ADMIN_ADDRESS: &str = "localhost:2999";
+
+/// Units the user may start/stop without a password prompt (polkit rule)
+const MANAGED_UNITS_PATTERN: &str =
+    "^(php[0-9.]+-fpm|mysql|mariadb|postgresql|redis|redis-server|memcached|dnsmasq)\\.service$";
+
+pub fn rootless_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("servermark")
+        .join("caddy")
+}
+
+fn sites_dir() -> PathBuf {
+    rootless_dir().join("sites.d")
+}
+
+fn ca_dir() -> PathBuf {
+    rootless_dir().join("ca")
+}
+
+fn unit_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("systemd")
+        .join("user")
+        .join(USER_UNIT)
+}
+
+/// Path of a site's config for the user-level Caddy
+pub fn rootless_site_config_path(site_name: &str) -> PathBuf {
+    sites_dir().join(CaddyBackend.site_file_name(site_name))
+}
+
+pub fn rootless_config() -> Option<RootlessConfig> {
+    load_webserver_config().rootless
+}
+
+pub fn user_unit_active() -> bool {
+    Command::new("systemctl")
+        .args(["--user", "is-active", USER_UNIT])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+pub fn user_systemctl(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run systemctl --user: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+fn find_caddy() -> Result<String, String> {
+    Command::new("which")
+        .arg("caddy")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .ok_or_else(|| "Caddy is not installed".to_string())
+}
+
+fn render_caddyfile(ports: ListenPorts, dir: &Path) -> String {
+    format!(
+        r#"{{
+    admin {admin}
+    http_port {http}
+    https_port {https}
+    skip_install_trust
+    pki {{
+        ca local {{
+            name "ServerMark Local CA"
+            root {{
+                cert {dir}/ca/root.crt
+                key {dir}/ca/root.key
+            }}
+        }}
+    }}
+}}
+
+import {dir}/sites.d/*.conf
+"#,
+        admin = ADMIN_ADDRESS,
+        http = ports.http,
+        https = ports.https,
+        dir = dir.display(),
+    )
+}
+
+fn render_unit(caddy: &str, caddyfile: &Path) -> String {
+    format!(
+        r#"[Unit]
+Description=ServerMark Caddy (rootless)
+After=network.target
+
+[Service]
+ExecStart={caddy} run --config {config} --adapter caddyfile
+ExecReload={caddy} reload --config {config} --adapter caddyfile --force
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+"#,
+        caddy = caddy,
+        config = caddyfile.display(),
+    )
+}
+
+/// Root CA for `tls internal`, created up front so the one-time setup can trust it
+fn ensure_local_ca() -> Result<(), String> {
+    let dir = ca_dir();
+    if dir.join("root.crt").exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create CA dir: {}", e))?;
+
+    let output = Command::new("openssl")
+        .args([
+            "req",
+            "-x509",
+            "-newkey",
+            "rsa:2048",
+            "-nodes",
+            "-days",
+            "3650",
+            "-subj",
+            "/CN=ServerMark Local CA",
+            "-addext",
+            "basicConstraints=critical,CA:TRUE",
+            "-addext",
+            "keyUsage=critical,keyCertSign,cRLSign",
+        ])
+        .arg("-keyout")
+        .arg(dir.join("root.key"))
+        .arg("-out")
+        .arg(dir.join("root.crt"))
+        .output()
+        .map_err(|e| format!("Failed to create local CA: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to create local CA: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Write the Caddyfile and user unit
+fn write_user_files(config: &RootlessConfig) -> Result<(), String> {
+    let dir = rootless_dir();
+    fs::create_dir_all(sites_dir()).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    ensure_local_ca()?;
+
+    let caddyfile = dir.join("Caddyfile");
+    fs::write(&caddyfile, render_caddyfile(config.ports(), &dir))
+        .map_err(|e| format!("Failed to write Caddyfile: {}", e))?;
+
+    let unit = unit_path();
+    if let Some(parent) = unit.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create unit dir: {}", e))?;
+    }
+    fs::write(&unit, render_unit(&find_caddy()?, &caddyfile))
+        .map_err(|e| format!("Failed to write user unit: {}", e))?;
+
+    user_systemctl(&["daemon-reload"])
+}
+
+fn write_site(site: &Site, ports: ListenPorts) -> Result<(), String> {
+    fs::write(
+        rootless_site_config_path(&site.name),
+        CaddyBackend.render_site_config(site, ports),
+    )
+    .map_err(|e| format!("Failed to write site config: {}", e))
+}
+
+/// Rootless counterpart of `execute_webserver_operation`: plain file writes
+/// and a user unit reload, no root needed. Host names resolve through DNS.
+pub fn apply_rootless_operation(
+    config: &RootlessConfig,
+    operation: &str,
+    sites: &[Site],
+    site_name: Option<&str>,
+) -> Result<(), String> {
+    let ports = config.ports();
+    fs::create_dir_all(sites_dir()).map_err(|e| format!("Failed to create config dir: {}", e))?;
+
+    match operation {
+        "sync_all" | "switch_server" => {
+            if let Ok(entries) = fs::read_dir(sites_dir()) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+            for site in sites {
+                write_site(site, ports)?;
+            }
+        }
+        "add_site" | "update_site" | "renew_certificate" => {
+            let site = site_name
+                .and_then(|name| sites.iter().find(|s| s.name == name))
+                .ok_or("Site not found")?;
+            if operation == "renew_certificate" {
+                if let Some(data) = dirs::data_dir() {
+                    let _ = fs::remove_dir_all(
+                        data.join("caddy/certificates/local").join(&site.domain),
+                    );
+                }
+            }
+            write_site(site, ports)?;
+        }
+        "remove_site" => {
+            if let Some(name) = site_name {
+                let _ = fs::remove_file(rootless_site_config_path(name));
+            }
+        }
+        _ => {}
+    }
+
+    user_systemctl(&["reload-or-restart", USER_UNIT])
+}
+
+/// One-time root setup: port access, PHP-FPM socket group, service control and CA trust
+fn setup_script(config: &RootlessConfig, user: &str, caddy: &str) -> String {
+    let mut script = String::from("#!/bin/bash\nset -e\n\n# Stop the system web servers\n");
+    for kind in WebServerKind::ALL {
+        script.push_str(&backend(kind).stop_script());
+    }
+
+    script.push_str(&format!(
+        r#"
+# PHP-FPM sockets are owned by www-data
+usermod -aG www-data {user} || true
+
+# Keep the user-level Caddy running without a login session
+loginctl enable-linger {user}
+"#,
+        user = user
+    ));
+
+    if config.method == "capability" {
+        script.push_str(&format!(
+            "\n# Allow Caddy to bind ports below 1024\nsetcap cap_net_bind_service=+ep '{}'\n",
+            caddy
+        ));
+    } else {
+        for (name, public, target) in [
+            ("http", 80, config.http_port),
+            ("https", 443, config.https_port),
+        ] {
+            script.push_str(&format!(
+                r#"
+# Forward port {public} to the user-level Caddy
+cat > /etc/systemd/system/servermark-{name}.socket << 'UNITEOF'
+[Unit]
+Description=ServerMark {name} forward
+
+[Socket]
+ListenStream={public}
+
+[Install]
+WantedBy=sockets.target
+UNITEOF
+cat > /etc/systemd/system/servermark-{name}.service << 'UNITEOF'
+[Unit]
+Description=ServerMark {name} forward
+Requires=servermark-{name}.socket
+
+[Service]
+ExecStart=/usr/lib/systemd/systemd-socket-proxyd 127.0.0.1:{target}
+UNITEOF
+"#,
+                name = name,
+                public = public,
+                target = target
+            ));
+        }
+        script.push_str(
+            "systemctl daemon-reload\nsystemctl enable --now servermark-http.socket servermark-https.socket\n",
+        );
+    }
+
+    script.push_str(&format!(
+        r#"
+# Let {user} start and stop PHP-FPM and database services without a password
+mkdir -p /etc/polkit-1/rules.d
+cat > /etc/polkit-1/rules.d/50-servermark.rules << 'RULEEOF'
+polkit.addRule(function(action, subject) {{
+    if (action.id == "org.freedesktop.systemd1.manage-units" &&
+        subject.user == "{user}" &&
+        /{pattern}/.test(action.lookup("unit"))) {{
+        return polkit.Result.YES;
+    }}
+}});
+RULEEOF
+
+# Trust the local CA used for HTTPS sites
+if [ -d /usr/local/share/ca-certificates ]; then
+    cp '{ca}' /usr/local/share/ca-certificates/servermark-local-ca.crt
+    update-ca-certificates
+elif command -v trust >/dev/null; then
+    trust anchor --store '{ca}'
+fi
+"#,
+        user = user,
+        pattern = MANAGED_UNITS_PATTERN,
+        ca = ca_dir().join("root.crt").display(),
+    ));
+
+    script
+}
+
+/// Undo `setup_script`. The www-data membership is kept since site files
+/// may depend on it; the passwordless polkit grant and the trusted CA are not.
+fn teardown_script(caddy: Option<&str>) -> String {
+    let mut script = format!(
+        r#"
+# Remove the rootless port forwards
+systemctl disable --now servermark-http.socket servermark-https.socket 2>/dev/null || true
+systemctl stop servermark-http.service servermark-https.service 2>/dev/null || true
+rm -f /etc/systemd/system/servermark-http.socket /etc/systemd/system/servermark-http.service
+rm -f /etc/systemd/system/servermark-https.socket /etc/systemd/system/servermark-https.service
+systemctl daemon-reload
+
+# Revoke the passwordless unit control
+rm -f /etc/polkit-1/rules.d/50-servermark.rules
+
+# Stop trusting the local CA
+if [ -f /usr/local/share/ca-certificates/servermark-local-ca.crt ]; then
+    rm -f /usr/local/share/ca-certificates/servermark-local-ca.crt
+    update-ca-certificates --fresh
+fi
+if command -v trust >/dev/null && [ -f '{ca}' ]; then
+    trust anchor --remove '{ca}' 2>/dev/null || true
+fi
+"#,
+        ca = ca_dir().join("root.crt").display(),
+    );
+    if let Some(caddy) = caddy {
+        script.push_str(&format!("setcap -r '{}' 2>/dev/null || true\n", caddy));
+    }
+    script
+}
+
+/// Whether the user was added to www-data after this session started
+fn relogin_required() -> bool {
+    let groups = |args: &[&str]| {
+        Command::new("id")
+            .args(args)
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+            .unwrap_or_default()
+    };
+    let user = std::env::var("USER").unwrap_or_default();
+    let configured = groups(&["-Gn", &user]);
+    let current = groups(&["-Gn"]);
+    let has = |list: &str| list.split_whitespace().any(|g| g == "www-data");
+
+    has(&configured) && !has(&current)
+}
+
+fn run_privileged(script: &str) -> Result<(), String> {
+    let output = Command::new("pkexec")
+        .args(["bash", "-c", script])
+        .output()
+        .map_err(|e| format!("Failed to execute: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Operation failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Current rootless mode state
+#[tauri::command]
+pub fn get_rootless_status() -> RootlessStatus {
+    let config = rootless_config();
+    RootlessStatus {
+        enabled: config.is_some(),
+        method: config.as_ref().map(|c| c.method.clone()),
+        caddy_running: user_unit_active(),
+        config_dir: rootless_dir().to_string_lossy().to_string(),
+        http_port: config.as_ref().map(|c| c.http_port),
+        https_port: config.as_ref().map(|c| c.https_port),
+        dns_configured: detect_dns().dnsmasq_configured,
+        relogin_required: relogin_required(),
+    }
+}
+
+/// Switch to a user-level Caddy. Asks for the password once; site changes
+/// afterwards only touch files under the user's config directory.
+#[tauri::command]
+pub fn enable_rootless_mode(method: String) -> Result<RootlessStatus, String> {
+    let config = match method.as_str() {
+        "forward" => RootlessConfig {
+            method,
+            http_port: 8080,
+            https_port: 8443,
+        },
+        "capability" => RootlessConfig {
+            method,
+            http_port: 80,
+            https_port: 443,
+        },
+        _ => return Err(format!("Unknown rootless method: {}", method)),
+    };
+
+    let caddy = find_caddy()?;
+    let user = std::env::var("USER").map_err(|_| "Could not determine the current user")?;
+
+    write_user_files(&config)?;
+    run_privileged(&setup_script(&config, &user, &caddy))?;
+
+    let mut ws_config = load_webserver_config();
+    ws_config.rootless = Some(config.clone());
+    save_webserver_config(&ws_config)?;
+
+    user_systemctl(&["enable", USER_UNIT])?;
+    apply_rootless_operation(&config, "sync_all", &load_sites_config().sites, None)?;
+
+    Ok(get_rootless_status())
+}
+
+/// Go back to the system web server
+#[tauri::command]
+pub fn disable_rootless_mode() -> Result<RootlessStatus, String> {
+    let mut ws_config = load_webserver_config();
+    if ws_config.rootless.take().is_none() {
+        return Ok(get_rootless_status());
+    }
+
+    let _ = user_systemctl(&["disable", "--now", USER_UNIT]);
+
+    // Teardown and restoring the system server share one password prompt
+    let mut script = teardown_script(find_caddy().ok().as_deref());
+    script.push_str(&build_webserver_script(
+        "switch_server",
+        &load_sites_config().sites,
+        &ws_config,
+        None,
+    ));
+    run_privileged(&script)?;
+    save_webserver_config(&ws_config)?;
+
+    Ok(get_rootless_status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_caddyfile() {
+        let config = RootlessConfig {
+            method: "forward".to_string(),
+            http_port: 8080,
+            https_port: 8443,
+        };
+        let caddyfile = render_caddyfile(
+            config.ports(),
+            Path::new("/home/dev/.config/servermark/caddy"),
+        );
+        assert!(caddyfile.contains("http_port 8080\n"));
+        assert!(caddyfile.contains("cert /home/dev/.config/servermark/caddy/ca/root.crt"));
+        assert!(caddyfile.ends_with("import /home/dev/.config/servermark/caddy/sites.d/*.conf\n"));
+    }
+}
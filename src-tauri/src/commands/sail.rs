@@ -0,0 +1,469 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use super::catalog::{find_preset, preset_container_params, PresetOptions, ServicePreset};
+use super::compose::{find_compose_file, parse_compose, ComposeService};
+use super::docker::{create_container_with, start_container, wait_until_ready, Container};
+use super::dotenv::DotEnv;
+use super::engine::EngineClient;
+use super::ownership::owned_containers;
+use super::php::get_php_versions;
+use super::provision::provision_env;
+use super::sites::{add_site_blocking, Site};
+use super::system::create_database;
+
+// Moving a Laravel Sail project onto ServerMark. The app container is
+// replaced by the native PHP version of its Sail runtime, and every other
+// service by the matching preset container with the same version, ports and
+// environment.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SailImportReport {
+    pub site: Site,
+    /// Preset containers created or reused, by name
+    pub containers: Vec<String>,
+    pub databases: Vec<String>,
+    /// Compose services without a matching preset
+    pub skipped: Vec<String>,
+    /// `.env` credentials rewritten to the accounts of reused containers
+    pub changed_credentials: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Keys naming the database an image creates on first start
+const DATABASE_KEYS: &[&str] = &["MYSQL_DATABASE", "MARIADB_DATABASE", "POSTGRES_DB"];
+
+/// PHP version of a Sail runtime path (`./vendor/laravel/sail/runtimes/8.3`)
+/// or image (`sail-8.3/app`)
+fn runtime_version(value: &str) -> Option<String> {
+    let version = match value.split_once("sail/runtimes/") {
+        Some((_, rest)) => rest.split('/').next()?,
+        None => value
+            .trim_start_matches("./")
+            .strip_prefix("sail-")?
+            .split('/')
+            .next()?,
+    };
+    let valid = !version.is_empty() && version.chars().all(|c| c.is_ascii_digit() || c == '.');
+    valid.then(|| version.to_string())
+}
+
+/// The app service and its PHP version: the service built from a Sail runtime
+pub fn sail_runtime(doc: &Value) -> Option<(String, String)> {
+    doc.get("services")?
+        .as_mapping()?
+        .iter()
+        .find_map(|(name, spec)| {
+            let build = spec.get("build");
+            let context = build
+                .and_then(|b| b.get("context"))
+                .or(build)
+                .and_then(Value::as_str);
+            let version = context
+                .and_then(runtime_version)
+                .or_else(|| spec.get("image")?.as_str().and_then(runtime_version))?;
+            Some((name.as_str()?.to_string(), version))
+        })
+}
+
+/// Whether a service mounts Sail's script creating the `testing` database
+fn creates_testing_database(doc: &Value, service: &str) -> bool {
+    doc.get("services")
+        .and_then(|services| services.get(service))
+        .and_then(|spec| spec.get("volumes"))
+        .and_then(Value::as_sequence)
+        .is_some_and(|volumes| {
+            volumes
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|v| v.contains("create-testing-database"))
+        })
+}
+
+/// The preset version closest to an image tag: the tag itself, the version
+/// it is a patch of (`8.0.36` gives `8.0`), else the preset's default
+pub fn preset_version(preset: &ServicePreset, image: Option<&str>) -> String {
+    let tag = image
+        .and_then(|image| {
+            let name_end = image.rfind('/').map_or(0, |slash| slash + 1);
+            image[name_end..].split_once(':').map(|(_, tag)| tag)
+        })
+        .unwrap_or_default();
+    preset
+        .versions
+        .iter()
+        .find(|&&v| v == tag)
+        .or_else(|| {
+            preset.versions.iter().find(|&&v| {
+                tag.strip_prefix(v)
+                    .is_some_and(|rest| rest.starts_with(['.', '-']))
+            })
+        })
+        .copied()
+        .unwrap_or(preset.default_version)
+        .to_string()
+}
+
+/// Preset overrides reproducing a Sail service
+pub fn sail_service_options(
+    preset: &ServicePreset,
+    service: &ComposeService,
+    site: &str,
+) -> PresetOptions {
+    let ports = service
+        .ports
+        .iter()
+        .filter(|p| preset.ports.contains(&p.container))
+        .filter_map(|p| Some((p.container, p.host?)))
+        .collect();
+    let environment = service
+        .environment
+        .iter()
+        // Sail's credentials are kept, except MYSQL_USER=root, which the
+        // images refuse as root is the admin account already
+        .filter(|(key, value)| {
+            !(matches!(key.as_str(), "MYSQL_USER" | "MARIADB_USER") && value.as_str() == "root")
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    PresetOptions {
+        version: Some(preset_version(preset, service.image.as_deref())),
+        ports,
+        environment,
+        site: Some(site.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Databases a Sail service had: its image's database and Sail's `testing` one
+fn sail_databases(service: &ComposeService, env: &DotEnv, testing: bool) -> Vec<String> {
+    let mut databases: Vec<String> = DATABASE_KEYS
+        .iter()
+        .find_map(|key| service.environment.get(*key))
+        .map(String::as_str)
+        .or_else(|| env.get("DB_DATABASE"))
+        .filter(|name| !name.is_empty())
+        .map(|name| vec![name.to_string()])
+        .unwrap_or_default();
+    if testing && !databases.iter().any(|d| d == "testing") {
+        databases.push("testing".to_string());
+    }
+    databases
+}
+
+/// How an existing preset container differs from the Sail service it stands in for
+fn reuse_warnings(
+    preset: &ServicePreset,
+    service: &ComposeService,
+    container: &Container,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let wanted = preset_version(preset, service.image.as_deref());
+    let running = preset_version(preset, Some(&container.image));
+    if running != wanted {
+        warnings.push(format!(
+            "{}: using the existing {} ({}), while the Sail project used {} {}",
+            service.name, container.name, container.image, preset.name, wanted
+        ));
+    }
+    for port in service
+        .ports
+        .iter()
+        .filter(|p| preset.ports.contains(&p.container))
+    {
+        let published = container
+            .ports
+            .iter()
+            .find(|p| p.container == port.container)
+            .map(|p| p.host);
+        if let (Some(sail), Some(published)) = (port.host, published) {
+            if sail != published {
+                warnings.push(format!(
+                    "{}: {} publishes port {} instead of {}",
+                    service.name, container.name, published, sail
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Create (or reuse) the preset container for a Sail service; returns its id,
+/// its name and whether it was reused. Reusing one that differs from the
+/// service adds warnings.
+fn provision_sail_service(
+    preset: &ServicePreset,
+    service: &ComposeService,
+    site: &Site,
+    warnings: &mut Vec<String>,
+) -> Result<(String, String, bool), String> {
+    let existing = owned_containers(&EngineClient::connect()?)?
+        .into_iter()
+        .find(|c| c.preset.as_deref() == Some(preset.id));
+    if let Some(container) = existing {
+        warnings.extend(reuse_warnings(preset, service, &container));
+        if container.status != "running" {
+            start_container(container.id.clone())?;
+        }
+        return Ok((container.id, container.name, true));
+    }
+
+    let params = preset_container_params(preset, &sail_service_options(preset, service, &site.id))?;
+    let name = params.name.clone();
+    Ok((
+        create_container_with(&EngineClient::connect()?, &params)?,
+        name,
+        false,
+    ))
+}
+
+/// Import a Laravel Sail project: add the site with its Sail PHP version,
+/// replace its services with preset containers, create its databases and
+/// point `.env` at them
+#[tauri::command]
+pub async fn import_sail_project(path: String) -> Result<SailImportReport, String> {
+    tauri::async_runtime::spawn_blocking(move || import_sail_project_blocking(path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn import_sail_project_blocking(path: String) -> Result<SailImportReport, String> {
+    let project = Path::new(&path);
+    let file = find_compose_file(project).ok_or_else(|| format!("No compose file in {}", path))?;
+    let content = fs::read_to_string(&file)
+        .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let doc: Value =
+        serde_yaml::from_str(&content).map_err(|e| format!("Invalid compose file: {}", e))?;
+    let (app_service, php_version) =
+        sail_runtime(&doc).ok_or_else(|| format!("{} is not a Laravel Sail project", path))?;
+
+    let env = DotEnv::load(&project.join(".env")).unwrap_or_default();
+    let lookup = |name: &str| env.get(name).map(str::to_string);
+    let services = parse_compose(&content, &lookup)?;
+
+    let mut warnings = Vec::new();
+    let installed = get_php_versions()
+        .unwrap_or_default()
+        .iter()
+        .any(|v| v.installed && v.version == php_version);
+    if !installed {
+        warnings.push(format!(
+            "PHP {} is not installed; install it for the site to run",
+            php_version
+        ));
+    }
+
+    // Hostnames and APP_URL are rewritten by add_site
+    let site = add_site_blocking(path.clone(), None, Some(php_version), Some(false), false)?;
+    let mut report = SailImportReport {
+        site,
+        containers: Vec::new(),
+        databases: Vec::new(),
+        skipped: Vec::new(),
+        changed_credentials: Vec::new(),
+        warnings,
+    };
+
+    let mut started: HashMap<&str, String> = HashMap::new();
+    // A reused database container has its own accounts, not Sail's
+    let mut reused_database = false;
+    for service in services.iter().filter(|s| s.name != app_service) {
+        let Some(preset) = service.preset.as_deref().and_then(find_preset) else {
+            report.skipped.push(service.name.clone());
+            continue;
+        };
+        if started.contains_key(preset.id) {
+            continue; // One container per preset
+        }
+        match provision_sail_service(preset, service, &report.site, &mut report.warnings) {
+            Ok((id, name, reused)) => {
+                reused_database |= reused && is_database(preset);
+                report.containers.push(name);
+                started.insert(preset.id, id);
+            }
+            Err(e) => report
+                .warnings
+                .push(format!("Failed to create {}: {}", service.name, e)),
+        }
+    }
+
+    for service in &services {
+        let Some(preset) = service.preset.as_deref().and_then(find_preset) else {
+            continue;
+        };
+        let db_type = match preset.id {
+            "mysql" | "mariadb" => "mysql",
+            "postgresql" => "postgresql",
+            _ => continue,
+        };
+        let Some(id) = started.remove(preset.id) else {
+            continue;
+        };
+        if let Err(e) = wait_until_ready(&id, Duration::from_secs(120)) {
+            report.warnings.push(format!("{}: {}", service.name, e));
+            continue;
+        }
+        let testing = creates_testing_database(&doc, &service.name);
+        for name in sail_databases(service, &env, testing) {
+            match create_database(db_type.to_string(), name.clone(), Some(id.clone())) {
+                Ok(()) => report.databases.push(name),
+                Err(e) => report
+                    .warnings
+                    .push(format!("Failed to create database {}: {}", name, e)),
+            }
+        }
+    }
+
+    // Wire .env to the containers, keeping DB_DATABASE. New containers run
+    // with the Sail credentials; reused ones need their own in .env.
+    match provision_env(&report.site, false, !reused_database) {
+        Ok(env_report) => {
+            report.changed_credentials = credential_changes(&env, &env_report.updated_keys);
+            report.warnings.extend(env_report.warnings);
+        }
+        Err(e) => report
+            .warnings
+            .push(format!("Failed to update .env: {}", e)),
+    }
+
+    Ok(report)
+}
+
+fn is_database(preset: &ServicePreset) -> bool {
+    matches!(preset.id, "mysql" | "mariadb" | "postgresql")
+}
+
+/// The credential keys `provision_env` rewrote, with the old username
+fn credential_changes(before: &DotEnv, updated: &[String]) -> Vec<String> {
+    updated
+        .iter()
+        .filter_map(|key| match key.as_str() {
+            "DB_USERNAME" => Some(format!(
+                "DB_USERNAME (was {})",
+                before.get(key).unwrap_or_default()
+            )),
+            "DB_PASSWORD" => Some(key.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAIL: &str = r#"
+services:
+    laravel.test:
+        build:
+            context: './vendor/laravel/sail/runtimes/8.3'
+            dockerfile: Dockerfile
+        image: 'sail-8.3/app'
+    pgsql:
+        image: 'postgres:17.2'
+        ports:
+            - '${FORWARD_DB_PORT:-5433}:5432'
+        environment:
+            PGPASSWORD: '${DB_PASSWORD:-secret}'
+            POSTGRES_DB: '${DB_DATABASE}'
+            POSTGRES_USER: '${DB_USERNAME}'
+            POSTGRES_PASSWORD: '${DB_PASSWORD:-secret}'
+        volumes:
+            - './vendor/laravel/sail/database/pgsql/create-testing-database.sql:/docker-entrypoint-initdb.d/10-create-testing-database.sql'
+    redis:
+        image: 'redis:alpine'
+    selenium:
+        image: 'selenium/standalone-chromium'
+"#;
+
+    fn parse() -> (Value, Vec<ComposeService>, DotEnv) {
+        let env = DotEnv::parse("DB_DATABASE=shop\nDB_USERNAME=sail\nDB_PASSWORD=password\n");
+        let lookup = |name: &str| env.get(name).map(str::to_string);
+        let services = parse_compose(SAIL, &lookup).unwrap();
+        (serde_yaml::from_str(SAIL).unwrap(), services, env)
+    }
+
+    #[test]
+    fn test_sail_runtime() {
+        let (doc, _, _) = parse();
+        assert_eq!(
+            sail_runtime(&doc),
+            Some(("laravel.test".to_string(), "8.3".to_string()))
+        );
+        assert_eq!(runtime_version("sail-8.4/app").as_deref(), Some("8.4"));
+        assert_eq!(runtime_version("./docker/8.2"), None);
+    }
+
+    #[test]
+    fn test_sail_service_options() {
+        let (doc, services, env) = parse();
+        let pgsql = &services[1];
+        let preset = find_preset(pgsql.preset.as_deref().unwrap()).unwrap();
+        assert_eq!(preset.id, "postgresql");
+
+        let options = sail_service_options(preset, pgsql, "site-1");
+        assert_eq!(options.version.as_deref(), Some("17"));
+        assert_eq!(options.ports, HashMap::from([(5432, 5433)]));
+        assert_eq!(options.environment["POSTGRES_DB"], "shop");
+        assert_eq!(options.environment["POSTGRES_USER"], "sail");
+        assert_eq!(options.environment["POSTGRES_PASSWORD"], "password");
+
+        let testing = creates_testing_database(&doc, &pgsql.name);
+        assert_eq!(
+            sail_databases(pgsql, &env, testing),
+            vec!["shop", "testing"]
+        );
+
+        let redis = find_preset("redis").unwrap();
+        assert_eq!(
+            preset_version(redis, services[2].image.as_deref()),
+            redis.default_version
+        );
+        assert_eq!(services[3].preset, None);
+    }
+
+    #[test]
+    fn test_reuse_warnings() {
+        let (_, services, _) = parse();
+        let pgsql = &services[1];
+        let preset = find_preset("postgresql").unwrap();
+        let container = |image: &str, host: u16| -> Container {
+            serde_json::from_value(serde_json::json!({
+                "id": "c1", "name": "servermark-postgresql", "image": image, "status": "running",
+                "ports": [{"host": host, "container": 5432, "protocol": "tcp"}],
+                "created": "", "health": null, "labels": {}, "mounts": [],
+                "preset": "postgresql", "site": null,
+            }))
+            .unwrap()
+        };
+
+        assert!(reuse_warnings(preset, pgsql, &container("postgres:17", 5433)).is_empty());
+        let warnings = reuse_warnings(preset, pgsql, &container("postgres:13", 5432));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("postgres:13"), "{}", warnings[0]);
+        assert!(
+            warnings[1].contains("5432 instead of 5433"),
+            "{}",
+            warnings[1]
+        );
+    }
+
+    #[test]
+    fn test_credential_changes() {
+        let (_, _, env) = parse();
+        let updated = vec![
+            "DB_HOST".to_string(),
+            "DB_USERNAME".to_string(),
+            "DB_PASSWORD".to_string(),
+        ];
+        assert_eq!(
+            credential_changes(&env, &updated),
+            vec!["DB_USERNAME (was sail)", "DB_PASSWORD"]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::{IpAddr, UdpSocket};
+use std::path::PathBuf;
+use std::process::Command;
+
+use super::backends::ListenPorts;
+use super::rootless::user_systemctl;
+use super::sites::{load_sites_config, save_config, Site};
+use super::webserver::{load_webserver_config, webserver_update_site};
+
+// Sharing a site with other devices. A shared site gets a second server block
+// for a hostname those devices can resolve, with its own certificate; the
+// web servers already listen on every interface.
+
+/// How other devices on the LAN resolve a shared site
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LanShareMode {
+    /// `name.192-168-1-20.nip.io`, resolved by the public nip.io DNS
+    NipIo,
+    /// `name.local`, announced over mDNS by avahi
+    Mdns,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanShare {
+    pub mode: LanShareMode,
+    pub hostname: String,
+    pub address: IpAddr,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanShareInfo {
+    pub site_id: String,
+    pub hostname: String,
+    pub address: IpAddr,
+    pub url: String, // Meant for a QR code
+}
+
+/// Address other machines reach this one on: the source address of the
+/// default route (connecting a UDP socket sends nothing)
+pub fn lan_address() -> Result<IpAddr, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket
+        .connect("192.0.2.1:80")
+        .map_err(|_| "No network connection found".to_string())?;
+    let address = socket.local_addr().map_err(|e| e.to_string())?.ip();
+    if address.is_loopback() || address.is_unspecified() {
+        return Err("No LAN address found".to_string());
+    }
+    Ok(address)
+}
+
+/// The site's whole domain as one host name label (`app.dev.internal` ->
+/// `app-dev-internal`), so sites differing only in their TLD stay apart
+fn host_label(site: &Site) -> String {
+    let label: String = site
+        .domain
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(63)
+        .collect();
+    label.trim_matches('-').to_string()
+}
+
+pub fn lan_hostname(site: &Site, mode: LanShareMode, address: IpAddr) -> String {
+    match mode {
+        LanShareMode::NipIo => format!(
+            "{}.{}.nip.io",
+            host_label(site),
+            address.to_string().replace(['.', ':'], "-")
+        ),
+        LanShareMode::Mdns => format!("{}.local", host_label(site)),
+    }
+}
+
+/// The site as served under its LAN hostname, for rendering the extra
+/// server block and issuing its certificate
+pub fn lan_site(site: &Site) -> Option<Site> {
+    let share = site.lan_share.as_ref()?;
+    let mut shared = site.clone();
+    shared.domain = share.hostname.clone();
+    shared.aliases = Vec::new();
+    shared.lan_share = None;
+    Some(shared)
+}
+
+pub fn lan_share_url(site: &Site, share: &LanShare, ports: ListenPorts) -> String {
+    let scheme = if site.secured { "https" } else { "http" };
+    format!(
+        "{}://{}{}",
+        scheme,
+        share.hostname,
+        ports.url_suffix(site.secured)
+    )
+}
+
+/// User unit announcing an mDNS name, one per site. It is enabled, so the
+/// name is announced again after a reboot.
+fn mdns_unit(site: &Site) -> String {
+    format!("servermark-mdns-{}.service", host_label(site))
+}
+
+fn mdns_unit_path(site: &Site) -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("systemd")
+        .join("user")
+        .join(mdns_unit(site))
+}
+
+fn render_mdns_unit(avahi_publish: &str, share: &LanShare) -> String {
+    format!(
+        r#"[Unit]
+Description=ServerMark mDNS name {hostname}
+After=network-online.target avahi-daemon.service
+
+[Service]
+ExecStart={avahi_publish} -a -R {hostname} {address}
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=default.target
+"#,
+        avahi_publish = avahi_publish,
+        hostname = share.hostname,
+        address = share.address
+    )
+}
+
+/// Absolute path of avahi-publish, for the unit's ExecStart
+fn avahi_publish_path() -> Option<String> {
+    let output = Command::new("which").arg("avahi-publish").output().ok()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !path.is_empty()).then_some(path)
+}
+
+fn publish_mdns(site: &Site, share: &LanShare) -> Result<(), String> {
+    let avahi_publish = avahi_publish_path()
+        .ok_or_else(|| "mDNS sharing needs avahi-publish (avahi-utils)".to_string())?;
+    let path = mdns_unit_path(site);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, render_mdns_unit(&avahi_publish, share))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    user_systemctl(&["daemon-reload"])?;
+    user_systemctl(&["enable", &mdns_unit(site)])?;
+    user_systemctl(&["restart", &mdns_unit(site)])
+        .map_err(|e| format!("Failed to announce {}: {}", share.hostname, e))
+}
+
+fn ports() -> ListenPorts {
+    let config = load_webserver_config();
+    match config.rootless {
+        // Both rootless methods answer on 80/443 from outside
+        Some(_) => ListenPorts::default(),
+        None => config.ports(),
+    }
+}
+
+/// Stop announcing the LAN hostname; the server block goes with the next render
+pub fn stop_lan_share(site: &Site, share: &LanShare) {
+    if share.mode == LanShareMode::Mdns {
+        let _ = user_systemctl(&["disable", "--now", &mdns_unit(site)]);
+        if fs::remove_file(mdns_unit_path(site)).is_ok() {
+            let _ = user_systemctl(&["daemon-reload"]);
+        }
+    }
+}
+
+/// Serve a site to phones and other machines on the LAN
+#[tauri::command]
+pub fn share_site_lan(id: String, mode: Option<LanShareMode>) -> Result<LanShareInfo, String> {
+    let mode = mode.unwrap_or(LanShareMode::NipIo);
+    if mode == LanShareMode::Mdns && avahi_publish_path().is_none() {
+        return Err("mDNS sharing needs avahi-publish (avahi-utils)".to_string());
+    }
+
+    let address = lan_address()?;
+    let mut config = load_sites_config();
+    let site = config
+        .sites
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+    let share = LanShare {
+        mode,
+        hostname: lan_hostname(site, mode, address),
+        address,
+    };
+    site.lan_share = Some(share.clone());
+    let site = site.clone();
+    save_config(&config)?;
+
+    webserver_update_site(&site.name)?;
+    if mode == LanShareMode::Mdns {
+        publish_mdns(&site, &share)?;
+    }
+
+    Ok(LanShareInfo {
+        site_id: site.id.clone(),
+        url: lan_share_url(&site, &share, ports()),
+        hostname: share.hostname,
+        address,
+    })
+}
+
+/// Stop sharing a site on the LAN
+#[tauri::command]
+pub fn unshare_site(id: String) -> Result<Site, String> {
+    let mut config = load_sites_config();
+    let site = config
+        .sites
+        .iter_mut()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+    let Some(share) = site.lan_share.take() else {
+        return Ok(site.clone());
+    };
+    let site = site.clone();
+    save_config(&config)?;
+
+    stop_lan_share(&site, &share);
+    webserver_update_site(&site.name)?;
+
+    Ok(site)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_lan_hostname_and_site() {
+        let mut site: Site = serde_json::from_str(
+            r#"{"id":"s","name":"Shop","path":"/p","domain":"shop.test","php_version":"8.3",
+                "secured":true,"site_type":"static","proxy_target":null,"laravel":null,
+                "aliases":["api.shop.test"]}"#,
+        )
+        .unwrap();
+        let address = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+        let hostname = lan_hostname(&site, LanShareMode::NipIo, address);
+        assert_eq!(hostname, "shop-test.192-168-1-20.nip.io");
+        assert_eq!(
+            lan_hostname(&site, LanShareMode::Mdns, address),
+            "shop-test.local"
+        );
+        assert_eq!(mdns_unit(&site), "servermark-mdns-shop-test.service");
+        assert!(lan_site(&site).is_none());
+
+        let share = LanShare {
+            mode: LanShareMode::NipIo,
+            hostname,
+            address,
+        };
+        site.lan_share = Some(share.clone());
+        let shared = lan_site(&site).unwrap();
+        assert_eq!(shared.domain, "shop-test.192-168-1-20.nip.io");
+        assert!(shared.aliases.is_empty());
+
+        let ports = ListenPorts {
+            http: 8080,
+            https: 8443,
+        };
+        assert_eq!(
+            lan_share_url(&site, &share, ports),
+            "https://shop-test.192-168-1-20.nip.io:8443"
+        );
+    }
+
+    #[test]
+    fn test_host_labels_keep_the_tld() {
+        let site = |domain: &str| -> Site {
+            serde_json::from_value(serde_json::json!({
+                "id": "s", "name": "App", "path": "/p", "domain": domain, "php_version": "8.3",
+                "secured": false, "site_type": "static", "proxy_target": null, "laravel": null,
+            }))
+            .unwrap()
+        };
+        assert_eq!(host_label(&site("app.test")), "app-test");
+        assert_eq!(host_label(&site("App.Dev.Internal")), "app-dev-internal");
+        assert_ne!(
+            mdns_unit(&site("app.test")),
+            mdns_unit(&site("app.dev.internal"))
+        );
+
+        let share = LanShare {
+            mode: LanShareMode::Mdns,
+            hostname: "app-test.local".to_string(),
+            address: "192.168.1.20".parse().unwrap(),
+        };
+        let unit = render_mdns_unit("/usr/bin/avahi-publish", &share);
+        assert!(
+            unit.contains("ExecStart=/usr/bin/avahi-publish -a -R app-test.local 192.168.1.20\n")
+        );
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+}
@@ -3,6 +3,12 @@ use std::fs;
 use std::path::Path;
 use std::process::Command;
 
+use super::dotenv::DotEnv;
+use super::helper::{helper_available, helper_request, validate_domain, HelperOp};
+use super::manifest::{apply_manifest_to_site, provision_manifest, read_manifest, ProjectManifest};
+use super::provision::provision_env;
+use super::share::{stop_lan_share, LanShare};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Site {
     pub id: String,
@@ -14,6 +20,16 @@ pub struct Site {
     pub site_type: SiteType,
     pub proxy_target: Option<String>,
     pub laravel: Option<LaravelInfo>,
+    #[serde(default)]
+    pub document_root: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Last applied `servermark.yml`, used to detect manifest changes
+    #[serde(default)]
+    pub manifest: Option<ProjectManifest>,
+    /// Extra hostname serving the site to other devices on the LAN
+    #[serde(default)]
+    pub lan_share: Option<LanShare>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +55,22 @@ pub struct SitesConfig {
     pub sites: Vec<Site>,
     pub tld: String,
     pub sites_path: String,
+    /// Further TLDs resolved to localhost besides `tld` (used by hand-picked aliases)
+    #[serde(default)]
+    pub extra_tlds: Vec<String>,
+}
+
+impl SitesConfig {
+    /// Every TLD the DNS setup must answer for, primary first
+    pub fn tlds(&self) -> Vec<String> {
+        let mut tlds = vec![self.tld.clone()];
+        for tld in &self.extra_tlds {
+            if !tlds.contains(tld) {
+                tlds.push(tld.clone());
+            }
+        }
+        tlds
+    }
 }
 
 impl Default for SitesConfig {
@@ -49,6 +81,7 @@ impl Default for SitesConfig {
             sites_path: dirs::home_dir()
                 .map(|h| h.join("Code").to_string_lossy().to_string())
                 .unwrap_or_else(|| "/home".to_string()),
+            extra_tlds: Vec::new(),
         }
     }
 }
@@ -72,7 +105,7 @@ pub fn load_sites_config() -> SitesConfig {
     }
 }
 
-fn save_config(config: &SitesConfig) -> Result<(), String> {
+pub fn save_config(config: &SitesConfig) -> Result<(), String> {
     let path = get_config_path();
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
@@ -118,12 +151,31 @@ pub fn list_sites() -> Result<Vec<Site>, String> {
     Ok(config.sites)
 }
 
-/// Add a new site
+/// Add a new site. Off the main thread, as provisioning waits for
+/// containers to accept connections.
 #[tauri::command]
-pub fn add_site(
+pub async fn add_site(
+    path: String,
+    name: Option<String>,
+    php_version: Option<String>,
+    create_database: Option<bool>,
+) -> Result<Site, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        add_site_blocking(path, name, php_version, create_database, true)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Register and serve a site. With `wire_env`, a Laravel `.env` is pointed
+/// at the running services; importers that start their own containers do
+/// that themselves afterwards.
+pub fn add_site_blocking(
     path: String,
     name: Option<String>,
     php_version: Option<String>,
+    create_database: Option<bool>,
+    wire_env: bool,
 ) -> Result<Site, String> {
     let site_path = Path::new(&path);
 
@@ -160,10 +212,13 @@ pub fn add_site(
         None
     };
 
+    // Read the project manifest (servermark.yml) if the repository ships one
+    let manifest = read_manifest(&path)?;
+
     // Get active PHP version if not specified
-    let php = php_version.unwrap_or_else(get_active_php_version);
+    let php = php_version.clone().unwrap_or_else(get_active_php_version);
 
-    let site = Site {
+    let mut site = Site {
         id: format!("site-{}", chrono::Utc::now().timestamp_millis()),
         name: site_name.clone(),
         path: path.clone(),
@@ -177,24 +232,55 @@ pub fn add_site(
         site_type,
         proxy_target: None,
         laravel,
+        document_root: None,
+        aliases: Vec::new(),
+        manifest: None,
+        lan_share: None,
     };
 
+    if let Some(manifest) = &manifest {
+        apply_manifest_to_site(&mut site, manifest, &config.tlds(), php_version)?;
+    }
+
     // Update .env file and fix permissions for Laravel projects
     if matches!(site.site_type, SiteType::Laravel) {
         update_laravel_env(&site)?;
         // Fix Docker hostnames (mysql -> 127.0.0.1, redis -> 127.0.0.1, etc.)
         let _ = super::webserver::fix_docker_hostnames_in_env(&site.path);
-        // Fix storage/cache permissions
-        let _ = fix_laravel_permissions_internal(&site.path);
     }
 
     config.sites.push(site.clone());
     save_config(&config)?;
 
+    // Fix storage/cache permissions (once registered, so the helper accepts the path)
+    if matches!(site.site_type, SiteType::Laravel) {
+        let _ = fix_laravel_permissions_internal(&site.path);
+    }
+
     // Configure web server (Caddy or Nginx) with single pkexec call
-    // This also adds Docker hostnames to /etc/hosts
+    // This also rewrites the managed /etc/hosts block
     super::webserver::webserver_add_site(&site.name)?;
 
+    // Provision containers, databases and workers declared in the manifest
+    if let Some(manifest) = &manifest {
+        let report = provision_manifest(&site, manifest);
+        for warning in &report.warnings {
+            log::warn!("{}: {}", site.name, warning);
+        }
+    }
+
+    // Wire .env to the running database, cache and mail services
+    if wire_env && matches!(site.site_type, SiteType::Laravel) {
+        match provision_env(&site, create_database.unwrap_or(false), false) {
+            Ok(report) => {
+                for warning in &report.warnings {
+                    log::warn!("{}: {}", site.name, warning);
+                }
+            }
+            Err(e) => log::warn!("{}: failed to provision .env: {}", site.name, e),
+        }
+    }
+
     Ok(site)
 }
 
@@ -205,37 +291,15 @@ fn update_laravel_env(site: &Site) -> Result<(), String> {
         return Ok(()); // No .env file
     }
 
-    let content =
-        fs::read_to_string(&env_path).map_err(|e| format!("Failed to read .env: {}", e))?;
+    let mut env = DotEnv::load(&env_path)?;
 
     let protocol = if site.secured { "https" } else { "http" };
-    let new_app_url = format!("APP_URL={}://{}", protocol, site.domain);
+    let app_url = format!("{}://{}", protocol, site.domain);
 
-    // Replace or add APP_URL
-    let new_content = if content.contains("APP_URL=") {
-        // Replace existing APP_URL
-        let lines: Vec<&str> = content.lines().collect();
-        let updated_lines: Vec<String> = lines
-            .iter()
-            .map(|line| {
-                if line.starts_with("APP_URL=") {
-                    new_app_url.clone()
-                } else {
-                    line.to_string()
-                }
-            })
-            .collect();
-        updated_lines.join("\n")
-    } else {
-        // Add APP_URL after APP_NAME if exists, or at the beginning
-        if content.contains("APP_NAME=") {
-            content.replace("APP_NAME=", &format!("{}\nAPP_NAME=", new_app_url))
-        } else {
-            format!("{}\n{}", new_app_url, content)
-        }
-    };
-
-    fs::write(&env_path, new_content).map_err(|e| format!("Failed to write .env: {}", e))?;
+    // Replace APP_URL in place, or add it next to the other APP_* keys
+    if env.set("APP_URL", &app_url) {
+        env.save(&env_path)?;
+    }
 
     Ok(())
 }
@@ -252,6 +316,10 @@ pub fn remove_site(id: String) -> Result<(), String> {
         config.sites.retain(|s| s.id != id);
         save_config(&config)?;
 
+        if let Some(share) = &site.lan_share {
+            stop_lan_share(&site, share);
+        }
+
         // Remove from web server config (handles hosts, caddy/nginx config, reload)
         super::webserver::webserver_remove_site(&site.name)?;
 
@@ -301,6 +369,43 @@ pub fn fix_laravel_permissions(path: String) -> Result<(), String> {
     // Get current user
     let current_user = std::env::var("USER").unwrap_or_else(|_| "www-data".to_string());
 
+    // In rootless mode the user owns the files and is in www-data, so changing
+    // the group is enough and needs no root
+    let rootless = super::rootless::rootless_config().is_some();
+    let owner = if rootless {
+        String::new()
+    } else {
+        current_user
+    };
+
+    // The helper only changes ownership inside registered sites, so existing
+    // directories are handed over first and the missing ones created as the user
+    if !rootless && helper_available() {
+        let chown = || -> Vec<HelperOp> {
+            [&storage_path, &cache_path]
+                .into_iter()
+                .filter(|p| p.exists())
+                .map(|p| HelperOp::ChownSitePath {
+                    path: p.to_string_lossy().to_string(),
+                })
+                .collect()
+        };
+        helper_request(chown()).map_err(|e| format!("Failed to fix permissions: {}", e))?;
+        for dir in [
+            "app/public",
+            "framework/cache",
+            "framework/sessions",
+            "framework/views",
+            "logs",
+        ] {
+            fs::create_dir_all(storage_path.join(dir))
+                .map_err(|e| format!("Failed to create storage/{}: {}", dir, e))?;
+        }
+        fs::create_dir_all(&cache_path)
+            .map_err(|e| format!("Failed to create bootstrap/cache: {}", e))?;
+        return helper_request(chown()).map_err(|e| format!("Failed to fix permissions: {}", e));
+    }
+
     // Script to fix permissions - uses pkexec for single password prompt
     let script = format!(
         r#"
@@ -308,7 +413,7 @@ set -e
 
 # Fix storage directory
 if [ -d "{storage}" ]; then
-    chown -R {user}:www-data "{storage}"
+    chown -R {owner}:www-data "{storage}"
     chmod -R 775 "{storage}"
     find "{storage}" -type d -exec chmod 775 {{}} \;
     find "{storage}" -type f -exec chmod 664 {{}} \;
@@ -316,7 +421,7 @@ fi
 
 # Fix bootstrap/cache directory
 if [ -d "{cache}" ]; then
-    chown -R {user}:www-data "{cache}"
+    chown -R {owner}:www-data "{cache}"
     chmod -R 775 "{cache}"
 fi
 
@@ -329,8 +434,8 @@ mkdir -p "{storage}/logs"
 mkdir -p "{cache}"
 
 # Set permissions on newly created dirs
-chown -R {user}:www-data "{storage}"
-chown -R {user}:www-data "{cache}"
+chown -R {owner}:www-data "{storage}"
+chown -R {owner}:www-data "{cache}"
 chmod -R 775 "{storage}"
 chmod -R 775 "{cache}"
 
@@ -338,13 +443,17 @@ echo "Permissions fixed successfully"
 "#,
         storage = storage_path.display(),
         cache = cache_path.display(),
-        user = current_user
+        owner = owner
     );
 
-    let output = Command::new("pkexec")
-        .args(["bash", "-c", &script])
-        .output()
-        .map_err(|e| format!("Failed to fix permissions: {}", e))?;
+    let output = if rootless {
+        Command::new("bash").args(["-c", &script]).output()
+    } else {
+        Command::new("pkexec")
+            .args(["bash", "-c", &script])
+            .output()
+    }
+    .map_err(|e| format!("Failed to fix permissions: {}", e))?;
 
     if !output.status.success() {
         return Err(format!(
@@ -367,23 +476,76 @@ pub fn get_sites_config() -> SitesConfig {
     load_sites_config()
 }
 
-/// Update sites configuration
+/// `.test` or `test` to `test`, rejecting anything that is not a host name
+fn normalize_tld(tld: &str) -> Result<String, String> {
+    let tld = tld.trim().trim_matches('.').to_lowercase();
+    validate_domain(&tld).map_err(|_| format!("Invalid TLD: {}", tld))?;
+    Ok(tld)
+}
+
+/// Move a site's domain and aliases from one TLD to another; false when none used it
+pub fn migrate_site_tld(site: &mut Site, old_tld: &str, new_tld: &str) -> bool {
+    let suffix = format!(".{}", old_tld);
+    let mut changed = false;
+    for domain in std::iter::once(&mut site.domain).chain(site.aliases.iter_mut()) {
+        if let Some(stem) = domain.strip_suffix(&suffix) {
+            *domain = format!("{}.{}", stem, new_tld);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Update sites configuration. A TLD change moves existing sites to the new
+/// TLD, resyncs the web server and regenerates the DNS configuration.
 #[tauri::command]
 #[allow(non_snake_case)]
 pub fn update_sites_config(
     tld: Option<String>,
     sitesPath: Option<String>,
+    extraTlds: Option<Vec<String>>,
 ) -> Result<SitesConfig, String> {
     let mut config = load_sites_config();
+    let old_tld = config.tld.clone();
+    let old_tlds = config.tlds();
 
     if let Some(tld) = tld {
-        config.tld = tld;
+        config.tld = normalize_tld(&tld)?;
+    }
+    if let Some(extra) = extraTlds {
+        config.extra_tlds = extra
+            .iter()
+            .map(|tld| normalize_tld(tld))
+            .collect::<Result<_, _>>()?;
     }
     if let Some(path) = sitesPath {
         config.sites_path = path;
     }
 
+    let mut migrated = Vec::new();
+    if config.tld != old_tld {
+        for site in &mut config.sites {
+            if migrate_site_tld(site, &old_tld, &config.tld) {
+                migrated.push(site.clone());
+            }
+        }
+    }
+
     save_config(&config)?;
+
+    if !migrated.is_empty() {
+        for site in migrated
+            .iter()
+            .filter(|s| matches!(s.site_type, SiteType::Laravel))
+        {
+            let _ = update_laravel_env(site);
+        }
+        super::webserver::sync_webserver_configs()?;
+    }
+    if config.tlds() != old_tlds {
+        super::dns::reconfigure_dns(&config.tlds())?;
+    }
+
     Ok(config)
 }
 
@@ -477,7 +639,21 @@ pub fn get_framework_templates() -> Vec<FrameworkTemplate> {
 
 /// Create a new project with a framework
 #[tauri::command]
-pub fn create_project(
+pub async fn create_project(
+    name: String,
+    framework: String,
+    version: Option<String>,
+    php_version: Option<String>,
+    path: Option<String>,
+) -> Result<Site, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        create_project_blocking(name, framework, version, php_version, path)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn create_project_blocking(
     name: String,
     framework: String,
     version: Option<String>,
@@ -610,16 +786,30 @@ pub fn create_project(
     }
 
     // Now add the site
-    add_site(
+    add_site_blocking(
         project_path.to_string_lossy().to_string(),
         Some(name),
         Some(php),
+        None,
+        true,
     )
 }
 
 /// Clone a Git repository and set up as a site
 #[tauri::command]
-pub fn clone_repository(
+pub async fn clone_repository(
+    repo_url: String,
+    name: Option<String>,
+    php_version: Option<String>,
+) -> Result<Site, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        clone_repository_blocking(repo_url, name, php_version)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn clone_repository_blocking(
     repo_url: String,
     name: Option<String>,
     php_version: Option<String>,
@@ -696,10 +886,12 @@ pub fn clone_repository(
     }
 
     // Add the site
-    add_site(
+    add_site_blocking(
         project_path.to_string_lossy().to_string(),
         Some(project_name),
         php_version,
+        None,
+        true,
     )
 }
 
@@ -768,6 +960,32 @@ mod tests {
         assert!(config.sites.is_empty());
     }
 
+    #[test]
+    fn test_migrate_site_tld() {
+        let mut site: Site = serde_json::from_str(
+            r#"{"id":"s","name":"shop","path":"/p","domain":"shop.test","php_version":"8.3",
+                "secured":false,"site_type":"static","proxy_target":null,"laravel":null,
+                "aliases":["api.shop.test","shop.local","mytest"]}"#,
+        )
+        .unwrap();
+
+        assert!(migrate_site_tld(&mut site, "test", "dev.internal"));
+        assert_eq!(site.domain, "shop.dev.internal");
+        assert_eq!(
+            site.aliases,
+            ["api.shop.dev.internal", "shop.local", "mytest"]
+        );
+        assert!(!migrate_site_tld(&mut site, "test", "localhost"));
+
+        let config = SitesConfig {
+            extra_tlds: vec!["local".to_string(), "test".to_string()],
+            ..SitesConfig::default()
+        };
+        assert_eq!(config.tlds(), ["test", "local"]);
+        assert_eq!(normalize_tld(".Localhost").unwrap(), "localhost");
+        assert!(normalize_tld("te st").is_err());
+    }
+
     #[test]
     fn test_site_type_serialization() {
         let laravel = SiteType::Laravel;
@@ -796,6 +1014,10 @@ mod tests {
                 constraint: Some("^11.0".to_string()),
                 php_version: Some("8.2".to_string()),
             }),
+            document_root: None,
+            aliases: Vec::new(),
+            manifest: None,
+            lan_share: None,
         };
 
         let json = serde_json::to_string(&site).unwrap();
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, State};
+
+use super::docker::ContainerStats;
+use super::engine::{encode, ApiStats, EngineClient, LineBuffer};
+use super::ownership::owned_containers;
+
+// Live resource usage of containers. A stream follows the engine's stats
+// endpoint of each container (a sample per second) and emits every sample as
+// a "container-stats" event until it is stopped or the container goes away.
+
+/// Running stats streams by handle (Tauri managed state)
+#[derive(Default)]
+pub struct StatsStreams {
+    streams: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+static NEXT_STATS_STREAM: AtomicU64 = AtomicU64::new(1);
+
+impl StatsStreams {
+    fn stop(&self, handle: u64) -> bool {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        match streams.remove(&handle) {
+            Some(stop) => {
+                stop.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every stream, when the app exits
+    pub fn stop_all(&self) {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        for (_, stop) in streams.drain() {
+            stop.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Payload of "container-stats-ended", sent when a container's stream ends on its own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsStreamEnded {
+    pub handle: u64,
+    pub container_id: String,
+    pub error: Option<String>,
+}
+
+/// Follow one container's stats until the engine ends the stream or `stop` is set
+fn follow_stats(
+    client: &EngineClient,
+    id: &str,
+    stop: &AtomicBool,
+    mut on_sample: impl FnMut(ContainerStats),
+) -> Result<(), String> {
+    let mut lines = LineBuffer::default();
+    client.stream(
+        "GET",
+        &format!("/containers/{}/stats?stream=true", encode(id)),
+        None,
+        |data| {
+            for line in lines.push(data) {
+                if let Ok(stats) = serde_json::from_str::<ApiStats>(&line) {
+                    on_sample(ContainerStats::from_api(id, &stats));
+                }
+            }
+            !stop.load(Ordering::SeqCst)
+        },
+    )
+}
+
+/// Stream CPU, memory, network and block IO of containers as
+/// "container-stats" events; all running ServerMark containers when `ids`
+/// is empty. Returns a handle for `stop_container_stats`.
+#[tauri::command]
+pub fn stream_container_stats(
+    app: AppHandle,
+    streams: State<'_, StatsStreams>,
+    ids: Option<Vec<String>>,
+) -> Result<u64, String> {
+    let client = EngineClient::connect()?;
+    let ids = match ids {
+        Some(ids) if !ids.is_empty() => ids,
+        _ => owned_containers(&client)?
+            .into_iter()
+            .filter(|c| c.status == "running")
+            .map(|c| c.id)
+            .collect(),
+    };
+
+    let handle = NEXT_STATS_STREAM.fetch_add(1, Ordering::SeqCst);
+    let stop = Arc::new(AtomicBool::new(false));
+    streams
+        .streams
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(handle, stop.clone());
+
+    for id in ids {
+        let (app, client, stop) = (app.clone(), client.clone(), stop.clone());
+        thread::spawn(move || {
+            let result = follow_stats(&client, &id, &stop, |sample| {
+                let _ = app.emit("container-stats", sample);
+            });
+            if !stop.load(Ordering::SeqCst) {
+                let _ = app.emit(
+                    "container-stats-ended",
+                    StatsStreamEnded {
+                        handle,
+                        container_id: id,
+                        error: result.err(),
+                    },
+                );
+            }
+        });
+    }
+    Ok(handle)
+}
+
+#[tauri::command]
+pub fn stop_container_stats(streams: State<'_, StatsStreams>, handle: u64) -> Result<(), String> {
+    if streams.stop(handle) {
+        Ok(())
+    } else {
+        Err("No such stats stream".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine::FakeEngine;
+    use super::*;
+
+    #[test]
+    fn test_follow_stats_emits_each_sample() {
+        let sample = r#"{"cpu_stats":{"cpu_usage":{"total_usage":300},"system_cpu_usage":2000,"online_cpus":2},"precpu_stats":{"cpu_usage":{"total_usage":100},"system_cpu_usage":1000},"memory_stats":{"usage":512,"limit":1024}}"#;
+        let engine = FakeEngine::start(vec![(
+            "GET",
+            "/containers/abc/stats",
+            200,
+            format!("{}\n{}\n", sample, sample),
+        )]);
+
+        let mut samples = Vec::new();
+        follow_stats(&engine.client, "abc", &AtomicBool::new(false), |s| {
+            samples.push(s)
+        })
+        .unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].id, "abc");
+        assert_eq!(samples[0].cpu_percent, 40.0);
+        assert_eq!(samples[1].memory_limit, 1024);
+        assert!(engine.requests()[0].starts_with("GET /containers/abc/stats?stream=true"));
+    }
+}
@@ -1,7 +1,16 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::PathBuf;
 use std::process::Command;
 
+use super::backends::{backend, WebServerKind};
+use super::docker::{exec_in_container_with_env, inspect_container};
+use super::engine::EngineClient;
+use super::ports::ensure_ports_available;
+use super::provision::container_credentials;
+use super::rootless::{rootless_config, user_systemctl, USER_UNIT};
+use super::webserver::load_webserver_config;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SystemInfo {
     pub distro: String,
@@ -11,6 +20,15 @@ pub struct SystemInfo {
     pub hostname: String,
 }
 
+/// The binary for systemd units to run. From an AppImage that is the
+/// AppImage itself: the binary inside is only mounted while the app runs.
+pub fn app_executable() -> Result<PathBuf, String> {
+    if let Some(appimage) = std::env::var_os("APPIMAGE").filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(appimage));
+    }
+    std::env::current_exe().map_err(|e| format!("Failed to locate the ServerMark binary: {}", e))
+}
+
 #[tauri::command]
 pub fn detect_system() -> Result<SystemInfo, String> {
     // Read /etc/os-release to detect distribution
@@ -76,7 +94,13 @@ pub struct WebServerStatus {
     pub nginx_installed: bool,
     pub nginx_running: bool,
     pub nginx_version: Option<String>,
-    pub active: Option<String>, // "caddy" | "nginx" | null
+    #[serde(default)]
+    pub apache_installed: bool,
+    #[serde(default)]
+    pub apache_running: bool,
+    #[serde(default)]
+    pub apache_version: Option<String>,
+    pub active: Option<String>, // "caddy" | "nginx" | "apache" | null
 }
 
 #[tauri::command]
@@ -126,11 +150,39 @@ pub fn detect_web_server() -> WebServerStatus {
         .map(|o| o.status.success())
         .unwrap_or(false);
 
+    // Check Apache (apache2 on Debian/SUSE, httpd elsewhere)
+    let apache_version = ["apache2", "httpd"].iter().find_map(|binary| {
+        Command::new(binary)
+            .arg("-v")
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .and_then(|o| {
+                String::from_utf8_lossy(&o.stdout)
+                    .lines()
+                    .next()
+                    .and_then(|line| line.split_once("Server version: "))
+                    .map(|(_, version)| version.trim().to_string())
+            })
+    });
+
+    let apache_installed = apache_version.is_some();
+
+    let apache_running = ["apache2", "httpd"].iter().any(|unit| {
+        Command::new("systemctl")
+            .args(["is-active", unit])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    });
+
     // Determine active server
     let active = if caddy_running {
         Some("caddy".to_string())
     } else if nginx_running {
         Some("nginx".to_string())
+    } else if apache_running {
+        Some("apache".to_string())
     } else {
         None
     };
@@ -142,114 +194,28 @@ pub fn detect_web_server() -> WebServerStatus {
         nginx_installed,
         nginx_running,
         nginx_version,
+        apache_installed,
+        apache_running,
+        apache_version,
         active,
     }
 }
 
 #[tauri::command]
 pub fn install_web_server(server: String, package_manager: String) -> Result<(), String> {
-    match server.as_str() {
-        "caddy" => install_caddy(&package_manager),
-        "nginx" => install_nginx(&package_manager),
-        _ => Err("Unknown web server".to_string()),
-    }
-}
-
-fn install_caddy(package_manager: &str) -> Result<(), String> {
-    // Build the install command based on package manager
-    let install_cmd = match package_manager {
-        "apt" => "apt install -y caddy",
-        "dnf" => "dnf install -y caddy",
-        "pacman" => "pacman -S --noconfirm caddy",
-        "zypper" => "zypper install -y caddy",
-        _ => return Err("Unsupported package manager".to_string()),
-    };
-
-    // Complete setup script - runs with single pkexec prompt
-    // Uses /etc/caddy/sites.d/ which Caddy can access (unlike user's home)
-    let setup_script = format!(
-        r##"
-set -e
-
-# 1. Install Caddy
-{install_cmd}
-
-# 2. Add caddy user to www-data group (for PHP-FPM socket access)
-usermod -aG www-data caddy || true
-
-# 3. Create ServerMark sites directory
-mkdir -p /etc/caddy/sites.d
-chown caddy:caddy /etc/caddy/sites.d
-chmod 755 /etc/caddy/sites.d
-
-# 4. Create empty Caddyfile for ServerMark sites
-echo "# ServerMark sites" > /etc/caddy/sites.d/servermark.conf
-chown caddy:caddy /etc/caddy/sites.d/servermark.conf
-
-# 5. Add import to system Caddyfile if not present
-IMPORT_LINE="import /etc/caddy/sites.d/*.conf"
-if ! grep -q "sites.d" /etc/caddy/Caddyfile 2>/dev/null; then
-    # Prepend import line to existing Caddyfile
-    if [ -f /etc/caddy/Caddyfile ]; then
-        TEMP_FILE=$(mktemp)
-        echo "$IMPORT_LINE" > "$TEMP_FILE"
-        echo "" >> "$TEMP_FILE"
-        cat /etc/caddy/Caddyfile >> "$TEMP_FILE"
-        mv "$TEMP_FILE" /etc/caddy/Caddyfile
-    else
-        echo "$IMPORT_LINE" > /etc/caddy/Caddyfile
-    fi
-fi
-
-# 6. Enable and start Caddy
-systemctl enable caddy
-systemctl restart caddy
-"##,
-        install_cmd = install_cmd
-    );
+    let kind = WebServerKind::parse(&server)?;
+    ensure_ports_available(load_webserver_config().ports(), &[kind])?;
+    let setup_script = backend(kind).install_script(&package_manager)?;
 
     let output = Command::new("pkexec")
         .args(["bash", "-c", &setup_script])
         .output()
-        .map_err(|e| format!("Failed to install Caddy: {}", e))?;
+        .map_err(|e| format!("Failed to install {}: {}", kind, e))?;
 
     if !output.status.success() {
         return Err(format!(
-            "Failed to install Caddy: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-
-    Ok(())
-}
-
-fn install_nginx(package_manager: &str) -> Result<(), String> {
-    let install_cmd = match package_manager {
-        "apt" => "apt install -y nginx",
-        "dnf" => "dnf install -y nginx",
-        "pacman" => "pacman -S --noconfirm nginx",
-        "zypper" => "zypper install -y nginx",
-        _ => return Err("Unsupported package manager".to_string()),
-    };
-
-    let setup_script = format!(
-        r#"
-set -e
-{install_cmd}
-systemctl enable nginx
-systemctl start nginx
-"#,
-        install_cmd = install_cmd
-    );
-
-    let output = Command::new("pkexec")
-        .args(["bash", "-c", &setup_script])
-        .output()
-        .map_err(|e| format!("Failed to install Nginx: {}", e))?;
-
-    if !output.status.success() {
-        return Err(format!(
-            "Failed to install Nginx: {}",
+            "Failed to install {}: {}",
+            kind,
             String::from_utf8_lossy(&output.stderr)
         ));
     }
@@ -259,216 +225,19 @@ systemctl start nginx
 
 #[tauri::command]
 pub fn switch_web_server(server: String) -> Result<(), String> {
-    match server.as_str() {
-        "caddy" => {
-            // Stop nginx, start caddy
-            let _ = Command::new("pkexec")
-                .args(["systemctl", "stop", "nginx"])
-                .output();
-            Command::new("pkexec")
-                .args(["systemctl", "start", "caddy"])
-                .output()
-                .map_err(|e| format!("Failed to start Caddy: {}", e))?;
-        }
-        "nginx" => {
-            // Stop caddy, start nginx
-            let _ = Command::new("pkexec")
-                .args(["systemctl", "stop", "caddy"])
-                .output();
-            Command::new("pkexec")
-                .args(["systemctl", "start", "nginx"])
-                .output()
-                .map_err(|e| format!("Failed to start Nginx: {}", e))?;
-        }
-        _ => return Err("Unknown web server".to_string()),
-    }
-
-    Ok(())
-}
-
-// ============================================================================
-// DNS (dnsmasq) Detection and Installation
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DnsStatus {
-    pub dnsmasq_installed: bool,
-    pub dnsmasq_running: bool,
-    pub dnsmasq_configured: bool,  // Has .test domain config
-    pub resolver_configured: bool, // systemd-resolved configured
-}
-
-#[tauri::command]
-pub fn detect_dns() -> DnsStatus {
-    // Check if dnsmasq is installed
-    let dnsmasq_installed = Command::new("which")
-        .arg("dnsmasq")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false);
-
-    // Check if dnsmasq is running (via systemctl or by checking if port 5353 is in use)
-    let dnsmasq_running = Command::new("systemctl")
-        .args(["is-active", "dnsmasq"])
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
-        || Command::new("ss")
-            .args(["-tlnp"])
-            .output()
-            .map(|o| {
-                String::from_utf8_lossy(&o.stdout).contains(":5353")
-                    && String::from_utf8_lossy(&o.stdout).contains("dnsmasq")
-            })
-            .unwrap_or(false);
-
-    // Check if .test domain is configured in dnsmasq (ServerMark config or any other)
-    let dnsmasq_configured = fs::read_to_string("/etc/dnsmasq.d/servermark.conf")
-        .map(|content| content.contains("address=/.test/"))
-        .unwrap_or(false)
-        || fs::read_dir("/etc/dnsmasq.d")
-            .map(|entries| {
-                entries.filter_map(|e| e.ok()).any(|entry| {
-                    fs::read_to_string(entry.path())
-                        .map(|content| {
-                            content.contains("address=/.test/")
-                                || content.contains("address=/test/")
-                        })
-                        .unwrap_or(false)
-                })
-            })
-            .unwrap_or(false);
-
-    // Check if systemd-resolved is configured to forward .test queries
-    let resolver_configured = fs::read_to_string("/etc/systemd/resolved.conf.d/servermark.conf")
-        .map(|content| content.contains("Domains=~test") || content.contains("DNS=127.0.0.1"))
-        .unwrap_or(false);
-
-    DnsStatus {
-        dnsmasq_installed,
-        dnsmasq_running,
-        dnsmasq_configured,
-        resolver_configured,
+    let kind = WebServerKind::parse(&server)?;
+    ensure_ports_available(load_webserver_config().ports(), &WebServerKind::ALL)?;
+
+    // Stop the other servers, then start this one
+    for other in WebServerKind::ALL.into_iter().filter(|k| *k != kind) {
+        let _ = Command::new("pkexec")
+            .args(["systemctl", "stop", backend(other).service()])
+            .output();
     }
-}
-
-#[tauri::command]
-pub fn install_dns(package_manager: String) -> Result<(), String> {
-    let install_cmd = match package_manager.as_str() {
-        "apt" => "apt install -y dnsmasq",
-        "dnf" => "dnf install -y dnsmasq",
-        "pacman" => "pacman -S --noconfirm dnsmasq",
-        "zypper" => "zypper install -y dnsmasq",
-        _ => return Err("Unsupported package manager".to_string()),
-    };
-
-    // Robust DNS setup script that works with systemd-resolved
-    // Strategy: Use dnsmasq on port 5353, forward via systemd-resolved
-    let setup_script = format!(
-        r##"
-set -e
-
-echo "=== ServerMark DNS Setup ==="
-
-# 1. Install dnsmasq if not present
-if ! command -v dnsmasq &> /dev/null; then
-    echo "Installing dnsmasq..."
-    {install_cmd}
-fi
-
-# 2. Stop dnsmasq service to reconfigure
-echo "Stopping dnsmasq service..."
-systemctl stop dnsmasq 2>/dev/null || true
-systemctl disable dnsmasq 2>/dev/null || true
-
-# 3. Create ServerMark dnsmasq config (minimal to avoid conflicts with existing configs)
-echo "Configuring dnsmasq..."
-mkdir -p /etc/dnsmasq.d
-
-cat > /etc/dnsmasq.d/servermark.conf << 'DNSCONF'
-# ServerMark DNS configuration
-# Resolve *.test domains to localhost
-address=/.test/127.0.0.1
-
-# Use port 5353 to coexist with systemd-resolved
-port=5353
-DNSCONF
-
-# 3b. Create base config only if no other config exists
-if [ ! -f /etc/dnsmasq.d/local-dev.conf ] && [ ! -f /etc/dnsmasq.d/base.conf ]; then
-    cat > /etc/dnsmasq.d/servermark-base.conf << 'BASECONF'
-# ServerMark base DNS configuration
-listen-address=127.0.0.1
-bind-interfaces
-no-resolv
-server=8.8.8.8
-server=1.1.1.1
-cache-size=1000
-BASECONF
-fi
-
-# 4. Ensure main dnsmasq.conf doesn't conflict
-if [ -f /etc/dnsmasq.conf ]; then
-    # Comment out any port= line in main config
-    sed -i 's/^port=/#port=/' /etc/dnsmasq.conf 2>/dev/null || true
-fi
-
-# 5. Configure systemd-resolved to forward .test to dnsmasq
-echo "Configuring systemd-resolved integration..."
-mkdir -p /etc/systemd/resolved.conf.d
-
-cat > /etc/systemd/resolved.conf.d/servermark.conf << 'RESOLVEDCONF'
-[Resolve]
-# Forward .test domain queries to local dnsmasq
-DNS=127.0.0.1#5353
-Domains=~test
-RESOLVEDCONF
-
-# 6. Restart systemd-resolved to pick up new config
-echo "Restarting systemd-resolved..."
-systemctl restart systemd-resolved
-
-# 7. Enable and start dnsmasq
-echo "Starting dnsmasq..."
-systemctl enable dnsmasq
-systemctl start dnsmasq
-
-# 8. Verify setup
-sleep 1
-echo ""
-echo "=== Verification ==="
-
-if systemctl is-active --quiet dnsmasq; then
-    echo "✓ dnsmasq is running on port 5353"
-else
-    echo "✗ dnsmasq failed to start"
-    journalctl -u dnsmasq -n 5 --no-pager
-    exit 1
-fi
-
-# Test DNS resolution
-echo "Testing DNS resolution..."
-if command -v resolvectl &> /dev/null; then
-    resolvectl query test.test 2>/dev/null && echo "✓ DNS resolution working" || echo "Note: DNS test query (this is normal if no .test site exists yet)"
-fi
-
-echo ""
-echo "=== Setup Complete ==="
-echo "All *.test domains will now resolve to 127.0.0.1"
-"##,
-        install_cmd = install_cmd
-    );
-
-    let output = Command::new("pkexec")
-        .args(["bash", "-c", &setup_script])
+    Command::new("pkexec")
+        .args(["systemctl", "start", backend(kind).service()])
         .output()
-        .map_err(|e| format!("Failed to install DNS: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!("Failed to install DNS:\n{}\n{}", stdout, stderr));
-    }
+        .map_err(|e| format!("Failed to start {}: {}", kind, e))?;
 
     Ok(())
 }
@@ -666,6 +435,7 @@ pub fn control_native_service(service: String, action: String) -> Result<(), Str
         "dnsmasq" => "dnsmasq",
         "caddy" => "caddy",
         "nginx" => "nginx",
+        "apache" => backend(WebServerKind::Apache).service(),
         _ => return Err(format!("Unknown service: {}", service)),
     };
 
@@ -674,10 +444,21 @@ pub fn control_native_service(service: String, action: String) -> Result<(), Str
         _ => return Err(format!("Unknown action: {}", action)),
     };
 
-    let output = Command::new("pkexec")
-        .args(["systemctl", action_arg, service_name])
-        .output()
-        .map_err(|e| format!("Failed to {} {}: {}", action, service, e))?;
+    // In rootless mode Caddy is a user unit, and the polkit rule installed
+    // by the one-time setup lets the user manage the other services directly
+    let output = if rootless_config().is_some() {
+        if service == "caddy" {
+            return user_systemctl(&[action_arg, USER_UNIT]);
+        }
+        Command::new("systemctl")
+            .args([action_arg, service_name])
+            .output()
+    } else {
+        Command::new("pkexec")
+            .args(["systemctl", action_arg, service_name])
+            .output()
+    }
+    .map_err(|e| format!("Failed to {} {}: {}", action, service, e))?;
 
     if !output.status.success() {
         return Err(format!(
@@ -743,19 +524,26 @@ pub fn open_terminal(path: String) -> Result<(), String> {
     Err("No supported terminal emulator found".to_string())
 }
 
-#[tauri::command]
-pub fn get_service_logs(service: String, lines: u32) -> Result<String, String> {
-    let service_name = match service.as_str() {
+/// The systemd unit of a service known to `get_service_logs`
+pub fn service_unit(service: &str) -> Option<&'static str> {
+    Some(match service {
         "caddy" => "caddy",
         "nginx" => "nginx",
+        "apache" => backend(WebServerKind::Apache).service(),
         "php-fpm" => "php*-fpm", // Wildcard for any PHP version
         "mysql" => "mysql",
         "mariadb" => "mariadb",
         "postgresql" => "postgresql",
         "redis" => "redis",
         "dnsmasq" => "dnsmasq",
-        _ => return Err(format!("Unknown service: {}", service)),
-    };
+        _ => return None,
+    })
+}
+
+#[tauri::command]
+pub fn get_service_logs(service: String, lines: u32) -> Result<String, String> {
+    let service_name =
+        service_unit(&service).ok_or_else(|| format!("Unknown service: {}", service))?;
 
     // Use journalctl to get logs
     let output = Command::new("journalctl")
@@ -770,6 +558,11 @@ pub fn get_service_logs(service: String, lines: u32) -> Result<String, String> {
         let log_paths = match service.as_str() {
             "caddy" => vec!["/var/log/caddy/access.log", "/var/log/caddy/error.log"],
             "nginx" => vec!["/var/log/nginx/access.log", "/var/log/nginx/error.log"],
+            "apache" => vec![
+                "/var/log/apache2/error.log",
+                "/var/log/apache2/error_log",
+                "/var/log/httpd/error_log",
+            ],
             _ => vec![],
         };
 
@@ -794,28 +587,152 @@ pub struct Database {
     pub size: Option<String>,
 }
 
+/// Output of a database client, run natively or, with a container id, inside
+/// the container through the engine API
+struct ClientOutput {
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+/// Who a database client logs in as. Containers use the credentials they
+/// were started with; native servers the default admin without a password.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbLogin {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+fn db_login(
+    db_type: &str,
+    container_id: Option<&str>,
+    login: Option<DbLogin>,
+) -> Result<DbLogin, String> {
+    if let Some(login) = login {
+        return Ok(login);
+    }
+    let Some(id) = container_id else {
+        let username = if db_type == "postgresql" {
+            "postgres"
+        } else {
+            "root"
+        };
+        return Ok(DbLogin {
+            username: username.to_string(),
+            password: None,
+        });
+    };
+    let env = inspect_container(&EngineClient::connect()?, id)?.config.env;
+    match container_credentials(db_type, &env) {
+        (Some(username), Some(password)) => Ok(DbLogin {
+            username,
+            password: Some(password),
+        }),
+        _ => Err(format!(
+            "The {} credentials of container {} are unknown",
+            db_type, id
+        )),
+    }
+}
+
+/// Client command running `sql`; the password goes through the environment
+fn db_client_args(db_type: &str, login: &DbLogin, sql: &str) -> Vec<String> {
+    match db_type {
+        "postgresql" => vec![
+            "psql".to_string(),
+            "-U".to_string(),
+            login.username.clone(),
+            "-c".to_string(),
+            sql.to_string(),
+        ],
+        _ => vec![
+            "mysql".to_string(),
+            format!("-u{}", login.username),
+            "-e".to_string(),
+            sql.to_string(),
+        ],
+    }
+}
+
+fn db_client_env(db_type: &str, login: &DbLogin) -> Vec<String> {
+    let key = if db_type == "postgresql" {
+        "PGPASSWORD"
+    } else {
+        "MYSQL_PWD"
+    };
+    login
+        .password
+        .iter()
+        .map(|password| format!("{}={}", key, password))
+        .collect()
+}
+
+fn run_db_client(
+    container_id: Option<&str>,
+    args: &[String],
+    env: &[String],
+    action: &str,
+) -> Result<ClientOutput, String> {
+    match container_id {
+        Some(id) => {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let output = exec_in_container_with_env(&EngineClient::connect()?, id, &args, env)
+                .map_err(|e| format!("Failed to {}: {}", action, e))?;
+            Ok(ClientOutput {
+                success: output.exit_code == 0,
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        }
+        None => {
+            let output = Command::new(&args[0])
+                .args(&args[1..])
+                .envs(env.iter().filter_map(|entry| entry.split_once('=')))
+                .output()
+                .map_err(|e| format!("Failed to {}: {}", action, e))?;
+            Ok(ClientOutput {
+                success: output.status.success(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            })
+        }
+    }
+}
+
+/// Run `sql` with the database's client, logged in as `login` or the detected admin
+fn run_sql(
+    db_type: &str,
+    container_id: Option<&str>,
+    login: Option<DbLogin>,
+    sql: &str,
+    extra_args: &[&str],
+    action: &str,
+) -> Result<ClientOutput, String> {
+    let login = db_login(db_type, container_id, login)?;
+    let mut args = db_client_args(db_type, &login, sql);
+    args.extend(extra_args.iter().map(|a| a.to_string()));
+    run_db_client(container_id, &args, &db_client_env(db_type, &login), action)
+}
+
 #[tauri::command]
 pub fn list_databases(
     db_type: String,
     container_id: Option<String>,
 ) -> Result<Vec<Database>, String> {
+    let container_id = container_id.as_deref();
     match db_type.as_str() {
         "mysql" => {
-            let cmd = if let Some(id) = container_id {
-                format!(
-                    "docker exec {} mysql -uroot -psecret -e 'SHOW DATABASES;' 2>/dev/null",
-                    id
-                )
-            } else {
-                "mysql -uroot -e 'SHOW DATABASES;' 2>/dev/null".to_string()
-            };
-
-            let output = Command::new("sh")
-                .args(["-c", &cmd])
-                .output()
-                .map_err(|e| format!("Failed to list databases: {}", e))?;
-
-            let databases: Vec<Database> = String::from_utf8_lossy(&output.stdout)
+            let output = run_sql(
+                "mysql",
+                container_id,
+                None,
+                "SHOW DATABASES;",
+                &[],
+                "list databases",
+            )?;
+
+            let databases: Vec<Database> = output
+                .stdout
                 .lines()
                 .skip(1) // Skip header
                 .filter(|name| {
@@ -830,21 +747,17 @@ pub fn list_databases(
             Ok(databases)
         }
         "postgresql" => {
-            let cmd = if let Some(id) = container_id {
-                format!(
-                    "docker exec {} psql -U postgres -c '\\l' -t 2>/dev/null",
-                    id
-                )
-            } else {
-                "psql -U postgres -c '\\l' -t 2>/dev/null".to_string()
-            };
-
-            let output = Command::new("sh")
-                .args(["-c", &cmd])
-                .output()
-                .map_err(|e| format!("Failed to list databases: {}", e))?;
-
-            let databases: Vec<Database> = String::from_utf8_lossy(&output.stdout)
+            let output = run_sql(
+                "postgresql",
+                container_id,
+                None,
+                "\\l",
+                &["-t"],
+                "list databases",
+            )?;
+
+            let databases: Vec<Database> = output
+                .stdout
                 .lines()
                 .filter_map(|line| {
                     let name = line.split('|').next()?.trim();
@@ -870,65 +783,50 @@ pub fn create_database(
     db_type: String,
     name: String,
     container_id: Option<String>,
+) -> Result<(), String> {
+    create_database_as(db_type, name, container_id, None)
+}
+
+/// Create a database, logged in as `login` when the caller already knows the
+/// credentials, else as the admin detected for the container
+pub fn create_database_as(
+    db_type: String,
+    name: String,
+    container_id: Option<String>,
+    login: Option<DbLogin>,
 ) -> Result<(), String> {
     // Validate database name (alphanumeric and underscore only)
     if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
         return Err("Database name can only contain letters, numbers, and underscores".to_string());
     }
 
-    match db_type.as_str() {
+    let container_id = container_id.as_deref();
+    let output = match db_type.as_str() {
         "mysql" => {
-            let cmd = if let Some(id) = container_id {
-                format!(
-                    "docker exec {} mysql -uroot -psecret -e 'CREATE DATABASE IF NOT EXISTS `{}`;'",
-                    id, name
-                )
-            } else {
-                format!(
-                    "mysql -uroot -e 'CREATE DATABASE IF NOT EXISTS `{}`;'",
-                    name
-                )
-            };
-
-            let output = Command::new("sh")
-                .args(["-c", &cmd])
-                .output()
-                .map_err(|e| format!("Failed to create database: {}", e))?;
-
-            if !output.status.success() {
-                return Err(format!(
-                    "Failed to create database: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ));
-            }
+            let sql = format!("CREATE DATABASE IF NOT EXISTS `{}`;", name);
+            run_sql("mysql", container_id, login, &sql, &[], "create database")?
         }
         "postgresql" => {
-            let cmd = if let Some(id) = container_id {
-                format!(
-                    "docker exec {} psql -U postgres -c 'CREATE DATABASE \"{}\";'",
-                    id, name
-                )
-            } else {
-                format!("psql -U postgres -c 'CREATE DATABASE \"{}\";'", name)
-            };
-
-            let output = Command::new("sh")
-                .args(["-c", &cmd])
-                .output()
-                .map_err(|e| format!("Failed to create database: {}", e))?;
-
-            if !output.status.success()
-                && !String::from_utf8_lossy(&output.stderr).contains("already exists")
-            {
-                return Err(format!(
-                    "Failed to create database: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ));
+            let sql = format!("CREATE DATABASE \"{}\";", name);
+            let output = run_sql(
+                "postgresql",
+                container_id,
+                login,
+                &sql,
+                &[],
+                "create database",
+            )?;
+            if output.stderr.contains("already exists") {
+                return Ok(());
             }
+            output
         }
         _ => return Err(format!("Unsupported database type: {}", db_type)),
-    }
+    };
 
+    if !output.success {
+        return Err(format!("Failed to create database: {}", output.stderr));
+    }
     Ok(())
 }
 
@@ -952,56 +850,51 @@ pub fn drop_database(
         return Err("Cannot drop system database".to_string());
     }
 
-    match db_type.as_str() {
+    let container_id = container_id.as_deref();
+    let output = match db_type.as_str() {
         "mysql" => {
-            let cmd = if let Some(id) = container_id {
-                format!(
-                    "docker exec {} mysql -uroot -psecret -e 'DROP DATABASE IF EXISTS `{}`;'",
-                    id, name
-                )
-            } else {
-                format!("mysql -uroot -e 'DROP DATABASE IF EXISTS `{}`;'", name)
-            };
-
-            let output = Command::new("sh")
-                .args(["-c", &cmd])
-                .output()
-                .map_err(|e| format!("Failed to drop database: {}", e))?;
-
-            if !output.status.success() {
-                return Err(format!(
-                    "Failed to drop database: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ));
-            }
+            let sql = format!("DROP DATABASE IF EXISTS `{}`;", name);
+            run_sql("mysql", container_id, None, &sql, &[], "drop database")?
         }
         "postgresql" => {
-            let cmd = if let Some(id) = container_id {
-                format!(
-                    "docker exec {} psql -U postgres -c 'DROP DATABASE IF EXISTS \"{}\";'",
-                    id, name
-                )
-            } else {
-                format!(
-                    "psql -U postgres -c 'DROP DATABASE IF EXISTS \"{}\";'",
-                    name
-                )
-            };
-
-            let output = Command::new("sh")
-                .args(["-c", &cmd])
-                .output()
-                .map_err(|e| format!("Failed to drop database: {}", e))?;
-
-            if !output.status.success() {
-                return Err(format!(
-                    "Failed to drop database: {}",
-                    String::from_utf8_lossy(&output.stderr)
-                ));
-            }
+            let sql = format!("DROP DATABASE IF EXISTS \"{}\";", name);
+            run_sql("postgresql", container_id, None, &sql, &[], "drop database")?
         }
         _ => return Err(format!("Unsupported database type: {}", db_type)),
-    }
+    };
 
+    if !output.success {
+        return Err(format!("Failed to drop database: {}", output.stderr));
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_db_client_login() {
+        let login = DbLogin {
+            username: "sail".to_string(),
+            password: Some("password".to_string()),
+        };
+        assert_eq!(
+            db_client_args("mysql", &login, "SELECT 1;"),
+            vec!["mysql", "-usail", "-e", "SELECT 1;"]
+        );
+        assert_eq!(db_client_env("mysql", &login), vec!["MYSQL_PWD=password"]);
+        assert_eq!(
+            db_client_args("postgresql", &login, "SELECT 1;"),
+            vec!["psql", "-U", "sail", "-c", "SELECT 1;"]
+        );
+        assert_eq!(
+            db_client_env("postgresql", &login),
+            vec!["PGPASSWORD=password"]
+        );
+
+        let native = db_login("postgresql", None, None).unwrap();
+        assert_eq!(native.username, "postgres");
+        assert!(db_client_env("postgresql", &native).is_empty());
+    }
+}
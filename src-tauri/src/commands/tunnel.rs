@@ -0,0 +1,828 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::backends::ListenPorts;
+use super::sites::load_sites_config;
+use super::webserver::load_webserver_config;
+
+// Public sharing through a tunnel client run as a child process of the app.
+// The client forwards to the local web server with the Host header set to the
+// site's domain, so the server block that already exists answers. Clients
+// that cannot set the header go through a small local proxy instead.
+
+/// Origin a tunnel exposes
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunnelTarget {
+    pub host: String,
+    pub port: u16,
+    pub https: bool,
+}
+
+impl TunnelTarget {
+    fn url(&self) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        format!("{}://127.0.0.1:{}", scheme, self.port)
+    }
+}
+
+/// A tunnel client: how to run it and where it reports the public URL
+pub trait TunnelProvider: Send {
+    fn name(&self) -> &'static str;
+
+    fn binary(&self) -> &'static str;
+
+    fn args(&self, target: &TunnelTarget) -> Vec<String>;
+
+    /// Public URL in a line of the client's output
+    fn parse_url(&self, line: &str) -> Option<String>;
+
+    /// Public URL known before the client starts (self-hosted targets)
+    fn public_url(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the client forwards raw TCP and needs the Host rewriting proxy
+    fn needs_host_proxy(&self) -> bool {
+        false
+    }
+}
+
+fn url_token<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.split(|c: char| c.is_whitespace() || c == '|' || c == '"')
+        .find_map(|token| token.strip_prefix(prefix).map(|_| token))
+}
+
+/// Cloudflare quick tunnels (`*.trycloudflare.com`), no account needed
+pub struct CloudflaredProvider;
+
+impl TunnelProvider for CloudflaredProvider {
+    fn name(&self) -> &'static str {
+        "cloudflared"
+    }
+
+    fn binary(&self) -> &'static str {
+        "cloudflared"
+    }
+
+    fn args(&self, target: &TunnelTarget) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "tunnel",
+            "--no-autoupdate",
+            "--url",
+            &target.url(),
+            "--http-host-header",
+            &target.host,
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        if target.https {
+            // Local certificates are self-signed
+            args.extend(
+                ["--origin-server-name", &target.host, "--no-tls-verify"]
+                    .iter()
+                    .map(|a| a.to_string()),
+            );
+        }
+        args
+    }
+
+    fn parse_url(&self, line: &str) -> Option<String> {
+        url_token(line, "https://")
+            .filter(|url| url.ends_with(".trycloudflare.com"))
+            .map(str::to_string)
+    }
+}
+
+/// ngrok agent (needs an authtoken configured with `ngrok config`)
+pub struct NgrokProvider;
+
+impl TunnelProvider for NgrokProvider {
+    fn name(&self) -> &'static str {
+        "ngrok"
+    }
+
+    fn binary(&self) -> &'static str {
+        "ngrok"
+    }
+
+    fn args(&self, target: &TunnelTarget) -> Vec<String> {
+        vec![
+            "http".to_string(),
+            target.url(),
+            format!("--host-header={}", target.host),
+            "--log=stdout".to_string(),
+            "--log-format=logfmt".to_string(),
+        ]
+    }
+
+    fn parse_url(&self, line: &str) -> Option<String> {
+        url_token(line, "url=https://").map(|token| token["url=".len()..].to_string())
+    }
+}
+
+/// Reverse forward to a server of your own (`ssh -R`), whose web server
+/// proxies the public URL to the forwarded port
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshTunnelTarget {
+    pub destination: String, // user@host
+    #[serde(default)]
+    pub ssh_port: Option<u16>,
+    pub remote_port: u16,
+    #[serde(default)]
+    pub identity_file: Option<String>,
+    /// Public URL served by the remote side; otherwise read from ssh's output
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+pub struct SshProvider {
+    pub target: SshTunnelTarget,
+}
+
+impl TunnelProvider for SshProvider {
+    fn name(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn binary(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn args(&self, target: &TunnelTarget) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "-N",
+            "-T",
+            "-o",
+            "BatchMode=yes",
+            "-o",
+            "ExitOnForwardFailure=yes",
+            "-o",
+            "ServerAliveInterval=30",
+            "-R",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        args.push(format!(
+            "{}:127.0.0.1:{}",
+            self.target.remote_port, target.port
+        ));
+        if let Some(port) = self.target.ssh_port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if let Some(identity) = &self.target.identity_file {
+            args.extend(["-i".to_string(), identity.clone()]);
+        }
+        // End of options: a destination starting with `-` is not an option
+        args.extend(["--".to_string(), self.target.destination.clone()]);
+        args
+    }
+
+    /// Services like localhost.run print the URL they assigned
+    fn parse_url(&self, line: &str) -> Option<String> {
+        url_token(line, "https://").map(str::to_string)
+    }
+
+    fn public_url(&self) -> Option<String> {
+        self.target.public_url.clone()
+    }
+
+    fn needs_host_proxy(&self) -> bool {
+        true
+    }
+}
+
+// ============================================================================
+// Settings
+// ============================================================================
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TunnelSettings {
+    #[serde(default)]
+    pub ssh: Option<SshTunnelTarget>,
+}
+
+fn get_tunnel_settings_path() -> std::path::PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("/tmp"))
+        .join("servermark")
+        .join("tunnels.json")
+}
+
+fn load_tunnel_settings() -> TunnelSettings {
+    fs::read_to_string(get_tunnel_settings_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_tunnel_settings(settings: &TunnelSettings) -> Result<(), String> {
+    let path = get_tunnel_settings_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    fs::write(&path, content).map_err(|e| format!("Failed to write config: {}", e))?;
+    Ok(())
+}
+
+pub fn tunnel_provider(name: &str) -> Result<Box<dyn TunnelProvider>, String> {
+    match name {
+        "cloudflared" => Ok(Box::new(CloudflaredProvider)),
+        "ngrok" => Ok(Box::new(NgrokProvider)),
+        "ssh" => {
+            let target = load_tunnel_settings()
+                .ssh
+                .ok_or("Configure an SSH tunnel target first")?;
+            Ok(Box::new(SshProvider { target }))
+        }
+        _ => Err(format!("Unknown tunnel provider: {}", name)),
+    }
+}
+
+// ============================================================================
+// Host rewriting proxy
+// ============================================================================
+
+/// Replace the Host header of a request head and ask for the connection to
+/// be closed after the response, so each request arrives on a fresh
+/// connection and gets rewritten too
+fn rewrite_request_head(head: &str, host: &str) -> String {
+    let mut lines = head.split("\r\n");
+    let mut out = String::from(lines.next().unwrap_or_default());
+    out.push_str("\r\n");
+    for line in lines.filter(|l| !l.is_empty()) {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (line, None),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "host" => {
+                // A Host line without a value is dropped
+                if let Some(original) = value {
+                    out.push_str(&format!("X-Forwarded-Host: {}\r\n", original.trim()));
+                }
+            }
+            "connection" | "keep-alive" | "proxy-connection" => {}
+            _ => {
+                out.push_str(line);
+                out.push_str("\r\n");
+            }
+        }
+    }
+    out.push_str(&format!("Host: {}\r\nConnection: close\r\n\r\n", host));
+    out
+}
+
+fn proxy_connection(mut client: TcpStream, host: &str, upstream: u16) -> io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let n = client.read(&mut chunk)?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buf.len() > 64 * 1024 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "header too large",
+            ));
+        }
+    };
+
+    let mut server = TcpStream::connect(("127.0.0.1", upstream))?;
+    let head = String::from_utf8_lossy(&buf[..head_end]);
+    server.write_all(rewrite_request_head(&head, host).as_bytes())?;
+    server.write_all(&buf[head_end..])?;
+
+    let mut client_reader = client.try_clone()?;
+    let mut server_writer = server.try_clone()?;
+    thread::spawn(move || {
+        let _ = io::copy(&mut client_reader, &mut server_writer);
+        let _ = server_writer.shutdown(Shutdown::Write);
+    });
+    io::copy(&mut server, &mut client)?;
+    client.shutdown(Shutdown::Write)
+}
+
+/// Local proxy forwarding to `upstream` with the Host header set to `host`
+pub struct HostProxy {
+    pub port: u16,
+    stop: Arc<AtomicBool>,
+}
+
+impl HostProxy {
+    pub fn start(host: String, upstream: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let host = Arc::new(host);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let host = host.clone();
+                thread::spawn(move || {
+                    let _ = proxy_connection(stream, &host, upstream);
+                });
+            }
+        });
+
+        Ok(Self { port, stop })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the accept loop up
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+    }
+}
+
+// ============================================================================
+// Running tunnels
+// ============================================================================
+
+struct RunningTunnel {
+    id: u64,
+    info: TunnelInfo,
+    child: Child,
+    proxy: Option<HostProxy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelInfo {
+    pub site_id: String,
+    pub provider: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelStopped {
+    pub site_id: String,
+    pub provider: String,
+    pub error: Option<String>, // Last output line when the client exited on its own
+}
+
+/// Tunnel clients started by this app, by site id (Tauri managed state)
+#[derive(Default)]
+pub struct TunnelRegistry {
+    tunnels: Mutex<HashMap<String, RunningTunnel>>,
+}
+
+static NEXT_TUNNEL_ID: AtomicU64 = AtomicU64::new(1);
+
+impl TunnelRegistry {
+    fn take(&self, site_id: &str, id: Option<u64>) -> Option<RunningTunnel> {
+        let mut tunnels = self.tunnels.lock().unwrap_or_else(|e| e.into_inner());
+        match tunnels.get(site_id) {
+            Some(tunnel) if id.map_or(true, |id| tunnel.id == id) => tunnels.remove(site_id),
+            _ => None,
+        }
+    }
+
+    fn set_url(&self, site_id: &str, id: u64, url: &str) -> Option<TunnelInfo> {
+        let mut tunnels = self.tunnels.lock().unwrap_or_else(|e| e.into_inner());
+        let tunnel = tunnels.get_mut(site_id).filter(|t| t.id == id)?;
+        tunnel.info.url = Some(url.to_string());
+        Some(tunnel.info.clone())
+    }
+
+    pub fn list(&self) -> Vec<TunnelInfo> {
+        let tunnels = self.tunnels.lock().unwrap_or_else(|e| e.into_inner());
+        tunnels.values().map(|t| t.info.clone()).collect()
+    }
+
+    /// Stop every tunnel, when the app exits
+    pub fn stop_all(&self) {
+        let tunnels: Vec<RunningTunnel> = {
+            let mut tunnels = self.tunnels.lock().unwrap_or_else(|e| e.into_inner());
+            tunnels.drain().map(|(_, t)| t).collect()
+        };
+        for tunnel in tunnels {
+            terminate(tunnel);
+        }
+    }
+}
+
+/// SIGTERM first so the client can close the tunnel, SIGKILL after 3 seconds
+fn terminate(mut tunnel: RunningTunnel) {
+    // SAFETY: kill has no memory-safety preconditions; the pid is our child's
+    unsafe {
+        libc::kill(tunnel.child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    for _ in 0..30 {
+        if matches!(tunnel.child.try_wait(), Ok(Some(_))) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    if matches!(tunnel.child.try_wait(), Ok(None)) {
+        let _ = tunnel.child.kill();
+    }
+    let _ = tunnel.child.wait();
+    if let Some(proxy) = tunnel.proxy {
+        proxy.stop();
+    }
+}
+
+/// Forward the client's output lines until it exits, reporting the public URL
+/// once it shows up and an unexpected exit afterwards
+fn watch_tunnel(
+    app: AppHandle,
+    provider: Box<dyn TunnelProvider>,
+    site_id: String,
+    id: u64,
+    output: Vec<Box<dyn Read + Send>>,
+) {
+    let (tx, rx) = mpsc::channel::<String>();
+    for stream in output {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                let _ = tx.send(line);
+            }
+        });
+    }
+    drop(tx);
+
+    thread::spawn(move || {
+        let registry = app.state::<TunnelRegistry>();
+        let mut reported = false;
+        let mut last_line = None;
+        for line in rx {
+            if !reported {
+                if let Some(info) = provider
+                    .parse_url(&line)
+                    .and_then(|url| registry.set_url(&site_id, id, &url))
+                {
+                    let _ = app.emit("tunnel-started", info);
+                    reported = true;
+                }
+            }
+            log::debug!("{} tunnel: {}", provider.name(), line);
+            last_line = Some(line);
+        }
+
+        // Still registered: the client exited without being asked to
+        if let Some(tunnel) = registry.take(&site_id, Some(id)) {
+            let provider = tunnel.info.provider.clone();
+            terminate(tunnel);
+            let _ = app.emit(
+                "tunnel-stopped",
+                TunnelStopped {
+                    site_id,
+                    provider,
+                    error: Some(last_line.unwrap_or_else(|| "Tunnel exited".to_string())),
+                },
+            );
+        }
+    });
+}
+
+/// Ports the web server answers on locally (rootless Caddy's own ports)
+fn local_ports() -> ListenPorts {
+    let config = load_webserver_config();
+    match &config.rootless {
+        Some(rootless) => rootless.ports(),
+        None => config.ports(),
+    }
+}
+
+fn installed(binary: &str) -> bool {
+    Command::new("which")
+        .arg(binary)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Share a site publicly through `provider` ("cloudflared", "ngrok" or "ssh").
+/// The URL arrives with the `tunnel-started` event.
+#[tauri::command]
+pub fn share_site(
+    app: AppHandle,
+    registry: State<'_, TunnelRegistry>,
+    id: String,
+    provider: String,
+) -> Result<TunnelInfo, String> {
+    let site = load_sites_config()
+        .sites
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or("Site not found")?;
+    let provider = tunnel_provider(&provider)?;
+    if !installed(provider.binary()) {
+        return Err(format!("{} is not installed", provider.binary()));
+    }
+
+    let ports = local_ports();
+    let mut target = TunnelTarget {
+        host: site.domain.clone(),
+        port: if site.secured {
+            ports.https
+        } else {
+            ports.http
+        },
+        https: site.secured,
+    };
+    let proxy = if provider.needs_host_proxy() {
+        if site.secured {
+            return Err(format!(
+                "The {} tunnel forwards plain HTTP; unsecure the site or use cloudflared or ngrok",
+                provider.name()
+            ));
+        }
+        let proxy = HostProxy::start(site.domain.clone(), target.port)
+            .map_err(|e| format!("Failed to start the local proxy: {}", e))?;
+        target.port = proxy.port;
+        Some(proxy)
+    } else {
+        None
+    };
+
+    // One tunnel per site
+    if let Some(previous) = registry.take(&site.id, None) {
+        terminate(previous);
+    }
+
+    let mut child = match Command::new(provider.binary())
+        .args(provider.args(&target))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            if let Some(proxy) = proxy {
+                proxy.stop();
+            }
+            return Err(format!("Failed to start {}: {}", provider.binary(), e));
+        }
+    };
+
+    let id = NEXT_TUNNEL_ID.fetch_add(1, Ordering::SeqCst);
+    let info = TunnelInfo {
+        site_id: site.id.clone(),
+        provider: provider.name().to_string(),
+        url: provider.public_url(),
+    };
+    let mut output: Vec<Box<dyn Read + Send>> = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        output.push(Box::new(stdout));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        output.push(Box::new(stderr));
+    }
+    registry
+        .tunnels
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(
+            site.id.clone(),
+            RunningTunnel {
+                id,
+                info: info.clone(),
+                child,
+                proxy,
+            },
+        );
+    // Registered first, so the URL found in the output has a tunnel to update
+    watch_tunnel(app.clone(), provider, site.id.clone(), id, output);
+
+    if info.url.is_some() {
+        let _ = app.emit("tunnel-started", info.clone());
+    }
+    Ok(info)
+}
+
+/// Close a site's tunnel
+#[tauri::command]
+pub fn stop_sharing_site(
+    app: AppHandle,
+    registry: State<'_, TunnelRegistry>,
+    id: String,
+) -> Result<(), String> {
+    let tunnel = registry.take(&id, None).ok_or("The site is not shared")?;
+    let provider = tunnel.info.provider.clone();
+    terminate(tunnel);
+    let _ = app.emit(
+        "tunnel-stopped",
+        TunnelStopped {
+            site_id: id,
+            provider,
+            error: None,
+        },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_tunnels(registry: State<'_, TunnelRegistry>) -> Vec<TunnelInfo> {
+    registry.list()
+}
+
+#[tauri::command]
+pub fn get_tunnel_settings() -> TunnelSettings {
+    load_tunnel_settings()
+}
+
+/// Set (or clear) the server used by the ssh provider
+#[tauri::command]
+pub fn set_ssh_tunnel_target(target: Option<SshTunnelTarget>) -> Result<TunnelSettings, String> {
+    let mut settings = load_tunnel_settings();
+    settings.ssh = target;
+    save_tunnel_settings(&settings)?;
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(https: bool) -> TunnelTarget {
+        TunnelTarget {
+            host: "shop.test".to_string(),
+            port: if https { 443 } else { 80 },
+            https,
+        }
+    }
+
+    #[test]
+    fn test_provider_args_and_urls() {
+        let args = CloudflaredProvider.args(&target(true));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--url", "https://127.0.0.1:443"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--http-host-header", "shop.test"]));
+        assert_eq!(
+            CloudflaredProvider
+                .parse_url("INF |  https://calm-river-abc.trycloudflare.com                   |")
+                .as_deref(),
+            Some("https://calm-river-abc.trycloudflare.com")
+        );
+        assert_eq!(
+            CloudflaredProvider
+                .parse_url("INF Requesting new quick Tunnel on https://api.trycloudflare.com/x"),
+            None
+        );
+
+        assert!(NgrokProvider
+            .args(&target(false))
+            .contains(&"--host-header=shop.test".to_string()));
+        assert_eq!(
+            NgrokProvider
+                .parse_url(r#"t=2024 lvl=info msg="started tunnel" obj=tunnels name=command_line addr=http://127.0.0.1:80 url=https://ab12.ngrok-free.app"#)
+                .as_deref(),
+            Some("https://ab12.ngrok-free.app")
+        );
+
+        let ssh = SshProvider {
+            target: SshTunnelTarget {
+                destination: "dev@share.example.com".to_string(),
+                ssh_port: Some(2222),
+                remote_port: 9000,
+                identity_file: None,
+                public_url: Some("https://share.example.com".to_string()),
+            },
+        };
+        let args = ssh.args(&TunnelTarget {
+            port: 41000,
+            ..target(false)
+        });
+        assert!(args.windows(2).any(|w| w == ["-R", "9000:127.0.0.1:41000"]));
+        assert!(args.windows(2).any(|w| w == ["-p", "2222"]));
+        assert_eq!(args[args.len() - 2..], ["--", "dev@share.example.com"]);
+    }
+
+    #[test]
+    fn test_rewrite_request_head() {
+        let head = "GET /cart HTTP/1.1\r\nHost: abc.example.com\r\nConnection: keep-alive\r\nAccept: */*\r\n\r\n";
+        assert_eq!(
+            rewrite_request_head(head, "shop.test"),
+            "GET /cart HTTP/1.1\r\nX-Forwarded-Host: abc.example.com\r\nAccept: */*\r\nHost: shop.test\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(
+            rewrite_request_head("GET / HTTP/1.1\r\nHost\r\n\r\n", "shop.test"),
+            "GET / HTTP/1.1\r\nHost: shop.test\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_host_proxy_rewrites_every_connection() {
+        // Upstream echoing the Host header it received
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in upstream.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut head = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut head).unwrap() > 2 {}
+                let host = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Host: "))
+                    .unwrap_or_default()
+                    .to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    host.len(),
+                    host
+                )
+                .unwrap();
+            }
+        });
+
+        let proxy = HostProxy::start("shop.test".to_string(), upstream_port).unwrap();
+        for _ in 0..2 {
+            let mut client = TcpStream::connect(("127.0.0.1", proxy.port)).unwrap();
+            client
+                .write_all(b"GET / HTTP/1.1\r\nHost: public.example.com\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            assert!(response.ends_with("\r\n\r\nshop.test"));
+        }
+        proxy.stop();
+    }
+
+    /// End to end against a local sshd accepting key logins for the current
+    /// user: `cargo test ssh_tunnel -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_ssh_tunnel_against_local_sshd() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = upstream.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            let host_ok = String::from_utf8_lossy(&buf[..n]).contains("Host: shop.test\r\n");
+            let body = if host_ok { "ok" } else { "wrong host" };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let proxy = HostProxy::start("shop.test".to_string(), upstream_port).unwrap();
+        let remote_port = 42000 + (std::process::id() % 1000) as u16;
+        let provider = SshProvider {
+            target: SshTunnelTarget {
+                destination: format!("{}@127.0.0.1", std::env::var("USER").unwrap()),
+                ssh_port: None,
+                remote_port,
+                identity_file: None,
+                public_url: None,
+            },
+        };
+        let mut child = Command::new("ssh")
+            .args(provider.args(&TunnelTarget {
+                port: proxy.port,
+                ..target(false)
+            }))
+            .spawn()
+            .unwrap();
+
+        let mut response = String::new();
+        for _ in 0..50 {
+            thread::sleep(Duration::from_millis(100));
+            if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", remote_port)) {
+                stream
+                    .write_all(b"GET / HTTP/1.1\r\nHost: elsewhere\r\n\r\n")
+                    .unwrap();
+                stream.read_to_string(&mut response).unwrap();
+                break;
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+        proxy.stop();
+        assert!(response.ends_with("ok"), "{}", response);
+    }
+}
@@ -0,0 +1,465 @@
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::docker::{pull_image_blocking, query_containers, Container};
+use super::engine::{api_error, encode, ApiDiskUsage, ApiVolumeList, EngineClient};
+use super::ownership::{ownership_labels, LABEL_MANAGED};
+
+// Named volumes and their backups. A backup is the volume's content as a tar
+// archive under ~/.local/share/servermark/backups, read and written through
+// the engine's archive endpoint on a throwaway busybox container that mounts
+// the volume, so it works the same for rootless engines and Podman.
+
+/// Image of the throwaway containers mounting a volume
+const HELPER_IMAGE: &str = "busybox:latest";
+/// Where helper containers mount the volume
+const MOUNT_POINT: &str = "/volume";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub name: String,
+    pub driver: String,
+    pub mountpoint: String,
+    pub created: Option<String>,
+    pub labels: HashMap<String, String>,
+    /// Created by ServerMark (with a preset container, or restored)
+    pub managed: bool,
+    pub size: Option<u64>, // Bytes, when the engine reports it
+    /// Names of the containers mounting the volume; empty when orphaned
+    pub containers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeBackup {
+    pub file: String,
+    pub volume: String,
+    pub created: String, // RFC 3339
+    pub size: u64,
+}
+
+fn get_backups_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("servermark")
+        .join("backups")
+}
+
+/// Check a name against the engine's volume name rule before using it in
+/// a bind (`name:/path`) or a backup file name
+fn validate_volume_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid volume name: {}", name))
+    }
+}
+
+/// `<volume>-<YYYYMMDD-HHMMSS>.tar`
+fn backup_file_name(volume: &str, time: &NaiveDateTime) -> String {
+    format!("{}-{}.tar", volume, time.format(BACKUP_TIME_FORMAT))
+}
+
+/// Volume name and time of a backup file name
+fn parse_backup_file_name(file: &str) -> Option<(String, NaiveDateTime)> {
+    let stem = file.strip_suffix(".tar")?;
+    // The timestamp is the last two dash-separated fields
+    let (rest, time) = stem.rsplit_once('-')?;
+    let (volume, date) = rest.rsplit_once('-')?;
+    let time =
+        NaiveDateTime::parse_from_str(&format!("{}-{}", date, time), BACKUP_TIME_FORMAT).ok()?;
+    (!volume.is_empty()).then(|| (volume.to_string(), time))
+}
+
+/// Containers mounting each named volume
+fn volume_users(containers: &[Container]) -> HashMap<&str, Vec<&Container>> {
+    let mut users: HashMap<&str, Vec<&Container>> = HashMap::new();
+    for container in containers {
+        for mount in &container.mounts {
+            if mount.kind == "volume" {
+                if let Some(name) = &mount.name {
+                    users.entry(name.as_str()).or_default().push(container);
+                }
+            }
+        }
+    }
+    users
+}
+
+fn list_volumes_with(client: &EngineClient) -> Result<Vec<Volume>, String> {
+    let list: ApiVolumeList = client.get("/volumes")?;
+    let containers = query_containers(client, json!({}))?;
+    let users = volume_users(&containers);
+    // Sizes need a disk usage scan, which some engines do not offer
+    let sizes: HashMap<String, u64> = client
+        .get::<ApiDiskUsage>("/system/df?type=volume")
+        .unwrap_or_default()
+        .volumes
+        .into_iter()
+        .filter_map(|v| {
+            let size = v.usage_data?.size;
+            (size >= 0).then_some((v.name, size as u64))
+        })
+        .collect();
+
+    let mut volumes: Vec<Volume> = list
+        .volumes
+        .into_iter()
+        .map(|v| Volume {
+            managed: v.labels.get(LABEL_MANAGED).is_some_and(|m| m == "true"),
+            size: sizes.get(&v.name).copied(),
+            containers: users
+                .get(v.name.as_str())
+                .map(|cs| cs.iter().map(|c| c.name.clone()).collect())
+                .unwrap_or_default(),
+            name: v.name,
+            driver: v.driver,
+            mountpoint: v.mountpoint,
+            created: v.created_at,
+            labels: v.labels,
+        })
+        .collect();
+    volumes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(volumes)
+}
+
+/// Running containers mounting a volume
+fn running_users(client: &EngineClient, volume: &str) -> Result<Vec<Container>, String> {
+    Ok(query_containers(client, json!({ "volume": [volume] }))?
+        .into_iter()
+        .filter(|c| c.status == "running")
+        .collect())
+}
+
+/// Create a stopped container mounting the volume at `MOUNT_POINT`
+fn create_helper(
+    client: &EngineClient,
+    volume: &str,
+    read_only: bool,
+    cmd: &[&str],
+) -> Result<String, String> {
+    validate_volume_name(volume)?;
+    let body = json!({
+        "Image": HELPER_IMAGE,
+        "Cmd": cmd,
+        "HostConfig": {
+            "Binds": [format!("{}:{}{}", volume, MOUNT_POINT, if read_only { ":ro" } else { "" })],
+        },
+    });
+    let mut response = client.request("POST", "/containers/create", Some(&body))?;
+    if response.status == 404 {
+        pull_image_blocking(client, HELPER_IMAGE)?;
+        response = client.request("POST", "/containers/create", Some(&body))?;
+    }
+    if response.status >= 400 {
+        return Err(api_error(&response));
+    }
+    response.json::<Value>()?["Id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| "Container created without an id".to_string())
+}
+
+/// Run `work` against a helper container, removing it afterwards
+fn with_helper<T>(
+    client: &EngineClient,
+    volume: &str,
+    read_only: bool,
+    cmd: &[&str],
+    work: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<T, String> {
+    let id = create_helper(client, volume, read_only, cmd)?;
+    let result = work(&id);
+    let _ = client.call(
+        "DELETE",
+        &format!("/containers/{}?force=true", encode(&id)),
+        None,
+    );
+    result
+}
+
+fn backup_volume_with(client: &EngineClient, volume: &str) -> Result<VolumeBackup, String> {
+    validate_volume_name(volume)?;
+    client
+        .call("GET", &format!("/volumes/{}", encode(volume)), None)
+        .map_err(|e| format!("Volume {}: {}", volume, e))?;
+
+    let dir = get_backups_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backups dir: {}", e))?;
+    let now = Local::now();
+    let file = backup_file_name(volume, &now.naive_local());
+    let path = dir.join(&file);
+    // Never overwrite an earlier backup taken within the same second
+    let mut out = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                format!("Backup {} already exists, try again in a moment", file)
+            }
+            _ => format!("Failed to create {}: {}", file, e),
+        })?;
+
+    // Pause the containers writing to the volume, so the copy is consistent
+    let mut paused = Vec::new();
+    for container in running_users(client, volume)? {
+        let pause = format!("/containers/{}/pause", encode(&container.id));
+        if client.call("POST", &pause, None).is_ok() {
+            paused.push(container);
+        }
+    }
+
+    let result = with_helper(client, volume, true, &["true"], |id| {
+        let mut write_error = None;
+        client.stream(
+            "GET",
+            &format!("/containers/{}/archive?path={}", encode(id), MOUNT_POINT),
+            None,
+            |data| match out.write_all(data) {
+                Ok(()) => true,
+                Err(e) => {
+                    write_error = Some(e.to_string());
+                    false
+                }
+            },
+        )?;
+        match write_error {
+            Some(e) => Err(format!("Failed to write {}: {}", file, e)),
+            None => Ok(()),
+        }
+    });
+
+    for container in &paused {
+        let _ = client.call(
+            "POST",
+            &format!("/containers/{}/unpause", encode(&container.id)),
+            None,
+        );
+    }
+    if let Err(e) = result {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    Ok(VolumeBackup {
+        file,
+        volume: volume.to_string(),
+        created: now.to_rfc3339(),
+        size,
+    })
+}
+
+fn restore_volume_with(client: &EngineClient, file: &str, volume: &str) -> Result<(), String> {
+    validate_volume_name(volume)?;
+    let path = get_backups_dir().join(file);
+    let mut archive = File::open(&path).map_err(|e| format!("Failed to open {}: {}", file, e))?;
+    let length = archive
+        .metadata()
+        .map_err(|e| format!("Failed to read {}: {}", file, e))?
+        .len();
+
+    let running = running_users(client, volume)?;
+    if !running.is_empty() {
+        let names: Vec<&str> = running.iter().map(|c| c.name.as_str()).collect();
+        return Err(format!(
+            "Stop {} before restoring {}",
+            names.join(", "),
+            volume
+        ));
+    }
+
+    let exists = client
+        .request("GET", &format!("/volumes/{}", encode(volume)), None)?
+        .status
+        == 200;
+    if !exists {
+        client.call(
+            "POST",
+            "/volumes/create",
+            Some(&json!({ "Name": volume, "Labels": ownership_labels(None, None) })),
+        )?;
+    }
+
+    // Empty the volume, then unpack the archive (its entries start with `volume/`)
+    let clear = format!("find {} -mindepth 1 -delete", MOUNT_POINT);
+    with_helper(client, volume, false, &["sh", "-c", &clear], |id| {
+        client.call("POST", &format!("/containers/{}/start", encode(id)), None)?;
+        let waited = client.call("POST", &format!("/containers/{}/wait", encode(id)), None)?;
+        if waited.json::<Value>()?["StatusCode"].as_i64() != Some(0) {
+            return Err(format!("Failed to empty volume {}", volume));
+        }
+        client.upload(
+            "PUT",
+            &format!("/containers/{}/archive?path=/", encode(id)),
+            "application/x-tar",
+            length,
+            &mut archive,
+        )?;
+        Ok(())
+    })
+}
+
+/// Named volumes with their size and the containers using them
+#[tauri::command]
+pub fn list_volumes() -> Result<Vec<Volume>, String> {
+    list_volumes_with(&EngineClient::connect()?)
+}
+
+/// Remove a named volume that no container uses
+#[tauri::command]
+pub fn remove_volume(name: String) -> Result<(), String> {
+    let client = EngineClient::connect()?;
+    let containers = query_containers(&client, json!({ "volume": [name] }))?;
+    if !containers.is_empty() {
+        let names: Vec<&str> = containers.iter().map(|c| c.name.as_str()).collect();
+        return Err(format!("Volume {} is used by {}", name, names.join(", ")));
+    }
+    client
+        .call("DELETE", &format!("/volumes/{}", encode(&name)), None)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to remove volume: {}", e))
+}
+
+/// Snapshot a volume to a tar archive. Containers using it are paused meanwhile.
+#[tauri::command]
+pub async fn backup_volume(name: String) -> Result<VolumeBackup, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        backup_volume_with(&EngineClient::connect()?, &name)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Replace a volume's content with a backup; the volume is created when
+/// missing, and defaults to the one the backup was taken from
+#[tauri::command]
+pub async fn restore_volume(file: String, volume: Option<String>) -> Result<(), String> {
+    let (source, _) = parse_backup_file_name(&file)
+        .filter(|_| !file.contains('/'))
+        .ok_or_else(|| format!("Not a volume backup: {}", file))?;
+    let volume = volume.unwrap_or(source);
+    tauri::async_runtime::spawn_blocking(move || {
+        restore_volume_with(&EngineClient::connect()?, &file, &volume)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Backups on disk, newest first
+#[tauri::command]
+pub fn list_volume_backups() -> Result<Vec<VolumeBackup>, String> {
+    let entries = match fs::read_dir(get_backups_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let mut backups: Vec<(NaiveDateTime, VolumeBackup)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().to_string_lossy().to_string();
+            let (volume, time) = parse_backup_file_name(&file)?;
+            let created = time.and_local_timezone(Local).earliest()?.to_rfc3339();
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            Some((
+                time,
+                VolumeBackup {
+                    file,
+                    volume,
+                    created,
+                    size,
+                },
+            ))
+        })
+        .collect();
+    backups.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Delete a backup archive
+#[tauri::command]
+pub fn delete_volume_backup(file: String) -> Result<(), String> {
+    if file.contains('/') || parse_backup_file_name(&file).is_none() {
+        return Err(format!("Not a volume backup: {}", file));
+    }
+    fs::remove_file(get_backups_dir().join(&file))
+        .map_err(|e| format!("Failed to delete {}: {}", file, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine::FakeEngine;
+    use super::*;
+
+    #[test]
+    fn test_backup_file_names() {
+        let time = NaiveDateTime::parse_from_str("20240501-103000", BACKUP_TIME_FORMAT).unwrap();
+        let file = backup_file_name("servermark-mysql-mysql_data", &time);
+        assert_eq!(file, "servermark-mysql-mysql_data-20240501-103000.tar");
+        assert_eq!(
+            parse_backup_file_name(&file),
+            Some(("servermark-mysql-mysql_data".to_string(), time))
+        );
+        assert_eq!(parse_backup_file_name("notes.tar"), None);
+        assert_eq!(parse_backup_file_name("x-20240501-103000.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_validate_volume_name() {
+        assert!(validate_volume_name("servermark-mysql_data.1").is_ok());
+        for name in ["", "-v", "_data", "data:/etc", "../data", "a b"] {
+            assert!(validate_volume_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_list_volumes_with_sizes_and_users() {
+        let engine = FakeEngine::start(vec![
+            (
+                "GET",
+                "/volumes",
+                200,
+                r#"{"Volumes":[
+                    {"Name":"servermark-mysql-mysql_data","Driver":"local","Mountpoint":"/v/a","Labels":{"servermark.managed":"true"}},
+                    {"Name":"orphan","Driver":"local","Mountpoint":"/v/b","Labels":null}
+                ],"Warnings":null}"#
+                    .to_string(),
+            ),
+            (
+                "GET",
+                "/containers/json",
+                200,
+                r#"[{"Id":"abc","Names":["/servermark-mysql"],"Image":"mysql:8.0","State":"running","Status":"Up","Ports":[],"Created":0,"Labels":{},
+                     "Mounts":[{"Type":"volume","Name":"servermark-mysql-mysql_data","Source":"/v/a","Destination":"/var/lib/mysql"},
+                               {"Type":"bind","Source":"/home/me/seed","Destination":"/seed"}]}]"#
+                    .to_string(),
+            ),
+            (
+                "GET",
+                "/system/df",
+                200,
+                r#"{"Volumes":[{"Name":"servermark-mysql-mysql_data","UsageData":{"Size":2048,"RefCount":1}},
+                               {"Name":"orphan","UsageData":{"Size":-1,"RefCount":0}}]}"#
+                    .to_string(),
+            ),
+        ]);
+
+        let volumes = list_volumes_with(&engine.client).unwrap();
+        assert_eq!(volumes.len(), 2);
+        assert_eq!(volumes[0].name, "orphan");
+        assert!(!volumes[0].managed);
+        assert_eq!(volumes[0].size, None);
+        assert!(volumes[0].containers.is_empty());
+        assert!(volumes[1].managed);
+        assert_eq!(volumes[1].size, Some(2048));
+        assert_eq!(volumes[1].containers, vec!["servermark-mysql"]);
+    }
+}
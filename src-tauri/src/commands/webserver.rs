@@ -3,31 +3,62 @@ use std::fs;
 use std::path::Path;
 use std::process::Command;
 
+use super::backends::{all_backends, backend, ListenPorts, WebServerKind};
+use super::dotenv::DotEnv;
+use super::helper::{helper_available, helper_request, HelperOp};
+use super::hosts::{hosts_script, managed_host_names};
+use super::ports::ensure_ports_available;
+use super::rootless::{apply_rootless_operation, RootlessConfig};
 use super::sites::{load_sites_config, Site, SiteType};
 
-/// Common Docker hostnames that should resolve to localhost
-#[allow(dead_code)]
-const DOCKER_HOSTNAMES: &[&str] = &[
-    "mysql",
-    "mariadb",
-    "postgres",
-    "redis",
-    "memcached",
-    "mailhog",
-    "mailpit",
-    "meilisearch",
-    "elasticsearch",
-    "mongo",
-    "mongodb",
-    "rabbitmq",
-    "minio",
-];
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebServerConfig {
-    pub active: String, // "caddy" or "nginx"
+    pub active: WebServerKind,
     pub caddy_installed: bool,
     pub nginx_installed: bool,
+    #[serde(default)]
+    pub apache_installed: bool,
+    #[serde(default = "default_http_port")]
+    pub http_port: u16,
+    #[serde(default = "default_https_port")]
+    pub https_port: u16,
+    #[serde(default)]
+    pub rootless: Option<RootlessConfig>,
+    /// Map common Docker hostnames (mysql, redis, ...) to localhost in /etc/hosts
+    #[serde(default)]
+    pub docker_host_aliases: bool,
+}
+
+fn default_http_port() -> u16 {
+    80
+}
+
+fn default_https_port() -> u16 {
+    443
+}
+
+impl WebServerConfig {
+    pub fn ports(&self) -> ListenPorts {
+        ListenPorts {
+            http: self.http_port,
+            https: self.https_port,
+        }
+    }
+}
+
+impl Default for WebServerConfig {
+    fn default() -> Self {
+        Self {
+            active: WebServerKind::Caddy,
+            caddy_installed: false,
+            nginx_installed: false,
+            apache_installed: false,
+            http_port: default_http_port(),
+            https_port: default_https_port(),
+            rootless: None,
+            docker_host_aliases: false,
+        }
+    }
 }
 
 fn get_webserver_config_path() -> std::path::PathBuf {
@@ -37,27 +68,14 @@ fn get_webserver_config_path() -> std::path::PathBuf {
         .join("webserver.json")
 }
 
-fn load_webserver_config() -> WebServerConfig {
-    let path = get_webserver_config_path();
-    if path.exists() {
-        fs::read_to_string(&path)
-            .ok()
-            .and_then(|content| serde_json::from_str(&content).ok())
-            .unwrap_or_else(|| WebServerConfig {
-                active: "caddy".to_string(),
-                caddy_installed: false,
-                nginx_installed: false,
-            })
-    } else {
-        WebServerConfig {
-            active: "caddy".to_string(),
-            caddy_installed: false,
-            nginx_installed: false,
-        }
-    }
+pub fn load_webserver_config() -> WebServerConfig {
+    fs::read_to_string(get_webserver_config_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
 }
 
-fn save_webserver_config(config: &WebServerConfig) -> Result<(), String> {
+pub fn save_webserver_config(config: &WebServerConfig) -> Result<(), String> {
     let path = get_webserver_config_path();
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
@@ -68,89 +86,56 @@ fn save_webserver_config(config: &WebServerConfig) -> Result<(), String> {
     Ok(())
 }
 
-/// Generate Caddy config for a site
-fn generate_caddy_site_config(site: &Site) -> String {
-    let public_path = if matches!(site.site_type, SiteType::Laravel | SiteType::Symfony) {
-        format!("{}/public", site.path)
-    } else {
-        site.path.clone()
-    };
-
-    let php_socket = format!("/var/run/php/php{}-fpm.sock", site.php_version);
-
-    let (domain, tls_directive) = if site.secured {
-        (format!("https://{}", site.domain), "    tls internal\n")
-    } else {
-        (format!("http://{}", site.domain), "")
-    };
-
-    format!(
-        r#"{domain} {{
-{tls_directive}    root * {public_path}
-
-    php_fastcgi unix/{php_socket}
-    file_server
-
-    encode gzip
-}}
-"#,
-        domain = domain,
-        tls_directive = tls_directive,
-        public_path = public_path,
-        php_socket = php_socket,
-    )
+/// Resolve the directory served for a site (manifest override or framework default)
+pub fn site_document_root(site: &Site) -> String {
+    match &site.document_root {
+        Some(root) if root.starts_with('/') => root.clone(),
+        Some(root) => format!(
+            "{}/{}",
+            site.path.trim_end_matches('/'),
+            root.trim_matches('/')
+        ),
+        None if matches!(site.site_type, SiteType::Laravel | SiteType::Symfony) => {
+            format!("{}/public", site.path)
+        }
+        None => site.path.clone(),
+    }
+}
+
+/// All host names a site answers on (primary domain first, then aliases)
+pub fn site_domains(site: &Site) -> Vec<&str> {
+    std::iter::once(site.domain.as_str())
+        .chain(site.aliases.iter().map(|a| a.as_str()))
+        .collect()
 }
 
-/// Generate Nginx config for a site
-fn generate_nginx_site_config(site: &Site) -> String {
-    let public_path = if matches!(site.site_type, SiteType::Laravel | SiteType::Symfony) {
-        format!("{}/public", site.path)
-    } else {
-        site.path.clone()
-    };
-
-    let php_socket = format!("/var/run/php/php{}-fpm.sock", site.php_version);
-
-    let ssl_config = if site.secured {
-        format!(
-            r#"
-    listen 443 ssl;
-    ssl_certificate /etc/servermark/ssl/{domain}.crt;
-    ssl_certificate_key /etc/servermark/ssl/{domain}.key;
-"#,
-            domain = site.domain
-        )
-    } else {
-        "    listen 80;".to_string()
-    };
-
-    format!(
-        r#"server {{
-    server_name {domain};
-{ssl_config}
-    root {public_path};
-    index index.php index.html;
-
-    location / {{
-        try_files $uri $uri/ /index.php?$query_string;
-    }}
-
-    location ~ \.php$ {{
-        fastcgi_pass unix:{php_socket};
-        fastcgi_param SCRIPT_FILENAME $realpath_root$fastcgi_script_name;
-        include fastcgi_params;
-    }}
-
-    location ~ /\.(?!well-known).* {{
-        deny all;
-    }}
-}}
-"#,
-        domain = site.domain,
-        ssl_config = ssl_config,
-        public_path = public_path,
-        php_socket = php_socket,
-    )
+/// `.env` keys that may point at a Docker Compose service, with the hostnames to rewrite
+const DOCKER_HOST_KEYS: &[(&str, &[&str])] = &[
+    ("DB_HOST", &["mysql", "mariadb", "postgres", "pgsql"]),
+    ("REDIS_HOST", &["redis"]),
+    ("CACHE_HOST", &["redis"]),
+    ("SESSION_HOST", &["redis"]),
+    ("QUEUE_HOST", &["redis"]),
+    ("MAIL_HOST", &["mailhog", "mailpit"]),
+    ("MEILISEARCH_HOST", &["meilisearch"]),
+    ("ELASTICSEARCH_HOST", &["elasticsearch"]),
+    ("MONGODB_HOST", &["mongo", "mongodb"]),
+];
+
+/// Rewrite a value whose host is a Docker hostname, either bare (`mysql`)
+/// or inside a URL (`http://meilisearch:7700`). Other values are left alone.
+fn replace_docker_host(value: &str, hostnames: &[&str]) -> Option<String> {
+    if hostnames.contains(&value) {
+        return Some("127.0.0.1".to_string());
+    }
+
+    let (scheme, rest) = value.split_once("://")?;
+    let host_end = rest.find([':', '/']).unwrap_or(rest.len());
+    let host = &rest[..host_end];
+
+    hostnames
+        .contains(&host)
+        .then(|| format!("{}://127.0.0.1{}", scheme, &rest[host_end..]))
 }
 
 /// Fix Docker hostnames in a Laravel .env file
@@ -162,41 +147,20 @@ pub fn fix_docker_hostnames_in_env(site_path: &str) -> Result<(), String> {
         return Ok(()); // No .env file to fix
     }
 
-    let content =
-        fs::read_to_string(&env_path).map_err(|e| format!("Failed to read .env: {}", e))?;
-
-    let mut modified = content.clone();
+    let mut env = DotEnv::load(&env_path)?;
     let mut changes_made = false;
 
-    // Common Docker hostname patterns to replace
-    let patterns = [
-        ("DB_HOST=mysql", "DB_HOST=127.0.0.1"),
-        ("DB_HOST=mariadb", "DB_HOST=127.0.0.1"),
-        ("DB_HOST=postgres", "DB_HOST=127.0.0.1"),
-        ("REDIS_HOST=redis", "REDIS_HOST=127.0.0.1"),
-        ("CACHE_HOST=redis", "CACHE_HOST=127.0.0.1"),
-        ("SESSION_HOST=redis", "SESSION_HOST=127.0.0.1"),
-        ("QUEUE_HOST=redis", "QUEUE_HOST=127.0.0.1"),
-        ("MAIL_HOST=mailhog", "MAIL_HOST=127.0.0.1"),
-        ("MAIL_HOST=mailpit", "MAIL_HOST=127.0.0.1"),
-        ("MEILISEARCH_HOST=meilisearch", "MEILISEARCH_HOST=127.0.0.1"),
-        (
-            "ELASTICSEARCH_HOST=elasticsearch",
-            "ELASTICSEARCH_HOST=127.0.0.1",
-        ),
-        ("MONGODB_HOST=mongo", "MONGODB_HOST=127.0.0.1"),
-        ("MONGODB_HOST=mongodb", "MONGODB_HOST=127.0.0.1"),
-    ];
-
-    for (from, to) in patterns {
-        if modified.contains(from) {
-            modified = modified.replace(from, to);
-            changes_made = true;
+    for (key, hostnames) in DOCKER_HOST_KEYS {
+        let replacement = env
+            .get(key)
+            .and_then(|value| replace_docker_host(value, hostnames));
+        if let Some(value) = replacement {
+            changes_made |= env.set(key, &value);
         }
     }
 
     if changes_made {
-        fs::write(&env_path, &modified).map_err(|e| format!("Failed to write .env: {}", e))?;
+        env.save(&env_path)?;
     }
 
     Ok(())
@@ -207,216 +171,184 @@ pub fn fix_docker_hostnames_in_env(site_path: &str) -> Result<(), String> {
 pub fn build_webserver_script(
     operation: &str,
     sites: &[Site],
-    active_server: &str,
+    config: &WebServerConfig,
     site_name: Option<&str>,
 ) -> String {
+    let active_server = config.active;
+    let ports = config.ports();
+    let server = backend(active_server);
     let mut script = String::from("#!/bin/bash\nset -e\n\n");
 
-    // Ensure directories exist and add Docker hostnames to /etc/hosts
-    script.push_str(r#"
-# Ensure directories exist
-mkdir -p /etc/caddy/sites.d
-mkdir -p /etc/nginx/sites-available
-mkdir -p /etc/nginx/sites-enabled
-mkdir -p /etc/servermark/ssl
-chown -R root:root /etc/caddy/sites.d 2>/dev/null || true
-chmod 755 /etc/caddy/sites.d
-
-# Add common Docker hostnames to /etc/hosts (for Laravel projects migrated from Docker)
-for hostname in mysql mariadb postgres redis memcached mailhog mailpit meilisearch elasticsearch mongo mongodb rabbitmq minio; do
-    grep -q "127.0.0.1.*\b${hostname}\b" /etc/hosts 2>/dev/null || echo "127.0.0.1 ${hostname}" >> /etc/hosts
-done
-
-"#);
+    // Ensure directories exist
+    script.push_str("# Ensure directories exist\n");
+    script.push_str(&server.prepare_script());
+    script.push_str("mkdir -p /etc/servermark/ssl\n\n");
 
     match operation {
         "sync_all" => {
-            // Generate all site configs for the active server
-            if active_server == "caddy" {
-                script.push_str("# Clear old Caddy configs\nrm -f /etc/caddy/sites.d/*.conf\n\n");
-                for site in sites {
-                    let config = generate_caddy_site_config(site);
-                    script.push_str(&format!(
-                        "# Site: {}\ncat > '/etc/caddy/sites.d/{}.conf' << 'SITEEOF'\n{}\nSITEEOF\n\n",
-                        site.name, site.name, config
-                    ));
-                }
-                script.push_str("systemctl reload caddy || systemctl restart caddy\n");
-            } else {
-                script.push_str("# Clear old Nginx configs\nrm -f /etc/nginx/sites-enabled/servermark-*\nrm -f /etc/nginx/sites-available/servermark-*\n\n");
-                for site in sites {
-                    let config = generate_nginx_site_config(site);
-                    let filename = format!("servermark-{}", site.name);
-                    script.push_str(&format!(
-                        "# Site: {}\ncat > '/etc/nginx/sites-available/{}' << 'SITEEOF'\n{}\nSITEEOF\nln -sf '/etc/nginx/sites-available/{}' '/etc/nginx/sites-enabled/{}'\n\n",
-                        site.name, filename, config, filename, filename
-                    ));
-
-                    // Generate self-signed SSL cert if needed
-                    if site.secured {
-                        script.push_str(&format!(
-                            r#"if [ ! -f "/etc/servermark/ssl/{domain}.crt" ]; then
-    openssl req -x509 -nodes -days 365 -newkey rsa:2048 \
-        -keyout "/etc/servermark/ssl/{domain}.key" \
-        -out "/etc/servermark/ssl/{domain}.crt" \
-        -subj "/CN={domain}" 2>/dev/null
-fi
-"#,
-                            domain = site.domain
-                        ));
-                    }
-                }
-                script.push_str("nginx -t && systemctl reload nginx || systemctl restart nginx\n");
+            // Regenerate all site configs for the active server
+            script.push_str("# Clear old configs\n");
+            script.push_str(&server.clear_sites_script());
+            script.push_str(&server.ports_script(ports));
+            for site in sites {
+                script.push_str(&server.write_site_script(site, ports));
+                script.push('\n');
             }
+            script.push_str(&server.reload_script());
         }
-        "add_site" | "update_site" => {
+        "add_site" | "update_site" | "renew_certificate" => {
             if let Some(name) = site_name {
                 if let Some(site) = sites.iter().find(|s| s.name == name) {
-                    if active_server == "caddy" {
-                        let config = generate_caddy_site_config(site);
-                        script.push_str(&format!(
-                            "cat > '/etc/caddy/sites.d/{}.conf' << 'SITEEOF'\n{}\nSITEEOF\n",
-                            site.name, config
-                        ));
-                        script.push_str("systemctl reload caddy || systemctl restart caddy\n");
-                    } else {
-                        let config = generate_nginx_site_config(site);
-                        let filename = format!("servermark-{}", site.name);
+                    // Drop the existing certificate so it gets issued again below
+                    if operation == "renew_certificate" {
                         script.push_str(&format!(
-                            "cat > '/etc/nginx/sites-available/{}' << 'SITEEOF'\n{}\nSITEEOF\nln -sf '/etc/nginx/sites-available/{}' '/etc/nginx/sites-enabled/{}'\n",
-                            filename, config, filename, filename
+                            "rm -f '/etc/servermark/ssl/{domain}.crt' '/etc/servermark/ssl/{domain}.key'\nrm -rf '/var/lib/caddy/.local/share/caddy/certificates/local/{domain}'\n",
+                            domain = site.domain
                         ));
-
-                        if site.secured {
-                            script.push_str(&format!(
-                                r#"if [ ! -f "/etc/servermark/ssl/{domain}.crt" ]; then
-    openssl req -x509 -nodes -days 365 -newkey rsa:2048 \
-        -keyout "/etc/servermark/ssl/{domain}.key" \
-        -out "/etc/servermark/ssl/{domain}.crt" \
-        -subj "/CN={domain}" 2>/dev/null
-fi
-"#,
-                                domain = site.domain
-                            ));
-                        }
-                        script.push_str(
-                            "nginx -t && systemctl reload nginx || systemctl restart nginx\n",
-                        );
                     }
 
-                    // Add hosts entry
-                    script.push_str(&format!(
-                        "grep -q '{}' /etc/hosts || echo '127.0.0.1 {}' >> /etc/hosts\n",
-                        site.domain, site.domain
-                    ));
+                    script.push_str(&server.write_site_script(site, ports));
+                    script.push_str(&server.reload_script());
                 }
             }
         }
         "remove_site" => {
             if let Some(name) = site_name {
-                // Remove from both servers to be safe
-                script.push_str(&format!("rm -f '/etc/caddy/sites.d/{}.conf'\n", name));
-                script.push_str(&format!(
-                    "rm -f '/etc/nginx/sites-available/servermark-{}'\n",
-                    name
-                ));
+                // Remove from every server to be safe
+                for kind in WebServerKind::ALL {
+                    script.push_str(&backend(kind).remove_site_script(name));
+                }
+
+                // Reload active server
                 script.push_str(&format!(
-                    "rm -f '/etc/nginx/sites-enabled/servermark-{}'\n",
-                    name
+                    "{{ {}; }} 2>/dev/null || true\n",
+                    server.reload_script().trim_end()
                 ));
+            }
+        }
+        "switch_server" => {
+            script.push_str(&format!("\n# Switch to {}\n", active_server));
+            for kind in WebServerKind::ALL
+                .into_iter()
+                .filter(|k| *k != active_server)
+            {
+                script.push_str(&backend(kind).stop_script());
+            }
 
-                // Reload active server
-                if active_server == "caddy" {
-                    script.push_str("systemctl reload caddy 2>/dev/null || true\n");
-                } else {
-                    script.push_str("nginx -t && systemctl reload nginx 2>/dev/null || true\n");
-                }
+            script.push_str("\n# Regenerate all site configs\n");
+            script.push_str(&server.clear_sites_script());
+            script.push_str(&server.ports_script(ports));
+            for site in sites {
+                script.push_str(&server.write_site_script(site, ports));
+            }
+            script.push('\n');
+            script.push_str(&server.start_script());
+        }
+        _ => {}
+    }
 
-                // Remove from hosts (find domain from sites list)
-                if let Some(site) = sites.iter().find(|s| s.name == name) {
-                    script.push_str(&format!("sed -i '/{}/d' /etc/hosts\n", site.domain));
-                }
+    // Rewrite the managed /etc/hosts block from the current sites
+    script.push_str(&hosts_script(&hosts_names(
+        sites, config, operation, site_name,
+    )));
+
+    script
+}
+
+/// Names for the managed /etc/hosts block once `operation` is applied
+fn hosts_names(
+    sites: &[Site],
+    config: &WebServerConfig,
+    operation: &str,
+    site_name: Option<&str>,
+) -> Vec<String> {
+    let remaining: Vec<Site> = sites
+        .iter()
+        .filter(|s| operation != "remove_site" || Some(s.name.as_str()) != site_name)
+        .cloned()
+        .collect();
+    managed_host_names(&remaining, config.docker_host_aliases)
+}
+
+/// Helper operations for a web server operation, mirroring `build_webserver_script`.
+/// `None` when part of it falls outside the helper's API.
+pub fn build_webserver_ops(
+    operation: &str,
+    sites: &[Site],
+    config: &WebServerConfig,
+    site_name: Option<&str>,
+) -> Option<Vec<HelperOp>> {
+    let ports = config.ports();
+    let server = backend(config.active);
+    let mut ops = Vec::new();
+
+    match operation {
+        "sync_all" => {
+            ops.extend(server.clear_sites_ops());
+            ops.extend(server.ports_ops(ports)?);
+            for site in sites {
+                ops.extend(server.write_site_ops(site, ports));
             }
+            ops.extend(server.reload_ops());
         }
-        "switch_server" => {
-            if active_server == "caddy" {
-                script.push_str(
-                    r#"
-# Switch to Caddy
-systemctl stop nginx 2>/dev/null || true
-systemctl disable nginx 2>/dev/null || true
-
-# Regenerate all Caddy configs
-rm -f /etc/caddy/sites.d/*.conf
-"#,
-                );
-                for site in sites {
-                    let config = generate_caddy_site_config(site);
-                    script.push_str(&format!(
-                        "cat > '/etc/caddy/sites.d/{}.conf' << 'SITEEOF'\n{}\nSITEEOF\n",
-                        site.name, config
-                    ));
+        "add_site" | "update_site" | "renew_certificate" => {
+            if let Some(site) = site_name.and_then(|name| sites.iter().find(|s| s.name == name)) {
+                if operation == "renew_certificate" {
+                    ops.push(HelperOp::RemoveCertificate {
+                        domain: site.domain.clone(),
+                    });
                 }
-                script.push_str(
-                    r#"
-systemctl enable caddy
-systemctl start caddy
-"#,
-                );
-            } else {
-                script.push_str(
-                    r#"
-# Switch to Nginx
-systemctl stop caddy 2>/dev/null || true
-systemctl disable caddy 2>/dev/null || true
-
-# Regenerate all Nginx configs
-rm -f /etc/nginx/sites-enabled/servermark-*
-rm -f /etc/nginx/sites-available/servermark-*
-"#,
-                );
-                for site in sites {
-                    let config = generate_nginx_site_config(site);
-                    let filename = format!("servermark-{}", site.name);
-                    script.push_str(&format!(
-                        "cat > '/etc/nginx/sites-available/{}' << 'SITEEOF'\n{}\nSITEEOF\nln -sf '/etc/nginx/sites-available/{}' '/etc/nginx/sites-enabled/{}'\n",
-                        filename, config, filename, filename
-                    ));
-
-                    if site.secured {
-                        script.push_str(&format!(
-                            r#"if [ ! -f "/etc/servermark/ssl/{domain}.crt" ]; then
-    openssl req -x509 -nodes -days 365 -newkey rsa:2048 \
-        -keyout "/etc/servermark/ssl/{domain}.key" \
-        -out "/etc/servermark/ssl/{domain}.crt" \
-        -subj "/CN={domain}" 2>/dev/null
-fi
-"#,
-                            domain = site.domain
-                        ));
-                    }
+                ops.extend(server.write_site_ops(site, ports));
+                ops.extend(server.reload_ops());
+            }
+        }
+        "remove_site" => {
+            if let Some(name) = site_name {
+                for other in all_backends() {
+                    ops.extend(other.remove_site_ops(name));
                 }
-                script.push_str(
-                    r#"
-systemctl enable nginx
-nginx -t && systemctl start nginx
-"#,
-                );
+                ops.extend(server.reload_ops());
+            }
+        }
+        "switch_server" => {
+            for kind in WebServerKind::ALL
+                .into_iter()
+                .filter(|k| *k != config.active)
+            {
+                ops.extend(backend(kind).stop_ops());
+            }
+            ops.extend(server.clear_sites_ops());
+            ops.extend(server.ports_ops(ports)?);
+            for site in sites {
+                ops.extend(server.write_site_ops(site, ports));
             }
+            ops.extend(server.start_ops());
         }
         _ => {}
     }
 
-    script
+    ops.push(HelperOp::WriteHostsBlock {
+        names: hosts_names(sites, config, operation, site_name),
+    });
+
+    Some(ops)
 }
 
-/// Execute a web server operation with a single pkexec call
-pub fn execute_webserver_operation(operation: &str, site_name: Option<&str>) -> Result<(), String> {
-    let sites_config = load_sites_config();
-    let ws_config = load_webserver_config();
+/// Apply an operation through the privileged helper when it is installed,
+/// otherwise with a single pkexec call
+fn apply_webserver_operation(
+    operation: &str,
+    sites: &[Site],
+    config: &WebServerConfig,
+    site_name: Option<&str>,
+) -> Result<(), String> {
+    if helper_available() {
+        if let Some(ops) = build_webserver_ops(operation, sites, config, site_name) {
+            return helper_request(ops).map_err(|e| format!("Operation failed: {}", e));
+        }
+    }
 
-    let script =
-        build_webserver_script(operation, &sites_config.sites, &ws_config.active, site_name);
+    let script = build_webserver_script(operation, sites, config, site_name);
 
     let output = Command::new("pkexec")
         .args(["bash", "-c", &script])
@@ -433,41 +365,55 @@ pub fn execute_webserver_operation(operation: &str, site_name: Option<&str>) ->
     Ok(())
 }
 
+/// Execute a web server operation with a single privileged call
+pub fn execute_webserver_operation(operation: &str, site_name: Option<&str>) -> Result<(), String> {
+    let sites_config = load_sites_config();
+    let ws_config = load_webserver_config();
+
+    if let Some(rootless) = &ws_config.rootless {
+        return apply_rootless_operation(rootless, operation, &sites_config.sites, site_name);
+    }
+
+    apply_webserver_operation(operation, &sites_config.sites, &ws_config, site_name)
+}
+
 /// Sync all sites to the active web server
 #[tauri::command]
 pub fn sync_webserver_configs() -> Result<(), String> {
     execute_webserver_operation("sync_all", None)
 }
 
-/// Switch between Caddy and Nginx
+/// Switch between Caddy, Nginx and Apache
 #[tauri::command]
 pub fn switch_active_webserver(server: String) -> Result<(), String> {
+    let server = WebServerKind::parse(&server)?;
     let mut config = load_webserver_config();
-    config.active = server.clone();
+    if config.rootless.is_some() {
+        return Err("Disable rootless mode before switching web servers".to_string());
+    }
+    config.active = server;
+
+    // Other managed servers are stopped by the switch; anything else on the ports is a conflict
+    ensure_ports_available(config.ports(), &WebServerKind::ALL)?;
     save_webserver_config(&config)?;
 
     let sites_config = load_sites_config();
-    let script = build_webserver_script("switch_server", &sites_config.sites, &server, None);
-
-    let output = Command::new("pkexec")
-        .args(["bash", "-c", &script])
-        .output()
-        .map_err(|e| format!("Failed to switch server: {}", e))?;
-
-    if !output.status.success() {
-        return Err(format!(
-            "Failed to switch server: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
+    apply_webserver_operation("switch_server", &sites_config.sites, &config, None)
+}
 
-    Ok(())
+/// Opt in or out of mapping Docker hostnames to localhost in /etc/hosts
+#[tauri::command]
+pub fn set_docker_host_aliases(enabled: bool) -> Result<(), String> {
+    let mut config = load_webserver_config();
+    config.docker_host_aliases = enabled;
+    save_webserver_config(&config)?;
+    execute_webserver_operation("sync_hosts", None)
 }
 
 /// Get current active web server
 #[tauri::command]
 pub fn get_active_webserver() -> String {
-    load_webserver_config().active
+    load_webserver_config().active.to_string()
 }
 
 /// Add/update a site in the web server config
@@ -484,3 +430,26 @@ pub fn webserver_remove_site(site_name: &str) -> Result<(), String> {
 pub fn webserver_update_site(site_name: &str) -> Result<(), String> {
     execute_webserver_operation("update_site", Some(site_name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_docker_host() {
+        let db = &["mysql", "mariadb"];
+        assert_eq!(
+            replace_docker_host("mysql", db).as_deref(),
+            Some("127.0.0.1")
+        );
+        assert_eq!(replace_docker_host("mysql-primary", db), None);
+        assert_eq!(
+            replace_docker_host("http://meilisearch:7700", &["meilisearch"]).as_deref(),
+            Some("http://127.0.0.1:7700")
+        );
+        assert_eq!(
+            replace_docker_host("http://meilisearch-eu:7700", &["meilisearch"]),
+            None
+        );
+    }
+}
@@ -7,6 +7,16 @@ use tauri_plugin_log::{Target, TargetKind};
 
 mod commands;
 
+/// Entry point of `servermark --privileged-helper` (started by systemd as root)
+pub fn run_privileged_helper() -> i32 {
+    commands::run_privileged_helper()
+}
+
+/// Entry point of `servermark --dns-responder` (started by a systemd user unit)
+pub fn run_dns_responder() -> i32 {
+    commands::run_dns_responder()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -21,6 +31,9 @@ pub fn run() {
                 ])
                 .build(),
         )
+        .manage(commands::TunnelRegistry::default())
+        .manage(commands::StatsStreams::default())
+        .manage(commands::LogFollowers::default())
         .setup(|app| {
             // Create tray menu items
             let open_i = MenuItem::with_id(app, "open", "Open ServerMark", true, None::<&str>)?;
@@ -116,6 +129,31 @@ pub fn run() {
             commands::stop_container,
             commands::remove_container,
             commands::get_container_logs,
+            commands::wait_for_container_ready,
+            commands::get_container_stats,
+            commands::stream_container_stats,
+            commands::stop_container_stats,
+            commands::update_container_limits,
+            commands::list_adoptable_containers,
+            commands::adopt_container,
+            commands::unadopt_container,
+            commands::follow_logs,
+            commands::stop_follow,
+            commands::get_compose_project,
+            commands::compose_up,
+            commands::compose_down,
+            commands::import_sail_project,
+            commands::list_volumes,
+            commands::remove_volume,
+            commands::backup_volume,
+            commands::restore_volume,
+            commands::list_volume_backups,
+            commands::delete_volume_backup,
+            commands::pull_image,
+            commands::check_container_updates,
+            commands::recreate_container,
+            commands::list_service_presets,
+            commands::create_service_from_preset,
             // Laravel commands
             commands::detect_laravel_version,
             commands::get_latest_laravel_version,
@@ -135,6 +173,13 @@ pub fn run() {
             commands::add_site,
             commands::remove_site,
             commands::update_site_php,
+            commands::share_site_lan,
+            commands::unshare_site,
+            commands::share_site,
+            commands::stop_sharing_site,
+            commands::list_tunnels,
+            commands::get_tunnel_settings,
+            commands::set_ssh_tunnel_target,
             commands::detect_site_type,
             commands::get_sites_config,
             commands::update_sites_config,
@@ -144,6 +189,29 @@ pub fn run() {
             commands::secure_site,
             commands::unsecure_site,
             commands::fix_laravel_permissions,
+            // Project manifest (servermark.yml)
+            commands::check_site_manifest,
+            commands::apply_site_manifest,
+            // .env provisioning
+            commands::list_managed_services,
+            commands::provision_site_env,
+            commands::get_site_env,
+            commands::set_site_env,
+            // Rootless mode
+            commands::get_rootless_status,
+            commands::enable_rootless_mode,
+            commands::disable_rootless_mode,
+            // Privileged helper
+            commands::install_helper,
+            commands::get_helper_status,
+            // Port conflicts
+            commands::get_port_owners,
+            commands::resolve_port_conflict,
+            // Diagnostics
+            commands::diagnose_site,
+            commands::fix_site_issue,
+            commands::run_doctor,
+            commands::fix_doctor_issue,
             // Web server commands
             commands::detect_web_server,
             commands::install_web_server,
@@ -151,9 +219,11 @@ pub fn run() {
             commands::sync_webserver_configs,
             commands::switch_active_webserver,
             commands::get_active_webserver,
+            commands::set_docker_host_aliases,
             // DNS commands
             commands::detect_dns,
             commands::install_dns,
+            commands::uninstall_dns,
             // Native services commands
             commands::detect_native_services,
             commands::control_native_service,
@@ -165,6 +235,14 @@ pub fn run() {
             commands::create_database,
             commands::drop_database,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Public tunnels are child processes; close them with the app
+            if let tauri::RunEvent::Exit = event {
+                app.state::<commands::TunnelRegistry>().stop_all();
+                app.state::<commands::StatsStreams>().stop_all();
+                app.state::<commands::LogFollowers>().stop_all();
+            }
+        });
 }
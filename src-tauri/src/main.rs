@@ -2,5 +2,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--privileged-helper") {
+        std::process::exit(servermark_lib::run_privileged_helper());
+    }
+    if std::env::args().any(|arg| arg == "--dns-responder") {
+        std::process::exit(servermark_lib::run_dns_responder());
+    }
     servermark_lib::run();
 }